@@ -12,6 +12,7 @@ Page1Form {
 
     HWEngine {
       engineLibrary: "./libhedgewars_engine.so"
+      dataPath: "../share/hedgewars/Data"
       previewAcceptor: PreviewAcceptor
       onPreviewImageChanged: previewImage.source = "image://preview/image"
       onPreviewIsRendering: previewImage.source = "qrc:/res/iconTime.png"
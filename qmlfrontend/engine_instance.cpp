@@ -13,7 +13,8 @@ extern "C" void (*getProcAddress(const char* fn))() {
     return currentOpenglContext->getProcAddress(fn);
 }
 
-EngineInstance::EngineInstance(const QString& libraryPath, QObject* parent)
+EngineInstance::EngineInstance(const QString& libraryPath,
+                               const QString& dataPath, QObject* parent)
     : QObject(parent) {
   QLibrary hwlib(libraryPath);
 
@@ -56,16 +57,19 @@ EngineInstance::EngineInstance(const QString& libraryPath, QObject* parent)
               advance_simulation && move_camera && simple_event && long_event &&
               positioned_event;
 
-  emit isValidChanged(m_isValid);
-
-  if (isValid()) {
+  if (m_isValid) {
     qDebug() << "Loaded engine library with protocol version"
              << hedgewars_engine_protocol_version();
 
-    m_instance = start_engine();
+    m_instance = start_engine(dataPath.toUtf8().constData());
+    m_isValid = m_instance != nullptr;
+
+    if (!m_isValid) qWarning() << "Engine failed to load data from" << dataPath;
   } else {
     qDebug("Engine library load failed");
   }
+
+  emit isValidChanged(m_isValid);
 }
 
 EngineInstance::~EngineInstance() {
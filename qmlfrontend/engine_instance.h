@@ -12,7 +12,7 @@ class EngineInstance : public QObject {
   Q_OBJECT
 
  public:
-  explicit EngineInstance(const QString& libraryPath,
+  explicit EngineInstance(const QString& libraryPath, const QString& dataPath,
                           QObject* parent = nullptr);
   ~EngineInstance();
 
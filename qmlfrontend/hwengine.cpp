@@ -19,7 +19,7 @@ void HWEngine::getPreview() {
   m_gameConfig = GameConfig();
   m_gameConfig.cmdSeed(QUuid::createUuid().toByteArray());
 
-  EngineInstance engine(m_engineLibrary);
+  EngineInstance engine(m_engineLibrary, m_dataPath);
   if (!engine.isValid())  // TODO: error notification
     return;
 
@@ -43,7 +43,8 @@ EngineInstance* HWEngine::runQuickGame() {
   m_gameConfig.cmdTeam(team1);
   m_gameConfig.cmdTeam(team2);
 
-  EngineInstance* engine = new EngineInstance(m_engineLibrary, this);
+  EngineInstance* engine =
+      new EngineInstance(m_engineLibrary, m_dataPath, this);
 
   return engine;
   // m_runQueue->queue(m_gameConfig);
@@ -55,6 +56,8 @@ PreviewAcceptor* HWEngine::previewAcceptor() const { return m_previewAcceptor; }
 
 QString HWEngine::engineLibrary() const { return m_engineLibrary; }
 
+QString HWEngine::dataPath() const { return m_dataPath; }
+
 void HWEngine::setPreviewAcceptor(PreviewAcceptor* previewAcceptor) {
   if (m_previewAcceptor == previewAcceptor) return;
 
@@ -68,3 +71,10 @@ void HWEngine::setEngineLibrary(const QString& engineLibrary) {
   m_engineLibrary = engineLibrary;
   emit engineLibraryChanged(m_engineLibrary);
 }
+
+void HWEngine::setDataPath(const QString& dataPath) {
+  if (m_dataPath == dataPath) return;
+
+  m_dataPath = dataPath;
+  emit dataPathChanged(m_dataPath);
+}
@@ -20,6 +20,8 @@ class HWEngine : public QObject {
                  setPreviewAcceptor NOTIFY previewAcceptorChanged)
   Q_PROPERTY(QString engineLibrary READ engineLibrary WRITE setEngineLibrary
                  NOTIFY engineLibraryChanged)
+  Q_PROPERTY(QString dataPath READ dataPath WRITE setDataPath NOTIFY
+                 dataPathChanged)
 
  public:
   explicit HWEngine(QObject* parent = nullptr);
@@ -31,10 +33,12 @@ class HWEngine : public QObject {
   int previewHedgehogsCount() const;
   PreviewAcceptor* previewAcceptor() const;
   QString engineLibrary() const;
+  QString dataPath() const;
 
  public slots:
   void setPreviewAcceptor(PreviewAcceptor* previewAcceptor);
   void setEngineLibrary(const QString& engineLibrary);
+  void setDataPath(const QString& dataPath);
 
  signals:
   void previewIsRendering();
@@ -44,6 +48,7 @@ class HWEngine : public QObject {
   void previewHedgehogsCountChanged(int previewHedgehogsCount);
   void previewAcceptorChanged(PreviewAcceptor* previewAcceptor);
   void engineLibraryChanged(const QString& engineLibrary);
+  void dataPathChanged(const QString& dataPath);
 
  private:
   QQmlEngine* m_engine;
@@ -51,6 +56,7 @@ class HWEngine : public QObject {
   int m_previewHedgehogsCount;
   PreviewAcceptor* m_previewAcceptor;
   QString m_engineLibrary;
+  QString m_dataPath;
 };
 
 #endif  // HWENGINE_H
@@ -81,6 +81,10 @@ named!(unordered_message<&[u8], UnorderedEngineMessage>, alt!(
 named!(config_message<&[u8], ConfigEngineMessage>, alt!(
     do_parse!(tag!("C") >> (ConfigRequest))
     | do_parse!(tag!("eseed ") >> s: string_tail >> ( SetSeed(s)) )
+    | do_parse!(tag!("e$mapgen ") >> s: string_tail >> ( SetMapGenerator(s)) )
+    | do_parse!(tag!("e$template_filter ") >> s: string_tail >> ( SetTemplateFilter(s)) )
+    | do_parse!(tag!("e$maze_size ") >> s: string_tail >> ( SetTemplateFilter(s)) )
+    | do_parse!(tag!("e$feature_size ") >> s: map_res!(string_tail, |s: String| s.parse()) >> ( SetFeatureSize(s)) )
 ));
 
 named!(timestamped_message<&[u8], (SyncedEngineMessage, u16)>,
@@ -186,6 +190,18 @@ mod tests {
     #[test]
     fn parse_config_messages() {
         assert_eq!(message(b"\x01C"), Ok((&b""[..], Config(ConfigRequest))));
+        assert_eq!(
+            message(b"\x0ae$mapgen 2"),
+            Ok((&b""[..], Config(SetMapGenerator(String::from("2")))))
+        );
+        assert_eq!(
+            message(b"\x0de$maze_size 4"),
+            Ok((&b""[..], Config(SetTemplateFilter(String::from("4")))))
+        );
+        assert_eq!(
+            message(b"\x11e$feature_size 12"),
+            Ok((&b""[..], Config(SetFeatureSize(12))))
+        );
     }
 
     #[test]
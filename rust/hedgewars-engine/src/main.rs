@@ -1,13 +1,15 @@
 extern crate libloading;
 
 use libloading::{Library, Symbol};
-use std::ops::Deref;
+use std::{env, ffi::CString, ops::Deref, os::raw::c_char};
+
+const DEFAULT_DATA_PATH: &str = "../../share/hedgewars/Data";
 
 struct EngineInstance {}
 
 struct Engine<'a> {
     protocol_version: Symbol<'a, unsafe fn() -> u32>,
-    start_engine: Symbol<'a, unsafe fn() -> *mut EngineInstance>,
+    start_engine: Symbol<'a, unsafe fn(data_path: *const c_char) -> *mut EngineInstance>,
     cleanup: Symbol<'a, unsafe fn(engine_state: *mut EngineInstance)>,
 }
 
@@ -22,5 +24,16 @@ fn main() {
         };
 
         println!("Hedgewars engine, protocol version {}", engine.protocol_version.deref()());
+
+        let data_path = env::args()
+            .nth(1)
+            .unwrap_or_else(|| DEFAULT_DATA_PATH.to_string());
+        let data_path = CString::new(data_path).expect("The data path contains a NUL byte");
+        let engine_state = engine.start_engine.deref()(data_path.as_ptr());
+        if engine_state.is_null() {
+            eprintln!("Unable to start the engine");
+        } else {
+            engine.cleanup.deref()(engine_state);
+        }
     }
 }
@@ -1,6 +1,10 @@
+#[macro_use]
+pub mod snapshot;
+
 pub mod anteroom;
 pub mod client;
 pub mod indexslab;
 pub mod room;
 pub mod server;
+pub mod turns;
 pub mod types;
@@ -1,7 +1,9 @@
 use super::{indexslab::IndexSlab, types::ClientId};
 use chrono::{offset, DateTime};
+use serde_derive::{Deserialize, Serialize};
 use std::{iter::Iterator, num::NonZeroU16};
 
+#[derive(Serialize, Deserialize)]
 pub struct HwAnteroomClient {
     pub nick: Option<String>,
     pub protocol_number: Option<NonZeroU16>,
@@ -11,8 +13,10 @@ pub struct HwAnteroomClient {
     pub is_registered: bool,
     pub is_admin: bool,
     pub is_contributor: bool,
+    pub is_secure: bool,
 }
 
+#[derive(Serialize, Deserialize)]
 struct Ipv4AddrRange {
     min: [u8; 4],
     max: [u8; 4],
@@ -24,6 +28,7 @@ impl Ipv4AddrRange {
     }
 }
 
+#[derive(Serialize, Deserialize)]
 struct BanCollection {
     ban_ips: Vec<Ipv4AddrRange>,
     ban_timeouts: Vec<DateTime<offset::Utc>>,
@@ -49,6 +54,7 @@ impl BanCollection {
     }
 }
 
+#[derive(Serialize, Deserialize)]
 pub struct HwAnteroom {
     pub clients: IndexSlab<HwAnteroomClient>,
     bans: BanCollection,
@@ -67,7 +73,13 @@ impl HwAnteroom {
         self.bans.find(addr)
     }
 
-    pub fn add_client(&mut self, client_id: ClientId, salt: String, is_local_admin: bool) {
+    pub fn add_client(
+        &mut self,
+        client_id: ClientId,
+        salt: String,
+        is_local_admin: bool,
+        is_secure: bool,
+    ) {
         let client = HwAnteroomClient {
             nick: None,
             protocol_number: None,
@@ -77,6 +89,7 @@ impl HwAnteroom {
             is_registered: false,
             is_admin: false,
             is_contributor: false,
+            is_secure,
         };
         self.clients.insert(client_id, client);
     }
@@ -1,8 +1,9 @@
-use super::types::ClientId;
+use super::types::{ClientId, RoomFilter};
 use bitflags::*;
+use serde_derive::{Deserialize, Serialize};
 
 bitflags! {
-    pub struct ClientFlags: u8 {
+    pub struct ClientFlags: u16 {
         const IS_ADMIN = 0b0000_0001;
         const IS_MASTER = 0b0000_0010;
         const IS_READY = 0b0000_0100;
@@ -11,12 +12,16 @@ bitflags! {
         const IS_CONTRIBUTOR = 0b0010_0000;
         const HAS_SUPER_POWER = 0b0100_0000;
         const IS_REGISTERED = 0b1000_0000;
+        const IS_AWAY = 0b1_0000_0000;
 
         const NONE = 0b0000_0000;
         const DEFAULT = Self::NONE.bits;
     }
 }
 
+serde_bitflags!(ClientFlags, u16);
+
+#[derive(Serialize, Deserialize)]
 pub struct HwClient {
     pub id: ClientId,
     pub room_id: Option<usize>,
@@ -26,6 +31,10 @@ pub struct HwClient {
     pub teams_in_game: u8,
     pub team_indices: Vec<u8>,
     pub clan: Option<u8>,
+    pub engine_msg_violations: u8,
+    #[serde(skip)]
+    pub language: Option<&'static str>,
+    pub room_filter: Option<RoomFilter>,
 }
 
 impl HwClient {
@@ -39,6 +48,9 @@ impl HwClient {
             teams_in_game: 0,
             team_indices: Vec::new(),
             clan: None,
+            engine_msg_violations: 0,
+            language: None,
+            room_filter: None,
         }
     }
 
@@ -74,6 +86,9 @@ impl HwClient {
     pub fn is_registered(&self) -> bool {
         self.contains(ClientFlags::IS_REGISTERED)
     }
+    pub fn is_away(&self) -> bool {
+        self.contains(ClientFlags::IS_AWAY)
+    }
 
     pub fn set_is_admin(&mut self, value: bool) {
         self.set(ClientFlags::IS_ADMIN, value)
@@ -99,4 +114,7 @@ impl HwClient {
     pub fn set_is_registered(&mut self, value: bool) {
         self.set(ClientFlags::IS_REGISTERED, value)
     }
+    pub fn set_is_away(&mut self, value: bool) {
+        self.set(ClientFlags::IS_AWAY, value)
+    }
 }
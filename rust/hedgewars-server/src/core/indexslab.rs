@@ -1,9 +1,11 @@
+use serde_derive::{Deserialize, Serialize};
 use std::{
     iter,
     mem::replace,
     ops::{Index, IndexMut},
 };
 
+#[derive(Serialize, Deserialize)]
 pub struct IndexSlab<T> {
     data: Vec<Option<T>>,
 }
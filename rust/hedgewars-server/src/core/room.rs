@@ -1,17 +1,43 @@
 use super::{
     client::HwClient,
+    snapshot,
+    turns::TurnOrder,
     types::{
-        ClientId, GameCfg, GameCfg::*, RoomConfig, RoomId, TeamInfo, Voting, MAX_HEDGEHOGS_PER_TEAM,
+        ClientId, GameCfg, GameCfg::*, RoomConfig, RoomFilter, RoomId, TeamInfo, Voting,
+        MAX_HEDGEHOGS_PER_TEAM,
     },
 };
 use bitflags::*;
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
 use serde::{Deserialize, Serialize};
 use serde_derive::{Deserialize, Serialize};
 use serde_yaml;
-use std::{collections::HashMap, iter};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    iter,
+    mem::replace,
+    time::{Duration, Instant},
+};
 
 pub const MAX_TEAMS_IN_ROOM: u8 = 8;
 pub const MAX_HEDGEHOGS_IN_ROOM: u8 = MAX_TEAMS_IN_ROOM * MAX_HEDGEHOGS_PER_TEAM;
+pub const MAX_SPECTATOR_DELAY: u32 = 600;
+pub const MAX_ROOM_INVITES: usize = 16;
+pub const MAX_INVITE_LIFETIME_MINUTES: u32 = 24 * 60;
+/// How long the claims of the clients not expected to take the turn are held back,
+/// the engine of the expected one claims it within a second of game time
+pub const TURN_CLAIM_GRACE: Duration = Duration::from_secs(2);
+
+const PASSWORD_SALT_LENGTH: usize = 16;
+const INVITE_CODE_LENGTH: usize = 10;
+
+fn random_string(length: usize) -> String {
+    thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(length)
+        .collect()
+}
 
 fn client_teams_impl(
     teams: &[(ClientId, TeamInfo)],
@@ -23,33 +49,220 @@ fn client_teams_impl(
         .map(|(_, t)| t)
 }
 
+#[derive(Serialize, Deserialize)]
+struct DelayedMessages {
+    #[serde(with = "snapshot::instant")]
+    time: Instant,
+    log_length: usize,
+    messages: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
 pub struct GameInfo {
     pub original_teams: Vec<(ClientId, TeamInfo)>,
     pub left_teams: Vec<String>,
     pub msg_log: Vec<String>,
     pub sync_msg: Option<String>,
     pub is_paused: bool,
+    pub turn_owner: Option<ClientId>,
+    turn_order: TurnOrder,
+    #[serde(with = "snapshot::instant")]
+    turn_opened: Instant,
+    held_msgs: Vec<(ClientId, String)>,
+    rejected_claims: Vec<ClientId>,
     original_config: RoomConfig,
+    spectator_delay: Duration,
+    delayed_msgs: VecDeque<DelayedMessages>,
+    released_log_length: usize,
 }
 
 impl GameInfo {
-    fn new(teams: Vec<(ClientId, TeamInfo)>, config: RoomConfig) -> GameInfo {
+    fn new(teams: Vec<(ClientId, TeamInfo)>, config: RoomConfig, spectator_delay: u32) -> GameInfo {
         GameInfo {
             left_teams: Vec::new(),
             msg_log: Vec::new(),
             sync_msg: None,
             is_paused: false,
+            turn_owner: None,
+            turn_order: TurnOrder::new(&teams, &config.scheme),
+            turn_opened: Instant::now(),
+            held_msgs: Vec::new(),
+            rejected_claims: Vec::new(),
             original_teams: teams,
             original_config: config,
+            spectator_delay: Duration::from_secs(spectator_delay as u64),
+            delayed_msgs: VecDeque::new(),
+            released_log_length: 0,
         }
     }
 
     pub fn client_teams(&self, client_id: ClientId) -> impl Iterator<Item = &TeamInfo> + Clone {
         client_teams_impl(&self.original_teams, client_id)
     }
+
+    pub fn is_turn_owner(&self, client_id: ClientId) -> bool {
+        self.turn_owner.map_or(true, |id| id == client_id)
+    }
+
+    pub fn is_turn_open(&self) -> bool {
+        self.turn_owner.is_none()
+    }
+
+    pub fn end_turn(&mut self) {
+        self.turn_owner = None;
+        self.turn_opened = Instant::now();
+    }
+
+    /// Gives the open turn to the client if it owns the team expected to take it, the claims
+    /// of other clients are held until the expected one claims it or the grace time runs out
+    pub fn claim_turn(&mut self, client_id: ClientId) -> bool {
+        let order = &self.turn_order;
+        let team = order
+            .expected_teams()
+            .into_iter()
+            .find(|t| order.owner(*t) == client_id);
+        match team {
+            Some(team) => {
+                self.turn_order.pass_to(team);
+                self.turn_owner = Some(client_id);
+                self.rejected_claims
+                    .extend(self.held_msgs.drain(..).map(|(id, _)| id));
+                self.rejected_claims.dedup();
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn is_claim_held(&self, client_id: ClientId) -> bool {
+        self.held_msgs.iter().any(|(id, _)| *id == client_id)
+    }
+
+    pub fn hold_msg(&mut self, client_id: ClientId, msg: String) {
+        self.held_msgs.push((client_id, msg));
+    }
+
+    pub fn take_rejected_claims(&mut self) -> Vec<ClientId> {
+        replace(&mut self.rejected_claims, vec![])
+    }
+
+    /// Gives the turn to the held claim nearest to the expected team once the grace time
+    /// is over, returns its held messages
+    pub fn resolve_turn_claims(&mut self, now: Instant) -> Option<(ClientId, Vec<String>)> {
+        if !self.is_turn_open()
+            || self.held_msgs.is_empty()
+            || now < self.turn_opened + TURN_CLAIM_GRACE
+        {
+            return None;
+        }
+
+        let claimants: Vec<_> = self.held_msgs.iter().map(|(id, _)| *id).collect();
+        let team = self.turn_order.find_first_team(&claimants);
+
+        let held_msgs = replace(&mut self.held_msgs, vec![]);
+        match team {
+            Some(team) => {
+                let client_id = self.turn_order.owner(team);
+                self.turn_order.pass_to(team);
+                self.turn_owner = Some(client_id);
+                let mut messages = vec![];
+                for (id, msg) in held_msgs {
+                    if id == client_id {
+                        messages.push(msg);
+                    } else if !self.rejected_claims.contains(&id) {
+                        self.rejected_claims.push(id);
+                    }
+                }
+                Some((client_id, messages))
+            }
+            None => None,
+        }
+    }
+
+    /// Takes the client out of the turn order when it leaves the game
+    pub fn release_turn(&mut self, client_id: ClientId) {
+        if self.turn_owner == Some(client_id) {
+            self.end_turn();
+        }
+        self.turn_order.set_client_gone(client_id, true);
+        self.held_msgs.retain(|(id, _)| *id != client_id);
+        self.rejected_claims.retain(|id| *id != client_id);
+    }
+
+    pub fn restore_turn(&mut self, client_id: ClientId) {
+        self.turn_order.set_client_gone(client_id, false);
+    }
+
+    pub fn remove_turn_team(&mut self, team_name: &str) {
+        if let Some(index) = self
+            .original_teams
+            .iter()
+            .position(|(_, t)| t.name == team_name)
+        {
+            self.turn_order.set_team_gone(index, true);
+        }
+    }
+
+    pub fn is_spectator(&self, client_id: ClientId) -> bool {
+        self.client_teams(client_id).next().is_none()
+    }
+
+    pub fn is_spectator_feed_delayed(&self) -> bool {
+        self.spectator_delay > Duration::from_secs(0)
+    }
+
+    pub fn is_feed_delayed_for(&self, client_id: ClientId) -> bool {
+        self.is_spectator_feed_delayed() && self.is_spectator(client_id)
+    }
+
+    pub fn log_msg(&mut self, msg: String) {
+        self.msg_log.push(msg.clone());
+        self.delay_msgs(vec![msg]);
+    }
+
+    pub fn delay_msgs(&mut self, messages: Vec<String>) {
+        if self.is_spectator_feed_delayed() {
+            self.delayed_msgs.push_back(DelayedMessages {
+                time: Instant::now(),
+                log_length: self.msg_log.len(),
+                messages,
+            });
+        }
+    }
+
+    /// Releases the messages delayed for at least the spectator delay by `now`
+    pub fn release_delayed_msgs(&mut self, now: Instant) -> Vec<String> {
+        let mut result = vec![];
+        while let Some(entry) = self.delayed_msgs.front() {
+            if now < entry.time + self.spectator_delay {
+                break;
+            }
+            let entry = self.delayed_msgs.pop_front().unwrap();
+            self.released_log_length = entry.log_length;
+            result.extend(entry.messages);
+        }
+        result
+    }
+
+    pub fn release_all_delayed_msgs(&mut self) -> Vec<String> {
+        let mut result = vec![];
+        for entry in self.delayed_msgs.drain(..) {
+            self.released_log_length = entry.log_length;
+            result.extend(entry.messages);
+        }
+        result
+    }
+
+    pub fn client_msg_log(&self, client_id: ClientId) -> &[String] {
+        if self.is_feed_delayed_for(client_id) {
+            &self.msg_log[..self.released_log_length]
+        } else {
+            &self.msg_log
+        }
+    }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct RoomSave {
     pub location: String,
     config: RoomConfig,
@@ -61,14 +274,99 @@ bitflags! {
         const RESTRICTED_JOIN = 0b0000_0010;
         const RESTRICTED_TEAM_ADD = 0b0000_0100;
         const REGISTRATION_REQUIRED = 0b0000_1000;
+        const HIDDEN = 0b0001_0000;
+    }
+}
+
+serde_bitflags!(RoomFlags, u8);
+
+/// Room password stored as a salted SHA-256 digest
+#[derive(Serialize, Deserialize)]
+pub struct RoomPassword {
+    salt: String,
+    hash: Vec<u8>,
+}
+
+impl RoomPassword {
+    pub fn new(password: &str) -> Self {
+        let salt = random_string(PASSWORD_SALT_LENGTH);
+        let hash = Self::digest(&salt, password);
+        Self { salt, hash }
+    }
+
+    fn digest(salt: &str, password: &str) -> Vec<u8> {
+        Sha256::new()
+            .chain(salt.as_bytes())
+            .chain(password.as_bytes())
+            .result()
+            .to_vec()
+    }
+
+    pub fn matches(&self, password: &str) -> bool {
+        Self::digest(&self.salt, password) == self.hash
     }
 }
 
+/// Invite code generated by the room master, either for a single use or until it expires
+#[derive(Serialize, Deserialize)]
+pub struct RoomInvite {
+    code: String,
+    #[serde(with = "snapshot::optional_instant")]
+    expires: Option<Instant>,
+}
+
+impl RoomInvite {
+    fn is_expired(&self, now: Instant) -> bool {
+        self.expires.map_or(false, |t| t <= now)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct FixedRoom {
+    pub name: String,
+    pub greeting: String,
+    pub max_teams: u8,
+    pub flags: u8,
+    pub config: RoomConfig,
+    pub saves: HashMap<String, RoomSave>,
+}
+
+/// The part of the room state that decides which filtered room lists show the room
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoomListing {
+    name: String,
+    is_hidden: bool,
+    in_game: bool,
+    has_password: bool,
+    registration_required: bool,
+    has_free_slots: bool,
+}
+
+impl RoomListing {
+    pub fn matches(&self, filter: &RoomFilter) -> bool {
+        fn check(condition: Option<bool>, value: bool) -> bool {
+            condition.map_or(true, |c| c == value)
+        }
+
+        filter.name.as_ref().map_or(true, |name| {
+            self.name.to_lowercase().contains(&name.to_lowercase())
+        }) && check(filter.in_game, self.in_game)
+            && check(filter.has_password, self.has_password)
+            && check(filter.registration_required, self.registration_required)
+            && check(filter.has_free_slots, self.has_free_slots)
+    }
+
+    pub fn is_listed_for(&self, filter: Option<&RoomFilter>) -> bool {
+        !self.is_hidden && filter.map_or(true, |f| self.matches(f))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
 pub struct HwRoom {
     pub id: RoomId,
     pub master_id: Option<ClientId>,
     pub name: String,
-    pub password: Option<String>,
+    pub password: Option<RoomPassword>,
     pub greeting: String,
     pub protocol_number: u16,
     pub flags: RoomFlags,
@@ -76,12 +374,16 @@ pub struct HwRoom {
     pub players_number: u8,
     pub default_hedgehog_number: u8,
     pub max_teams: u8,
+    pub spectator_delay: u32,
     pub ready_players_number: u8,
     pub teams: Vec<(ClientId, TeamInfo)>,
     config: RoomConfig,
     pub voting: Option<Voting>,
     pub saves: HashMap<String, RoomSave>,
     pub game_info: Option<GameInfo>,
+    invites: Vec<RoomInvite>,
+    allowed_nicks: HashSet<String>,
+    listed: Option<RoomListing>,
 }
 
 impl HwRoom {
@@ -97,12 +399,40 @@ impl HwRoom {
             players_number: 0,
             default_hedgehog_number: 4,
             max_teams: MAX_TEAMS_IN_ROOM,
+            spectator_delay: 0,
             ready_players_number: 0,
             teams: Vec::new(),
             config: RoomConfig::new(),
             voting: None,
             saves: HashMap::new(),
             game_info: None,
+            invites: Vec::new(),
+            allowed_nicks: HashSet::new(),
+            listed: None,
+        }
+    }
+
+    pub fn from_fixed(id: RoomId, protocol_number: u16, fixed: FixedRoom) -> HwRoom {
+        let mut room = HwRoom::new(id);
+        room.name = fixed.name;
+        room.greeting = fixed.greeting;
+        room.protocol_number = protocol_number;
+        room.max_teams = fixed.max_teams.max(2).min(MAX_TEAMS_IN_ROOM);
+        room.flags = RoomFlags::from_bits_truncate(fixed.flags) | RoomFlags::FIXED;
+        room.config = fixed.config;
+        room.saves = fixed.saves;
+        room.listed = Some(room.listing());
+        room
+    }
+
+    pub fn to_fixed(&self) -> FixedRoom {
+        FixedRoom {
+            name: self.name.clone(),
+            greeting: self.greeting.clone(),
+            max_teams: self.max_teams,
+            flags: self.flags.bits(),
+            config: self.config.clone(),
+            saves: self.saves.clone(),
         }
     }
 
@@ -146,6 +476,7 @@ impl HwRoom {
 
             if let Some(info) = &mut self.game_info {
                 info.left_teams.push(team_name.to_string());
+                info.remove_turn_team(team_name);
 
                 if let Some(m) = &info.sync_msg {
                     info.msg_log.push(m.clone());
@@ -153,7 +484,7 @@ impl HwRoom {
                 }
                 let remove_msg =
                     crate::utils::to_engine_msg(iter::once(b'F').chain(team_name.bytes()));
-                info.msg_log.push(remove_msg.clone());
+                info.log_msg(remove_msg);
             }
         }
     }
@@ -242,7 +573,11 @@ impl HwRoom {
 
     pub fn start_round(&mut self) {
         if self.game_info.is_none() {
-            self.game_info = Some(GameInfo::new(self.teams.clone(), self.config.clone()));
+            self.game_info = Some(GameInfo::new(
+                self.teams.clone(),
+                self.config.clone(),
+                self.spectator_delay,
+            ));
         }
     }
 
@@ -258,6 +593,9 @@ impl HwRoom {
     pub fn is_registration_required(&self) -> bool {
         self.flags.contains(RoomFlags::REGISTRATION_REQUIRED)
     }
+    pub fn is_hidden(&self) -> bool {
+        self.flags.contains(RoomFlags::HIDDEN)
+    }
 
     pub fn set_is_fixed(&mut self, value: bool) {
         self.flags.set(RoomFlags::FIXED, value)
@@ -272,6 +610,59 @@ impl HwRoom {
         self.flags.set(RoomFlags::REGISTRATION_REQUIRED, value)
     }
 
+    pub fn set_password(&mut self, password: Option<&str>) {
+        self.password = password.map(RoomPassword::new)
+    }
+
+    pub fn is_password_valid(&self, password: Option<&str>) -> bool {
+        match (&self.password, password) {
+            (None, _) => true,
+            (Some(hashed), Some(password)) => hashed.matches(password),
+            (Some(_), None) => false,
+        }
+    }
+
+    /// Creates an invite code usable once or, given a lifetime, any number of times until it expires
+    pub fn create_invite(&mut self, lifetime: Option<Duration>) -> String {
+        let now = Instant::now();
+        self.invites.retain(|i| !i.is_expired(now));
+        if self.invites.len() >= MAX_ROOM_INVITES {
+            self.invites.remove(0);
+        }
+
+        let code = random_string(INVITE_CODE_LENGTH);
+        self.invites.push(RoomInvite {
+            code: code.clone(),
+            expires: lifetime.map(|l| now + l),
+        });
+        code
+    }
+
+    pub fn is_invite_valid(&self, code: &str) -> bool {
+        let now = Instant::now();
+        self.invites
+            .iter()
+            .any(|i| i.code == code && !i.is_expired(now))
+    }
+
+    pub fn use_invite(&mut self, code: &str) {
+        let now = Instant::now();
+        self.invites
+            .retain(|i| !i.is_expired(now) && (i.code != code || i.expires.is_some()));
+    }
+
+    pub fn allow_nick(&mut self, nick: &str) {
+        self.allowed_nicks.insert(nick.to_lowercase());
+    }
+
+    pub fn disallow_nick(&mut self, nick: &str) -> bool {
+        self.allowed_nicks.remove(&nick.to_lowercase())
+    }
+
+    pub fn is_nick_allowed(&self, nick: &str) -> bool {
+        self.allowed_nicks.contains(&nick.to_lowercase())
+    }
+
     fn flags_string(&self) -> String {
         let mut result = "-".to_string();
         if self.game_info.is_some() {
@@ -289,6 +680,30 @@ impl HwRoom {
         result
     }
 
+    pub fn matches(&self, filter: &RoomFilter) -> bool {
+        self.listing().matches(filter)
+    }
+
+    pub fn listing(&self) -> RoomListing {
+        RoomListing {
+            name: self.name.clone(),
+            is_hidden: self.is_hidden(),
+            in_game: self.game_info.is_some(),
+            has_password: self.password.is_some(),
+            registration_required: self.is_registration_required(),
+            has_free_slots: (self.teams.len() as u8) < self.max_teams,
+        }
+    }
+
+    /// The listing the room list clients have last been told about, `None` if never announced
+    pub fn listed(&self) -> Option<&RoomListing> {
+        self.listed.as_ref()
+    }
+
+    pub fn update_listed(&mut self) {
+        self.listed = Some(self.listing());
+    }
+
     pub fn info(&self, master: Option<&HwClient>) -> Vec<String> {
         let c = &self.config;
         vec![
@@ -2,15 +2,27 @@ use super::{
     anteroom::HwAnteroomClient,
     client::HwClient,
     indexslab::IndexSlab,
-    room::HwRoom,
-    types::{ClientId, GameCfg, RoomId, ServerVar, TeamInfo, Vote, VoteType, Voting},
+    room::{FixedRoom, HwRoom},
+    types::{
+        ClientId, GameCfg, InvalidGameCfg, ListingChange, RoomFilter, RoomId, ServerVar, TeamInfo,
+        Vote, VoteType, Voting,
+    },
 };
-use crate::utils;
+use crate::{protocol::translation, utils};
 
 use bitflags::*;
 use log::*;
+use serde_derive::{Deserialize, Serialize};
 use slab::Slab;
-use std::{borrow::BorrowMut, cmp::min, collections::HashSet, iter, mem::replace};
+use std::{
+    borrow::BorrowMut,
+    cmp::min,
+    collections::{HashMap, HashSet},
+    fs, io, iter,
+    mem::replace,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
 
 #[derive(Debug)]
 pub enum CreateRoomError {
@@ -30,7 +42,10 @@ pub enum JoinRoomError {
 
 #[derive(Debug)]
 pub enum LeaveRoomResult {
-    RoomRemoved,
+    RoomRemoved {
+        name: String,
+        protocol_number: u16,
+    },
     RoomRemains {
         is_empty: bool,
         was_master: bool,
@@ -59,6 +74,7 @@ pub enum AddTeamError {
     TooManyTeams,
     TooManyHedgehogs,
     TeamAlreadyExists,
+    TeamNameReserved,
     GameInProgress,
     Restricted,
 }
@@ -81,6 +97,24 @@ pub enum SetTeamCountError {
     NotMaster,
 }
 
+#[derive(Debug)]
+pub enum SetSpectatorDelayError {
+    InvalidNumber,
+    NotMaster,
+}
+
+#[derive(Debug)]
+pub enum CreateInviteError {
+    InvalidLifetime,
+    NotMaster,
+}
+
+#[derive(Debug)]
+pub enum ModifyAllowListError {
+    NotListed,
+    NotMaster,
+}
+
 #[derive(Debug)]
 pub enum SetHedgehogsError {
     NoTeam,
@@ -92,6 +126,7 @@ pub enum SetHedgehogsError {
 pub enum SetConfigError {
     NotMaster,
     RoomFixed,
+    InvalidConfig(InvalidGameCfg),
 }
 
 #[derive(Debug)]
@@ -131,6 +166,8 @@ pub struct EndGameResult {
     pub joined_mid_game_clients: Vec<ClientId>,
     pub left_teams: Vec<String>,
     pub unreadied_nicks: Vec<String>,
+    pub delayed_msgs: Vec<String>,
+    pub delayed_feed_clients: Vec<ClientId>,
 }
 
 #[derive(Debug)]
@@ -138,6 +175,7 @@ pub struct UninitializedError();
 #[derive(Debug)]
 pub struct AccessError();
 
+#[derive(Serialize, Deserialize)]
 pub struct ServerGreetings {
     pub for_latest_protocol: String,
     pub for_old_protocols: String,
@@ -158,6 +196,9 @@ bitflags! {
     }
 }
 
+serde_bitflags!(ServerFlags, u8);
+
+#[derive(Serialize, Deserialize)]
 struct HwChecker {
     pub id: ClientId,
     pub is_ready: bool,
@@ -172,6 +213,7 @@ impl HwChecker {
     }
 }
 
+#[derive(Serialize, Deserialize)]
 pub struct HwServer {
     clients: IndexSlab<HwClient>,
     rooms: Slab<HwRoom>,
@@ -179,21 +221,51 @@ pub struct HwServer {
     latest_protocol: u16,
     flags: ServerFlags,
     greetings: ServerGreetings,
+    fixed_rooms_file: Option<PathBuf>,
+    fixed_rooms_changed: bool,
+    team_reservations: HashMap<String, (String, String)>,
 }
 
 impl HwServer {
-    pub fn new(clients_limit: usize, rooms_limit: usize) -> Self {
+    pub fn new(
+        clients_limit: usize,
+        rooms_limit: usize,
+        fixed_rooms_file: Option<PathBuf>,
+    ) -> Self {
         let rooms = Slab::with_capacity(rooms_limit);
         let clients = IndexSlab::with_capacity(clients_limit);
         let checkers = IndexSlab::new();
-        Self {
+        let mut server = Self {
             clients,
             rooms,
             checkers,
             greetings: ServerGreetings::new(),
             latest_protocol: 58,
             flags: ServerFlags::empty(),
+            fixed_rooms_file,
+            fixed_rooms_changed: false,
+            team_reservations: HashMap::new(),
+        };
+
+        if let Some(ref path) = server.fixed_rooms_file {
+            match load_fixed_rooms(path) {
+                Ok(fixed_rooms) => {
+                    for fixed in fixed_rooms {
+                        if utils::is_name_illegal(&fixed.name) || server.has_room(&fixed.name) {
+                            warn!("Skipping invalid fixed room {}", fixed.name);
+                        } else {
+                            let entry = server.rooms.vacant_entry();
+                            let room =
+                                HwRoom::from_fixed(entry.key(), server.latest_protocol, fixed);
+                            entry.insert(room);
+                        }
+                    }
+                }
+                Err(e) => warn!("Unable to load fixed rooms from {:?}: {}", path, e),
+            }
         }
+
+        server
     }
 
     #[inline]
@@ -231,6 +303,12 @@ impl HwServer {
         self.rooms.iter().map(|(_, r)| r)
     }
 
+    /// Rooms shown in the room lists, i.e. all but the hidden ones
+    #[inline]
+    pub fn iter_listed_rooms(&self) -> impl Iterator<Item = &HwRoom> {
+        self.iter_rooms().filter(|r| !r.is_hidden())
+    }
+
     #[inline]
     pub fn client_and_room(&self, client_id: ClientId, room_id: RoomId) -> (&HwClient, &HwRoom) {
         (&self.clients[client_id], &self.rooms[room_id])
@@ -322,12 +400,15 @@ impl HwServer {
         let room = &mut self.rooms[room_id];
         let client = &mut self.clients[client_id];
 
-        if client.protocol_number != room.protocol_number {
+        let is_allowed = client.has_super_power()
+            || client.is_registered() && room.is_nick_allowed(&client.nick);
+        let invite = room_password.filter(|code| !is_allowed && room.is_invite_valid(code));
+
+        if !translation::are_compatible(client.protocol_number, room.protocol_number) {
             Err(WrongProtocol)
-        } else if room.password.is_some()
-            && room_password != room.password.as_deref()
-            && !client.has_super_power()
-        {
+        } else if room.is_hidden() && !is_allowed && invite.is_none() {
+            Err(DoesntExist)
+        } else if !room.is_password_valid(room_password) && !is_allowed && invite.is_none() {
             Err(WrongPassword)
         } else if room.is_join_restricted() {
             Err(Restricted)
@@ -336,6 +417,9 @@ impl HwServer {
         } else if room.players_number == u8::max_value() {
             Err(Full)
         } else {
+            if let Some(code) = invite {
+                room.use_invite(code);
+            }
             move_to_room(client, room);
             let room_id = room.id;
             Ok((
@@ -372,6 +456,55 @@ impl HwServer {
         client.is_admin()
     }
 
+    pub fn reserve_team_name(&mut self, team_name: &str, owner: &str) {
+        self.team_reservations.insert(
+            team_name.to_lowercase(),
+            (team_name.to_string(), owner.to_string()),
+        );
+    }
+
+    pub fn release_team_name(&mut self, team_name: &str) {
+        self.team_reservations.remove(&team_name.to_lowercase());
+    }
+
+    pub fn team_name_owner(&self, team_name: &str) -> Option<&str> {
+        self.team_reservations
+            .get(&team_name.to_lowercase())
+            .map(|(_, owner)| &owner[..])
+    }
+
+    pub fn is_team_name_reserved_for_others(&self, client_id: ClientId, team_name: &str) -> bool {
+        self.team_name_owner(team_name)
+            .map_or(false, |owner| owner != self.clients[client_id].nick)
+    }
+
+    pub fn reserved_team_names(&self, owner: &str) -> Vec<String> {
+        let mut names: Vec<_> = self
+            .team_reservations
+            .values()
+            .filter(|(_, o)| o == owner)
+            .map(|(name, _)| name.clone())
+            .collect();
+        names.sort();
+        names
+    }
+
+    pub fn set_client_room_filter(&mut self, client_id: ClientId, filter: RoomFilter) {
+        self.clients[client_id].room_filter = Some(filter);
+    }
+
+    /// Returns `true` if the away status of the client has changed
+    pub fn set_client_away(&mut self, client_id: ClientId, value: bool) -> bool {
+        let client = &mut self.clients[client_id];
+        let is_changed = client.is_away() != value;
+        client.set_is_away(value);
+        is_changed
+    }
+
+    pub fn set_client_language(&mut self, client_id: ClientId, language: Option<&'static str>) {
+        self.clients[client_id].language = language;
+    }
+
     #[inline]
     pub fn set_var(&mut self, client_id: ClientId, var: ServerVar) -> Result<(), AccessError> {
         if self.clients[client_id].is_admin() {
@@ -489,10 +622,58 @@ impl HwServer {
         self.filter_clients(move |(_, c)| c.room_id == Some(room_id))
     }
 
+    pub fn room_live_feed_client_ids(
+        &self,
+        room_id: RoomId,
+    ) -> impl Iterator<Item = ClientId> + '_ {
+        let info = self.rooms.get(room_id).and_then(|r| r.game_info.as_ref());
+        self.filter_clients(move |(_, c)| {
+            c.room_id == Some(room_id) && !info.map_or(false, |i| i.is_feed_delayed_for(c.id))
+        })
+    }
+
+    pub fn room_delayed_feed_client_ids(
+        &self,
+        room_id: RoomId,
+    ) -> impl Iterator<Item = ClientId> + '_ {
+        let info = self.rooms.get(room_id).and_then(|r| r.game_info.as_ref());
+        self.filter_clients(move |(_, c)| {
+            c.room_id == Some(room_id) && info.map_or(false, |i| i.is_feed_delayed_for(c.id))
+        })
+    }
+
+    /// Clients whose room list changes in the given way between the last announced listing
+    /// of the room and its current state
+    pub fn room_list_client_ids(
+        &self,
+        protocol: u16,
+        room_id: RoomId,
+        change: ListingChange,
+    ) -> impl Iterator<Item = ClientId> + '_ {
+        let room = self.rooms.get(room_id);
+        let listed = room.and_then(|r| r.listed());
+        let listing = room.map(|r| r.listing());
+        self.filter_clients(move |(_, c)| {
+            let filter = c.room_filter.as_ref();
+            let was_listed = listed.map_or(false, |l| l.is_listed_for(filter));
+            let is_listed = listing.as_ref().map_or(false, |l| l.is_listed_for(filter));
+            translation::are_compatible(c.protocol_number, protocol)
+                && match change {
+                    ListingChange::Added => !was_listed && is_listed,
+                    ListingChange::Updated => was_listed && is_listed,
+                    ListingChange::Removed => was_listed && !is_listed,
+                }
+        })
+    }
+
     pub fn protocol_client_ids(&self, protocol: u16) -> impl Iterator<Item = ClientId> + '_ {
         self.filter_clients(move |(_, c)| c.protocol_number == protocol)
     }
 
+    pub fn compatible_client_ids(&self, protocol: u16) -> impl Iterator<Item = ClientId> + '_ {
+        self.filter_clients(move |(_, c)| translation::are_compatible(c.protocol_number, protocol))
+    }
+
     pub fn protocol_room_ids(&self, protocol: u16) -> impl Iterator<Item = RoomId> + '_ {
         self.filter_rooms(move |(_, r)| r.protocol_number == protocol)
     }
@@ -512,10 +693,88 @@ impl HwServer {
 
     pub fn set_room_saves(&mut self, room_id: RoomId, text: &str) -> Result<(), serde_yaml::Error> {
         if let Some(room) = self.rooms.get_mut(room_id) {
-            room.set_saves(text)
-        } else {
-            Ok(())
+            room.set_saves(text)?;
+            if room.is_fixed() {
+                self.update_fixed_rooms();
+            }
         }
+        Ok(())
+    }
+
+    /// Ids of the rooms with a running game
+    pub fn game_room_ids(&self) -> Vec<RoomId> {
+        self.rooms
+            .iter()
+            .filter(|(_, r)| r.game_info.is_some())
+            .map(|(id, _)| id)
+            .collect()
+    }
+
+    /// Returns the claim taking the open turn of the room once the grace time is over and
+    /// the clients whose held claims were rejected
+    pub fn resolve_turn_claims(
+        &mut self,
+        room_id: RoomId,
+        now: Instant,
+    ) -> (Option<(ClientId, Vec<String>)>, Vec<ClientId>) {
+        match self
+            .rooms
+            .get_mut(room_id)
+            .and_then(|r| r.game_info.as_mut())
+        {
+            Some(info) => {
+                let resolved = info.resolve_turn_claims(now);
+                (resolved, info.take_rejected_claims())
+            }
+            None => (None, vec![]),
+        }
+    }
+
+    /// Releases the messages of the delayed spectator feed of the room that are due by `now`
+    pub fn release_delayed_msgs(&mut self, room_id: RoomId, now: Instant) -> Vec<String> {
+        match self
+            .rooms
+            .get_mut(room_id)
+            .and_then(|r| r.game_info.as_mut())
+        {
+            Some(info) => info.release_delayed_msgs(now),
+            None => vec![],
+        }
+    }
+
+    /// Remembers the current state of the rooms as announced to the room list clients
+    pub fn update_room_listings(&mut self, room_ids: impl IntoIterator<Item = RoomId>) {
+        for room_id in room_ids {
+            if let Some(room) = self.rooms.get_mut(room_id) {
+                room.update_listed();
+            }
+        }
+    }
+
+    /// Returns the fixed rooms file and its new contents if the fixed rooms changed since
+    /// the last call, the file is written by the IO thread
+    pub fn take_fixed_rooms_update(&mut self) -> Option<(PathBuf, String)> {
+        if !replace(&mut self.fixed_rooms_changed, false) {
+            return None;
+        }
+
+        let path = self.fixed_rooms_file.clone()?;
+        let fixed_rooms: Vec<_> = self
+            .iter_rooms()
+            .filter(|r| r.is_fixed())
+            .map(|r| r.to_fixed())
+            .collect();
+        match serde_yaml::to_string(&fixed_rooms) {
+            Ok(contents) => Some((path, contents)),
+            Err(e) => {
+                warn!("Unable to serialize fixed rooms: {}", e);
+                None
+            }
+        }
+    }
+
+    fn update_fixed_rooms(&mut self) {
+        self.fixed_rooms_changed = true;
     }
 }
 
@@ -616,9 +875,14 @@ impl<'a> HwRoomControl<'a> {
         client.set_is_ready(false);
         client.set_is_in_game(false);
 
+        if let Some(ref mut info) = room.game_info {
+            info.release_turn(client.id);
+        }
+
+        let mut removed_room = None;
         if !is_fixed {
             if room.players_number == 0 {
-                self.server.rooms.remove(self.room_id);
+                removed_room = Some(self.server.rooms.remove(self.room_id));
             } else if room.master_id == None {
                 let protocol_number = room.protocol_number;
                 let new_master_id = self.server.room_client_ids(self.room_id).next();
@@ -629,7 +893,7 @@ impl<'a> HwRoomControl<'a> {
                     let new_master = &mut self.server.clients[new_master_id];
                     new_master.set_is_master(true);
 
-                    if protocol_number < 42 {
+                    if translation::has_legacy_rooms(protocol_number) {
                         let nick = new_master.nick.clone();
                         self.room_mut().name = nick;
                     }
@@ -642,8 +906,11 @@ impl<'a> HwRoomControl<'a> {
             }
         }
 
-        if is_empty && !is_fixed {
-            LeaveRoomResult::RoomRemoved
+        if let Some(room) = removed_room {
+            LeaveRoomResult::RoomRemoved {
+                name: room.name,
+                protocol_number: room.protocol_number,
+            }
         } else {
             LeaveRoomResult::RoomRemains {
                 is_empty,
@@ -739,10 +1006,12 @@ impl<'a> HwRoomControl<'a> {
 
     pub fn toggle_flag(&mut self, flags: super::room::RoomFlags) -> bool {
         let (client, room) = self.get_mut();
-        if client.is_master() {
+        let is_master = client.is_master();
+        if is_master {
             room.flags.toggle(flags);
+            self.update_fixed_room();
         }
-        client.is_master()
+        is_master
     }
 
     pub fn fix_room(&mut self) -> Result<(), AccessError> {
@@ -752,6 +1021,7 @@ impl<'a> HwRoomControl<'a> {
             room.set_join_restriction(false);
             room.set_team_add_restriction(false);
             room.set_unregistered_players_restriction(true);
+            self.server.update_fixed_rooms();
             Ok(())
         } else {
             Err(AccessError())
@@ -761,7 +1031,11 @@ impl<'a> HwRoomControl<'a> {
     pub fn unfix_room(&mut self) -> Result<(), AccessError> {
         let (client, room) = self.get_mut();
         if client.is_admin() {
+            let was_fixed = room.is_fixed();
             room.set_is_fixed(false);
+            if was_fixed {
+                self.server.update_fixed_rooms();
+            }
             Ok(())
         } else {
             Err(AccessError())
@@ -788,6 +1062,7 @@ impl<'a> HwRoomControl<'a> {
         let (client, room) = self.get_mut();
         if client.is_admin() {
             room.greeting = greeting.unwrap_or(String::new());
+            self.update_fixed_room();
             Ok(())
         } else {
             Err(AccessError())
@@ -803,6 +1078,56 @@ impl<'a> HwRoomControl<'a> {
             Err(InvalidNumber)
         } else {
             room.max_teams = count;
+            self.update_fixed_room();
+            Ok(())
+        }
+    }
+
+    pub fn set_spectator_delay(&mut self, delay: u32) -> Result<(), SetSpectatorDelayError> {
+        use SetSpectatorDelayError::*;
+        let (client, room) = self.get_mut();
+        if !client.is_master() {
+            Err(NotMaster)
+        } else if delay > super::room::MAX_SPECTATOR_DELAY {
+            Err(InvalidNumber)
+        } else {
+            room.spectator_delay = delay;
+            Ok(())
+        }
+    }
+
+    pub fn create_invite(&mut self, minutes: Option<u32>) -> Result<String, CreateInviteError> {
+        use CreateInviteError::*;
+        let (client, room) = self.get_mut();
+        if !client.is_master() {
+            Err(NotMaster)
+        } else if minutes.map_or(false, |m| {
+            !(1..=super::room::MAX_INVITE_LIFETIME_MINUTES).contains(&m)
+        }) {
+            Err(InvalidLifetime)
+        } else {
+            Ok(room.create_invite(minutes.map(|m| Duration::from_secs(u64::from(m) * 60))))
+        }
+    }
+
+    pub fn allow_nick(&mut self, nick: &str) -> Result<(), ModifyAllowListError> {
+        let (client, room) = self.get_mut();
+        if client.is_master() {
+            room.allow_nick(nick);
+            Ok(())
+        } else {
+            Err(ModifyAllowListError::NotMaster)
+        }
+    }
+
+    pub fn disallow_nick(&mut self, nick: &str) -> Result<(), ModifyAllowListError> {
+        use ModifyAllowListError::*;
+        let (client, room) = self.get_mut();
+        if !client.is_master() {
+            Err(NotMaster)
+        } else if !room.disallow_nick(nick) {
+            Err(NotListed)
+        } else {
             Ok(())
         }
     }
@@ -839,6 +1164,9 @@ impl<'a> HwRoomControl<'a> {
 
     pub fn add_team(&mut self, mut info: Box<TeamInfo>) -> Result<&TeamInfo, AddTeamError> {
         use AddTeamError::*;
+        let is_name_reserved = self
+            .server
+            .is_team_name_reserved_for_others(self.client_id, &info.name);
         let (client, room) = self.get_mut();
         if room.teams.len() >= room.max_teams as usize {
             Err(TooManyTeams)
@@ -846,13 +1174,19 @@ impl<'a> HwRoomControl<'a> {
             Err(TooManyHedgehogs)
         } else if room.find_team(|t| t.name == info.name) != None {
             Err(TeamAlreadyExists)
+        } else if is_name_reserved {
+            Err(TeamNameReserved)
         } else if room.game_info.is_some() {
             Err(GameInProgress)
         } else if room.is_team_add_restricted() {
             Err(Restricted)
         } else {
             info.owner = client.nick.clone();
-            let team = room.add_team(client.id, *info, client.protocol_number < 42);
+            let team = room.add_team(
+                client.id,
+                *info,
+                translation::has_legacy_rooms(client.protocol_number),
+            );
             client.teams_in_game += 1;
             client.clan = Some(team.color);
             Ok(team)
@@ -897,19 +1231,9 @@ impl<'a> HwRoomControl<'a> {
             Err(RoomFixed)
         } else if !client.is_master() {
             Err(NotMaster)
+        } else if let Err(e) = cfg.validate(client.protocol_number) {
+            Err(InvalidConfig(e))
         } else {
-            let cfg = match cfg {
-                GameCfg::Scheme(name, mut values) => {
-                    if client.protocol_number == 49 && values.len() >= 2 {
-                        let mut s = "X".repeat(50);
-                        s.push_str(&values.pop().unwrap());
-                        values.push(s);
-                    }
-                    GameCfg::Scheme(name, values)
-                }
-                cfg => cfg,
-            };
-
             room.set_config(cfg);
             Ok(())
         }
@@ -917,6 +1241,7 @@ impl<'a> HwRoomControl<'a> {
 
     pub fn save_config(&mut self, name: String, location: String) {
         self.room_mut().save_config(name, location);
+        self.update_fixed_room();
     }
 
     pub fn load_config(&mut self, name: &str) -> Option<&str> {
@@ -924,7 +1249,17 @@ impl<'a> HwRoomControl<'a> {
     }
 
     pub fn delete_config(&mut self, name: &str) -> bool {
-        self.room_mut().delete_config(name)
+        let is_deleted = self.room_mut().delete_config(name);
+        if is_deleted {
+            self.update_fixed_room();
+        }
+        is_deleted
+    }
+
+    fn update_fixed_room(&mut self) {
+        if self.room().is_fixed() {
+            self.server.update_fixed_rooms();
+        }
     }
 
     pub fn toggle_ready(&mut self) -> bool {
@@ -940,10 +1275,12 @@ impl<'a> HwRoomControl<'a> {
 
     pub fn start_game(&mut self) -> Result<Vec<String>, StartGameError> {
         use StartGameError::*;
+        let room_id = self.room_id;
         let (room_clients, room_nicks): (Vec<_>, Vec<_>) = self
             .server
             .clients
             .iter()
+            .filter(|(_, c)| c.room_id == Some(room_id))
             .map(|(id, c)| (id, c.nick.clone()))
             .unzip();
 
@@ -951,7 +1288,9 @@ impl<'a> HwRoomControl<'a> {
 
         if !room.has_multiple_clans() {
             Err(NotEnoughClans)
-        } else if room.protocol_number <= 43 && room.players_number != room.ready_players_number {
+        } else if translation::requires_all_ready(room.protocol_number)
+            && room.players_number != room.ready_players_number
+        {
             Err(NotReady)
         } else if room.game_info.is_some() {
             Err(AlreadyInGame)
@@ -962,6 +1301,7 @@ impl<'a> HwRoomControl<'a> {
                 let c = &mut self.server.clients[id];
                 c.set_is_in_game(true);
                 c.team_indices = team_indices;
+                c.engine_msg_violations = 0;
             }
             Ok(room_nicks)
         }
@@ -986,6 +1326,7 @@ impl<'a> HwRoomControl<'a> {
                 .collect();
 
             if let Some(ref mut info) = room.game_info {
+                info.release_turn(client.id);
                 for team_name in &team_names {
                     let remove_msg =
                         utils::to_engine_msg(std::iter::once(b'F').chain(team_name.bytes()));
@@ -995,7 +1336,7 @@ impl<'a> HwRoomControl<'a> {
                     if info.sync_msg.is_some() {
                         info.sync_msg = None
                     }
-                    info.msg_log.push(remove_msg);
+                    info.log_msg(remove_msg);
                 }
                 Some(team_names)
             } else {
@@ -1024,6 +1365,14 @@ impl<'a> HwRoomControl<'a> {
                 .map(|(_, c)| c.id)
                 .collect();
 
+            let delayed_feed_clients = self
+                .server
+                .clients
+                .iter()
+                .filter(|(_, c)| c.room_id == Some(room_id) && info.is_feed_delayed_for(c.id))
+                .map(|(_, c)| c.id)
+                .collect();
+
             let unreadied_nicks: Vec<_> = self
                 .server
                 .clients
@@ -1047,12 +1396,67 @@ impl<'a> HwRoomControl<'a> {
                 joined_mid_game_clients,
                 left_teams: replace(&mut info.left_teams, vec![]),
                 unreadied_nicks,
+                delayed_msgs: info.release_all_delayed_msgs(),
+                delayed_feed_clients,
             })
         } else {
             None
         }
     }
 
+    /// Holds the message back if the client already waits for the turn
+    pub fn hold_engine_msg(&mut self, msg: &str) -> bool {
+        let client_id = self.client_id;
+        match self.room_mut().game_info {
+            Some(ref mut info) if info.is_claim_held(client_id) => {
+                info.hold_msg(client_id, msg.to_string());
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Tries to take the open turn, the message is held back when the client is not
+    /// expected to take it
+    pub fn claim_turn(&mut self, msg: &str) -> bool {
+        let client_id = self.client_id;
+        match self.room_mut().game_info {
+            Some(ref mut info) => {
+                let is_claimed = info.claim_turn(client_id);
+                if !is_claimed {
+                    info.hold_msg(client_id, msg.to_string());
+                }
+                is_claimed
+            }
+            None => true,
+        }
+    }
+
+    pub fn end_turn(&mut self) {
+        if let Some(ref mut info) = self.room_mut().game_info {
+            info.end_turn();
+        }
+    }
+
+    pub fn add_engine_msg_violation(&mut self) -> u8 {
+        let client = self.client_mut();
+        client.engine_msg_violations = client.engine_msg_violations.saturating_add(1);
+        client.engine_msg_violations
+    }
+
+    pub fn delay_engine_msgs(&mut self, messages: Vec<String>) {
+        if let Some(ref mut info) = self.room_mut().game_info {
+            info.delay_msgs(messages);
+        }
+    }
+
+    pub fn release_delayed_msgs(&mut self) -> Vec<String> {
+        match self.room_mut().game_info {
+            Some(ref mut info) => info.release_delayed_msgs(Instant::now()),
+            None => vec![],
+        }
+    }
+
     pub fn log_engine_msg(&mut self, log_msg: String, sync_msg: Option<Option<String>>) {
         if let Some(ref mut info) = self.room_mut().game_info {
             if !log_msg.is_empty() {
@@ -1065,6 +1469,15 @@ impl<'a> HwRoomControl<'a> {
     }
 }
 
+fn load_fixed_rooms(path: &Path) -> io::Result<Vec<FixedRoom>> {
+    match fs::read_to_string(path) {
+        Ok(contents) => serde_yaml::from_str(&contents)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(vec![]),
+        Err(e) => Err(e),
+    }
+}
+
 fn allocate_room(rooms: &mut Slab<HwRoom>) -> &mut HwRoom {
     let entry = rooms.vacant_entry();
     let room = HwRoom::new(entry.key());
@@ -1081,7 +1494,7 @@ fn create_room<'a, 'b>(
 
     room.master_id = Some(client.id);
     room.name = name;
-    room.password = password;
+    room.set_password(password.as_deref());
     room.protocol_number = client.protocol_number;
 
     room.players_number = 1;
@@ -1095,6 +1508,7 @@ fn create_room<'a, 'b>(
     client.clan = None;
     client.teams_in_game = 0;
     client.team_indices = vec![];
+    client.engine_msg_violations = 0;
 
     (client, room)
 }
@@ -1105,6 +1519,7 @@ fn move_to_room(client: &mut HwClient, room: &mut HwRoom) {
     room.players_number += 1;
 
     client.room_id = Some(room.id);
+    client.engine_msg_violations = 0;
     client.set_is_joined_mid_game(room.game_info.is_some());
     client.set_is_in_game(room.game_info.is_some());
 
@@ -1116,6 +1531,13 @@ fn move_to_room(client: &mut HwClient, room: &mut HwRoom) {
 
         if !team_names.is_empty() {
             info.left_teams.retain(|name| !team_names.contains(&name));
+            info.restore_turn(client.id);
+            info.delay_msgs(
+                team_names
+                    .iter()
+                    .map(|name| utils::to_engine_msg(iter::once(b'G').chain(name.bytes())))
+                    .collect(),
+            );
         }
     }
 }
@@ -0,0 +1,132 @@
+//! Serialization helpers for the server state that is passed to the new process on hot restart
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::time::{Duration, Instant};
+
+/// Serializes bitflags as their raw bits, unknown bits are dropped on deserialization
+macro_rules! serde_bitflags {
+    ($name: ident, $bits: ty) => {
+        impl serde::Serialize for $name {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serde::Serialize::serialize(&self.bits(), serializer)
+            }
+        }
+
+        impl<'de> serde::Deserialize<'de> for $name {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                <$bits as serde::Deserialize>::deserialize(deserializer)
+                    .map(Self::from_bits_truncate)
+            }
+        }
+    };
+}
+
+fn to_offset(instant: Instant) -> i64 {
+    let now = Instant::now();
+    if instant >= now {
+        (instant - now).as_millis() as i64
+    } else {
+        -((now - instant).as_millis() as i64)
+    }
+}
+
+fn from_offset(offset: i64) -> Instant {
+    let now = Instant::now();
+    let delta = Duration::from_millis(offset.abs() as u64);
+    if offset >= 0 {
+        now + delta
+    } else {
+        now.checked_sub(delta).unwrap_or(now)
+    }
+}
+
+/// Instants are meaningless outside of the process, so they are stored as the number of
+/// milliseconds relative to the time of the snapshot
+pub mod instant {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(instant: &Instant, serializer: S) -> Result<S::Ok, S::Error> {
+        to_offset(*instant).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Instant, D::Error> {
+        i64::deserialize(deserializer).map(from_offset)
+    }
+}
+
+pub mod optional_instant {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        instant: &Option<Instant>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        instant.map(to_offset).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Instant>, D::Error> {
+        Option::<i64>::deserialize(deserializer).map(|o| o.map(from_offset))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::client::ClientFlags;
+    use serde_derive::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize)]
+    struct Timestamps {
+        #[serde(with = "instant")]
+        past: Instant,
+        #[serde(with = "optional_instant")]
+        future: Option<Instant>,
+        #[serde(with = "optional_instant")]
+        missing: Option<Instant>,
+    }
+
+    fn is_close(left: Instant, right: Instant) -> bool {
+        let delta = if left > right {
+            left - right
+        } else {
+            right - left
+        };
+        delta < Duration::from_secs(1)
+    }
+
+    #[test]
+    fn instant_offsets() {
+        let now = Instant::now();
+        let timestamps = Timestamps {
+            past: now - Duration::from_secs(10),
+            future: Some(now + Duration::from_secs(20)),
+            missing: None,
+        };
+
+        let data = serde_json::to_string(&timestamps).unwrap();
+        let restored: Timestamps = serde_json::from_str(&data).unwrap();
+
+        assert!(is_close(restored.past, timestamps.past));
+        assert!(is_close(
+            restored.future.unwrap(),
+            timestamps.future.unwrap()
+        ));
+        assert!(restored.missing.is_none());
+    }
+
+    #[test]
+    fn bitflags() {
+        let flags = ClientFlags::IS_READY | ClientFlags::IS_AWAY;
+        let data = serde_json::to_string(&flags).unwrap();
+        assert_eq!(data, flags.bits().to_string());
+        assert_eq!(serde_json::from_str::<ClientFlags>(&data).unwrap(), flags);
+
+        let unknown = ClientFlags::IS_ADMIN.bits() | 0b1000_0000_0000_0000;
+        assert_eq!(
+            serde_json::from_str::<ClientFlags>(&unknown.to_string()).unwrap(),
+            ClientFlags::IS_ADMIN
+        );
+    }
+}
@@ -0,0 +1,272 @@
+use super::types::{ClientId, Scheme, TeamInfo};
+use serde_derive::{Deserialize, Serialize};
+
+/// Scheme settings changing the turn order, counted without the scheme name
+const RANDOM_ORDER_SETTING: usize = 11;
+const TAG_TEAM_SETTING: usize = 23;
+
+fn is_scheme_flag_set(scheme: &Scheme, index: usize) -> bool {
+    scheme.settings.get(index).map_or(false, |s| s == "true")
+}
+
+/// The order the engine passes the turn in. Teams of the same color form a clan, the clans
+/// play in the order their first team was added and every clan plays its teams in turn.
+/// Eliminated or frozen teams are skipped by the engine without telling anyone, so the order
+/// only says who is expected to claim the turn next.
+#[derive(Serialize, Deserialize)]
+pub struct TurnOrder {
+    owners: Vec<ClientId>,
+    is_gone: Vec<bool>,
+    clans: Vec<Vec<usize>>,
+    next_teams: Vec<usize>,
+    current_clan: Option<usize>,
+    /// With the random order the clans are shuffled by the engine, their order is only known
+    /// after each of them had a turn
+    played_clans: Option<Vec<usize>>,
+    is_tag_team: bool,
+}
+
+impl TurnOrder {
+    pub fn new(teams: &[(ClientId, TeamInfo)], scheme: &Scheme) -> Self {
+        let mut colors = vec![];
+        let mut clans: Vec<Vec<usize>> = vec![];
+        for (index, (_, team)) in teams.iter().enumerate() {
+            match colors.iter().position(|c| *c == team.color) {
+                Some(clan) => clans[clan].push(index),
+                None => {
+                    colors.push(team.color);
+                    clans.push(vec![index]);
+                }
+            }
+        }
+
+        Self {
+            owners: teams.iter().map(|(id, _)| *id).collect(),
+            is_gone: vec![false; teams.len()],
+            next_teams: vec![0; clans.len()],
+            clans,
+            current_clan: None,
+            played_clans: if is_scheme_flag_set(scheme, RANDOM_ORDER_SETTING) {
+                Some(vec![])
+            } else {
+                None
+            },
+            is_tag_team: is_scheme_flag_set(scheme, TAG_TEAM_SETTING),
+        }
+    }
+
+    pub fn owner(&self, team: usize) -> ClientId {
+        self.owners[team]
+    }
+
+    pub fn set_client_gone(&mut self, client_id: ClientId, is_gone: bool) {
+        for (owner, gone) in self.owners.iter().zip(self.is_gone.iter_mut()) {
+            if *owner == client_id {
+                *gone = is_gone;
+            }
+        }
+    }
+
+    pub fn set_team_gone(&mut self, team: usize, is_gone: bool) {
+        self.is_gone[team] = is_gone;
+    }
+
+    fn clan_teams(&self, clan: usize) -> impl Iterator<Item = usize> + '_ {
+        let teams = &self.clans[clan];
+        let start = self.next_teams[clan];
+        (0..teams.len())
+            .map(move |i| teams[(start + i) % teams.len()])
+            .filter(move |t| !self.is_gone[*t])
+    }
+
+    /// Teams that may take the turn in their rotation order, starting with the next clan
+    fn rotation(&self) -> Vec<usize> {
+        let clans_count = self.clans.len();
+        let start = self.current_clan.map_or(0, |c| c + 1);
+        (0..clans_count)
+            .flat_map(|i| self.clan_teams((start + i) % clans_count))
+            .collect()
+    }
+
+    fn is_clan_played(&self, clan: usize) -> bool {
+        self.played_clans
+            .as_ref()
+            .map_or(false, |played| played.contains(&clan))
+    }
+
+    /// Teams whose owners take the turn without waiting for the other claims
+    pub fn expected_teams(&self) -> Vec<usize> {
+        if self.played_clans.is_some() {
+            (0..self.clans.len())
+                .filter(|c| !self.is_clan_played(*c))
+                .filter_map(|c| self.clan_teams(c).next())
+                .collect()
+        } else {
+            let mut teams = vec![];
+            if self.is_tag_team {
+                if let Some(clan) = self.current_clan {
+                    teams.extend(self.clan_teams(clan).next());
+                }
+            }
+            if let Some(team) = self.rotation().first() {
+                if !teams.contains(team) {
+                    teams.push(*team);
+                }
+            }
+            teams
+        }
+    }
+
+    /// The team of the clients that comes first in the rotation
+    pub fn find_first_team(&self, client_ids: &[ClientId]) -> Option<usize> {
+        self.rotation()
+            .into_iter()
+            .find(|t| client_ids.contains(&self.owners[*t]))
+    }
+
+    /// Passes the turn to the team, the teams the engine skipped keep their place in the rotation
+    pub fn pass_to(&mut self, team: usize) {
+        let clan = match self.clans.iter().position(|c| c.contains(&team)) {
+            Some(clan) => clan,
+            None => return,
+        };
+        let position = self.clans[clan].iter().position(|t| *t == team).unwrap();
+        self.next_teams[clan] = (position + 1) % self.clans[clan].len();
+        self.current_clan = Some(clan);
+
+        let is_round_complete = match self.played_clans {
+            Some(ref played) if played.contains(&clan) => true,
+            Some(ref mut played) => {
+                played.push(clan);
+                let (clans, is_gone) = (&self.clans, &self.is_gone);
+                (0..clans.len())
+                    .all(|c| played.contains(&c) || clans[c].iter().all(|t| is_gone[*t]))
+            }
+            None => false,
+        };
+
+        if is_round_complete {
+            let mut order = self.played_clans.take().unwrap();
+            let unplayed: Vec<_> = (0..self.clans.len())
+                .filter(|c| !order.contains(c))
+                .collect();
+            order.extend(unplayed);
+            self.clans = order.iter().map(|c| self.clans[*c].clone()).collect();
+            self.next_teams = order.iter().map(|c| self.next_teams[*c]).collect();
+            self.current_clan = order.iter().position(|c| *c == clan);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::TurnOrder;
+    use crate::core::types::{HedgehogInfo, Scheme, TeamInfo};
+
+    fn team(color: u8) -> TeamInfo {
+        let hedgehog = || HedgehogInfo {
+            name: "hog".to_string(),
+            hat: "NoHat".to_string(),
+        };
+        TeamInfo {
+            owner: String::new(),
+            name: format!("team{}", color),
+            color,
+            grave: "Statue".to_string(),
+            fort: "Plane".to_string(),
+            voice_pack: "Default".to_string(),
+            flag: "hedgewars".to_string(),
+            difficulty: 0,
+            hedgehogs_number: 4,
+            hedgehogs: [
+                hedgehog(),
+                hedgehog(),
+                hedgehog(),
+                hedgehog(),
+                hedgehog(),
+                hedgehog(),
+                hedgehog(),
+                hedgehog(),
+            ],
+        }
+    }
+
+    fn scheme(random_order: bool, tag_team: bool) -> Scheme {
+        let mut settings = vec!["false".to_string(); 42];
+        settings[11] = random_order.to_string();
+        settings[23] = tag_team.to_string();
+        Scheme {
+            name: "Test".to_string(),
+            settings,
+        }
+    }
+
+    // teams 0 and 2 are one clan
+    fn order(random_order: bool, tag_team: bool) -> TurnOrder {
+        let teams = vec![(0, team(0)), (1, team(1)), (2, team(0)), (3, team(2))];
+        TurnOrder::new(&teams, &scheme(random_order, tag_team))
+    }
+
+    fn play(order: &mut TurnOrder) -> usize {
+        let team = order.expected_teams()[0];
+        order.pass_to(team);
+        team
+    }
+
+    #[test]
+    fn clan_rotation() {
+        let mut order = order(false, false);
+        let turns: Vec<_> = (0..6).map(|_| play(&mut order)).collect();
+        assert_eq!(turns, vec![0, 1, 3, 2, 1, 3]);
+    }
+
+    #[test]
+    fn gone_teams() {
+        let mut order = order(false, false);
+        order.set_client_gone(1, true);
+        assert_eq!(play(&mut order), 0);
+        assert_eq!(play(&mut order), 3);
+
+        order.set_team_gone(2, true);
+        assert_eq!(play(&mut order), 0);
+        order.set_client_gone(1, false);
+        assert_eq!(play(&mut order), 1);
+    }
+
+    #[test]
+    fn skipped_team() {
+        let mut order = order(false, false);
+        play(&mut order);
+        assert_eq!(order.expected_teams(), vec![1]);
+
+        // the engine skips the eliminated team 1
+        assert_eq!(order.find_first_team(&[0, 3]), Some(3));
+        order.pass_to(3);
+        assert_eq!(order.expected_teams(), vec![2]);
+    }
+
+    #[test]
+    fn random_order() {
+        let mut order = order(true, false);
+        assert_eq!(order.expected_teams(), vec![0, 1, 3]);
+        order.pass_to(3);
+        assert_eq!(order.expected_teams(), vec![0, 1]);
+        order.pass_to(0);
+        order.pass_to(1);
+        assert_eq!(order.expected_teams(), vec![3]);
+
+        order.pass_to(3);
+        let turns: Vec<_> = (0..3).map(|_| play(&mut order)).collect();
+        assert_eq!(turns, vec![2, 1, 3]);
+    }
+
+    #[test]
+    fn tag_team() {
+        let mut order = order(false, true);
+        assert_eq!(order.expected_teams(), vec![0]);
+        order.pass_to(0);
+        assert_eq!(order.expected_teams(), vec![2, 1]);
+        order.pass_to(2);
+        assert_eq!(order.expected_teams(), vec![0, 1]);
+    }
+}
@@ -28,7 +28,101 @@ pub enum GameCfg {
     DrawnMap(String),
 }
 
+pub const MAX_FEATURE_SIZE: u32 = 25;
+pub const MAX_MAP_GENERATOR: u32 = 4;
+pub const MAX_MAZE_SIZE: u32 = 5;
+pub const MAX_TEMPLATE: u32 = 5;
+pub const MAX_DRAWN_MAP_LENGTH: usize = 128 * 1024;
+
 #[derive(PartialEq, Eq, Clone, Debug)]
+pub enum InvalidGameCfg {
+    FeatureSize(u32),
+    MapGenerator(u32),
+    MazeSize(u32),
+    Template(u32),
+    AmmoLength { expected: usize, actual: usize },
+    SchemeLength { expected: usize, actual: usize },
+    DrawnMapTooLarge(usize),
+    UnknownLayout(u16),
+}
+
+/// Ammo and scheme settings are only checked against the layouts of the known protocols,
+/// older clients can't change them
+struct ProtocolConfigLayout {
+    scheme_settings: usize,
+    ammo_types: usize,
+}
+
+fn protocol_config_layout(protocol_number: u16) -> Option<ProtocolConfigLayout> {
+    match protocol_number {
+        58..=60 => Some(ProtocolConfigLayout {
+            scheme_settings: 43,
+            ammo_types: 59,
+        }),
+        _ => None,
+    }
+}
+
+impl GameCfg {
+    pub fn validate(&self, protocol_number: u16) -> Result<(), InvalidGameCfg> {
+        use InvalidGameCfg::*;
+        let layout = protocol_config_layout(protocol_number);
+        match self {
+            GameCfg::FeatureSize(s) if !(1..=MAX_FEATURE_SIZE).contains(s) => Err(FeatureSize(*s)),
+            GameCfg::MapGenerator(g) if *g > MAX_MAP_GENERATOR => Err(MapGenerator(*g)),
+            GameCfg::MazeSize(s) if *s > MAX_MAZE_SIZE => Err(MazeSize(*s)),
+            GameCfg::Template(t) if *t > MAX_TEMPLATE => Err(Template(*t)),
+            GameCfg::DrawnMap(m) if m.len() > MAX_DRAWN_MAP_LENGTH => {
+                Err(DrawnMapTooLarge(m.len()))
+            }
+            GameCfg::Ammo(..) | GameCfg::Scheme(..) if layout.is_none() => {
+                Err(UnknownLayout(protocol_number))
+            }
+            GameCfg::Ammo(_, settings) => {
+                let expected = layout.unwrap().ammo_types * 4;
+                let actual = settings.as_ref().map_or(0, |s| s.len());
+                if actual != expected {
+                    Err(AmmoLength { expected, actual })
+                } else {
+                    Ok(())
+                }
+            }
+            GameCfg::Scheme(_, settings) => {
+                let expected = layout.unwrap().scheme_settings;
+                if settings.len() != expected {
+                    Err(SchemeLength {
+                        expected,
+                        actual: settings.len(),
+                    })
+                } else {
+                    Ok(())
+                }
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+pub const MAX_ROOMS_PAGE_SIZE: u8 = 100;
+
+/// How the room list of a client changes when a room it could have listed is updated
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum ListingChange {
+    Added,
+    Updated,
+    Removed,
+}
+
+#[derive(PartialEq, Eq, Clone, Debug, Default, Serialize, Deserialize)]
+pub struct RoomFilter {
+    pub name: Option<String>,
+    pub in_game: Option<bool>,
+    pub has_password: Option<bool>,
+    pub registration_required: Option<bool>,
+    pub has_free_slots: Option<bool>,
+}
+
+#[derive(PartialEq, Eq, Clone, Debug, Serialize, Deserialize)]
 pub struct TeamInfo {
     pub owner: String,
     pub name: String,
@@ -42,7 +136,7 @@ pub struct TeamInfo {
     pub hedgehogs: [HedgehogInfo; MAX_HEDGEHOGS_PER_TEAM as usize],
 }
 
-#[derive(PartialEq, Eq, Clone, Debug)]
+#[derive(PartialEq, Eq, Clone, Debug, Serialize, Deserialize)]
 pub struct HedgehogInfo {
     pub name: String,
     pub hat: String,
@@ -160,7 +254,7 @@ pub struct Replay {
     pub message_log: Vec<String>,
 }
 
-#[derive(PartialEq, Eq, Clone, Debug)]
+#[derive(PartialEq, Eq, Clone, Debug, Serialize, Deserialize)]
 pub enum VoteType {
     Kick(String),
     Map(Option<String>),
@@ -174,7 +268,7 @@ pub struct Vote {
     pub is_forced: bool,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Voting {
     pub ttl: u32,
     pub voters: Vec<ClientId>,
@@ -192,3 +286,92 @@ impl Voting {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{GameCfg, InvalidGameCfg, MAX_DRAWN_MAP_LENGTH};
+
+    fn validate(cfg: GameCfg) -> Result<(), InvalidGameCfg> {
+        cfg.validate(60)
+    }
+
+    #[test]
+    fn map_settings() {
+        assert_eq!(validate(GameCfg::FeatureSize(12)), Ok(()));
+        assert_eq!(
+            validate(GameCfg::FeatureSize(0)),
+            Err(InvalidGameCfg::FeatureSize(0))
+        );
+        assert_eq!(
+            validate(GameCfg::FeatureSize(26)),
+            Err(InvalidGameCfg::FeatureSize(26))
+        );
+        assert_eq!(
+            validate(GameCfg::MapGenerator(5)),
+            Err(InvalidGameCfg::MapGenerator(5))
+        );
+        assert_eq!(
+            validate(GameCfg::MazeSize(6)),
+            Err(InvalidGameCfg::MazeSize(6))
+        );
+        assert_eq!(
+            validate(GameCfg::Template(6)),
+            Err(InvalidGameCfg::Template(6))
+        );
+    }
+
+    #[test]
+    fn drawn_map_length() {
+        let map = "A".repeat(MAX_DRAWN_MAP_LENGTH);
+        assert_eq!(validate(GameCfg::DrawnMap(map.clone())), Ok(()));
+        assert_eq!(
+            validate(GameCfg::DrawnMap(map + "A")),
+            Err(InvalidGameCfg::DrawnMapTooLarge(MAX_DRAWN_MAP_LENGTH + 1))
+        );
+    }
+
+    #[test]
+    fn ammo_length() {
+        let ammo = |settings: Option<String>| GameCfg::Ammo("Default".to_string(), settings);
+        assert_eq!(validate(ammo(Some("9".repeat(59 * 4)))), Ok(()));
+        assert_eq!(
+            validate(ammo(Some("9".repeat(58 * 4)))),
+            Err(InvalidGameCfg::AmmoLength {
+                expected: 59 * 4,
+                actual: 58 * 4
+            })
+        );
+        assert_eq!(
+            validate(ammo(None)),
+            Err(InvalidGameCfg::AmmoLength {
+                expected: 59 * 4,
+                actual: 0
+            })
+        );
+    }
+
+    #[test]
+    fn scheme_length() {
+        let scheme = |count| GameCfg::Scheme("Default".to_string(), vec!["0".to_string(); count]);
+        assert_eq!(validate(scheme(43)), Ok(()));
+        assert_eq!(
+            validate(scheme(42)),
+            Err(InvalidGameCfg::SchemeLength {
+                expected: 43,
+                actual: 42
+            })
+        );
+    }
+
+    #[test]
+    fn unknown_layout() {
+        let scheme = GameCfg::Scheme("Default".to_string(), vec!["0".to_string(); 43]);
+        assert_eq!(scheme.validate(58), Ok(()));
+        assert_eq!(scheme.validate(57), Err(InvalidGameCfg::UnknownLayout(57)));
+        assert_eq!(
+            GameCfg::Ammo("Default".to_string(), None).validate(61),
+            Err(InvalidGameCfg::UnknownLayout(61))
+        );
+        assert_eq!(GameCfg::FeatureSize(12).validate(57), Ok(()));
+    }
+}
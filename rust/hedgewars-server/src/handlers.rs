@@ -3,6 +3,8 @@ use std::{
     collections::HashMap,
     fmt::{Formatter, LowerHex},
     iter::Iterator,
+    path::PathBuf,
+    time::{Instant, SystemTime, UNIX_EPOCH},
 };
 
 use self::{
@@ -17,9 +19,12 @@ use crate::{
         server::HwServer,
         types::{ClientId, GameCfg, Replay, RoomId, TeamInfo},
     },
-    protocol::messages::{
-        global_chat, server_chat, HwProtocolMessage, HwProtocolMessage::EngineMessage,
-        HwServerMessage, HwServerMessage::*,
+    protocol::{
+        messages::{
+            global_chat, server_chat, HwProtocolMessage, HwProtocolMessage::EngineMessage,
+            HwServerMessage, HwServerMessage::*,
+        },
+        translation,
     },
     utils,
 };
@@ -30,11 +35,21 @@ use rand::{thread_rng, RngCore};
 mod actions;
 mod checker;
 mod common;
+mod events;
+mod federation;
+mod idle;
 mod inanteroom;
 mod inlobby;
 mod inroom;
+mod locale;
 mod strings;
 
+pub use self::{
+    events::{EventTeam, ServerEvent},
+    federation::{exchange_registry, Federation, InstanceInfo},
+    idle::{handle_idle_clients, IdlePolicy},
+};
+
 #[derive(PartialEq, Debug)]
 pub struct Sha1Digest([u8; 20]);
 
@@ -85,15 +100,39 @@ impl PartialEq<&str> for Sha1Digest {
 pub struct ServerState {
     pub server: HwServer,
     pub anteroom: HwAnteroom,
+    pub localization: locale::Localization,
+    pub federation: Option<Federation>,
+    pub idle_policy: IdlePolicy,
+    /// Registered players have to upgrade the connection with STARTTLS before logging in
+    #[cfg(feature = "tls-connections")]
+    pub requires_secure_login: bool,
 }
 
 impl ServerState {
-    pub fn new(clients_limit: usize, rooms_limit: usize) -> Self {
+    pub fn new(
+        clients_limit: usize,
+        rooms_limit: usize,
+        fixed_rooms_file: Option<PathBuf>,
+    ) -> Self {
         Self {
-            server: HwServer::new(clients_limit, rooms_limit),
+            server: HwServer::new(clients_limit, rooms_limit, fixed_rooms_file),
             anteroom: HwAnteroom::new(clients_limit),
+            localization: locale::Localization::new(),
+            federation: None,
+            idle_policy: IdlePolicy::default(),
+            #[cfg(feature = "tls-connections")]
+            requires_secure_login: false,
         }
     }
+
+    pub fn is_login_allowed(&self, client_id: ClientId) -> bool {
+        #[cfg(feature = "tls-connections")]
+        let is_secure_login_required = self.requires_secure_login;
+        #[cfg(not(feature = "tls-connections"))]
+        let is_secure_login_required = false;
+
+        !is_secure_login_required || self.anteroom.clients[client_id].is_secure
+    }
 }
 
 #[derive(Debug)]
@@ -131,6 +170,29 @@ pub enum IoTask {
         room_id: RoomId,
         filename: String,
     },
+    StoreTeam {
+        owner: String,
+        team: Box<TeamInfo>,
+    },
+    GetStoredTeam {
+        room_id: RoomId,
+        owner: String,
+        name: String,
+    },
+    DeleteStoredTeam {
+        owner: String,
+        name: String,
+    },
+    SaveFixedRooms {
+        path: PathBuf,
+        contents: String,
+    },
+    SyncFederation {
+        registry: PathBuf,
+        port: u16,
+        contents: String,
+        now: u64,
+    },
 }
 
 #[derive(Debug)]
@@ -141,28 +203,51 @@ pub enum IoResult {
     Replay(Option<Replay>),
     SaveRoom(RoomId, bool),
     LoadRoom(RoomId, Option<String>),
+    StoreTeam(String, bool),
+    StoredTeam(RoomId, Option<Box<TeamInfo>>),
+    DeleteStoredTeam(String, bool),
+    FederationInstances(Vec<InstanceInfo>),
 }
 
 pub struct Response {
-    client_id: ClientId,
+    client_id: Option<ClientId>,
     messages: Vec<PendingMessage>,
     io_tasks: Vec<IoTask>,
     removed_clients: Vec<ClientId>,
+    tls_upgrades: Vec<ClientId>,
+    events: Vec<ServerEvent>,
+    room_listings: Vec<RoomId>,
 }
 
 impl Response {
     pub fn new(client_id: ClientId) -> Self {
+        Self::with_client(Some(client_id))
+    }
+
+    /// Creates a response to a server event that was not caused by any client.
+    pub fn for_server() -> Self {
+        Self::with_client(None)
+    }
+
+    fn with_client(client_id: Option<ClientId>) -> Self {
         Self {
             client_id,
             messages: vec![],
             io_tasks: vec![],
             removed_clients: vec![],
+            tls_upgrades: vec![],
+            events: vec![],
+            room_listings: vec![],
         }
     }
 
     #[inline]
     pub fn is_empty(&self) -> bool {
-        self.messages.is_empty() && self.removed_clients.is_empty() && self.io_tasks.is_empty()
+        self.messages.is_empty()
+            && self.removed_clients.is_empty()
+            && self.io_tasks.is_empty()
+            && self.tls_upgrades.is_empty()
+            && self.events.is_empty()
     }
 
     #[inline]
@@ -170,13 +255,27 @@ impl Response {
         self.messages.len()
     }
 
+    /// The client the response is sent to, responses to server events don't have one
     #[inline]
     pub fn client_id(&self) -> ClientId {
+        self.client_id
+            .expect("A response to a server event has no client")
+    }
+
+    #[inline]
+    pub fn sender_id(&self) -> Option<ClientId> {
         self.client_id
     }
 
     #[inline]
     pub fn add(&mut self, message: PendingMessage) {
+        if let Destination::ToAll {
+            group: DestinationGroup::RoomList { room_id, .. },
+            ..
+        } = message.destination
+        {
+            self.room_listings.push(room_id);
+        }
         self.messages.push(message)
     }
 
@@ -197,15 +296,25 @@ impl Response {
 
     pub fn extract_messages<'a, 'b: 'a>(
         &'b mut self,
-        server: &'a HwServer,
+        state: &'a ServerState,
     ) -> impl Iterator<Item = (Vec<ClientId>, HwServerMessage)> + 'a {
         let client_id = self.client_id;
-        self.messages.drain(..).map(move |m| {
-            let ids = get_recipients(server, client_id, m.destination);
-            (ids, m.message)
+        self.messages.drain(..).flat_map(move |m| {
+            let ids = get_recipients(&state.server, client_id, m.destination);
+            state
+                .localization
+                .localize(&state.server, ids, m.message)
+                .into_iter()
+                .flat_map(move |(ids, message)| translate_outgoing(&state.server, ids, message))
         })
     }
 
+    /// Rooms whose room list entries were updated, their listings must be remembered
+    /// once the messages are extracted
+    pub fn extract_room_listings(&mut self) -> impl Iterator<Item = RoomId> + '_ {
+        self.room_listings.drain(..)
+    }
+
     pub fn remove_client(&mut self, client_id: ClientId) {
         self.removed_clients.push(client_id);
     }
@@ -214,6 +323,31 @@ impl Response {
         self.removed_clients.drain(..)
     }
 
+    /// Switches the connection of a plaintext client to TLS once the pending messages are sent.
+    #[cfg(feature = "tls-connections")]
+    pub fn upgrade_to_tls(&mut self, client_id: ClientId) {
+        self.tls_upgrades.push(client_id);
+    }
+
+    #[inline]
+    pub fn has_tls_upgrades(&self) -> bool {
+        !self.tls_upgrades.is_empty()
+    }
+
+    #[cfg(feature = "tls-connections")]
+    pub fn extract_tls_upgrades(&mut self) -> impl Iterator<Item = ClientId> + '_ {
+        self.tls_upgrades.drain(..)
+    }
+
+    #[inline]
+    pub fn emit(&mut self, event: ServerEvent) {
+        self.events.push(event)
+    }
+
+    pub fn extract_events(&mut self) -> impl Iterator<Item = ServerEvent> + '_ {
+        self.events.drain(..)
+    }
+
     pub fn extract_io_tasks(&mut self) -> impl Iterator<Item = IoTask> + '_ {
         self.io_tasks.drain(..)
     }
@@ -227,25 +361,66 @@ impl Extend<PendingMessage> for Response {
     }
 }
 
+fn translate_outgoing(
+    server: &HwServer,
+    recipients: Vec<ClientId>,
+    message: HwServerMessage,
+) -> Vec<(Vec<ClientId>, HwServerMessage)> {
+    if !translation::is_version_dependent(&message) {
+        return vec![(recipients, message)];
+    }
+
+    let mut groups: Vec<(Option<u16>, Vec<ClientId>)> = vec![];
+    for id in recipients {
+        let protocol = Some(id)
+            .filter(|id| server.has_client(*id))
+            .map(|id| server.client(id).protocol_number);
+        match groups.iter_mut().find(|(p, _)| *p == protocol) {
+            Some((_, ids)) => ids.push(id),
+            None => groups.push((protocol, vec![id])),
+        }
+    }
+
+    groups
+        .into_iter()
+        .filter(|(protocol, _)| !protocol.map_or(false, |p| translation::is_ignored(p, &message)))
+        .map(|(protocol, ids)| {
+            let translated = protocol.and_then(|p| translation::translate_outgoing(p, &message));
+            (ids, translated.unwrap_or_else(|| message.clone()))
+        })
+        .collect()
+}
+
 fn get_recipients(
     server: &HwServer,
-    client_id: ClientId,
+    client_id: Option<ClientId>,
     destination: Destination,
 ) -> Vec<ClientId> {
     match destination {
-        Destination::ToSelf => vec![client_id],
+        Destination::ToSelf => client_id.into_iter().collect(),
         Destination::ToId(id) => vec![id],
         Destination::ToIds(ids) => ids,
         Destination::ToAll { group, skip_self } => {
             let mut ids: Vec<_> = match group {
                 DestinationGroup::All => server.iter_client_ids().collect(),
                 DestinationGroup::Lobby => server.lobby_client_ids().collect(),
-                DestinationGroup::Protocol(proto) => server.protocol_client_ids(proto).collect(),
+                DestinationGroup::Protocol(proto) => server.compatible_client_ids(proto).collect(),
+                DestinationGroup::RoomList {
+                    protocol,
+                    room_id,
+                    change,
+                } => server
+                    .room_list_client_ids(protocol, room_id, change)
+                    .collect(),
                 DestinationGroup::Room(id) => server.room_client_ids(id).collect(),
+                DestinationGroup::LiveFeed(id) => server.room_live_feed_client_ids(id).collect(),
+                DestinationGroup::DelayedFeed(id) => {
+                    server.room_delayed_feed_client_ids(id).collect()
+                }
             };
 
             if skip_self {
-                if let Some(index) = ids.iter().position(|id| *id == client_id) {
+                if let Some(index) = ids.iter().position(|id| Some(*id) == client_id) {
                     ids.remove(index);
                 }
             }
@@ -273,7 +448,7 @@ pub fn handle(
                             let is_checker = client.is_checker;
                             state.server.add_client(client_id, client);
                             if !is_checker {
-                                common::get_lobby_join_data(&state.server, response);
+                                join_lobby(state, response);
                             }
                         }
                     }
@@ -283,16 +458,29 @@ pub fn handle(
                     }
                 }
             } else if state.server.has_client(client_id) {
+                let protocol = state.server.client(client_id).protocol_number;
+                let message = translation::translate_incoming(protocol, message);
+                if let HwProtocolMessage::JoinRoom(ref name, _) = message {
+                    if let Some((port, room_protocol)) = find_remote_room(state, name) {
+                        if translation::are_compatible(room_protocol, protocol) {
+                            response.add(Redirect(port).send_self());
+                        } else {
+                            response.warn(INCOMPATIBLE_ROOM_PROTOCOL);
+                        }
+                        return;
+                    }
+                }
+
                 match message {
                     HwProtocolMessage::Quit(Some(msg)) => {
                         common::remove_client(
                             &mut state.server,
                             response,
-                            "User quit: ".to_string() + &msg,
+                            locale::format_message(USER_QUIT_MESSAGE, &[&msg]),
                         );
                     }
                     HwProtocolMessage::Quit(None) => {
-                        common::remove_client(&mut state.server, response, "User quit".to_string());
+                        common::remove_client(&mut state.server, response, USER_QUIT.to_string());
                     }
                     HwProtocolMessage::Info(nick) => {
                         if let Some(client) = state.server.find_client(&nick) {
@@ -317,7 +505,7 @@ pub fn handle(
                             let info = vec![
                                 client.nick.clone(),
                                 "[]".to_string(),
-                                utils::protocol_version_string(client.protocol_number).to_string(),
+                                translation::version_string(client.protocol_number).to_string(),
                                 room_info,
                             ];
                             response.add(Info(info).send_self())
@@ -344,6 +532,10 @@ pub fn handle(
                         if !state.server.is_admin(client_id) {
                             response.warn(ACCESS_DENIED);
                         } else {
+                            response.emit(ServerEvent::GlobalMessage {
+                                nick: state.server.client(client_id).nick.clone(),
+                                message: msg.clone(),
+                            });
                             response.add(global_chat(msg).send_all())
                         }
                     }
@@ -354,6 +546,71 @@ pub fn handle(
                             response.warn(ACCESS_DENIED);
                         }
                     }
+                    HwProtocolMessage::Language(language) => {
+                        match state.localization.find_language(&language) {
+                            Some(language) => {
+                                state.server.set_client_language(client_id, Some(language))
+                            }
+                            None if language.eq_ignore_ascii_case("en") => {
+                                state.server.set_client_language(client_id, None)
+                            }
+                            None => response.warn(UNSUPPORTED_LANGUAGE),
+                        }
+                    }
+                    HwProtocolMessage::ListRooms(cursor, limit, filter) => {
+                        common::get_rooms_page(
+                            &state.server,
+                            client_id,
+                            cursor as RoomId,
+                            limit,
+                            &filter,
+                            response,
+                        );
+                        state.server.set_client_room_filter(client_id, filter);
+                    }
+                    HwProtocolMessage::StoreTeam(team) => {
+                        if !state.server.client(client_id).is_registered() {
+                            response.warn(TEAM_STORAGE_REGISTERED_ONLY);
+                        } else if state
+                            .server
+                            .is_team_name_reserved_for_others(client_id, &team.name)
+                        {
+                            response.warn(TEAM_NAME_RESERVED);
+                        } else {
+                            #[cfg(feature = "official-server")]
+                            {
+                                let owner = state.server.client(client_id).nick.clone();
+                                response.request_io(IoTask::StoreTeam { owner, team })
+                            }
+
+                            #[cfg(not(feature = "official-server"))]
+                            {
+                                response.warn(TEAM_STORAGE_NOT_SUPPORTED);
+                            }
+                        }
+                    }
+                    HwProtocolMessage::DeleteStoredTeam(name) => {
+                        let client = state.server.client(client_id);
+                        if state.server.team_name_owner(&name) != Some(&client.nick[..]) {
+                            response.warn(NO_STORED_TEAM);
+                        } else {
+                            #[cfg(feature = "official-server")]
+                            {
+                                let owner = client.nick.clone();
+                                response.request_io(IoTask::DeleteStoredTeam { owner, name })
+                            }
+
+                            #[cfg(not(feature = "official-server"))]
+                            {
+                                response.warn(TEAM_STORAGE_NOT_SUPPORTED);
+                            }
+                        }
+                    }
+                    HwProtocolMessage::ListStoredTeams => {
+                        let client = state.server.client(client_id);
+                        let names = state.server.reserved_team_names(&client.nick);
+                        response.add(StoredTeams(names).send_self());
+                    }
                     HwProtocolMessage::Watch(id) => {
                         #[cfg(feature = "official-server")]
                         {
@@ -375,12 +632,54 @@ pub fn handle(
     }
 }
 
+fn join_lobby(state: &ServerState, response: &mut Response) {
+    common::get_lobby_join_data(&state.server, response);
+
+    if let Some(ref federation) = state.federation {
+        let protocol = state.server.client(response.client_id()).protocol_number;
+        for room in federation.rooms(protocol) {
+            response.add(RoomAdd(room.info.clone()).send_self());
+        }
+    }
+}
+
+fn find_remote_room(state: &ServerState, name: &str) -> Option<(u16, u16)> {
+    if state.server.has_room(name) {
+        None
+    } else {
+        let (port, room) = state.federation.as_ref()?.find_room(name)?;
+        Some((port, room.protocol_number))
+    }
+}
+
+pub fn handle_federation_sync(state: &ServerState, response: &mut Response) {
+    if let Some(ref federation) = state.federation {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        if let Some(task) = federation.sync_task(&state.server, now) {
+            response.request_io(task);
+        }
+    }
+}
+
+/// Runs the timed part of the running games
+pub fn handle_running_games(state: &mut ServerState, now: Instant) -> Vec<Response> {
+    let mut responses = vec![];
+    for room_id in state.server.game_room_ids() {
+        inroom::handle_turn_claims(&mut state.server, room_id, now, &mut responses);
+        inroom::handle_delayed_feed(&mut state.server, room_id, now, &mut responses);
+    }
+    responses
+}
+
 pub fn handle_client_accept(
     state: &mut ServerState,
     client_id: ClientId,
     response: &mut Response,
     addr: [u8; 4],
     is_local: bool,
+    is_secure: bool,
 ) {
     let ban_reason = Some(addr)
         .filter(|_| !is_local)
@@ -394,7 +693,7 @@ pub fn handle_client_accept(
 
         state
             .anteroom
-            .add_client(client_id, encode(&salt), is_local);
+            .add_client(client_id, encode(&salt), is_local, is_secure);
 
         response.add(HwServerMessage::Connected(utils::SERVER_VERSION).send_self());
     }
@@ -406,6 +705,18 @@ pub fn handle_client_loss(state: &mut ServerState, client_id: ClientId, response
     }
 }
 
+/// Disconnects a client that can't be passed to the new server process on hot restart
+pub fn handle_client_drop(state: &mut ServerState, client_id: ClientId, response: &mut Response) {
+    if state.anteroom.remove_client(client_id).is_some() {
+        response.add(Bye(SERVER_RESTARTING.to_string()).send_self());
+        response.remove_client(client_id);
+    } else if state.server.has_client(client_id) {
+        common::remove_client(&mut state.server, response, SERVER_RESTARTING.to_string());
+    } else {
+        response.remove_client(client_id);
+    }
+}
+
 pub fn handle_io_result(
     state: &mut ServerState,
     client_id: ClientId,
@@ -417,12 +728,15 @@ pub fn handle_io_result(
             if !is_registered && state.server.is_registered_only() {
                 response.add(Bye(REGISTRATION_REQUIRED.to_string()).send_self());
                 response.remove_client(client_id);
+            } else if is_registered && !state.is_login_allowed(client_id) {
+                response.add(Bye(SECURE_LOGIN_REQUIRED.to_string()).send_self());
+                response.remove_client(client_id);
             } else if is_registered {
                 let client = &state.anteroom.clients[client_id];
                 response.add(AskPassword(client.server_salt.clone()).send_self());
             } else if let Some(client) = state.anteroom.remove_client(client_id) {
                 state.server.add_client(client_id, client);
-                common::get_lobby_join_data(&state.server, response);
+                join_lobby(state, response);
             }
         }
         IoResult::Account(None) => {
@@ -436,7 +750,7 @@ pub fn handle_io_result(
                 client.is_admin = info.is_admin;
                 client.is_contributor = info.is_contributor;
                 state.server.add_client(client_id, client);
-                common::get_lobby_join_data(&state.server, response);
+                join_lobby(state, response);
             }
         }
         IoResult::CheckerAccount { is_registered } => {
@@ -453,7 +767,7 @@ pub fn handle_io_result(
         IoResult::Replay(Some(replay)) => {
             let client = state.server.client(client_id);
             let protocol = client.protocol_number;
-            let start_msg = if protocol < 58 {
+            let start_msg = if translation::has_replay_rooms(protocol) {
                 RoomJoined(vec![client.nick.clone()])
             } else {
                 ReplayStart
@@ -465,7 +779,7 @@ pub fn handle_io_result(
             response.add(RunGame.send_self());
             response.add(ForwardEngineMessage(replay.message_log).send_self());
 
-            if protocol < 58 {
+            if translation::has_replay_rooms(protocol) {
                 response.add(Kicked.send_self());
             }
         }
@@ -490,6 +804,49 @@ pub fn handle_io_result(
         IoResult::LoadRoom(_, None) => {
             response.warn(ROOM_CONFIG_LOAD_FAILED);
         }
+        IoResult::StoreTeam(name, true) => {
+            let owner = state.server.client(client_id).nick.clone();
+            state.server.reserve_team_name(&name, &owner);
+            response.add(server_chat(TEAM_STORED.to_string()).send_self());
+        }
+        IoResult::StoreTeam(_, false) => {
+            response.warn(TEAM_STORE_FAILED);
+        }
+        IoResult::StoredTeam(room_id, Some(team)) => {
+            if let Some(mut control) = state.server.get_room_control(client_id) {
+                if control.room().id == room_id {
+                    inroom::add_team(&mut control, response, team);
+                }
+            }
+        }
+        IoResult::StoredTeam(_, None) => {
+            response.warn(NO_STORED_TEAM);
+        }
+        IoResult::DeleteStoredTeam(name, true) => {
+            state.server.release_team_name(&name);
+            response.add(server_chat(STORED_TEAM_DELETED.to_string()).send_self());
+        }
+        IoResult::DeleteStoredTeam(_, false) => {
+            response.warn(NO_STORED_TEAM);
+        }
+        IoResult::FederationInstances(_) => {
+            warn!("Federation registry read for client {}", client_id);
+        }
+    }
+}
+
+pub fn handle_server_io_result(
+    state: &mut ServerState,
+    response: &mut Response,
+    io_result: IoResult,
+) {
+    match io_result {
+        IoResult::FederationInstances(instances) => {
+            if let Some(ref mut federation) = state.federation {
+                federation.update(&state.server, instances, response);
+            }
+        }
+        result => warn!("Unexpected server io result {:?}", result),
     }
 }
 
@@ -4,7 +4,7 @@ use crate::{
         room::HwRoom,
         room::{GameInfo, RoomFlags},
         server::HwServer,
-        types::{ClientId, GameCfg, RoomId, VoteType},
+        types::{ClientId, GameCfg, ListingChange, RoomId, VoteType},
     },
     protocol::messages::{server_chat, HwProtocolMessage, HwServerMessage, HwServerMessage::*},
     utils::to_engine_msg,
@@ -17,7 +17,14 @@ pub enum DestinationGroup {
     All,
     Lobby,
     Room(RoomId),
+    LiveFeed(RoomId),
+    DelayedFeed(RoomId),
     Protocol(u16),
+    RoomList {
+        protocol: u16,
+        room_id: RoomId,
+        change: ListingChange,
+    },
 }
 
 #[derive(Clone)]
@@ -76,6 +83,20 @@ impl PendingMessage {
         self
     }
 
+    pub fn in_live_feed(mut self, clients_room_id: RoomId) -> PendingMessage {
+        if let Destination::ToAll { ref mut group, .. } = self.destination {
+            *group = DestinationGroup::LiveFeed(clients_room_id)
+        }
+        self
+    }
+
+    pub fn in_delayed_feed(mut self, clients_room_id: RoomId) -> PendingMessage {
+        if let Destination::ToAll { ref mut group, .. } = self.destination {
+            *group = DestinationGroup::DelayedFeed(clients_room_id)
+        }
+        self
+    }
+
     pub fn in_lobby(mut self) -> PendingMessage {
         if let Destination::ToAll { ref mut group, .. } = self.destination {
             *group = DestinationGroup::Lobby
@@ -90,6 +111,19 @@ impl PendingMessage {
         self
     }
 
+    pub fn on_listing_change(mut self, room_id: RoomId, change: ListingChange) -> PendingMessage {
+        if let Destination::ToAll { ref mut group, .. } = self.destination {
+            if let DestinationGroup::Protocol(protocol) = *group {
+                *group = DestinationGroup::RoomList {
+                    protocol,
+                    room_id,
+                    change,
+                }
+            }
+        }
+        self
+    }
+
     pub fn but_self(mut self) -> PendingMessage {
         if let Destination::ToAll {
             ref mut skip_self, ..
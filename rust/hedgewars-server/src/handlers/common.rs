@@ -3,10 +3,13 @@ use crate::{
         client::HwClient,
         room::HwRoom,
         server::{
-            EndGameResult, HwRoomControl, HwServer, JoinRoomError, LeaveRoomResult, StartGameError,
-            VoteError, VoteResult,
+            ChangeMasterResult, EndGameResult, HwRoomControl, HwServer, JoinRoomError,
+            LeaveRoomResult, StartGameError, VoteError, VoteResult,
+        },
+        types::{
+            ClientId, GameCfg, ListingChange, RoomFilter, RoomId, TeamInfo, Vote, VoteType,
+            MAX_HEDGEHOGS_PER_TEAM, MAX_ROOMS_PAGE_SIZE,
         },
-        types::{ClientId, GameCfg, RoomId, TeamInfo, Vote, VoteType, MAX_HEDGEHOGS_PER_TEAM},
     },
     protocol::messages::{
         add_flags, remove_flags, server_chat,
@@ -14,17 +17,21 @@ use crate::{
         HwServerMessage::{self, *},
         ProtocolFlags as Flags,
     },
+    protocol::translation,
     utils::to_engine_msg,
 };
 
 use super::{
     actions::{Destination, DestinationGroup},
-    Response,
+    events::room_teams,
+    locale::format_message,
+    strings::AVAILABLE_MAPS,
+    Response, ServerEvent,
 };
 
 use crate::core::types::RoomConfig;
 use rand::{self, seq::SliceRandom, thread_rng, Rng};
-use std::{iter::once, mem::replace};
+use std::{cmp::min, iter::once, mem::replace};
 
 pub fn rnd_reply(options: &[String]) -> HwServerMessage {
     let mut rng = thread_rng();
@@ -73,18 +80,22 @@ pub fn get_lobby_join_data(server: &HwServer, response: &mut Response) {
             Flags::InRoom,
             server.collect_nicks(|(_, c)| c.room_id.is_some()),
         ),
+        (Flags::Away, server.collect_nicks(|(_, c)| c.is_away())),
     ];
 
     let server_msg = ServerMessage(server.get_greetings(client).to_string());
 
     let rooms_msg = Rooms(
         server
-            .iter_rooms()
-            .filter(|r| r.protocol_number == client.protocol_number)
+            .iter_listed_rooms()
+            .filter(|r| translation::are_compatible(r.protocol_number, client.protocol_number))
             .flat_map(|r| r.info(r.master_id.map(|id| server.client(id))))
             .collect(),
     );
 
+    response.emit(ServerEvent::PlayerJoined {
+        nick: client.nick.clone(),
+    });
     response.add(LobbyJoined(nick).send_all().but_self());
     response.add(
         ClientFlags(add_flags(&flags), all_nicks.clone())
@@ -103,6 +114,34 @@ pub fn get_lobby_join_data(server: &HwServer, response: &mut Response) {
     response.add(rooms_msg.send_self());
 }
 
+pub fn get_rooms_page(
+    server: &HwServer,
+    client_id: ClientId,
+    cursor: RoomId,
+    limit: u8,
+    filter: &RoomFilter,
+    response: &mut Response,
+) {
+    let client = server.client(client_id);
+    let limit = min(limit, MAX_ROOMS_PAGE_SIZE) as usize;
+    let mut rooms = server
+        .iter_listed_rooms()
+        .filter(|r| r.id >= cursor)
+        .filter(|r| {
+            translation::are_compatible(r.protocol_number, client.protocol_number)
+                && r.matches(filter)
+        });
+
+    let page: Vec<_> = rooms
+        .by_ref()
+        .take(limit)
+        .flat_map(|r| r.info(r.master_id.map(|id| server.client(id))))
+        .collect();
+    let next_cursor = rooms.next().map(|r| r.id as u32);
+
+    response.add(RoomsPage(next_cursor, page).send_self());
+}
+
 pub fn get_room_join_data<'a, I: Iterator<Item = &'a HwClient> + Clone>(
     client: &HwClient,
     room: &HwRoom,
@@ -192,7 +231,7 @@ pub fn get_room_join_data<'a, I: Iterator<Item = &'a HwClient> + Clone>(
         response.add(
             ForwardEngineMessage(
                 once(to_engine_msg("e$spectate 1".bytes()))
-                    .chain(info.msg_log.iter().cloned())
+                    .chain(info.client_msg_log(client.id).iter().cloned())
                     .collect(),
             )
             .send_self(),
@@ -202,7 +241,7 @@ pub fn get_room_join_data<'a, I: Iterator<Item = &'a HwClient> + Clone>(
             response.add(
                 ForwardEngineMessage(vec![to_engine_msg(once(b'G').chain(team.name.bytes()))])
                     .send_all()
-                    .in_room(room.id),
+                    .in_live_feed(room.id),
             );
         }
 
@@ -239,7 +278,7 @@ pub fn get_remove_teams_data(
             response.add(
                 ForwardEngineMessage(vec![remove_msg])
                     .send_all()
-                    .in_room(room_id)
+                    .in_live_feed(room_id)
                     .but_self(),
             );
         }
@@ -252,21 +291,21 @@ pub fn get_remove_teams_data(
 
 pub fn get_room_leave_result(
     server: &HwServer,
-    room: &HwRoom,
+    room_id: RoomId,
     leave_message: &str,
     result: LeaveRoomResult,
     response: &mut Response,
 ) {
-    let client = server.client(response.client_id);
+    let client = server.client(response.client_id());
     response.add(ClientFlags(remove_flags(&[Flags::InRoom]), vec![client.nick.clone()]).send_all());
 
     match result {
-        LeaveRoomResult::RoomRemoved => {
-            response.add(
-                RoomRemove(room.name.clone())
-                    .send_all()
-                    .with_protocol(room.protocol_number),
-            );
+        LeaveRoomResult::RoomRemoved {
+            name,
+            protocol_number,
+        } => {
+            response.emit(ServerEvent::RoomClosed { room: name.clone() });
+            response.add(RoomRemove(name).send_all().with_protocol(protocol_number));
         }
 
         LeaveRoomResult::RoomRemains {
@@ -276,6 +315,7 @@ pub fn get_room_leave_result(
             was_in_game,
             removed_teams,
         } => {
+            let room = server.room(room_id);
             if !is_empty {
                 response.add(
                     RoomLeft(client.nick.clone(), leave_message.to_string())
@@ -307,11 +347,7 @@ pub fn get_room_leave_result(
 
             get_remove_teams_data(room.id, was_in_game, removed_teams, response);
 
-            response.add(
-                RoomUpdated(room.name.clone(), room.info(Some(&client)))
-                    .send_all()
-                    .with_protocol(room.protocol_number),
-            );
+            get_room_update(None, room, Some(client), response);
         }
     }
 }
@@ -324,11 +360,15 @@ pub fn remove_client(server: &mut HwServer, response: &mut Response, msg: String
     if let Some(mut room_control) = server.get_room_control(client_id) {
         let room_id = room_control.room().id;
         let result = room_control.leave_room();
-        get_room_leave_result(server, server.room(room_id), &msg, result, response);
+        get_room_leave_result(server, room_id, &msg, result, response);
     }
 
     server.remove_client(client_id);
 
+    response.emit(ServerEvent::PlayerLeft {
+        nick: nick.clone(),
+        message: msg.clone(),
+    });
     response.add(LobbyLeft(nick, msg.clone()).send_all());
     response.add(Bye(msg).send_self());
     response.remove_client(client_id);
@@ -340,8 +380,25 @@ pub fn get_room_update(
     master: Option<&HwClient>,
     response: &mut Response,
 ) {
-    let update_msg = RoomUpdated(room_name.unwrap_or(room.name.clone()), room.info(master));
-    response.add(update_msg.send_all().with_protocol(room.protocol_number));
+    let room_name = room_name.unwrap_or(room.name.clone());
+    response.add(
+        RoomAdd(room.info(master))
+            .send_all()
+            .with_protocol(room.protocol_number)
+            .on_listing_change(room.id, ListingChange::Added),
+    );
+    response.add(
+        RoomUpdated(room_name.clone(), room.info(master))
+            .send_all()
+            .with_protocol(room.protocol_number)
+            .on_listing_change(room.id, ListingChange::Updated),
+    );
+    response.add(
+        RoomRemove(room_name)
+            .send_all()
+            .with_protocol(room.protocol_number)
+            .on_listing_change(room.id, ListingChange::Removed),
+    );
 }
 
 pub fn get_room_config_impl(
@@ -433,7 +490,7 @@ pub fn check_vote(
             if names.is_empty() {
                 Some("/callvote map: No maps saved in this room!".to_string())
             } else {
-                Some(format!("Available maps: {}", names.join(", ")))
+                Some(format_message(AVAILABLE_MAPS, &[&names.join(", ")]))
             }
         }
         VoteType::Map(Some(name)) => {
@@ -504,10 +561,11 @@ pub fn handle_vote(
                     let kicked_id = kicked_client.id;
                     if let Some(mut room_control) = room_control.change_client(kicked_id) {
                         response.add(Kicked.send(kicked_id));
+                        let room_id = room_control.room().id;
                         let result = room_control.leave_room();
                         super::common::get_room_leave_result(
                             room_control.server(),
-                            room_control.room(),
+                            room_id,
                             "kicked",
                             result,
                             response,
@@ -565,6 +623,32 @@ pub fn handle_vote(
     }
 }
 
+pub fn get_change_master_data(
+    server: &HwServer,
+    room_id: RoomId,
+    result: ChangeMasterResult,
+    response: &mut Response,
+) {
+    if let Some(master_id) = result.old_master_id {
+        response.add(
+            ClientFlags(
+                remove_flags(&[Flags::RoomMaster]),
+                vec![server.client(master_id).nick.clone()],
+            )
+            .send_all()
+            .in_room(room_id),
+        );
+    }
+    response.add(
+        ClientFlags(
+            add_flags(&[Flags::RoomMaster]),
+            vec![server.client(result.new_master_id).nick.clone()],
+        )
+        .send_all()
+        .in_room(room_id),
+    );
+}
+
 pub fn get_start_game_data(
     server: &HwServer,
     room_id: RoomId,
@@ -574,6 +658,10 @@ pub fn get_start_game_data(
     match result {
         Ok(room_nicks) => {
             let room = server.room(room_id);
+            response.emit(ServerEvent::GameStarted {
+                room: room.name.clone(),
+                teams: room_teams(server, room),
+            });
             response.add(RunGame.send_all().in_room(room.id));
             response.add(
                 ClientFlags(add_flags(&[Flags::InGame]), room_nicks)
@@ -601,7 +689,15 @@ pub fn get_end_game_result(
     let room = server.room(room_id);
     let room_master = room.master_id.map(|id| server.client(id));
 
+    response.emit(ServerEvent::GameEnded {
+        room: room.name.clone(),
+        teams: room_teams(server, room),
+    });
     get_room_update(None, room, room_master, response);
+    if !result.delayed_msgs.is_empty() {
+        response
+            .add(ForwardEngineMessage(result.delayed_msgs).send_many(result.delayed_feed_clients));
+    }
     response.add(RoundFinished.send_all().in_room(room_id));
 
     response.extend(
@@ -677,4 +773,117 @@ mod tests {
             }
         }
     }
+
+    fn add_lobby_client(server: &mut HwServer, client_id: ClientId, nick: &str) {
+        use crate::core::anteroom::HwAnteroomClient;
+        server.add_client(
+            client_id,
+            HwAnteroomClient {
+                nick: Some(nick.to_string()),
+                protocol_number: std::num::NonZeroU16::new(60),
+                server_salt: String::new(),
+                is_checker: false,
+                is_local_admin: false,
+                is_registered: false,
+                is_admin: false,
+                is_contributor: false,
+                is_secure: false,
+            },
+        );
+    }
+
+    fn room_list_messages(server: &mut HwServer, room_id: RoomId) -> Vec<(String, Vec<ClientId>)> {
+        let mut response = Response::new(0);
+        get_room_update(None, server.room(room_id), None, &mut response);
+        let messages = response
+            .messages
+            .drain(..)
+            .map(|m| {
+                let ids = crate::handlers::get_recipients(server, Some(0), m.destination);
+                (
+                    m.message
+                        .to_raw_protocol()
+                        .lines()
+                        .nth(1)
+                        .unwrap()
+                        .to_string(),
+                    ids,
+                )
+            })
+            .filter(|(_, ids)| !ids.is_empty())
+            .collect();
+        server.update_room_listings(response.extract_room_listings());
+        messages
+    }
+
+    #[test]
+    fn room_list_follows_filter_membership() {
+        let mut server = HwServer::new(10, 10, None);
+        add_lobby_client(&mut server, 0, "master");
+        add_lobby_client(&mut server, 1, "watcher");
+        let room_id = server
+            .create_room(0, "lobby room".to_string(), None)
+            .map(|(_, room)| room.id)
+            .unwrap();
+        server.set_client_room_filter(
+            1,
+            RoomFilter {
+                name: Some("game".to_string()),
+                ..RoomFilter::default()
+            },
+        );
+
+        assert_eq!(
+            room_list_messages(&mut server, room_id),
+            vec![("ADD".to_string(), vec![0])]
+        );
+        assert_eq!(
+            room_list_messages(&mut server, room_id),
+            vec![("UPD".to_string(), vec![0])]
+        );
+
+        let mut room_control = server.get_room_control(0).unwrap();
+        room_control.set_room_name("game room".to_string()).unwrap();
+        assert_eq!(
+            room_list_messages(&mut server, room_id),
+            vec![("ADD".to_string(), vec![1]), ("UPD".to_string(), vec![0])]
+        );
+
+        let mut room_control = server.get_room_control(0).unwrap();
+        room_control
+            .set_room_name("lobby room".to_string())
+            .unwrap();
+        assert_eq!(
+            room_list_messages(&mut server, room_id),
+            vec![("UPD".to_string(), vec![0]), ("DEL".to_string(), vec![1])]
+        );
+    }
+
+    #[test]
+    fn room_list_follows_hidden_flag() {
+        use crate::core::room::RoomFlags;
+
+        let mut server = HwServer::new(10, 10, None);
+        add_lobby_client(&mut server, 0, "master");
+        add_lobby_client(&mut server, 1, "watcher");
+        let room_id = server
+            .create_room(0, "room".to_string(), None)
+            .map(|(_, room)| room.id)
+            .unwrap();
+        room_list_messages(&mut server, room_id);
+
+        let mut room_control = server.get_room_control(0).unwrap();
+        assert!(room_control.toggle_flag(RoomFlags::HIDDEN));
+        assert_eq!(
+            room_list_messages(&mut server, room_id),
+            vec![("DEL".to_string(), vec![0, 1])]
+        );
+
+        let mut room_control = server.get_room_control(0).unwrap();
+        assert!(room_control.toggle_flag(RoomFlags::HIDDEN));
+        assert_eq!(
+            room_list_messages(&mut server, room_id),
+            vec![("ADD".to_string(), vec![0, 1])]
+        );
+    }
 }
@@ -0,0 +1,192 @@
+use crate::core::{room::HwRoom, server::HwServer};
+use serde_derive::Serialize;
+
+#[derive(Serialize, Clone, Debug)]
+pub struct EventTeam {
+    pub name: String,
+    pub owner: String,
+}
+
+/// Lobby events reported to the external integrations
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ServerEvent {
+    PlayerJoined { nick: String },
+    PlayerLeft { nick: String, message: String },
+    RoomCreated { room: String, master: String },
+    RoomClosed { room: String },
+    GameStarted { room: String, teams: Vec<EventTeam> },
+    GameEnded { room: String, teams: Vec<EventTeam> },
+    GlobalMessage { nick: String, message: String },
+}
+
+impl ServerEvent {
+    pub fn kind(&self) -> &'static str {
+        use ServerEvent::*;
+        match self {
+            PlayerJoined { .. } => "player_joined",
+            PlayerLeft { .. } => "player_left",
+            RoomCreated { .. } => "room_created",
+            RoomClosed { .. } => "room_closed",
+            GameStarted { .. } => "game_started",
+            GameEnded { .. } => "game_ended",
+            GlobalMessage { .. } => "global_message",
+        }
+    }
+}
+
+pub fn room_teams(server: &HwServer, room: &HwRoom) -> Vec<EventTeam> {
+    room.teams
+        .iter()
+        .map(|(owner_id, team)| EventTeam {
+            name: team.name.clone(),
+            owner: if server.has_client(*owner_id) {
+                server.client(*owner_id).nick.clone()
+            } else {
+                String::new()
+            },
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        core::{
+            anteroom::HwAnteroomClient,
+            types::{ClientId, HedgehogInfo, TeamInfo},
+        },
+        handlers::{handle, Response, ServerState},
+        protocol::messages::HwProtocolMessage::{self, *},
+    };
+    use std::num::NonZeroU16;
+
+    const MASTER: ClientId = 0;
+    const PLAYER: ClientId = 1;
+    const ADMIN: ClientId = 2;
+
+    fn add_client(state: &mut ServerState, client_id: ClientId, nick: &str, is_admin: bool) {
+        state.server.add_client(
+            client_id,
+            HwAnteroomClient {
+                nick: Some(nick.to_string()),
+                protocol_number: NonZeroU16::new(60),
+                server_salt: String::new(),
+                is_checker: false,
+                is_local_admin: is_admin,
+                is_registered: false,
+                is_admin,
+                is_contributor: false,
+                is_secure: false,
+            },
+        );
+    }
+
+    fn team(name: &str, color: u8) -> Box<TeamInfo> {
+        let hedgehog = || HedgehogInfo {
+            name: "hog".to_string(),
+            hat: "NoHat".to_string(),
+        };
+        Box::new(TeamInfo {
+            owner: String::new(),
+            name: name.to_string(),
+            color,
+            grave: "Statue".to_string(),
+            fort: "Plane".to_string(),
+            voice_pack: "Default".to_string(),
+            flag: "hedgewars".to_string(),
+            difficulty: 0,
+            hedgehogs_number: 4,
+            hedgehogs: [
+                hedgehog(),
+                hedgehog(),
+                hedgehog(),
+                hedgehog(),
+                hedgehog(),
+                hedgehog(),
+                hedgehog(),
+                hedgehog(),
+            ],
+        })
+    }
+
+    fn events(
+        state: &mut ServerState,
+        client_id: ClientId,
+        message: HwProtocolMessage,
+    ) -> Vec<String> {
+        let mut response = Response::new(client_id);
+        handle(state, client_id, &mut response, message);
+        response
+            .extract_events()
+            .map(|e| serde_json::to_string(&e).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn lobby_events() {
+        let mut state = ServerState::new(10, 10, None);
+        state
+            .anteroom
+            .add_client(MASTER, String::new(), false, false);
+        add_client(&mut state, PLAYER, "player", false);
+        add_client(&mut state, ADMIN, "admin", true);
+
+        assert!(events(&mut state, MASTER, Nick("master".to_string())).is_empty());
+        assert_eq!(
+            events(&mut state, MASTER, Proto(60)),
+            vec![r#"{"event":"player_joined","nick":"master"}"#]
+        );
+
+        assert!(events(&mut state, PLAYER, Global("hi".to_string())).is_empty());
+        assert_eq!(
+            events(&mut state, ADMIN, Global("hi".to_string())),
+            vec![r#"{"event":"global_message","nick":"admin","message":"hi"}"#]
+        );
+
+        assert_eq!(
+            events(&mut state, PLAYER, Quit(Some("bye".to_string()))),
+            vec![r#"{"event":"player_left","nick":"player","message":"User quit: bye"}"#]
+        );
+    }
+
+    #[test]
+    fn room_events() {
+        let mut state = ServerState::new(10, 10, None);
+        add_client(&mut state, MASTER, "master", false);
+        add_client(&mut state, PLAYER, "player", false);
+
+        assert_eq!(
+            events(&mut state, MASTER, CreateRoom("room".to_string(), None)),
+            vec![r#"{"event":"room_created","room":"room","master":"master"}"#]
+        );
+        assert!(events(&mut state, PLAYER, JoinRoom("room".to_string(), None)).is_empty());
+
+        let mut room_control = state.server.get_room_control(MASTER).unwrap();
+        assert!(room_control.add_team(team("first", 0)).is_ok());
+        let mut room_control = state.server.get_room_control(PLAYER).unwrap();
+        assert!(room_control.add_team(team("second", 1)).is_ok());
+        assert!(room_control.toggle_ready());
+
+        let teams = r#"[{"name":"first","owner":"master"},{"name":"second","owner":"player"}]"#;
+        assert_eq!(
+            events(&mut state, MASTER, StartGame),
+            vec![format!(
+                r#"{{"event":"game_started","room":"room","teams":{}}}"#,
+                teams
+            )]
+        );
+
+        assert!(events(&mut state, MASTER, RemoveTeam("first".to_string())).is_empty());
+        assert_eq!(
+            events(&mut state, PLAYER, RemoveTeam("second".to_string())),
+            vec![r#"{"event":"game_ended","room":"room","teams":[]}"#]
+        );
+
+        assert!(events(&mut state, PLAYER, Part(None)).is_empty());
+        assert_eq!(
+            events(&mut state, MASTER, Part(None)),
+            vec![r#"{"event":"room_closed","room":"room"}"#]
+        );
+    }
+}
@@ -0,0 +1,342 @@
+use super::{IoTask, Response};
+use crate::{
+    core::server::HwServer,
+    protocol::{messages::HwServerMessage::*, translation},
+};
+use log::*;
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs, io,
+    mem::replace,
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+};
+
+/// Registry entries that were not updated for this long belong to stopped instances
+const STALE_ENTRY_SECS: u64 = 30;
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct RemoteRoom {
+    pub name: String,
+    pub protocol_number: u16,
+    pub info: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct InstanceInfo {
+    port: u16,
+    protocols: Option<(u16, u16)>,
+    updated: u64,
+    rooms: Vec<RemoteRoom>,
+}
+
+impl InstanceInfo {
+    fn serves(&self, protocol_number: u16) -> bool {
+        self.protocols
+            .map_or(true, |(from, to)| (from..=to).contains(&protocol_number))
+    }
+}
+
+/** Shares the room lists of several server instances through a registry directory,
+ * where every instance periodically publishes its own `<port>.yaml` file.
+ */
+pub struct Federation {
+    registry: PathBuf,
+    port: u16,
+    protocols: Option<RangeInclusive<u16>>,
+    instances: Vec<InstanceInfo>,
+    remote_rooms: HashMap<String, (u16, RemoteRoom)>,
+}
+
+impl Federation {
+    pub fn new(registry: PathBuf, port: u16, protocols: Option<RangeInclusive<u16>>) -> Self {
+        Self {
+            registry,
+            port,
+            protocols,
+            instances: vec![],
+            remote_rooms: HashMap::new(),
+        }
+    }
+
+    pub fn serves(&self, protocol_number: u16) -> bool {
+        self.protocols
+            .as_ref()
+            .map_or(true, |r| r.contains(&protocol_number))
+    }
+
+    pub fn find_instance(&self, protocol_number: u16) -> Option<u16> {
+        self.instances
+            .iter()
+            .find(|i| i.serves(protocol_number))
+            .map(|i| i.port)
+    }
+
+    pub fn find_room(&self, name: &str) -> Option<(u16, &RemoteRoom)> {
+        self.remote_rooms
+            .get(name)
+            .map(|(port, room)| (*port, room))
+    }
+
+    pub fn rooms(&self, protocol_number: u16) -> impl Iterator<Item = &RemoteRoom> {
+        self.remote_rooms
+            .values()
+            .map(|(_, room)| room)
+            .filter(move |r| translation::are_compatible(r.protocol_number, protocol_number))
+    }
+
+    /// Creates the task publishing the local rooms and reading the registry on the IO thread.
+    pub fn sync_task(&self, server: &HwServer, now: u64) -> Option<IoTask> {
+        let rooms = server
+            .iter_listed_rooms()
+            .map(|r| RemoteRoom {
+                name: r.name.clone(),
+                protocol_number: r.protocol_number,
+                info: r.info(r.master_id.map(|id| server.client(id))),
+            })
+            .collect();
+        let info = InstanceInfo {
+            port: self.port,
+            protocols: self.protocols.as_ref().map(|r| (*r.start(), *r.end())),
+            updated: now,
+            rooms,
+        };
+
+        match serde_yaml::to_string(&info) {
+            Ok(contents) => Some(IoTask::SyncFederation {
+                registry: self.registry.clone(),
+                port: self.port,
+                contents,
+                now,
+            }),
+            Err(e) => {
+                warn!("Unable to serialize the registry entry: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Announces the changes of the remote rooms read from the registry to the lobby.
+    pub fn update(
+        &mut self,
+        server: &HwServer,
+        instances: Vec<InstanceInfo>,
+        response: &mut Response,
+    ) {
+        self.instances = instances;
+
+        let mut remote_rooms = HashMap::new();
+        for instance in &self.instances {
+            for room in &instance.rooms {
+                if !server.has_room(&room.name) && !remote_rooms.contains_key(&room.name) {
+                    remote_rooms.insert(room.name.clone(), (instance.port, room.clone()));
+                }
+            }
+        }
+
+        let mut old_rooms = replace(&mut self.remote_rooms, remote_rooms);
+        for (name, (_, room)) in &self.remote_rooms {
+            match old_rooms.remove(name) {
+                None => response.add(
+                    RoomAdd(room.info.clone())
+                        .send_all()
+                        .with_protocol(room.protocol_number),
+                ),
+                Some((_, old_room)) if old_room != *room => response.add(
+                    RoomUpdated(name.clone(), room.info.clone())
+                        .send_all()
+                        .with_protocol(room.protocol_number),
+                ),
+                Some(_) => (),
+            }
+        }
+        for (name, (_, room)) in old_rooms {
+            if !server.has_room(&name) {
+                response.add(
+                    RoomRemove(name)
+                        .send_all()
+                        .with_protocol(room.protocol_number),
+                );
+            }
+        }
+    }
+}
+
+/// Publishes the registry entry of the local instance and reads the entries of the other
+/// running instances, called on the IO thread.
+pub fn exchange_registry(
+    registry: &Path,
+    port: u16,
+    contents: &str,
+    now: u64,
+) -> Vec<InstanceInfo> {
+    if let Err(e) = publish(registry, port, contents) {
+        warn!("Unable to publish to the registry {:?}: {}", registry, e);
+    }
+
+    match load_instances(registry, port, now) {
+        Ok(instances) => instances,
+        Err(e) => {
+            warn!("Unable to read the registry {:?}: {}", registry, e);
+            vec![]
+        }
+    }
+}
+
+fn publish(registry: &Path, port: u16, contents: &str) -> io::Result<()> {
+    let path = registry.join(format!("{}.yaml", port));
+    let temp_path = path.with_extension("tmp");
+    fs::write(&temp_path, contents)?;
+    fs::rename(temp_path, path)
+}
+
+fn load_instances(registry: &Path, port: u16, now: u64) -> io::Result<Vec<InstanceInfo>> {
+    let mut instances = vec![];
+    for entry in fs::read_dir(registry)? {
+        let path = entry?.path();
+        if path.extension().map_or(true, |e| e != "yaml") {
+            continue;
+        }
+        match load_instance(&path) {
+            Ok(info) if info.port != port && info.updated + STALE_ENTRY_SECS >= now => {
+                instances.push(info)
+            }
+            Ok(_) => (),
+            Err(e) => warn!("Unable to read the registry entry {:?}: {}", path, e),
+        }
+    }
+    instances.sort_by_key(|i| i.port);
+    Ok(instances)
+}
+
+fn load_instance(path: &Path) -> io::Result<InstanceInfo> {
+    let contents = fs::read_to_string(path)?;
+    serde_yaml::from_str(&contents).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::anteroom::HwAnteroomClient;
+    use std::num::NonZeroU16;
+
+    fn instance(port: u16, updated: u64, rooms: &[(&str, &str)]) -> InstanceInfo {
+        InstanceInfo {
+            port,
+            protocols: None,
+            updated,
+            rooms: rooms
+                .iter()
+                .map(|(name, players)| RemoteRoom {
+                    name: name.to_string(),
+                    protocol_number: 60,
+                    info: vec![name.to_string(), players.to_string()],
+                })
+                .collect(),
+        }
+    }
+
+    fn server_with_room(name: &str) -> HwServer {
+        let mut server = HwServer::new(10, 10, None);
+        server.add_client(
+            0,
+            HwAnteroomClient {
+                nick: Some("master".to_string()),
+                protocol_number: NonZeroU16::new(60),
+                server_salt: String::new(),
+                is_checker: false,
+                is_local_admin: false,
+                is_registered: false,
+                is_admin: false,
+                is_contributor: false,
+                is_secure: false,
+            },
+        );
+        assert!(server.create_room(0, name.to_string(), None).is_ok());
+        server
+    }
+
+    fn sent_messages(response: &mut Response) -> Vec<String> {
+        let mut messages: Vec<_> = response
+            .messages
+            .drain(..)
+            .map(|m| m.message.to_raw_protocol())
+            .collect();
+        messages.sort();
+        messages
+    }
+
+    #[test]
+    fn find_room() {
+        let server = server_with_room("local");
+        let mut federation = Federation::new(PathBuf::new(), 1, None);
+        let instances = vec![
+            instance(2, 0, &[("shared", "1"), ("local", "1")]),
+            instance(3, 0, &[("shared", "2"), ("remote", "1")]),
+        ];
+        federation.update(&server, instances, &mut Response::for_server());
+
+        assert_eq!(
+            federation.find_room("shared").map(|(port, _)| port),
+            Some(2)
+        );
+        assert_eq!(
+            federation.find_room("remote").map(|(port, _)| port),
+            Some(3)
+        );
+        assert!(federation.find_room("local").is_none());
+        assert!(federation.find_room("missing").is_none());
+        assert_eq!(federation.find_instance(60), Some(2));
+    }
+
+    #[test]
+    fn sync_announces_remote_changes() {
+        let server = server_with_room("local");
+        let mut federation = Federation::new(PathBuf::new(), 1, None);
+
+        let mut response = Response::for_server();
+        let instances = vec![instance(2, 0, &[("first", "1"), ("second", "1")])];
+        federation.update(&server, instances, &mut response);
+        assert_eq!(
+            sent_messages(&mut response),
+            vec!["ROOM\nADD\nfirst\n1\n\n", "ROOM\nADD\nsecond\n1\n\n"]
+        );
+
+        let instances = vec![instance(2, 0, &[("first", "1"), ("second", "2")])];
+        federation.update(&server, instances, &mut response);
+        assert_eq!(
+            sent_messages(&mut response),
+            vec!["ROOM\nUPD\nsecond\nsecond\n2\n\n"]
+        );
+
+        federation.update(&server, vec![], &mut response);
+        assert_eq!(
+            sent_messages(&mut response),
+            vec!["ROOM\nDEL\nfirst\n\n", "ROOM\nDEL\nsecond\n\n"]
+        );
+    }
+
+    #[test]
+    fn registry_exchange() {
+        let registry = std::env::temp_dir().join(format!("hw-registry-{}", std::process::id()));
+        fs::create_dir_all(&registry).unwrap();
+        let write_entry = |info: InstanceInfo| {
+            let path = registry.join(format!("{}.yaml", info.port));
+            fs::write(path, serde_yaml::to_string(&info).unwrap()).unwrap();
+        };
+        write_entry(instance(2, 100, &[("fresh", "1")]));
+        write_entry(instance(3, 100 - STALE_ENTRY_SECS - 1, &[("stale", "1")]));
+
+        let contents = serde_yaml::to_string(&instance(1, 100, &[])).unwrap();
+        let instances = exchange_registry(&registry, 1, &contents, 100);
+        let published = load_instance(&registry.join("1.yaml"));
+        fs::remove_dir_all(&registry).unwrap();
+
+        assert_eq!(
+            instances.iter().map(|i| i.port).collect::<Vec<_>>(),
+            vec![2]
+        );
+        assert_eq!(published.unwrap().port, 1);
+    }
+}
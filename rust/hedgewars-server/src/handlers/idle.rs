@@ -0,0 +1,334 @@
+use super::{common, strings::*, Response, ServerState};
+use crate::{
+    core::{server::HwRoomControl, types::ClientId},
+    protocol::messages::{
+        add_flags, remove_flags, server_chat, HwServerMessage::*, ProtocolFlags as Flags,
+    },
+};
+use std::{collections::HashMap, time::Duration};
+
+/// Actions taken on the clients that didn't send anything for a while, every action is
+/// disabled unless its timeout is set.
+#[derive(Clone, Default, Debug)]
+pub struct IdlePolicy {
+    pub away_after: Option<Duration>,
+    pub unready_after: Option<Duration>,
+    pub master_transfer_after: Option<Duration>,
+    pub spectator_kick_after: Option<Duration>,
+}
+
+impl IdlePolicy {
+    pub fn is_enabled(&self) -> bool {
+        self.away_after.is_some()
+            || self.unready_after.is_some()
+            || self.master_transfer_after.is_some()
+            || self.spectator_kick_after.is_some()
+    }
+}
+
+fn is_expired(timeout: Option<Duration>, idle_time: Duration) -> bool {
+    timeout.map_or(false, |t| idle_time >= t)
+}
+
+fn update_away_status(
+    state: &mut ServerState,
+    client_id: ClientId,
+    idle_time: Duration,
+    response: &mut Response,
+) {
+    let is_away = is_expired(state.idle_policy.away_after, idle_time);
+    if state.server.set_client_away(client_id, is_away) {
+        let flags = if is_away {
+            add_flags(&[Flags::Away])
+        } else {
+            remove_flags(&[Flags::Away])
+        };
+        response
+            .add(ClientFlags(flags, vec![state.server.client(client_id).nick.clone()]).send_all());
+    }
+}
+
+fn transfer_master(
+    room_control: &mut HwRoomControl,
+    idle_times: &HashMap<ClientId, Duration>,
+    timeout: Duration,
+    response: &mut Response,
+) {
+    let room_id = room_control.room().id;
+    let client_id = room_control.client().id;
+    let new_master = room_control
+        .server()
+        .iter_clients()
+        .filter(|c| c.room_id == Some(room_id) && c.id != client_id)
+        .filter_map(|c| idle_times.get(&c.id).map(|t| (c, *t)))
+        .filter(|(_, t)| *t < timeout)
+        .min_by_key(|(_, t)| *t)
+        .map(|(c, _)| c.nick.clone());
+
+    if let Some(nick) = new_master {
+        if let Ok(result) = room_control.change_master(nick) {
+            response.add(
+                server_chat(IDLE_MASTER_REPLACED.to_string())
+                    .send_all()
+                    .in_room(room_id),
+            );
+            common::get_change_master_data(room_control.server(), room_id, result, response);
+        }
+    }
+}
+
+fn kick_spectator(mut room_control: HwRoomControl, response: &mut Response) {
+    let client_id = room_control.client().id;
+    response.add(Kicked.send(client_id));
+    let room_id = room_control.room().id;
+    let result = room_control.leave_room();
+    common::get_room_leave_result(
+        room_control.server(),
+        room_id,
+        IDLE_SPECTATOR_KICKED,
+        result,
+        response,
+    );
+}
+
+fn handle_idle_room_client(
+    mut room_control: HwRoomControl,
+    idle_times: &HashMap<ClientId, Duration>,
+    policy: &IdlePolicy,
+    idle_time: Duration,
+    response: &mut Response,
+) {
+    let (client, room) = room_control.get();
+    if room.game_info.is_some() {
+        return;
+    }
+
+    let room_id = room.id;
+    let is_master = client.is_master();
+    let is_ready = client.is_ready();
+    let is_spectator = room.client_teams(client.id).next().is_none();
+    let is_room_full = room.teams.len() >= room.max_teams as usize;
+
+    if is_master {
+        if let Some(timeout) = policy.master_transfer_after {
+            if idle_time >= timeout {
+                transfer_master(&mut room_control, idle_times, timeout, response);
+            }
+        }
+    } else if is_spectator && is_room_full && is_expired(policy.spectator_kick_after, idle_time) {
+        kick_spectator(room_control, response);
+    } else if is_ready && is_expired(policy.unready_after, idle_time) {
+        room_control.toggle_ready();
+        let nick = room_control.client().nick.clone();
+        response.add(
+            ClientFlags(remove_flags(&[Flags::Ready]), vec![nick])
+                .send_all()
+                .in_room(room_id),
+        );
+    }
+}
+
+/// Applies the idle policy to the logged in clients, `idle_times` are measured since the last
+/// message of each client not counting the ping responses.
+pub fn handle_idle_clients(
+    state: &mut ServerState,
+    idle_times: &HashMap<ClientId, Duration>,
+) -> Vec<Response> {
+    let policy = state.idle_policy.clone();
+    let mut responses = vec![];
+
+    for (&client_id, &idle_time) in idle_times {
+        if !state.server.has_client(client_id) {
+            continue;
+        }
+
+        let mut response = Response::new(client_id);
+        update_away_status(state, client_id, idle_time, &mut response);
+        if let Some(room_control) = state.server.get_room_control(client_id) {
+            handle_idle_room_client(room_control, idle_times, &policy, idle_time, &mut response);
+        }
+
+        if !response.is_empty() {
+            responses.push(response);
+        }
+    }
+
+    responses
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::{
+        anteroom::HwAnteroomClient,
+        types::{HedgehogInfo, RoomId, TeamInfo},
+    };
+    use std::num::NonZeroU16;
+
+    const MASTER: ClientId = 0;
+    const PLAYER: ClientId = 1;
+
+    fn secs(seconds: u64) -> Duration {
+        Duration::from_secs(seconds)
+    }
+
+    fn add_client(state: &mut ServerState, client_id: ClientId, nick: &str) {
+        state.server.add_client(
+            client_id,
+            HwAnteroomClient {
+                nick: Some(nick.to_string()),
+                protocol_number: NonZeroU16::new(60),
+                server_salt: String::new(),
+                is_checker: false,
+                is_local_admin: false,
+                is_registered: false,
+                is_admin: false,
+                is_contributor: false,
+                is_secure: false,
+            },
+        );
+    }
+
+    fn team(name: &str) -> Box<TeamInfo> {
+        let hedgehog = || HedgehogInfo {
+            name: "hog".to_string(),
+            hat: "NoHat".to_string(),
+        };
+        Box::new(TeamInfo {
+            owner: String::new(),
+            name: name.to_string(),
+            color: 0,
+            grave: "Statue".to_string(),
+            fort: "Plane".to_string(),
+            voice_pack: "Default".to_string(),
+            flag: "hedgewars".to_string(),
+            difficulty: 0,
+            hedgehogs_number: 4,
+            hedgehogs: [
+                hedgehog(),
+                hedgehog(),
+                hedgehog(),
+                hedgehog(),
+                hedgehog(),
+                hedgehog(),
+                hedgehog(),
+                hedgehog(),
+            ],
+        })
+    }
+
+    fn create_state(policy: IdlePolicy) -> (ServerState, RoomId) {
+        let mut state = ServerState::new(10, 10, None);
+        state.idle_policy = policy;
+        add_client(&mut state, MASTER, "master");
+        add_client(&mut state, PLAYER, "player");
+        let room_id = state
+            .server
+            .create_room(MASTER, "room".to_string(), None)
+            .map(|(_, room)| room.id)
+            .unwrap();
+        assert!(state.server.join_room(PLAYER, room_id, None).is_ok());
+        (state, room_id)
+    }
+
+    fn idle_messages(state: &mut ServerState, master_idle: u64, player_idle: u64) -> Vec<String> {
+        let idle_times = [(MASTER, secs(master_idle)), (PLAYER, secs(player_idle))]
+            .iter()
+            .cloned()
+            .collect();
+        let mut messages: Vec<_> = handle_idle_clients(state, &idle_times)
+            .into_iter()
+            .flat_map(|mut response| {
+                response
+                    .messages
+                    .drain(..)
+                    .map(|m| m.message.to_raw_protocol())
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        messages.sort();
+        messages
+    }
+
+    #[test]
+    fn away_flag() {
+        let (mut state, _) = create_state(IdlePolicy {
+            away_after: Some(secs(60)),
+            ..IdlePolicy::default()
+        });
+
+        assert_eq!(
+            idle_messages(&mut state, 0, 60),
+            vec!["CLIENT_FLAGS\n+w\nplayer\n\n"]
+        );
+        assert!(state.server.client(PLAYER).is_away());
+        assert!(idle_messages(&mut state, 0, 61).is_empty());
+
+        assert_eq!(
+            idle_messages(&mut state, 0, 1),
+            vec!["CLIENT_FLAGS\n-w\nplayer\n\n"]
+        );
+        assert!(!state.server.client(PLAYER).is_away());
+    }
+
+    #[test]
+    fn unready() {
+        let (mut state, _) = create_state(IdlePolicy {
+            unready_after: Some(secs(30)),
+            ..IdlePolicy::default()
+        });
+        let mut room_control = state.server.get_room_control(PLAYER).unwrap();
+        assert!(room_control.toggle_ready());
+
+        assert!(idle_messages(&mut state, 0, 29).is_empty());
+        assert_eq!(
+            idle_messages(&mut state, 0, 30),
+            vec!["CLIENT_FLAGS\n-r\nplayer\n\n"]
+        );
+        assert!(!state.server.client(PLAYER).is_ready());
+        assert!(idle_messages(&mut state, 0, 60).is_empty());
+
+        // the master stays ready
+        assert!(state.server.client(MASTER).is_ready());
+    }
+
+    #[test]
+    fn master_transfer() {
+        let (mut state, room_id) = create_state(IdlePolicy {
+            master_transfer_after: Some(secs(120)),
+            ..IdlePolicy::default()
+        });
+
+        assert!(idle_messages(&mut state, 119, 0).is_empty());
+        assert!(idle_messages(&mut state, 120, 120).is_empty());
+        assert_eq!(state.server.room(room_id).master_id, Some(MASTER));
+
+        let messages = idle_messages(&mut state, 120, 10);
+        assert!(messages.contains(&"CLIENT_FLAGS\n+h\nplayer\n\n".to_string()));
+        assert!(messages.contains(&"CLIENT_FLAGS\n-h\nmaster\n\n".to_string()));
+        assert_eq!(state.server.room(room_id).master_id, Some(PLAYER));
+        assert!(!state.server.client(MASTER).is_master());
+    }
+
+    #[test]
+    fn spectator_kick() {
+        let (mut state, room_id) = create_state(IdlePolicy {
+            spectator_kick_after: Some(secs(300)),
+            ..IdlePolicy::default()
+        });
+
+        let mut room_control = state.server.get_room_control(MASTER).unwrap();
+        assert!(room_control.set_room_max_teams(2).is_ok());
+        assert!(room_control.add_team(team("first")).is_ok());
+        assert!(idle_messages(&mut state, 0, 300).is_empty());
+
+        let mut room_control = state.server.get_room_control(MASTER).unwrap();
+        assert!(room_control.add_team(team("second")).is_ok());
+        assert!(idle_messages(&mut state, 0, 299).is_empty());
+
+        let messages = idle_messages(&mut state, 300, 300);
+        assert!(messages.contains(&"KICKED\n\n".to_string()));
+        assert_eq!(state.server.client(PLAYER).room_id, None);
+        assert_eq!(state.server.client(MASTER).room_id, Some(room_id));
+    }
+}
@@ -64,7 +64,7 @@ pub fn handle(
 ) -> LoginResult {
     match message {
         HwProtocolMessage::Quit(_) => {
-            response.add(Bye("User quit".to_string()).send_self());
+            response.add(Bye(USER_QUIT.to_string()).send_self());
             LoginResult::Exit
         }
         HwProtocolMessage::Nick(nick) => {
@@ -95,6 +95,14 @@ pub fn handle(
             } else if proto < 48 {
                 response.add(Bye(PROTOCOL_TOO_OLD.to_string()).send_self());
                 LoginResult::Exit
+            } else if let Some(port) = server_state
+                .federation
+                .as_ref()
+                .filter(|f| !f.serves(proto))
+                .and_then(|f| f.find_instance(proto))
+            {
+                response.add(Redirect(port).send_self());
+                LoginResult::Exit
             } else {
                 client.protocol_number = NonZeroU16::new(proto);
                 response.add(Proto(proto).send_self());
@@ -106,11 +114,33 @@ pub fn handle(
                 }
             }
         }
+        HwProtocolMessage::StartTls => {
+            let client = &mut server_state.anteroom.clients[client_id];
+            if client.is_secure {
+                response.error(CONNECTION_SECURE);
+            } else {
+                #[cfg(feature = "tls-connections")]
+                {
+                    client.is_secure = true;
+                    response.add(StartTls.send_self());
+                    response.upgrade_to_tls(client_id);
+                }
+
+                #[cfg(not(feature = "tls-connections"))]
+                response.error(TLS_NOT_SUPPORTED);
+            }
+            LoginResult::Unchanged
+        }
         #[cfg(feature = "official-server")]
         HwProtocolMessage::Password(hash, salt) => {
             let client = &server_state.anteroom.clients[client_id];
 
-            if let (Some(nick), Some(protocol)) = (client.nick.as_ref(), client.protocol_number) {
+            if !server_state.is_login_allowed(client_id) {
+                response.add(Bye(SECURE_LOGIN_REQUIRED.to_string()).send_self());
+                return LoginResult::Exit;
+            } else if let (Some(nick), Some(protocol)) =
+                (client.nick.as_ref(), client.protocol_number)
+            {
                 response.request_io(super::IoTask::GetAccount {
                     nick: nick.clone(),
                     protocol: protocol.get(),
@@ -153,3 +183,94 @@ pub fn handle(
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::handlers::{IoResult, Response, ServerState};
+
+    const CLIENT: ClientId = 0;
+
+    fn create_state(is_secure: bool) -> ServerState {
+        let mut state = ServerState::new(10, 10, None);
+        state
+            .anteroom
+            .add_client(CLIENT, "salt".to_string(), false, is_secure);
+        state
+    }
+
+    fn messages(response: &mut Response) -> Vec<String> {
+        response
+            .messages
+            .drain(..)
+            .map(|m| m.message.to_raw_protocol())
+            .collect()
+    }
+
+    fn start_tls(state: &mut ServerState) -> Response {
+        let mut response = Response::new(CLIENT);
+        handle(state, CLIENT, &mut response, HwProtocolMessage::StartTls);
+        response
+    }
+
+    #[test]
+    fn start_tls_on_secure_connection() {
+        let mut state = create_state(true);
+        let mut response = start_tls(&mut state);
+        assert_eq!(
+            messages(&mut response),
+            vec![format!("ERROR\n{}\n\n", CONNECTION_SECURE)]
+        );
+        assert!(!response.has_tls_upgrades());
+    }
+
+    #[cfg(not(feature = "tls-connections"))]
+    #[test]
+    fn start_tls_not_supported() {
+        let mut state = create_state(false);
+        let mut response = start_tls(&mut state);
+        assert_eq!(
+            messages(&mut response),
+            vec![format!("ERROR\n{}\n\n", TLS_NOT_SUPPORTED)]
+        );
+        assert!(!response.has_tls_upgrades());
+        assert!(!state.anteroom.clients[CLIENT].is_secure);
+    }
+
+    #[cfg(feature = "tls-connections")]
+    #[test]
+    fn start_tls_upgrade() {
+        let mut state = create_state(false);
+        let mut response = start_tls(&mut state);
+        assert_eq!(messages(&mut response), vec!["STARTTLS\n\n".to_string()]);
+        assert_eq!(
+            response.extract_tls_upgrades().collect::<Vec<_>>(),
+            vec![CLIENT]
+        );
+        assert!(state.anteroom.clients[CLIENT].is_secure);
+    }
+
+    #[cfg(feature = "tls-connections")]
+    #[test]
+    fn secure_login_required() {
+        let registered = |state: &mut ServerState| {
+            let mut response = Response::new(CLIENT);
+            let result = IoResult::AccountRegistered(true);
+            crate::handlers::handle_io_result(state, CLIENT, &mut response, result);
+            messages(&mut response)
+        };
+
+        let mut state = create_state(false);
+        state.requires_secure_login = true;
+        assert_eq!(
+            registered(&mut state),
+            vec![format!("BYE\n{}\n\n", SECURE_LOGIN_REQUIRED)]
+        );
+
+        start_tls(&mut state);
+        assert_eq!(
+            registered(&mut state),
+            vec!["ASKPASSWORD\nsalt\n\n".to_string()]
+        );
+    }
+}
@@ -1,14 +1,15 @@
-use super::{common::rnd_reply, strings::*};
+use super::{common::rnd_reply, strings::*, ServerEvent};
 use crate::{
     core::{
         client::HwClient,
         server::{AccessError, CreateRoomError, HwServer, JoinRoomError},
-        types::{ClientId, ServerVar},
+        types::{ClientId, ListingChange, ServerVar},
     },
     protocol::messages::{
         add_flags, remove_flags, server_chat, HwProtocolMessage, HwServerMessage::*,
         ProtocolFlags as Flags,
     },
+    protocol::translation,
     utils::is_name_illegal,
 };
 use log::*;
@@ -27,10 +28,15 @@ pub fn handle(
             Err(CreateRoomError::InvalidName) => response.warn(ILLEGAL_ROOM_NAME),
             Err(CreateRoomError::AlreadyExists) => response.warn(ROOM_EXISTS),
             Ok((client, room)) => {
+                response.emit(ServerEvent::RoomCreated {
+                    room: room.name.clone(),
+                    master: client.nick.clone(),
+                });
                 response.add(
                     RoomAdd(room.info(Some(&client)))
                         .send_all()
-                        .with_protocol(room.protocol_number),
+                        .with_protocol(room.protocol_number)
+                        .on_listing_change(room.id, ListingChange::Added),
                 );
                 response.add(RoomJoined(vec![client.nick.clone()]).send_self());
                 response.add(
@@ -101,7 +107,7 @@ pub fn handle(
                 for protocol in protocols {
                     html.push(format!(
                         "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
-                        super::utils::protocol_version_string(protocol),
+                        translation::version_string(protocol),
                         server.protocol_client_ids(protocol).count(),
                         server.protocol_room_ids(protocol).count()
                     ));
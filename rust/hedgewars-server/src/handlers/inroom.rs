@@ -1,15 +1,18 @@
-use super::{common::rnd_reply, strings::*};
+use super::{common::rnd_reply, locale::format_message, strings::*};
 use crate::core::room::GameInfo;
 use crate::core::server::{AddTeamError, SetTeamCountError};
 use crate::{
     core::{
         room::{HwRoom, RoomFlags, MAX_TEAMS_IN_ROOM},
         server::{
-            ChangeMasterError, ChangeMasterResult, HwRoomControl, HwServer, LeaveRoomResult,
-            ModifyTeamError, StartGameError,
+            ChangeMasterError, HwRoomControl, HwServer, LeaveRoomResult, ModifyTeamError,
+            StartGameError,
         },
         types,
-        types::{ClientId, GameCfg, RoomId, VoteType, Voting, MAX_HEDGEHOGS_PER_TEAM},
+        types::{
+            ClientId, GameCfg, InvalidGameCfg, RoomId, TeamInfo, VoteType, Voting,
+            MAX_HEDGEHOGS_PER_TEAM,
+        },
     },
     protocol::messages::{
         add_flags, remove_flags, server_chat, HwProtocolMessage, HwServerMessage::*,
@@ -19,7 +22,7 @@ use crate::{
 };
 use base64::{decode, encode};
 use log::*;
-use std::{cmp::min, iter::once, mem::swap};
+use std::{cmp::min, iter::once, mem::swap, time::Instant};
 
 #[derive(Clone)]
 struct ByMsg<'a> {
@@ -31,7 +34,8 @@ impl<'a> Iterator for ByMsg<'a> {
 
     fn next(&mut self) -> Option<<Self as Iterator>::Item> {
         if let Some(size) = self.messages.get(0) {
-            let (msg, next) = self.messages.split_at(*size as usize + 1);
+            let length = min(*size as usize + 1, self.messages.len());
+            let (msg, next) = self.messages.split_at(length);
             self.messages = next;
             Some(msg)
         } else {
@@ -47,31 +51,37 @@ fn by_msg(source: &[u8]) -> ByMsg {
 const VALID_MESSAGES: &[u8] =
     b"M#+LlRrUuDdZzAaSjJ,NpPwtgfhbc12345\x80\x81\x82\x83\x84\x85\x86\x87\x88\x89\x8A";
 const NON_TIMED_MESSAGES: &[u8] = b"M#hb";
+const TURN_END_MESSAGE: u8 = b'N';
+
+const ENGINE_MSG_WARN_THRESHOLD: u8 = 3;
+const ENGINE_MSG_KICK_THRESHOLD: u8 = 6;
+
+fn max_msg_size(typ: u8) -> u8 {
+    match typ {
+        b'M' | b'h' | b'b' | b'f' | b'g' => u8::max_value(),
+        b'p' | b'P' => 11,
+        b'N' => 7,
+        b'w' | b't' | b'c' => 4,
+        _ => 3,
+    }
+}
 
-#[cfg(canhazslicepatterns)]
 fn is_msg_valid(msg: &[u8], team_indices: &[u8]) -> bool {
     match msg {
         [size, typ, body @ ..] => {
-            VALID_MESSAGES.contains(typ)
+            *size as usize == msg.len() - 1
+                && *size <= max_msg_size(*typ)
+                && VALID_MESSAGES.contains(typ)
                 && match body {
-                    [1..=MAX_HEDGEHOGS_PER_TEAM, team, ..] if *typ == b'h' => {
-                        team_indices.contains(team)
-                    }
-                    _ => *typ != b'h',
+                    [0..=3, team, ..] if *typ == b'h' => team_indices.contains(team),
+                    [0..=3, ..] | [] => *typ != b'h',
+                    _ => true,
                 }
         }
         _ => false,
     }
 }
 
-fn is_msg_valid(msg: &[u8], _team_indices: &[u8]) -> bool {
-    if let Some(typ) = msg.get(1) {
-        VALID_MESSAGES.contains(typ)
-    } else {
-        false
-    }
-}
-
 fn is_msg_empty(msg: &[u8]) -> bool {
     msg.get(1).filter(|t| **t == b'+').is_some()
 }
@@ -82,17 +92,204 @@ fn is_msg_timed(msg: &[u8]) -> bool {
         .is_some()
 }
 
+fn is_msg_turn_end(msg: &[u8]) -> bool {
+    msg.get(1).filter(|t| **t == TURN_END_MESSAGE).is_some()
+}
+
+struct EngineMessageSelection {
+    invalid_count: usize,
+    forward: String,
+    log: String,
+    sync_msg: Option<Option<String>>,
+    has_timed_msgs: bool,
+    is_turn_ended: bool,
+}
+
+fn select_engine_messages(
+    messages: ByMsg,
+    team_indices: &[u8],
+    is_turn_owner: bool,
+) -> EngineMessageSelection {
+    let valid = messages
+        .clone()
+        .filter(|m| is_msg_valid(m, team_indices) && (is_turn_owner || !is_msg_timed(m)));
+    let invalid_count = messages.count() - valid.clone().count();
+    let non_empty = valid.clone().filter(|m| !is_msg_empty(m));
+    let last_timed_msg = valid.clone().filter(|m| is_msg_timed(m)).last();
+    let sync_msg = last_timed_msg.map(|m| {
+        if is_msg_empty(m) {
+            Some(encode(m))
+        } else {
+            None
+        }
+    });
+
+    EngineMessageSelection {
+        invalid_count,
+        forward: encode(&valid.flat_map(|msg| msg).cloned().collect::<Vec<_>>()),
+        log: encode(&non_empty.flat_map(|msg| msg).cloned().collect::<Vec<_>>()),
+        sync_msg,
+        has_timed_msgs: last_timed_msg.is_some(),
+        is_turn_ended: last_timed_msg.map_or(false, is_msg_turn_end),
+    }
+}
+
+fn handle_engine_msg_violation(mut room_control: HwRoomControl, response: &mut super::Response) {
+    let client_id = room_control.client().id;
+    let violations = room_control.add_engine_msg_violation();
+    if violations >= ENGINE_MSG_KICK_THRESHOLD {
+        response.add(Kicked.send(client_id));
+        let room_id = room_control.room().id;
+        let result = room_control.leave_room();
+        super::common::get_room_leave_result(
+            room_control.server(),
+            room_id,
+            "kicked",
+            result,
+            response,
+        );
+    } else if violations == ENGINE_MSG_WARN_THRESHOLD {
+        response.add(Warning(INVALID_ENGINE_MESSAGES.to_string()).send(client_id));
+    }
+}
+
+fn handle_engine_message(
+    mut room_control: HwRoomControl,
+    response: &mut super::Response,
+    em: &str,
+) {
+    if room_control.hold_engine_msg(em) {
+        return;
+    }
+
+    let (client, room) = room_control.get();
+    let (client_id, room_id) = (client.id, room.id);
+
+    let decoding = decode(em);
+    let is_malformed = decoding.is_err();
+    let decoding = decoding.unwrap_or_default();
+    let messages = by_msg(&decoding);
+    let selection = match room.game_info {
+        Some(ref info) if info.is_turn_open() => {
+            let selection = select_engine_messages(messages, &client.team_indices, true);
+            if selection.has_timed_msgs && !room_control.claim_turn(em) {
+                return;
+            }
+            selection
+        }
+        Some(ref info) => {
+            let is_turn_owner = info.is_turn_owner(client_id);
+            select_engine_messages(messages, &client.team_indices, is_turn_owner)
+        }
+        None => select_engine_messages(messages, &client.team_indices, true),
+    };
+
+    if !selection.forward.is_empty() {
+        response.add(
+            ForwardEngineMessage(vec![selection.forward.clone()])
+                .send_all()
+                .in_live_feed(room_id)
+                .but_self(),
+        );
+    }
+
+    room_control.log_engine_msg(selection.log, selection.sync_msg);
+    if !selection.forward.is_empty() {
+        room_control.delay_engine_msgs(vec![selection.forward]);
+    }
+    if selection.is_turn_ended {
+        room_control.end_turn();
+    }
+
+    let delayed_msgs = room_control.release_delayed_msgs();
+    if !delayed_msgs.is_empty() {
+        response.add(
+            ForwardEngineMessage(delayed_msgs)
+                .send_all()
+                .in_delayed_feed(room_id),
+        );
+    }
+
+    if is_malformed || selection.invalid_count > 0 {
+        handle_engine_msg_violation(room_control, response);
+    }
+}
+
+/// Hands the turn over once its grace time is over and applies the violations of the
+/// rejected claims
+pub fn handle_turn_claims(
+    server: &mut HwServer,
+    room_id: RoomId,
+    now: Instant,
+    responses: &mut Vec<super::Response>,
+) {
+    let (resolved, rejected) = server.resolve_turn_claims(room_id, now);
+
+    if let Some((client_id, messages)) = resolved {
+        let mut response = super::Response::new(client_id);
+        for msg in messages {
+            match server.get_room_control(client_id) {
+                Some(room_control) => handle_engine_message(room_control, &mut response, &msg),
+                None => break,
+            }
+        }
+        responses.push(response);
+    }
+
+    for client_id in rejected {
+        if let Some(room_control) = server.get_room_control(client_id) {
+            if room_control.room().id == room_id {
+                let mut response = super::Response::new(client_id);
+                handle_engine_msg_violation(room_control, &mut response);
+                responses.push(response);
+            }
+        }
+    }
+}
+
+/// Sends the spectators with a delayed feed the engine messages that became due
+pub fn handle_delayed_feed(
+    server: &mut HwServer,
+    room_id: RoomId,
+    now: Instant,
+    responses: &mut Vec<super::Response>,
+) {
+    let delayed_msgs = server.release_delayed_msgs(room_id, now);
+    if !delayed_msgs.is_empty() {
+        let mut response = super::Response::for_server();
+        response.add(
+            ForwardEngineMessage(delayed_msgs)
+                .send_all()
+                .in_delayed_feed(room_id),
+        );
+        responses.push(response);
+    }
+}
+
 fn voting_description(kind: &VoteType) -> String {
-    format!(
-        "New voting started: {}",
-        match kind {
-            VoteType::Kick(nick) => format!("kick {}", nick),
-            VoteType::Map(name) => format!("map {}", name.as_ref().unwrap()),
-            VoteType::Pause => "pause".to_string(),
-            VoteType::NewSeed => "new seed".to_string(),
-            VoteType::HedgehogsPerTeam(number) => format!("hedgehogs per team: {}", number),
-        }
-    )
+    match kind {
+        VoteType::Kick(nick) => format_message(VOTING_KICK, &[nick]),
+        VoteType::Map(name) => format_message(VOTING_MAP, &[name.as_ref().unwrap()]),
+        VoteType::Pause => VOTING_PAUSE.to_string(),
+        VoteType::NewSeed => VOTING_NEW_SEED.to_string(),
+        VoteType::HedgehogsPerTeam(number) => format_message(VOTING_HEDGEHOGS, &[number]),
+    }
+}
+
+fn invalid_config_description(error: &InvalidGameCfg) -> String {
+    use InvalidGameCfg::*;
+    match error {
+        FeatureSize(size) => format_message(INVALID_FEATURE_SIZE, &[size]),
+        MapGenerator(generator) => format_message(INVALID_MAP_GENERATOR, &[generator]),
+        MazeSize(size) => format_message(INVALID_MAZE_SIZE, &[size]),
+        Template(template) => format_message(INVALID_TEMPLATE_FILTER, &[template]),
+        AmmoLength { expected, actual } => format_message(INVALID_AMMO_LENGTH, &[expected, actual]),
+        SchemeLength { expected, actual } => {
+            format_message(INVALID_SCHEME_LENGTH, &[expected, actual])
+        }
+        DrawnMapTooLarge(length) => format_message(INVALID_DRAWN_MAP_SIZE, &[length]),
+        UnknownLayout(protocol) => format_message(UNKNOWN_CONFIG_LAYOUT, &[protocol]),
+    }
 }
 
 fn room_message_flag(msg: &HwProtocolMessage) -> RoomFlags {
@@ -101,10 +298,50 @@ fn room_message_flag(msg: &HwProtocolMessage) -> RoomFlags {
         ToggleRestrictJoin => RoomFlags::RESTRICTED_JOIN,
         ToggleRestrictTeams => RoomFlags::RESTRICTED_TEAM_ADD,
         ToggleRegisteredOnly => RoomFlags::REGISTRATION_REQUIRED,
+        ToggleHidden => RoomFlags::HIDDEN,
         _ => RoomFlags::empty(),
     }
 }
 
+pub fn add_team(
+    room_control: &mut HwRoomControl,
+    response: &mut super::Response,
+    info: Box<TeamInfo>,
+) {
+    let room_id = room_control.room().id;
+    match room_control.add_team(info) {
+        Ok(team) => {
+            response.add(TeamAccepted(team.name.clone()).send_self());
+            response.add(
+                TeamAdd(team.to_protocol())
+                    .send_all()
+                    .in_room(room_id)
+                    .but_self(),
+            );
+            response.add(
+                TeamColor(team.name.clone(), team.color)
+                    .send_all()
+                    .in_room(room_id),
+            );
+            response.add(
+                HedgehogsNumber(team.name.clone(), team.hedgehogs_number)
+                    .send_all()
+                    .in_room(room_id),
+            );
+
+            let room = room_control.room();
+            let room_master = room.master_id.map(|id| room_control.server().client(id));
+            super::common::get_room_update(None, room, room_master, response);
+        }
+        Err(AddTeamError::TooManyTeams) => response.warn(TOO_MANY_TEAMS),
+        Err(AddTeamError::TooManyHedgehogs) => response.warn(TOO_MANY_HEDGEHOGS),
+        Err(AddTeamError::TeamAlreadyExists) => response.warn(TEAM_EXISTS),
+        Err(AddTeamError::TeamNameReserved) => response.warn(TEAM_NAME_RESERVED),
+        Err(AddTeamError::GameInProgress) => response.warn(ROUND_IN_PROGRESS),
+        Err(AddTeamError::Restricted) => response.warn(TEAM_ADD_RESTRICTED),
+    }
+}
+
 pub fn handle(
     mut room_control: HwRoomControl,
     response: &mut super::Response,
@@ -124,7 +361,7 @@ pub fn handle(
             let result = room_control.leave_room();
             super::common::get_room_leave_result(
                 room_control.server(),
-                room_control.room(),
+                room_id,
                 &msg,
                 result,
                 response,
@@ -175,6 +412,46 @@ pub fn handle(
                 }
             };
         }
+        SpectatorDelay(delay) => {
+            use crate::core::server::SetSpectatorDelayError;
+            match room_control.set_spectator_delay(delay) {
+                Ok(()) => response.add(
+                    server_chat(format_message(SPECTATOR_DELAY_SET, &[&delay]))
+                        .send_all()
+                        .in_room(room_id),
+                ),
+                Err(SetSpectatorDelayError::NotMaster) => response.warn(NOT_MASTER),
+                Err(SetSpectatorDelayError::InvalidNumber) => {
+                    response.warn(SPECTATOR_DELAY_INVALID)
+                }
+            }
+        }
+        Invite(minutes) => {
+            use crate::core::server::CreateInviteError;
+            match room_control.create_invite(minutes) {
+                Ok(code) => {
+                    let msg = match minutes {
+                        None => format_message(INVITE_CODE, &[&code]),
+                        Some(m) => format_message(INVITE_CODE_TIMED, &[&m, &code]),
+                    };
+                    response.add(server_chat(msg).send_self())
+                }
+                Err(CreateInviteError::NotMaster) => response.warn(NOT_MASTER),
+                Err(CreateInviteError::InvalidLifetime) => response.warn(INVITE_LIFETIME_INVALID),
+            }
+        }
+        AllowNick(nick) => match room_control.allow_nick(&nick) {
+            Ok(()) => response.add(server_chat(NICK_ALLOWED.to_string()).send_self()),
+            Err(_) => response.warn(NOT_MASTER),
+        },
+        DisallowNick(nick) => {
+            use crate::core::server::ModifyAllowListError;
+            match room_control.disallow_nick(&nick) {
+                Ok(()) => response.add(server_chat(NICK_DISALLOWED.to_string()).send_self()),
+                Err(ModifyAllowListError::NotMaster) => response.warn(NOT_MASTER),
+                Err(ModifyAllowListError::NotListed) => response.warn(NICK_NOT_ALLOWED),
+            }
+        }
         RoomName(new_name) => {
             use crate::core::server::ModifyRoomNameError;
             match room_control.set_room_name(new_name) {
@@ -195,12 +472,11 @@ pub fn handle(
             };
             let (client, room) = room_control.get();
 
-            let msg = if client.protocol_number < 38 {
-                LegacyReady(client.is_ready(), vec![client.nick.clone()])
-            } else {
+            response.add(
                 ClientFlags(flags, vec![client.nick.clone()])
-            };
-            response.add(msg.send_all().in_room(room_id));
+                    .send_all()
+                    .in_room(room_id),
+            );
 
             if room.is_fixed() && room.ready_players_number == room.players_number {
                 let result = room_control.start_game();
@@ -212,37 +488,24 @@ pub fn handle(
                 );
             }
         }
-        AddTeam(info) => {
-            use crate::core::server::AddTeamError;
-            match room_control.add_team(info) {
-                Ok(team) => {
-                    response.add(TeamAccepted(team.name.clone()).send_self());
-                    response.add(
-                        TeamAdd(team.to_protocol())
-                            .send_all()
-                            .in_room(room_id)
-                            .but_self(),
-                    );
-                    response.add(
-                        TeamColor(team.name.clone(), team.color)
-                            .send_all()
-                            .in_room(room_id),
-                    );
-                    response.add(
-                        HedgehogsNumber(team.name.clone(), team.hedgehogs_number)
-                            .send_all()
-                            .in_room(room_id),
-                    );
+        AddTeam(info) => add_team(&mut room_control, response, info),
+        AddStoredTeam(name) => {
+            if !client.is_registered() {
+                response.warn(TEAM_STORAGE_REGISTERED_ONLY);
+            } else {
+                #[cfg(feature = "official-server")]
+                {
+                    response.request_io(super::IoTask::GetStoredTeam {
+                        room_id,
+                        owner: client.nick.clone(),
+                        name,
+                    })
+                }
 
-                    let room = room_control.room();
-                    let room_master = room.master_id.map(|id| room_control.server().client(id));
-                    super::common::get_room_update(None, room, room_master, response);
+                #[cfg(not(feature = "official-server"))]
+                {
+                    response.warn(TEAM_STORAGE_NOT_SUPPORTED);
                 }
-                Err(AddTeamError::TooManyTeams) => response.warn(TOO_MANY_TEAMS),
-                Err(AddTeamError::TooManyHedgehogs) => response.warn(TOO_MANY_HEDGEHOGS),
-                Err(AddTeamError::TeamAlreadyExists) => response.warn(TEAM_EXISTS),
-                Err(AddTeamError::GameInProgress) => response.warn(ROUND_IN_PROGRESS),
-                Err(AddTeamError::Restricted) => response.warn(TEAM_ADD_RESTRICTED),
             }
         }
         RemoveTeam(name) => {
@@ -311,11 +574,14 @@ pub fn handle(
                 }
                 Err(SetConfigError::NotMaster) => response.error(NOT_MASTER),
                 Err(SetConfigError::RoomFixed) => response.warn(ACCESS_DENIED),
+                Err(SetConfigError::InvalidConfig(e)) => {
+                    response.warn(&invalid_config_description(&e))
+                }
             }
         }
         Save(name, location) => {
             response.add(
-                server_chat(format!("Room config saved as {}", name))
+                server_chat(format_message(ROOM_CONFIG_SAVED_AS, &[&name]))
                     .send_all()
                     .in_room(room_id),
             );
@@ -345,10 +611,10 @@ pub fn handle(
         }
         Delete(name) => {
             if !room_control.delete_config(&name) {
-                response.add(Warning(format!("Save doesn't exist: {}", name)).send_self());
+                response.add(Warning(format_message(NO_SAVE, &[&name])).send_self());
             } else {
                 response.add(
-                    server_chat(format!("Room config {} has been deleted", name))
+                    server_chat(format_message(ROOM_CONFIG_DELETED, &[&name]))
                         .send_all()
                         .in_room(room_id),
                 );
@@ -401,7 +667,7 @@ pub fn handle(
             });
             super::common::handle_vote(room_control, vote_result, response);
         }
-        ToggleRestrictJoin | ToggleRestrictTeams | ToggleRegisteredOnly => {
+        ToggleRestrictJoin | ToggleRestrictTeams | ToggleRegisteredOnly | ToggleHidden => {
             if room_control.toggle_flag(room_message_flag(&message)) {
                 let (client, room) = room_control.get();
                 super::common::get_room_update(None, room, Some(&client), response);
@@ -413,30 +679,7 @@ pub fn handle(
         }
         EngineMessage(em) => {
             if client.teams_in_game > 0 {
-                let decoding = decode(&em[..]).unwrap();
-                let messages = by_msg(&decoding);
-                let valid = messages.filter(|m| is_msg_valid(m, &client.team_indices));
-                let non_empty = valid.clone().filter(|m| !is_msg_empty(m));
-                let sync_msg = valid.clone().filter(|m| is_msg_timed(m)).last().map(|m| {
-                    if is_msg_empty(m) {
-                        Some(encode(m))
-                    } else {
-                        None
-                    }
-                });
-
-                let em_response = encode(&valid.flat_map(|msg| msg).cloned().collect::<Vec<_>>());
-                if !em_response.is_empty() {
-                    response.add(
-                        ForwardEngineMessage(vec![em_response])
-                            .send_all()
-                            .in_room(room.id)
-                            .but_self(),
-                    );
-                }
-                let em_log = encode(&non_empty.flat_map(|msg| msg).cloned().collect::<Vec<_>>());
-
-                room_control.log_engine_msg(em_log, sync_msg);
+                handle_engine_message(room_control, response, &em);
             }
         }
         RoundFinished => {
@@ -453,7 +696,7 @@ pub fn handle(
                     response.add(
                         ForwardEngineMessage(vec![to_engine_msg(msg)])
                             .send_all()
-                            .in_room(room_id)
+                            .in_live_feed(room_id)
                             .but_self(),
                     );
                 }
@@ -482,29 +725,12 @@ pub fn handle(
             response.add(result.send_all().in_room(room_id));
         }
         Delegate(nick) => match room_control.change_master(nick) {
-            Ok(ChangeMasterResult {
-                old_master_id,
-                new_master_id,
-            }) => {
-                if let Some(master_id) = old_master_id {
-                    response.add(
-                        ClientFlags(
-                            remove_flags(&[Flags::RoomMaster]),
-                            vec![room_control.server().client(master_id).nick.clone()],
-                        )
-                        .send_all()
-                        .in_room(room_id),
-                    );
-                }
-                response.add(
-                    ClientFlags(
-                        add_flags(&[Flags::RoomMaster]),
-                        vec![room_control.server().client(new_master_id).nick.clone()],
-                    )
-                    .send_all()
-                    .in_room(room_id),
-                );
-            }
+            Ok(result) => super::common::get_change_master_data(
+                room_control.server(),
+                room_id,
+                result,
+                response,
+            ),
             Err(ChangeMasterError::NoAccess) => {
                 response.warn("You're not the room master or a server admin!")
             }
@@ -519,3 +745,372 @@ pub fn handle(
         _ => warn!("Unimplemented!"),
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{by_msg, format_message, is_msg_valid};
+    use crate::{
+        core::{
+            anteroom::HwAnteroomClient,
+            room::{MAX_SPECTATOR_DELAY, TURN_CLAIM_GRACE},
+            types::{ClientId, HedgehogInfo, TeamInfo},
+        },
+        handlers::{
+            actions::{Destination, DestinationGroup},
+            handle, handle_running_games,
+            strings::*,
+            Response, ServerState,
+        },
+        protocol::messages::HwProtocolMessage::{self, *},
+    };
+    use base64::encode;
+    use std::{
+        num::NonZeroU16,
+        time::{Duration, Instant},
+    };
+
+    const MASTER: ClientId = 0;
+    const PLAYER: ClientId = 1;
+    const SPECTATOR: ClientId = 2;
+
+    fn add_client(state: &mut ServerState, client_id: ClientId, nick: &str) {
+        state.server.add_client(
+            client_id,
+            HwAnteroomClient {
+                nick: Some(nick.to_string()),
+                protocol_number: NonZeroU16::new(60),
+                server_salt: String::new(),
+                is_checker: false,
+                is_local_admin: false,
+                is_registered: false,
+                is_admin: false,
+                is_contributor: false,
+                is_secure: false,
+            },
+        );
+    }
+
+    fn team(name: &str, color: u8) -> Box<TeamInfo> {
+        let hedgehog = || HedgehogInfo {
+            name: "hog".to_string(),
+            hat: "NoHat".to_string(),
+        };
+        Box::new(TeamInfo {
+            owner: String::new(),
+            name: name.to_string(),
+            color,
+            grave: "Statue".to_string(),
+            fort: "Plane".to_string(),
+            voice_pack: "Default".to_string(),
+            flag: "hedgewars".to_string(),
+            difficulty: 0,
+            hedgehogs_number: 4,
+            hedgehogs: [
+                hedgehog(),
+                hedgehog(),
+                hedgehog(),
+                hedgehog(),
+                hedgehog(),
+                hedgehog(),
+                hedgehog(),
+                hedgehog(),
+            ],
+        })
+    }
+
+    fn start_game() -> ServerState {
+        start_delayed_game(0)
+    }
+
+    fn start_delayed_game(spectator_delay: u32) -> ServerState {
+        let mut state = ServerState::new(10, 10, None);
+        add_client(&mut state, MASTER, "master");
+        add_client(&mut state, PLAYER, "player");
+        add_client(&mut state, SPECTATOR, "spectator");
+        let room_id = state
+            .server
+            .create_room(MASTER, "room".to_string(), None)
+            .map(|(_, room)| room.id)
+            .unwrap();
+        assert!(state.server.join_room(PLAYER, room_id, None).is_ok());
+        assert!(state.server.join_room(SPECTATOR, room_id, None).is_ok());
+
+        let mut room_control = state.server.get_room_control(MASTER).unwrap();
+        assert!(room_control.add_team(team("first", 0)).is_ok());
+        assert!(room_control.set_spectator_delay(spectator_delay).is_ok());
+        let mut room_control = state.server.get_room_control(PLAYER).unwrap();
+        assert!(room_control.add_team(team("second", 1)).is_ok());
+        assert!(room_control.toggle_ready());
+        let mut room_control = state.server.get_room_control(MASTER).unwrap();
+        assert!(room_control.start_game().is_ok());
+        state
+    }
+
+    fn send(
+        state: &mut ServerState,
+        client_id: ClientId,
+        message: HwProtocolMessage,
+    ) -> Vec<String> {
+        let mut response = Response::new(client_id);
+        handle(state, client_id, &mut response, message);
+        response
+            .messages
+            .drain(..)
+            .map(|m| m.message.to_raw_protocol())
+            .collect()
+    }
+
+    fn run_games(state: &mut ServerState, now: Instant) -> Vec<(ClientId, String)> {
+        handle_running_games(state, now)
+            .into_iter()
+            .flat_map(|mut response| {
+                let client_id = response.client_id();
+                response
+                    .messages
+                    .drain(..)
+                    .map(|m| (client_id, m.message.to_raw_protocol()))
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    fn delayed_feed(state: &mut ServerState, now: Instant) -> Vec<String> {
+        let room_id = state.server.client(MASTER).room_id.unwrap();
+        handle_running_games(state, now)
+            .into_iter()
+            .flat_map(|mut response| response.messages.drain(..).collect::<Vec<_>>())
+            .filter(|m| match m.destination {
+                Destination::ToAll {
+                    group: DestinationGroup::DelayedFeed(id),
+                    ..
+                } => id == room_id,
+                _ => false,
+            })
+            .map(|m| m.message.to_raw_protocol())
+            .collect()
+    }
+
+    fn violations(state: &ServerState, client_id: ClientId) -> u8 {
+        state.server.client(client_id).engine_msg_violations
+    }
+
+    fn engine_msg(msg: &[u8]) -> HwProtocolMessage {
+        EngineMessage(encode(msg))
+    }
+
+    fn forwarded(msg: &[u8]) -> String {
+        format!("EM\n{}\n\n", encode(msg))
+    }
+
+    fn msg_log(state: &ServerState) -> Vec<String> {
+        let room_id = state.server.client(MASTER).room_id.unwrap();
+        state
+            .server
+            .room(room_id)
+            .game_info
+            .as_ref()
+            .unwrap()
+            .msg_log
+            .clone()
+    }
+
+    #[test]
+    fn engine_msg_client_order() {
+        let mut state = start_game();
+
+        assert_eq!(
+            send(&mut state, MASTER, engine_msg(b"\x01L")),
+            vec![forwarded(b"\x01L")]
+        );
+        assert_eq!(
+            send(&mut state, MASTER, engine_msg(b"\x01R")),
+            vec![forwarded(b"\x01R")]
+        );
+        assert_eq!(msg_log(&state), vec![encode(b"\x01L"), encode(b"\x01R")]);
+    }
+
+    #[test]
+    fn engine_msg_before_round_finished() {
+        let mut state = start_game();
+
+        send(&mut state, MASTER, engine_msg(b"\x01L"));
+        let messages = send(&mut state, PLAYER, RoundFinished);
+        assert!(messages.contains(&forwarded(b"\x07Fsecond")));
+        send(&mut state, MASTER, engine_msg(b"\x01R"));
+
+        assert_eq!(
+            msg_log(&state),
+            vec![encode(b"\x01L"), encode(b"\x07Fsecond"), encode(b"\x01R")]
+        );
+    }
+
+    #[test]
+    fn engine_msg_before_part() {
+        let mut state = start_game();
+
+        send(&mut state, MASTER, engine_msg(b"\x01L"));
+        let messages = send(&mut state, PLAYER, Part(None));
+        assert!(!messages.iter().any(|m| m.starts_with("EM\n")));
+        assert_eq!(
+            send(&mut state, MASTER, engine_msg(b"\x01R")),
+            vec![forwarded(b"\x01R")]
+        );
+
+        assert_eq!(msg_log(&state), vec![encode(b"\x01L"), encode(b"\x01R")]);
+    }
+
+    #[test]
+    fn engine_msg_validation() {
+        let team_indices = [1];
+
+        assert!(is_msg_valid(b"\x03+\x00\x01", &team_indices));
+        assert!(is_msg_valid(b"\x06h\x01\x01hi\x00", &team_indices));
+        assert!(!is_msg_valid(b"\x06h\x01\x02hi\x00", &team_indices));
+        assert!(!is_msg_valid(b"\x04L\x00\x01\x02", &team_indices));
+        assert!(!is_msg_valid(b"\x03Q\x00\x01", &team_indices));
+        assert!(!is_msg_valid(b"\x05+\x00", &team_indices));
+    }
+
+    #[test]
+    fn truncated_engine_msg() {
+        let messages: Vec<_> = by_msg(b"\x03+\x00\x01\x05L\x00").collect();
+        assert_eq!(messages, vec![&b"\x03+\x00\x01"[..], &b"\x05L\x00"[..]]);
+    }
+
+    #[test]
+    fn turn_rotation() {
+        let mut state = start_game();
+
+        assert!(send(&mut state, PLAYER, engine_msg(b"\x01L")).is_empty());
+        send(&mut state, MASTER, engine_msg(b"\x01L"));
+        send(&mut state, MASTER, engine_msg(b"\x01N"));
+        assert_eq!(
+            send(&mut state, PLAYER, engine_msg(b"\x01R")),
+            vec![forwarded(b"\x01R")]
+        );
+        assert!(send(&mut state, MASTER, engine_msg(b"\x01R")).is_empty());
+        assert_eq!(violations(&state, MASTER), 1);
+
+        // the claim held at the start was rejected when the master took the turn
+        assert!(run_games(&mut state, Instant::now()).is_empty());
+        assert_eq!(violations(&state, PLAYER), 1);
+        assert_eq!(
+            msg_log(&state),
+            vec![encode(b"\x01L"), encode(b"\x01N"), encode(b"\x01R")]
+        );
+    }
+
+    #[test]
+    fn held_turn_claim() {
+        let mut state = start_game();
+        let now = Instant::now();
+
+        // the team of the master is eliminated, so only the player claims the turn
+        assert!(send(&mut state, PLAYER, engine_msg(b"\x01L")).is_empty());
+        assert!(send(&mut state, PLAYER, engine_msg(b"\x01R")).is_empty());
+        assert!(run_games(&mut state, now).is_empty());
+        assert!(msg_log(&state).is_empty());
+
+        assert_eq!(
+            run_games(&mut state, now + TURN_CLAIM_GRACE),
+            vec![(PLAYER, forwarded(b"\x01L")), (PLAYER, forwarded(b"\x01R"))]
+        );
+        assert_eq!(msg_log(&state), vec![encode(b"\x01L"), encode(b"\x01R")]);
+        assert_eq!(violations(&state, PLAYER), 0);
+
+        assert!(send(&mut state, MASTER, engine_msg(b"\x01L")).is_empty());
+        assert_eq!(violations(&state, MASTER), 1);
+    }
+
+    #[test]
+    fn violations_reset_per_game() {
+        let mut state = start_game();
+
+        send(&mut state, MASTER, engine_msg(b"\x01L"));
+        send(&mut state, PLAYER, engine_msg(b"\x01L"));
+        assert_eq!(violations(&state, PLAYER), 1);
+
+        let mut room_control = state.server.get_room_control(MASTER).unwrap();
+        assert!(room_control.end_game().is_some());
+        let mut room_control = state.server.get_room_control(PLAYER).unwrap();
+        assert!(room_control.toggle_ready());
+        let mut room_control = state.server.get_room_control(MASTER).unwrap();
+        assert!(room_control.start_game().is_ok());
+        assert_eq!(violations(&state, PLAYER), 0);
+    }
+
+    #[test]
+    fn delayed_feed_timing() {
+        let mut state = start_delayed_game(10);
+
+        assert_eq!(
+            send(&mut state, MASTER, engine_msg(b"\x01L")),
+            vec![forwarded(b"\x01L")]
+        );
+        let sent = Instant::now();
+        assert!(delayed_feed(&mut state, sent + Duration::from_secs(9)).is_empty());
+        assert_eq!(
+            delayed_feed(&mut state, sent + Duration::from_secs(10)),
+            vec![forwarded(b"\x01L")]
+        );
+        assert!(delayed_feed(&mut state, sent + Duration::from_secs(20)).is_empty());
+
+        let room_id = state.server.client(MASTER).room_id.unwrap();
+        let info = state.server.room(room_id).game_info.as_ref().unwrap();
+        assert!(info.is_feed_delayed_for(SPECTATOR));
+        assert!(!info.is_feed_delayed_for(PLAYER));
+        assert_eq!(info.client_msg_log(SPECTATOR), &[encode(b"\x01L")][..]);
+    }
+
+    #[test]
+    fn delayed_feed_order() {
+        let mut state = start_delayed_game(5);
+
+        send(&mut state, MASTER, engine_msg(b"\x01L"));
+        send(&mut state, MASTER, engine_msg(b"\x01R"));
+        let sent = Instant::now();
+        send(&mut state, PLAYER, RoundFinished);
+
+        let room_id = state.server.client(MASTER).room_id.unwrap();
+        let info = state.server.room(room_id).game_info.as_ref().unwrap();
+        assert!(info.client_msg_log(SPECTATOR).is_empty());
+        assert_eq!(
+            delayed_feed(&mut state, sent + Duration::from_secs(5)),
+            vec![format!(
+                "EM\n{}\n{}\n\n",
+                encode(b"\x01L"),
+                encode(b"\x01R")
+            )]
+        );
+        assert_eq!(
+            delayed_feed(&mut state, Instant::now() + Duration::from_secs(5)),
+            vec![forwarded(b"\x07Fsecond")]
+        );
+    }
+
+    #[test]
+    fn spectator_delay_limit() {
+        let mut state = start_game();
+        let room_id = state.server.client(MASTER).room_id.unwrap();
+
+        assert_eq!(
+            send(&mut state, MASTER, SpectatorDelay(MAX_SPECTATOR_DELAY)),
+            vec![format!(
+                "CHAT\n[server]\n{}\n\n",
+                format_message(SPECTATOR_DELAY_SET, &[&MAX_SPECTATOR_DELAY])
+            )]
+        );
+        assert_eq!(
+            send(&mut state, MASTER, SpectatorDelay(MAX_SPECTATOR_DELAY + 1)),
+            vec![format!("WARNING\n{}\n\n", SPECTATOR_DELAY_INVALID)]
+        );
+        assert_eq!(
+            send(&mut state, PLAYER, SpectatorDelay(1)),
+            vec![format!("WARNING\n{}\n\n", NOT_MASTER)]
+        );
+        assert_eq!(
+            state.server.room(room_id).spectator_delay,
+            MAX_SPECTATOR_DELAY
+        );
+    }
+}
@@ -0,0 +1,255 @@
+use super::ClientId;
+use crate::{core::server::HwServer, protocol::messages::HwServerMessage};
+use log::*;
+use std::{borrow::Cow, collections::HashMap, fmt::Display};
+
+const CATALOGS: &[(&str, &str)] = &[
+    ("de", include_str!("../../locale/de.yaml")),
+    ("fi", include_str!("../../locale/fi.yaml")),
+    ("fr", include_str!("../../locale/fr.yaml")),
+    ("gd", include_str!("../../locale/gd.yaml")),
+    ("hu", include_str!("../../locale/hu.yaml")),
+    ("it", include_str!("../../locale/it.yaml")),
+    ("ja", include_str!("../../locale/ja.yaml")),
+    ("pl", include_str!("../../locale/pl.yaml")),
+    ("ru", include_str!("../../locale/ru.yaml")),
+    ("tr_TR", include_str!("../../locale/tr_TR.yaml")),
+    ("uk", include_str!("../../locale/uk.yaml")),
+    ("zh_TW", include_str!("../../locale/zh_TW.yaml")),
+];
+
+const SERVER_NICK: &str = "[server]";
+const PLACEHOLDER: &str = "{}";
+
+/// Substitutes the placeholders of a message template from strings.rs with the arguments
+/// in order, the translator recognizes the formatted message by its template
+pub fn format_message(template: &str, args: &[&dyn Display]) -> String {
+    let mut parts = template.split(PLACEHOLDER);
+    let mut result = parts.next().unwrap_or_default().to_string();
+    for (index, part) in parts.enumerate() {
+        if let Some(arg) = args.get(index) {
+            result.push_str(&arg.to_string());
+        }
+        result.push_str(part);
+    }
+    result
+}
+
+/// Extracts the arguments of the text if it was formatted from the template
+fn match_template<'a>(template: &str, text: &'a str) -> Option<Vec<&'a str>> {
+    let mut parts = template.split(PLACEHOLDER);
+    let mut rest = text.strip_prefix(parts.next()?)?;
+    let mut parts = parts.peekable();
+    let mut args = vec![];
+
+    while let Some(part) = parts.next() {
+        let arg_length = if parts.peek().is_none() {
+            rest.strip_suffix(part)?.len()
+        } else if part.is_empty() {
+            return None;
+        } else {
+            rest.find(part)?
+        };
+        args.push(&rest[..arg_length]);
+        rest = &rest[arg_length + part.len()..];
+    }
+
+    Some(args).filter(|args| !args.is_empty())
+}
+
+#[derive(Default)]
+struct Catalog {
+    messages: HashMap<String, String>,
+    templates: Vec<(String, String)>,
+}
+
+impl Catalog {
+    fn new(entries: HashMap<String, String>) -> Self {
+        let mut catalog = Self::default();
+        for (source, translation) in entries {
+            if source.contains(PLACEHOLDER) {
+                catalog.templates.push((source, translation));
+            } else {
+                catalog.messages.insert(source, translation);
+            }
+        }
+        catalog
+    }
+
+    fn translate<'a>(&'a self, text: &'a str) -> Cow<'a, str> {
+        if let Some(translation) = self.messages.get(text) {
+            return Cow::Borrowed(translation);
+        }
+
+        self.templates
+            .iter()
+            .find_map(|(template, translation)| {
+                let args = match_template(template, text)?;
+                let args: Vec<_> = args.iter().map(|a| a as &dyn Display).collect();
+                Some(Cow::Owned(format_message(translation, &args)))
+            })
+            .unwrap_or(Cow::Borrowed(text))
+    }
+}
+
+pub struct Localization {
+    catalogs: HashMap<&'static str, Catalog>,
+}
+
+impl Localization {
+    pub fn new() -> Self {
+        let mut catalogs = HashMap::with_capacity(CATALOGS.len());
+        for (language, contents) in CATALOGS {
+            match serde_yaml::from_str(contents) {
+                Ok(entries) => {
+                    catalogs.insert(*language, Catalog::new(entries));
+                }
+                Err(e) => warn!("Unable to parse the {} string catalog: {}", language, e),
+            }
+        }
+        Self { catalogs }
+    }
+
+    pub fn find_language(&self, language: &str) -> Option<&'static str> {
+        let language = language.replace('-', "_");
+        let base_language = language.split('_').next().unwrap_or("");
+        [language.as_str(), base_language]
+            .iter()
+            .filter_map(|l| self.catalogs.keys().find(|k| k.eq_ignore_ascii_case(l)))
+            .next()
+            .cloned()
+    }
+
+    pub fn translate<'a>(&'a self, language: &str, text: &'a str) -> Cow<'a, str> {
+        match self.catalogs.get(language) {
+            Some(catalog) => catalog.translate(text),
+            None => Cow::Borrowed(text),
+        }
+    }
+
+    fn localize_message(&self, language: &str, message: &HwServerMessage) -> HwServerMessage {
+        use HwServerMessage::*;
+        match message {
+            Warning(msg) => Warning(self.translate(language, msg).to_string()),
+            Error(msg) => Error(self.translate(language, msg).to_string()),
+            Bye(msg) => Bye(self.translate(language, msg).to_string()),
+            ServerMessage(msg) => ServerMessage(self.translate(language, msg).to_string()),
+            ChatMsg { nick, msg } => ChatMsg {
+                nick: nick.clone(),
+                msg: self.translate(language, msg).to_string(),
+            },
+            _ => message.clone(),
+        }
+    }
+
+    pub fn localize(
+        &self,
+        server: &HwServer,
+        recipients: Vec<ClientId>,
+        message: HwServerMessage,
+    ) -> Vec<(Vec<ClientId>, HwServerMessage)> {
+        use HwServerMessage::*;
+        let is_localizable = match &message {
+            Warning(_) | Error(_) | Bye(_) | ServerMessage(_) => true,
+            ChatMsg { nick, .. } => nick == SERVER_NICK,
+            _ => false,
+        };
+
+        if !is_localizable {
+            return vec![(recipients, message)];
+        }
+
+        let mut default_recipients = vec![];
+        let mut groups: Vec<(&'static str, Vec<ClientId>)> = vec![];
+
+        for id in recipients {
+            let language = if server.has_client(id) {
+                server.client(id).language
+            } else {
+                None
+            };
+            match language {
+                Some(language) => match groups.iter_mut().find(|(l, _)| *l == language) {
+                    Some((_, ids)) => ids.push(id),
+                    None => groups.push((language, vec![id])),
+                },
+                None => default_recipients.push(id),
+            }
+        }
+
+        let mut result: Vec<_> = groups
+            .into_iter()
+            .map(|(language, ids)| (ids, self.localize_message(language, &message)))
+            .collect();
+        if !default_recipients.is_empty() {
+            result.push((default_recipients, message));
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn catalogs_parse() {
+        let localization = Localization::new();
+        assert_eq!(localization.catalogs.len(), CATALOGS.len());
+        assert_eq!(localization.find_language("de-DE"), Some("de"));
+        assert_eq!(localization.find_language("tr_tr"), Some("tr_TR"));
+        assert_eq!(localization.find_language("xx"), None);
+        assert_eq!(
+            localization.translate("de", "Access denied."),
+            "Zutritt verweigert."
+        );
+        assert_eq!(localization.translate("de", "Unknown"), "Unknown");
+    }
+
+    #[test]
+    fn formatted_messages() {
+        use crate::handlers::strings::{INVITE_CODE_TIMED, VOTING_KICK};
+
+        let message = format_message(INVITE_CODE_TIMED, &[&5, &"abc"]);
+        assert_eq!(message, "Invite code valid for 5 minutes: abc");
+        assert_eq!(
+            match_template(INVITE_CODE_TIMED, &message),
+            Some(vec!["5", "abc"])
+        );
+        assert_eq!(match_template(INVITE_CODE_TIMED, "Invite code"), None);
+
+        let localization = Localization::new();
+        assert_eq!(
+            localization.translate("de", &format_message(VOTING_KICK, &[&"Bob"])),
+            "Neue Abstimmung gestartet: hinauswerfen Bob"
+        );
+        assert_eq!(
+            localization.translate("xx", &format_message(VOTING_KICK, &[&"Bob"])),
+            "New voting started: kick Bob"
+        );
+    }
+
+    #[test]
+    fn catalogs_only_contain_sent_strings() {
+        let sources = [
+            include_str!("../handlers.rs"),
+            include_str!("common.rs"),
+            include_str!("inanteroom.rs"),
+            include_str!("inlobby.rs"),
+            include_str!("inroom.rs"),
+            include_str!("strings.rs"),
+        ];
+        for (language, contents) in CATALOGS {
+            let entries: HashMap<String, String> = serde_yaml::from_str(contents).unwrap();
+            for source in entries.keys() {
+                let literal = format!("{:?}", source);
+                assert!(
+                    sources.iter().any(|s| s.contains(&literal)),
+                    "The {} catalog contains {}, which is never sent",
+                    language,
+                    literal
+                );
+            }
+        }
+    }
+}
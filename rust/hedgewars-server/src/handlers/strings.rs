@@ -1,13 +1,34 @@
 pub const ACCESS_DENIED: &str = "Access denied.";
 pub const AUTHENTICATION_FAILED: &str = "Authentication failed";
+pub const AVAILABLE_MAPS: &str = "Available maps: {}";
 pub const BAD_NUMBER: &str = "Bad number.";
+pub const CONNECTION_SECURE: &str = "The connection is already secure.";
+pub const IDLE_MASTER_REPLACED: &str = "The room master was idle for too long.";
+pub const IDLE_SPECTATOR_KICKED: &str = "Idle spectator";
 pub const ILLEGAL_CLIENT_NAME: &str = "Illegal nickname! Nicknames must be between 1-40 characters long, must not have a trailing or leading space and must not have any of these characters: $()*+?[]^{|}";
 pub const ILLEGAL_ROOM_NAME: &str = "Illegal room name! A room name must be between 1-40 characters long, must not have a trailing or leading space and must not have any of these characters: $()*+?[]^{|}";
+pub const INVALID_AMMO_LENGTH: &str = "Invalid ammo settings: expected {} characters, got {}";
+pub const INVALID_DRAWN_MAP_SIZE: &str = "Drawn map is too large: {} bytes";
+pub const INVALID_FEATURE_SIZE: &str = "Invalid feature size: {}";
+pub const INVALID_MAP_GENERATOR: &str = "Unknown map generator: {}";
+pub const INVALID_MAZE_SIZE: &str = "Invalid maze size: {}";
+pub const INVALID_SCHEME_LENGTH: &str = "Invalid scheme: expected {} settings, got {}";
+pub const INVALID_TEMPLATE_FILTER: &str = "Invalid template filter: {}";
+pub const INVITE_CODE: &str = "Invite code for a single use: {}";
+pub const INVITE_CODE_TIMED: &str = "Invite code valid for {} minutes: {}";
+pub const INVITE_LIFETIME_INVALID: &str = "/invite: specify number of minutes from 1 to 1440";
+pub const INVALID_ENGINE_MESSAGES: &str =
+    "Warning! Invalid game messages detected, you will be kicked if this continues.";
+pub const NICK_ALLOWED: &str = "Player added to the room allow list.";
+pub const NICK_DISALLOWED: &str = "Player removed from the room allow list.";
+pub const NICK_NOT_ALLOWED: &str = "This player is not in the room allow list.";
 pub const NICKNAME_PROVIDED: &str = "Nickname already provided.";
 pub const NO_CHECKER_RIGHTS: &str = "No checker rights";
 pub const NO_ROOM: &str = "No such room.";
 pub const NO_TEAM: &str = "No such team.";
 pub const NO_TEAM_TO_REMOVE: &str = "Error: The team you tried to remove does not exist.";
+pub const NO_SAVE: &str = "Save doesn't exist: {}";
+pub const NO_STORED_TEAM: &str = "No such stored team.";
 pub const NO_USER: &str = "No such user.";
 pub const NOT_MASTER: &str = "You're not the room master!";
 pub const PROTOCOL_PROVIDED: &str = "Protocol already known.";
@@ -21,7 +42,9 @@ pub const REGISTERED_ONLY_DISABLED: &str = "This server now allows unregistered
 pub const ROOM_CONFIG_SAVE_FAILED: &str = "Unable to save the room configs.";
 pub const ROOM_CONFIG_LOAD_FAILED: &str = "Unable to load the room configs.";
 pub const ROOM_CONFIG_DESERIALIZE_FAILED: &str = "Unable to deserialize the room configs.";
+pub const ROOM_CONFIG_DELETED: &str = "Room config {} has been deleted";
 pub const ROOM_CONFIG_LOADED: &str = "Room configs loaded successfully.";
+pub const ROOM_CONFIG_SAVED_AS: &str = "Room config saved as {}";
 pub const ROOM_CONFIG_SAVED: &str = "Room configs saved successfully.";
 pub const ROOM_EXISTS: &str = "A room with the same name already exists.";
 pub const ROOM_FULL: &str = "This room is already full.";
@@ -29,12 +52,34 @@ pub const ROOM_JOIN_RESTRICTED: &str = "Access denied. This room currently doesn
 pub const ROUND_IN_PROGRESS: &str = "Joining not possible: Round is in progress.";
 pub const ROOM_REGISTRATION_REQUIRED: &str =
     "Access denied. This room is for registered users only.";
+pub const SECURE_LOGIN_REQUIRED: &str =
+    "This server requires a secure connection to log in, use STARTTLS first.";
+pub const SERVER_RESTARTING: &str = "The server is restarting, please reconnect.";
+pub const SPECTATOR_DELAY_INVALID: &str = "/spectator_delay: specify number from 0 to 600";
+pub const SPECTATOR_DELAY_SET: &str = "Spectator delay set to {} seconds";
+pub const STORED_TEAM_DELETED: &str = "Stored team deleted.";
 pub const SUPER_POWER: &str = "Super power activated.";
 pub const TEAM_EXISTS: &str = "There's already a team with same name in the list.";
 pub const TEAM_NOT_OWNED: &str = "You can't remove a team you don't own.";
+pub const TEAM_NAME_RESERVED: &str = "This team name is reserved by another player.";
+pub const TEAM_STORAGE_NOT_SUPPORTED: &str = "This server does not support team storage!";
+pub const TEAM_STORAGE_REGISTERED_ONLY: &str = "Only registered players can store teams.";
+pub const TEAM_STORED: &str = "Team stored successfully.";
+pub const TEAM_STORE_FAILED: &str = "Unable to store the team.";
 pub const TEAM_ADD_RESTRICTED: &str = "This room currently does not allow adding new teams.";
+#[cfg(not(feature = "tls-connections"))]
+pub const TLS_NOT_SUPPORTED: &str = "This server does not support TLS connections!";
 pub const TOO_MANY_HEDGEHOGS: &str = "Too many hedgehogs!";
 pub const TOO_MANY_TEAMS: &str = "Too many teams!";
+pub const UNKNOWN_CONFIG_LAYOUT: &str = "Ammo and scheme settings of protocol {} are not supported";
+pub const UNSUPPORTED_LANGUAGE: &str = "This language is not supported, using English.";
 pub const USER_OFFLINE: &str = "Player is not online.";
+pub const USER_QUIT: &str = "User quit";
+pub const USER_QUIT_MESSAGE: &str = "User quit: {}";
 pub const VARIABLE_UPDATED: &str = "Server variable has been updated.";
+pub const VOTING_HEDGEHOGS: &str = "New voting started: hedgehogs per team: {}";
+pub const VOTING_KICK: &str = "New voting started: kick {}";
+pub const VOTING_MAP: &str = "New voting started: map {}";
+pub const VOTING_NEW_SEED: &str = "New voting started: new seed";
+pub const VOTING_PAUSE: &str = "New voting started: pause";
 pub const INCOMPATIBLE_ROOM_PROTOCOL: &str = "Room version incompatible to your Hedgewars version!";
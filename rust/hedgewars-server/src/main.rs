@@ -3,8 +3,10 @@
 
 use getopts::Options;
 use log::*;
-use mio::{net::*, *};
-use std::{env, str::FromStr as _, time::Duration};
+use mio::{net::*, unix::EventedFd, *};
+use std::{
+    env, io::ErrorKind, os::unix::io::AsRawFd, path::PathBuf, str::FromStr as _, time::Duration,
+};
 
 mod core;
 mod handlers;
@@ -12,7 +14,10 @@ mod protocol;
 mod server;
 mod utils;
 
-use crate::server::network::{NetworkLayer, NetworkLayerBuilder};
+use crate::{
+    handlers::IdlePolicy,
+    server::network::{NetworkLayer, NetworkLayerBuilder},
+};
 
 const PROGRAM_NAME: &'_ str = "Hedgewars Game Server";
 
@@ -25,6 +30,66 @@ fn main() {
     let mut opts = Options::new();
 
     opts.optopt("p", "port", "port - defaults to 46631", "PORT");
+    opts.optopt(
+        "f",
+        "fixed-rooms",
+        "fixed rooms file - defaults to fixed_rooms.yaml",
+        "FILE",
+    );
+    opts.optopt(
+        "r",
+        "registry",
+        "federation registry directory shared with other instances",
+        "DIR",
+    );
+    opts.optopt(
+        "",
+        "protocols",
+        "range of protocol versions served by this instance - defaults to all",
+        "FROM-TO",
+    );
+    opts.optopt(
+        "e",
+        "events",
+        "configuration file of the sinks receiving the server events",
+        "FILE",
+    );
+    opts.optopt(
+        "",
+        "away-after",
+        "minutes of inactivity before a client is marked as away",
+        "MINUTES",
+    );
+    opts.optopt(
+        "",
+        "unready-after",
+        "minutes of inactivity before a ready player is made not ready",
+        "MINUTES",
+    );
+    opts.optopt(
+        "",
+        "master-idle",
+        "minutes of inactivity before the room master is passed to another player",
+        "MINUTES",
+    );
+    opts.optopt(
+        "",
+        "kick-idle-spectators",
+        "minutes of inactivity before a spectator is kicked from a full room",
+        "MINUTES",
+    );
+    opts.optopt(
+        "",
+        "handoff",
+        "socket used to take over the clients of the running server on restart",
+        "FILE",
+    );
+    #[cfg(feature = "tls-connections")]
+    opts.optflag(
+        "",
+        "require-tls-login",
+        "registered players must upgrade the connection with STARTTLS before logging in",
+    );
     opts.optflag("h", "help", "help");
     let matches = match opts.parse(&args[1..]) {
         Ok(m) => m,
@@ -42,25 +107,94 @@ fn main() {
         .opt_str("p")
         .and_then(|s| u16::from_str(&s).ok())
         .unwrap_or(46631);
-    let address = format!("0.0.0.0:{}", port).parse().unwrap();
-
-    let listener = TcpListener::bind(&address).unwrap();
+    let handoff_path = matches.opt_str("handoff").map(PathBuf::from);
+    let handoff = handoff_path.as_ref().and_then(|path| {
+        server::handoff::take_over(path).unwrap_or_else(|e| {
+            warn!("Unable to take over the running server: {}", e);
+            None
+        })
+    });
 
     let poll = Poll::new().unwrap();
-    let mut hw_builder = NetworkLayerBuilder::default().with_listener(listener);
+    let fixed_rooms_file = matches
+        .opt_str("f")
+        .unwrap_or_else(|| "fixed_rooms.yaml".to_string());
+    let mut hw_builder =
+        NetworkLayerBuilder::default().with_fixed_rooms_file(fixed_rooms_file.into());
+
+    match handoff {
+        Some(handoff) => hw_builder = hw_builder.with_handoff(handoff),
+        None => {
+            let address = format!("0.0.0.0:{}", port).parse().unwrap();
+            hw_builder = hw_builder.with_listener(TcpListener::bind(&address).unwrap());
+        }
+    }
+
+    if let Some(registry) = matches.opt_str("r") {
+        let protocols = matches.opt_str("protocols").and_then(|s| {
+            let mut range = s.splitn(2, '-').map(u16::from_str);
+            match (range.next(), range.next()) {
+                (Some(Ok(from)), Some(Ok(to))) => Some(from..=to),
+                _ => None,
+            }
+        });
+        hw_builder = hw_builder.with_federation(registry.into(), port, protocols);
+    }
+
+    if let Some(events_file) = matches.opt_str("e") {
+        match server::events::load_config(events_file.as_ref()) {
+            Ok(sinks) => hw_builder = hw_builder.with_event_sinks(sinks),
+            Err(e) => warn!("Unable to load the event sinks from {}: {}", events_file, e),
+        }
+    }
+
+    let idle_minutes = |name| {
+        matches
+            .opt_str(name)
+            .and_then(|s| u64::from_str(&s).ok())
+            .map(|m| Duration::from_secs(m * 60))
+    };
+    hw_builder = hw_builder.with_idle_policy(IdlePolicy {
+        away_after: idle_minutes("away-after"),
+        unready_after: idle_minutes("unready-after"),
+        master_transfer_after: idle_minutes("master-idle"),
+        spectator_kick_after: idle_minutes("kick-idle-spectators"),
+    });
 
     #[cfg(feature = "tls-connections")]
     {
-        let address = format!("0.0.0.0:{}", port + 1).parse().unwrap();
-        hw_builder = hw_builder.with_secure_listener(TcpListener::bind(&address).unwrap());
+        if matches.opt_present("require-tls-login") {
+            hw_builder = hw_builder.with_secure_login_required();
+        }
+        if !hw_builder.has_secure_listener() {
+            let address = format!("0.0.0.0:{}", port + 1).parse().unwrap();
+            hw_builder = hw_builder.with_secure_listener(TcpListener::bind(&address).unwrap());
+        }
     }
 
     let mut hw_network = hw_builder.build();
     hw_network.register(&poll).unwrap();
 
+    let handoff_listener = handoff_path.and_then(|path| match server::handoff::listen(&path) {
+        Ok(listener) => Some(listener),
+        Err(e) => {
+            warn!("Unable to listen for the handoff on {:?}: {}", path, e);
+            None
+        }
+    });
+    if let Some(ref listener) = handoff_listener {
+        poll.register(
+            &EventedFd(&listener.as_raw_fd()),
+            utils::HANDOFF_TOKEN,
+            Ready::readable(),
+            PollOpt::edge(),
+        )
+        .unwrap();
+    }
+
     let mut events = Events::with_capacity(1024);
 
-    loop {
+    'events: loop {
         let timeout = if hw_network.has_pending_operations() {
             Some(Duration::from_millis(1))
         } else {
@@ -81,7 +215,34 @@ fn main() {
                         Ok(()) => (),
                         Err(e) => debug!("Error in timer event: {}", e),
                     },
-                    #[cfg(feature = "official-server")]
+                    utils::FEDERATION_TOKEN => match hw_network.handle_federation_sync(&poll) {
+                        Ok(()) => (),
+                        Err(e) => debug!("Error in federation sync: {}", e),
+                    },
+                    utils::IDLE_TOKEN => match hw_network.handle_idle_check(&poll) {
+                        Ok(()) => (),
+                        Err(e) => debug!("Error in idle check: {}", e),
+                    },
+                    utils::GAME_TOKEN => match hw_network.handle_game_check(&poll) {
+                        Ok(()) => (),
+                        Err(e) => debug!("Error in game check: {}", e),
+                    },
+                    utils::HANDOFF_TOKEN => {
+                        let listener = handoff_listener.as_ref().unwrap();
+                        loop {
+                            match listener.accept() {
+                                Ok((stream, _)) => match hw_network.hand_off(&poll, &stream) {
+                                    Ok(()) => break 'events,
+                                    Err(e) => warn!("Unable to hand off the server: {}", e),
+                                },
+                                Err(ref e) if e.kind() == ErrorKind::WouldBlock => break,
+                                Err(e) => {
+                                    warn!("Error accepting the handoff: {}", e);
+                                    break;
+                                }
+                            }
+                        }
+                    }
                     utils::IO_TOKEN => match hw_network.handle_io_result(&poll) {
                         Ok(()) => (),
                         Err(e) => debug!("Error in IO task: {}", e),
@@ -97,6 +258,10 @@ fn main() {
                     utils::SERVER_TOKEN
                     | utils::SECURE_SERVER_TOKEN
                     | utils::TIMER_TOKEN
+                    | utils::FEDERATION_TOKEN
+                    | utils::IDLE_TOKEN
+                    | utils::GAME_TOKEN
+                    | utils::HANDOFF_TOKEN
                     | utils::IO_TOKEN => unreachable!(),
                     Token(token) => match hw_network.client_writable(&poll, token) {
                         Ok(()) => (),
@@ -111,4 +276,7 @@ fn main() {
             Err(e) => debug!("Error in idle handler: {}", e),
         };
     }
+
+    info!("Server handed off to the new process");
+    hw_network.shut_down();
 }
@@ -1,14 +1,19 @@
 use self::parser::message;
+use crate::utils;
 use log::*;
 use netbuf;
+use serde_derive::{Deserialize, Serialize};
 use std::io::{Read, Result};
 
 pub mod messages;
 mod parser;
 #[cfg(test)]
 pub mod test;
+pub mod translation;
 
+#[derive(Serialize, Deserialize)]
 pub struct ProtocolDecoder {
+    #[serde(with = "utils::serde_buf")]
     buf: netbuf::Buf,
     is_recovering: bool,
 }
@@ -44,6 +49,12 @@ impl ProtocolDecoder {
         Ok(count)
     }
 
+    /// Drops the buffered input without parsing it
+    pub fn discard(&mut self) {
+        let length = self.buf.len();
+        self.buf.consume(length);
+    }
+
     pub fn extract_messages(&mut self) -> Vec<messages::HwProtocolMessage> {
         let mut messages = vec![];
         if !self.is_recovering {
@@ -1,4 +1,4 @@
-use crate::core::types::{GameCfg, HedgehogInfo, ServerVar, TeamInfo, VoteType};
+use crate::core::types::{GameCfg, HedgehogInfo, RoomFilter, ServerVar, TeamInfo, VoteType};
 use std::{convert::From, iter::once, ops};
 
 #[derive(PartialEq, Eq, Clone, Debug)]
@@ -12,13 +12,16 @@ pub enum HwProtocolMessage {
     ToggleServerRegisteredOnly,
     SuperPower,
     Info(String),
+    Language(String),
     // anteroom messages
     Nick(String),
     Proto(u16),
     Password(String, String),
     Checker(u16, String, String),
+    StartTls,
     // lobby messages
     List,
+    ListRooms(u32, u8, RoomFilter),
     Chat(String),
     CreateRoom(String, Option<String>),
     JoinRoom(String, Option<String>),
@@ -38,6 +41,10 @@ pub enum HwProtocolMessage {
     Part(Option<String>),
     Cfg(GameCfg),
     AddTeam(Box<TeamInfo>),
+    StoreTeam(Box<TeamInfo>),
+    AddStoredTeam(String),
+    DeleteStoredTeam(String),
+    ListStoredTeams,
     RemoveTeam(String),
     SetHedgehogsNumber(String, u8),
     SetTeamColor(String, u8),
@@ -48,10 +55,15 @@ pub enum HwProtocolMessage {
     ToggleRestrictJoin,
     ToggleRestrictTeams,
     ToggleRegisteredOnly,
+    ToggleHidden,
+    Invite(Option<u32>),
+    AllowNick(String),
+    DisallowNick(String),
     RoomName(String),
     Delegate(String),
     TeamChat(String),
     MaxTeams(u8),
+    SpectatorDelay(u32),
     Fix,
     Unfix,
     Greeting(Option<String>),
@@ -73,6 +85,7 @@ pub enum ProtocolFlags {
     Registered,
     Admin,
     Contributor,
+    Away,
 }
 
 impl ProtocolFlags {
@@ -86,6 +99,7 @@ impl ProtocolFlags {
             ProtocolFlags::Registered => 'u',
             ProtocolFlags::Admin => 'a',
             ProtocolFlags::Contributor => 'c',
+            ProtocolFlags::Away => 'w',
         }
     }
 
@@ -107,10 +121,12 @@ pub fn remove_flags(flags: &[ProtocolFlags]) -> String {
     ProtocolFlags::format('-', flags)
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum HwServerMessage {
     Connected(u32),
     Redirect(u16),
+    #[cfg(feature = "tls-connections")]
+    StartTls,
 
     Ping,
     Pong,
@@ -124,9 +140,14 @@ pub enum HwServerMessage {
 
     LobbyLeft(String, String),
     LobbyJoined(Vec<String>),
-    ChatMsg { nick: String, msg: String },
+    ChatMsg {
+        nick: String,
+        msg: String,
+    },
     ClientFlags(String, Vec<String>),
     Rooms(Vec<String>),
+    RoomsPage(Option<u32>, Vec<String>),
+    StoredTeams(Vec<String>),
     RoomAdd(Vec<String>),
     RoomJoined(Vec<String>),
     RoomLeft(String, String),
@@ -197,6 +218,28 @@ impl VoteType {
     }
 }
 
+impl RoomFilter {
+    pub fn to_protocol(&self) -> Vec<String> {
+        let flags: String = [
+            ('g', self.in_game),
+            ('p', self.has_password),
+            ('r', self.registration_required),
+            ('f', self.has_free_slots),
+        ]
+        .iter()
+        .filter_map(|(c, v)| v.map(|v| format!("{}{}", if v { '+' } else { '-' }, c)))
+        .collect();
+
+        let mut result = vec![if flags.is_empty() {
+            "*".to_string()
+        } else {
+            flags
+        }];
+        result.extend(self.name.iter().cloned());
+        result
+    }
+}
+
 impl GameCfg {
     pub fn to_protocol(&self) -> (String, Vec<String>) {
         use GameCfg::*;
@@ -267,6 +310,25 @@ macro_rules! several {
     [$part: expr, $($other: expr),*] => { once($part).chain(several![$($other),*]) };
 }
 
+#[cfg(test)]
+fn team_message(header: &str, info: &TeamInfo) -> String {
+    msg![
+        header,
+        info.name,
+        info.color,
+        info.grave,
+        info.fort,
+        info.voice_pack,
+        info.flag,
+        info.difficulty,
+        info.hedgehogs
+            .iter()
+            .flat_map(|h| several![&h.name[..], &h.hat[..]])
+            .collect::<Vec<_>>()
+            .join("\n")
+    ]
+}
+
 impl HwProtocolMessage {
     /** Converts the message to a raw `String`, which can be sent over the network.
      *
@@ -281,6 +343,7 @@ impl HwProtocolMessage {
             Quit(None) => msg!["QUIT"],
             Quit(Some(msg)) => msg!["QUIT", msg],
             Global(msg) => msg!["CMD", format!("GLOBAL {}", msg)],
+            Language(language) => msg!["CMD", format!("LANGUAGE {}", language)],
             Watch(name) => msg!["CMD", format!("WATCH {}", name)],
             ToggleServerRegisteredOnly => msg!["CMD", "REGISTERED_ONLY"],
             SuperPower => msg!["CMD", "SUPER_POWER"],
@@ -289,7 +352,12 @@ impl HwProtocolMessage {
             Proto(version) => msg!["PROTO", version],
             Password(p, s) => msg!["PASSWORD", p, s],
             Checker(i, n, p) => msg!["CHECKER", i, n, p],
+            StartTls => msg!["STARTTLS"],
             List => msg!["LIST"],
+            ListRooms(cursor, limit, filter) => construct_message(
+                &["LIST_ROOMS", &cursor.to_string(), &limit.to_string()],
+                &filter.to_protocol(),
+            ),
             Chat(msg) => msg!["CHAT", msg],
             CreateRoom(name, None) => msg!["CREATE_ROOM", name],
             CreateRoom(name, Some(password)) => msg!["CREATE_ROOM", name, password],
@@ -319,21 +387,11 @@ impl HwProtocolMessage {
                 let (name, args) = config.to_protocol();
                 msg!["CFG", name, args.join("\n")]
             }
-            AddTeam(info) => msg![
-                "ADD_TEAM",
-                info.name,
-                info.color,
-                info.grave,
-                info.fort,
-                info.voice_pack,
-                info.flag,
-                info.difficulty,
-                info.hedgehogs
-                    .iter()
-                    .flat_map(|h| several![&h.name[..], &h.hat[..]])
-                    .collect::<Vec<_>>()
-                    .join("\n")
-            ],
+            AddTeam(info) => team_message("ADD_TEAM", info),
+            StoreTeam(info) => team_message("STORE_TEAM", info),
+            AddStoredTeam(name) => msg!["CMD", format!("ADD_STORED_TEAM {}", name)],
+            DeleteStoredTeam(name) => msg!["CMD", format!("DELETE_STORED_TEAM {}", name)],
+            ListStoredTeams => msg!["CMD", "STORED_TEAMS"],
             RemoveTeam(name) => msg!["REMOVE_TEAM", name],
             SetHedgehogsNumber(team, number) => msg!["HH_NUM", team, number],
             SetTeamColor(team, color) => msg!["TEAM_COLOR", team, color],
@@ -344,10 +402,16 @@ impl HwProtocolMessage {
             ToggleRestrictJoin => msg!["TOGGLE_RESTRICT_JOINS"],
             ToggleRestrictTeams => msg!["TOGGLE_RESTRICT_TEAMS"],
             ToggleRegisteredOnly => msg!["TOGGLE_REGISTERED_ONLY"],
+            ToggleHidden => msg!["TOGGLE_HIDDEN"],
+            Invite(None) => msg!["CMD", "INVITE"],
+            Invite(Some(minutes)) => msg!["CMD", format!("INVITE {}", minutes)],
+            AllowNick(nick) => msg!["CMD", format!("ALLOW {}", nick)],
+            DisallowNick(nick) => msg!["CMD", format!("DISALLOW {}", nick)],
             RoomName(name) => msg!["ROOM_NAME", name],
             Delegate(name) => msg!["CMD", format!("DELEGATE {}", name)],
             TeamChat(msg) => msg!["TEAMCHAT", msg],
             MaxTeams(count) => msg!["CMD", format!("MAXTEAMS {}", count)],
+            SpectatorDelay(delay) => msg!["CMD", format!("SPECTATOR_DELAY {}", delay)],
             Fix => msg!["CMD", "FIX"],
             Unfix => msg!["CMD", "UNFIX"],
             Greeting(None) => msg!["CMD", "GREETING"],
@@ -386,6 +450,8 @@ impl HwServerMessage {
                 protocol_version
             ],
             Redirect(port) => msg!["REDIRECT", port],
+            #[cfg(feature = "tls-connections")]
+            StartTls => msg!["STARTTLS"],
             Bye(msg) => msg!["BYE", msg],
             Nick(nick) => msg!["NICK", nick],
             Proto(proto) => msg!["PROTO", proto],
@@ -396,6 +462,14 @@ impl HwServerMessage {
             LobbyJoined(nicks) => construct_message(&["LOBBY:JOINED"], &nicks),
             ClientFlags(flags, nicks) => construct_message(&["CLIENT_FLAGS", flags], &nicks),
             Rooms(info) => construct_message(&["ROOMS"], &info),
+            StoredTeams(names) => construct_message(&["STORED_TEAMS"], &names),
+            RoomsPage(next_cursor, info) => construct_message(
+                &[
+                    "ROOMS_PAGE",
+                    &next_cursor.map_or("END".to_string(), |c| c.to_string()),
+                ],
+                &info,
+            ),
             RoomAdd(info) => construct_message(&["ROOM", "ADD"], &info),
             RoomJoined(nicks) => construct_message(&["JOINED"], &nicks),
             RoomLeft(nick, msg) => msg!["LEFT", nick, msg],
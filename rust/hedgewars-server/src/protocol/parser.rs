@@ -9,10 +9,10 @@
 use nom::{
     branch::alt,
     bytes::complete::{tag, tag_no_case, take_until, take_while},
-    character::complete::{newline, not_line_ending},
-    combinator::{map, peek},
+    character::complete::{newline, not_line_ending, one_of},
+    combinator::{map, opt, peek},
     error::{ErrorKind, ParseError},
-    multi::separated_list,
+    multi::{many1, separated_list},
     sequence::{delimited, pair, preceded, terminated, tuple},
     Err, IResult,
 };
@@ -26,7 +26,7 @@ use std::{
 
 use super::messages::{HwProtocolMessage, HwProtocolMessage::*};
 use crate::core::types::{
-    GameCfg, HedgehogInfo, ServerVar, TeamInfo, VoteType, MAX_HEDGEHOGS_PER_TEAM,
+    GameCfg, HedgehogInfo, RoomFilter, ServerVar, TeamInfo, VoteType, MAX_HEDGEHOGS_PER_TEAM,
 };
 
 #[derive(Debug, PartialEq)]
@@ -168,6 +168,35 @@ fn hedgehog_array(input: &[u8]) -> HwResult<[HedgehogInfo; 8]> {
     Ok((i, [h1, h2, h3, h4, h5, h6, h7, h8]))
 }
 
+fn team_info(input: &[u8]) -> HwResult<Box<TeamInfo>> {
+    map(
+        tuple((
+            terminated(a_line, newline),
+            terminated(u8_line, newline),
+            terminated(a_line, newline),
+            terminated(a_line, newline),
+            terminated(a_line, newline),
+            terminated(a_line, newline),
+            terminated(u8_line, newline),
+            hedgehog_array,
+        )),
+        |(name, color, grave, fort, voice_pack, flag, difficulty, hedgehogs)| {
+            Box::new(TeamInfo {
+                owner: String::new(),
+                name,
+                color,
+                grave,
+                fort,
+                voice_pack,
+                flag,
+                difficulty,
+                hedgehogs,
+                hedgehogs_number: 0,
+            })
+        },
+    )(input)
+}
+
 fn voting(input: &[u8]) -> HwResult<VoteType> {
     alt((
         map(tag_no_case("PAUSE"), |_| VoteType::Pause),
@@ -184,17 +213,41 @@ fn voting(input: &[u8]) -> HwResult<VoteType> {
     ))(input)
 }
 
+fn room_filter(input: &[u8]) -> HwResult<RoomFilter> {
+    let (i, flags) = alt((
+        map(tag("*"), |_| vec![]),
+        many1(pair(one_of("+-"), one_of("gprf"))),
+    ))(input)?;
+    let (i, name) = opt_arg(i)?;
+
+    let mut filter = RoomFilter {
+        name,
+        ..RoomFilter::default()
+    };
+    for (sign, flag) in flags {
+        let value = Some(sign == '+');
+        match flag {
+            'g' => filter.in_game = value,
+            'p' => filter.has_password = value,
+            'r' => filter.registration_required = value,
+            _ => filter.has_free_slots = value,
+        }
+    }
+    Ok((i, filter))
+}
+
 fn no_arg_message(input: &[u8]) -> HwResult<HwProtocolMessage> {
     fn message<'a>(
         name: &'a str,
         msg: HwProtocolMessage,
     ) -> impl Fn(&'a [u8]) -> HwResult<'a, HwProtocolMessage> {
-        move |i| map(tag(name), |_| msg.clone())(i)
+        move |i| map(terminated(tag(name), peek(end_of_message)), |_| msg.clone())(i)
     }
 
     alt((
         message("PING", Ping),
         message("PONG", Pong),
+        message("STARTTLS", StartTls),
         message("LIST", List),
         message("BANLIST", BanList),
         message("GET_SERVER_VAR", GetServerVar),
@@ -203,6 +256,7 @@ fn no_arg_message(input: &[u8]) -> HwResult<HwProtocolMessage> {
         message("TOGGLE_RESTRICT_JOINS", ToggleRestrictJoin),
         message("TOGGLE_RESTRICT_TEAMS", ToggleRestrictTeams),
         message("TOGGLE_REGISTERED_ONLY", ToggleRegisteredOnly),
+        message("TOGGLE_HIDDEN", ToggleHidden),
     ))(input)
 }
 
@@ -267,6 +321,7 @@ fn cmd_message<'a>(input: &'a [u8]) -> HwResult<'a, HwProtocolMessage> {
             cmd_no_arg("UNFIX", Unfix),
             cmd_no_arg("REGISTERED_ONLY", ToggleServerRegisteredOnly),
             cmd_no_arg("SUPER_POWER", SuperPower),
+            cmd_no_arg("STORED_TEAMS", ListStoredTeams),
         ))(input)
     }
 
@@ -282,7 +337,13 @@ fn cmd_message<'a>(input: &'a [u8]) -> HwResult<'a, HwProtocolMessage> {
             cmd_single_arg("VOTE", yes_no_line, Vote),
             cmd_single_arg("FORCE", yes_no_line, ForceVote),
             cmd_single_arg("INFO", a_line, Info),
+            cmd_single_arg("LANGUAGE", a_line, Language),
+            cmd_single_arg("ADD_STORED_TEAM", a_line, AddStoredTeam),
+            cmd_single_arg("DELETE_STORED_TEAM", a_line, DeleteStoredTeam),
+            cmd_single_arg("ALLOW", a_line, AllowNick),
+            cmd_single_arg("DISALLOW", a_line, DisallowNick),
             cmd_single_arg("MAXTEAMS", u8_line, MaxTeams),
+            cmd_single_arg("SPECTATOR_DELAY", u32_line, SpectatorDelay),
             cmd_single_arg("CALLVOTE", voting, |v| CallVote(Some(v))),
         ))(input)
     }
@@ -293,6 +354,10 @@ fn cmd_message<'a>(input: &'a [u8]) -> HwResult<'a, HwProtocolMessage> {
             cmd_no_arg_message,
             cmd_single_arg_message,
             map(tag_no_case("CALLVOTE"), |_| CallVote(None)),
+            map(
+                preceded(tag_no_case("INVITE"), opt(preceded(spaces, u32_line))),
+                Invite,
+            ),
             map(preceded(tag_no_case("GREETING"), opt_space_arg), Greeting),
             map(preceded(tag_no_case("PART"), opt_space_arg), Part),
             map(preceded(tag_no_case("QUIT"), opt_space_arg), Quit),
@@ -401,6 +466,17 @@ fn complex_message(input: &[u8]) -> HwResult<HwProtocolMessage> {
                 |(protocol, name, pass)| Checker(protocol, name, pass),
             ),
         ),
+        preceded(
+            pair(tag("LIST_ROOMS"), newline),
+            map(
+                tuple((
+                    terminated(u32_line, newline),
+                    terminated(u8_line, newline),
+                    room_filter,
+                )),
+                |(cursor, limit, filter)| ListRooms(cursor, limit, filter),
+            ),
+        ),
         preceded(
             pair(tag("CREATE_ROOM"), newline),
             map(pair(a_line, opt_arg), |(name, pass)| CreateRoom(name, pass)),
@@ -409,35 +485,8 @@ fn complex_message(input: &[u8]) -> HwResult<HwProtocolMessage> {
             pair(tag("JOIN_ROOM"), newline),
             map(pair(a_line, opt_arg), |(name, pass)| JoinRoom(name, pass)),
         ),
-        preceded(
-            pair(tag("ADD_TEAM"), newline),
-            map(
-                tuple((
-                    terminated(a_line, newline),
-                    terminated(u8_line, newline),
-                    terminated(a_line, newline),
-                    terminated(a_line, newline),
-                    terminated(a_line, newline),
-                    terminated(a_line, newline),
-                    terminated(u8_line, newline),
-                    hedgehog_array,
-                )),
-                |(name, color, grave, fort, voice_pack, flag, difficulty, hedgehogs)| {
-                    AddTeam(Box::new(TeamInfo {
-                        owner: String::new(),
-                        name,
-                        color,
-                        grave,
-                        fort,
-                        voice_pack,
-                        flag,
-                        difficulty,
-                        hedgehogs,
-                        hedgehogs_number: 0,
-                    }))
-                },
-            ),
-        ),
+        preceded(pair(tag("ADD_TEAM"), newline), map(team_info, AddTeam)),
+        preceded(pair(tag("STORE_TEAM"), newline), map(team_info, StoreTeam)),
         preceded(
             pair(tag("HH_NUM"), newline),
             map(
@@ -511,7 +560,7 @@ pub fn message(input: &[u8]) -> HwResult<HwProtocolMessage> {
 mod test {
     use super::message;
     use crate::{
-        core::types::GameCfg,
+        core::types::{GameCfg, RoomFilter},
         protocol::{messages::HwProtocolMessage::*, parser::HwProtocolError, test::gen_proto_msg},
     };
     use proptest::{proptest, proptest_helper};
@@ -568,6 +617,38 @@ mod test {
             ))
         );
 
+        assert_eq!(message(b"CMD\nINVITE\n\n"), Ok((&b""[..], Invite(None))));
+        assert_eq!(
+            message(b"CMD\nINVITE 30\n\n"),
+            Ok((&b""[..], Invite(Some(30))))
+        );
+        assert_eq!(
+            message(b"CMD\nDISALLOW me\n\n"),
+            Ok((&b""[..], DisallowNick("me".to_string())))
+        );
+
+        assert_eq!(message(b"LIST\n\n"), Ok((&b""[..], List)));
+        assert_eq!(
+            message(b"LIST_ROOMS\n0\n20\n*\n\n"),
+            Ok((&b""[..], ListRooms(0, 20, RoomFilter::default())))
+        );
+        assert_eq!(
+            message(b"LIST_ROOMS\n5\n10\n-g+f\nfun\n\n"),
+            Ok((
+                &b""[..],
+                ListRooms(
+                    5,
+                    10,
+                    RoomFilter {
+                        name: Some("fun".to_string()),
+                        in_game: Some(false),
+                        has_free_slots: Some(true),
+                        ..RoomFilter::default()
+                    }
+                )
+            ))
+        );
+
         assert_eq!(
             message(b"QUIT\n1\n2\n\n"),
             Err(nom::Err::Error(HwProtocolError::new()))
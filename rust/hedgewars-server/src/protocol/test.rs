@@ -4,7 +4,9 @@ use proptest::{
     test_runner::{Reason, TestRunner},
 };
 
-use crate::core::types::{GameCfg, HedgehogInfo, ServerVar, ServerVar::*, TeamInfo, VoteType};
+use crate::core::types::{
+    GameCfg, HedgehogInfo, RoomFilter, ServerVar, ServerVar::*, TeamInfo, VoteType,
+};
 
 use super::messages::{HwProtocolMessage, HwProtocolMessage::*};
 
@@ -98,6 +100,24 @@ impl Arbitrary for GameCfg {
     type Strategy = BoxedStrategy<GameCfg>;
 }
 
+impl Arbitrary for RoomFilter {
+    type Parameters = ();
+
+    fn arbitrary_with(_args: <Self as Arbitrary>::Parameters) -> <Self as Arbitrary>::Strategy {
+        (any::<Option<Ascii>>(), any::<[Option<bool>; 4]>())
+            .prop_map(|(name, flags)| RoomFilter {
+                name: name.into2(),
+                in_game: flags[0],
+                has_password: flags[1],
+                registration_required: flags[2],
+                has_free_slots: flags[3],
+            })
+            .boxed()
+    }
+
+    type Strategy = BoxedStrategy<RoomFilter>;
+}
+
 impl Arbitrary for TeamInfo {
     type Parameters = ();
 
@@ -189,7 +209,7 @@ impl Arbitrary for VoteType {
 }
 
 pub fn gen_proto_msg() -> BoxedStrategy<HwProtocolMessage> where {
-    let res = (0..=55).no_shrink().prop_flat_map(|i| {
+    let res = (0..=67).no_shrink().prop_flat_map(|i| {
         proto_msg_match!(i, def = Ping,
             0 => Ping(),
             1 => Pong(),
@@ -245,7 +265,19 @@ pub fn gen_proto_msg() -> BoxedStrategy<HwProtocolMessage> where {
             52 => Save(Ascii, Ascii),
             53 => Delete(Ascii),
             54 => SaveRoom(Ascii),
-            55 => LoadRoom(Ascii)
+            55 => LoadRoom(Ascii),
+            56 => SpectatorDelay(u32),
+            57 => Language(Ascii),
+            58 => ListRooms(u32, u8, RoomFilter),
+            59 => StoreTeam(Box<TeamInfo>),
+            60 => AddStoredTeam(Ascii),
+            61 => DeleteStoredTeam(Ascii),
+            62 => ListStoredTeams(),
+            63 => ToggleHidden(),
+            64 => Invite(Option<u32>),
+            65 => AllowNick(Ascii),
+            66 => DisallowNick(Ascii),
+            67 => StartTls()
         )
     });
     res.boxed()
@@ -0,0 +1,181 @@
+//! Adapts messages between protocol versions.
+//!
+//! Everything that depends on the protocol version of a client should be decided here,
+//! so that clients of different, but compatible versions can share the lobby and the rooms.
+//! Incoming messages are converted to the form expected by the handlers and outgoing
+//! messages are converted to the form expected by each recipient.
+
+use super::messages::{
+    add_flags, remove_flags, HwProtocolMessage, HwServerMessage, ProtocolFlags as Flags,
+};
+use crate::core::types::GameCfg;
+use std::ops::RangeInclusive;
+
+/// Protocol versions that share the engine message stream and the room config layout
+const COMPATIBLE_PROTOCOLS: &[RangeInclusive<u16>] = &[58..=60];
+
+pub fn are_compatible(protocol1: u16, protocol2: u16) -> bool {
+    protocol1 == protocol2
+        || COMPATIBLE_PROTOCOLS
+            .iter()
+            .any(|r| r.contains(&protocol1) && r.contains(&protocol2))
+}
+
+pub fn version_string(protocol_number: u16) -> &'static str {
+    match protocol_number {
+        17 => "0.9.7-dev",
+        19 => "0.9.7",
+        20 => "0.9.8-dev",
+        21 => "0.9.8",
+        22 => "0.9.9-dev",
+        23 => "0.9.9",
+        24 => "0.9.10-dev",
+        25 => "0.9.10",
+        26 => "0.9.11-dev",
+        27 => "0.9.11",
+        28 => "0.9.12-dev",
+        29 => "0.9.12",
+        30 => "0.9.13-dev",
+        31 => "0.9.13",
+        32 => "0.9.14-dev",
+        33 => "0.9.14",
+        34 => "0.9.15-dev",
+        35 => "0.9.14.1",
+        37 => "0.9.15",
+        38 => "0.9.16-dev",
+        39 => "0.9.16",
+        40 => "0.9.17-dev",
+        41 => "0.9.17",
+        42 => "0.9.18-dev",
+        43 => "0.9.18",
+        44 => "0.9.19-dev",
+        45 => "0.9.19",
+        46 => "0.9.20-dev",
+        47 => "0.9.20",
+        48 => "0.9.21-dev",
+        49 => "0.9.21",
+        50 => "0.9.22-dev",
+        51 => "0.9.22",
+        52 => "0.9.23-dev",
+        53 => "0.9.23",
+        54 => "0.9.24-dev",
+        55 => "0.9.24",
+        56 => "0.9.25-dev",
+        57 => "0.9.25",
+        58 => "1.0.0-dev",
+        59 => "1.0.0",
+        60 => "1.0.1-dev",
+        _ => "Unknown",
+    }
+}
+
+/// Clients before 0.9.18-dev name the room after its master and choose team colors themselves
+#[inline]
+pub fn has_legacy_rooms(protocol_number: u16) -> bool {
+    protocol_number < 42
+}
+
+/// Rooms before 0.9.19-dev can only start the game once all players are ready
+#[inline]
+pub fn requires_all_ready(protocol_number: u16) -> bool {
+    protocol_number <= 43
+}
+
+/// Clients before 1.0.0-dev watch replays as a regular room they get kicked from afterwards
+#[inline]
+pub fn has_replay_rooms(protocol_number: u16) -> bool {
+    protocol_number < 58
+}
+
+/// Clients before 1.0.1-dev don't know the away flag
+#[inline]
+pub fn has_away_flag(protocol_number: u16) -> bool {
+    protocol_number >= 60
+}
+
+pub fn translate_incoming(protocol_number: u16, message: HwProtocolMessage) -> HwProtocolMessage {
+    use HwProtocolMessage::*;
+    match message {
+        Cfg(GameCfg::Scheme(name, mut values)) if protocol_number == 49 && values.len() >= 2 => {
+            let mut s = "X".repeat(50);
+            s.push_str(&values.pop().unwrap());
+            values.push(s);
+            Cfg(GameCfg::Scheme(name, values))
+        }
+        message => message,
+    }
+}
+
+pub fn is_version_dependent(message: &HwServerMessage) -> bool {
+    use HwServerMessage::*;
+    match message {
+        ClientFlags(..) => true,
+        _ => false,
+    }
+}
+
+/// Returns `true` if the message has no meaning for the protocol version and shouldn't be sent
+pub fn is_ignored(protocol_number: u16, message: &HwServerMessage) -> bool {
+    use HwServerMessage::*;
+    match message {
+        ClientFlags(flags, _) if !has_away_flag(protocol_number) => {
+            flags.chars().skip(1).all(|c| c == 'w')
+        }
+        _ => false,
+    }
+}
+
+/// Returns `None` if the message doesn't need to be changed for the protocol version
+pub fn translate_outgoing(
+    protocol_number: u16,
+    message: &HwServerMessage,
+) -> Option<HwServerMessage> {
+    use HwServerMessage::*;
+    match message {
+        ClientFlags(flags, nicks) if protocol_number < 38 => {
+            if *flags == add_flags(&[Flags::Ready]) {
+                Some(LegacyReady(true, nicks.clone()))
+            } else if *flags == remove_flags(&[Flags::Ready]) {
+                Some(LegacyReady(false, nicks.clone()))
+            } else {
+                None
+            }
+        }
+        ClientFlags(flags, nicks) if !has_away_flag(protocol_number) && flags.contains('w') => {
+            Some(ClientFlags(flags.replace('w', ""), nicks.clone()))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use HwServerMessage::ClientFlags;
+
+    #[test]
+    fn compatibility() {
+        assert!(are_compatible(58, 60));
+        assert!(are_compatible(43, 43));
+        assert!(!are_compatible(57, 58));
+        assert!(!are_compatible(43, 44));
+    }
+
+    #[test]
+    fn away_flag() {
+        let away = ClientFlags(add_flags(&[Flags::Away]), vec!["nick".to_string()]);
+        assert!(is_ignored(59, &away));
+        assert!(!is_ignored(60, &away));
+
+        let flags = ClientFlags(
+            add_flags(&[Flags::InRoom, Flags::Away]),
+            vec!["nick".to_string()],
+        );
+        assert!(!is_ignored(59, &flags));
+        match translate_outgoing(59, &flags) {
+            Some(ClientFlags(flags, _)) => assert_eq!(flags, "+i"),
+            _ => panic!("the away flag is not removed"),
+        }
+        assert!(translate_outgoing(60, &flags).is_none());
+    }
+}
@@ -1,5 +1,6 @@
 #[cfg(feature = "official-server")]
 mod database;
-#[cfg(feature = "official-server")]
+pub mod events;
+pub mod handoff;
 pub mod io;
 pub mod network;
@@ -19,6 +19,22 @@ const STORE_STATS_QUERY: &str = r"INSERT INTO gameserver_stats
 
 const GET_REPLAY_NAME_QUERY: &str = r"SELECT filename FROM achievements WHERE id = :id";
 
+const GET_TEAM_RESERVATIONS_QUERY: &str = r"SELECT name, owner FROM gameserver_teams";
+
+const GET_STORED_TEAM_QUERY: &str =
+    r"SELECT data FROM gameserver_teams WHERE owner = :owner AND name = :name";
+
+const STORE_TEAM_QUERY: &str = r"INSERT INTO gameserver_teams
+      (name, owner, data)
+      VALUES
+      (:name, :owner, :data)
+      ON DUPLICATE KEY UPDATE data = IF(owner = VALUES(owner), VALUES(data), data)";
+
+const GET_TEAM_OWNER_QUERY: &str = r"SELECT owner FROM gameserver_teams WHERE name = :name";
+
+const DELETE_STORED_TEAM_QUERY: &str =
+    r"DELETE FROM gameserver_teams WHERE owner = :owner AND name = :name";
+
 pub struct ServerStatistics {
     rooms: u32,
     players: u32,
@@ -135,6 +151,68 @@ impl Database {
             Err(DriverError::SetupError.into())
         }
     }
+
+    pub fn get_team_reservations(&mut self) -> Result<Vec<(String, String)>, Error> {
+        if let Some(pool) = &self.pool {
+            let mut reservations = vec![];
+            for row in pool.prep_exec(GET_TEAM_RESERVATIONS_QUERY, ())? {
+                reservations.push(from_row_opt::<(String, String)>(row?)?);
+            }
+            Ok(reservations)
+        } else {
+            Err(DriverError::SetupError.into())
+        }
+    }
+
+    pub fn get_stored_team(&mut self, owner: &str, name: &str) -> Result<Option<String>, Error> {
+        if let Some(pool) = &self.pool {
+            if let Some(row) = pool.first_exec(
+                GET_STORED_TEAM_QUERY,
+                params! { "owner" => owner, "name" => name },
+            )? {
+                let data = from_row_opt::<String>(row)?;
+                Ok(Some(data))
+            } else {
+                Ok(None)
+            }
+        } else {
+            Err(DriverError::SetupError.into())
+        }
+    }
+
+    /// Returns `false` if the team name belongs to another player
+    pub fn store_team(&mut self, owner: &str, name: &str, data: &str) -> Result<bool, Error> {
+        if let Some(pool) = &self.pool {
+            let result = pool.prep_exec(
+                STORE_TEAM_QUERY,
+                params! { "name" => name, "owner" => owner, "data" => data },
+            )?;
+            if result.affected_rows() > 0 {
+                return Ok(true);
+            }
+
+            // nothing changes if the data is the same or if the name is taken
+            let stored_owner = pool
+                .first_exec(GET_TEAM_OWNER_QUERY, params! { "name" => name })?
+                .map(from_row_opt::<String>)
+                .transpose()?;
+            Ok(stored_owner.as_deref() == Some(owner))
+        } else {
+            Err(DriverError::SetupError.into())
+        }
+    }
+
+    pub fn delete_stored_team(&mut self, owner: &str, name: &str) -> Result<bool, Error> {
+        if let Some(pool) = &self.pool {
+            let result = pool.prep_exec(
+                DELETE_STORED_TEAM_QUERY,
+                params! { "owner" => owner, "name" => name },
+            )?;
+            Ok(result.affected_rows() > 0)
+        } else {
+            Err(DriverError::SetupError.into())
+        }
+    }
 }
 
 fn get_hash(protocol_number: u16, web_password: &str, salt1: &str, salt2: &str) -> Sha1Digest {
@@ -0,0 +1,466 @@
+use std::{
+    collections::HashSet,
+    fs,
+    fs::{File, OpenOptions},
+    io,
+    io::{Error, ErrorKind, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    os::unix::net::{UnixListener, UnixStream},
+    path::{Path, PathBuf},
+    sync::mpsc,
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::handlers::ServerEvent;
+use log::*;
+#[cfg(feature = "tls-connections")]
+use openssl::ssl::{SslConnector, SslMethod};
+use serde_derive::{Deserialize, Serialize};
+
+/// Configuration of a single sink, only one of `file`, `socket` and `webhook` should be set.
+/// If `events` is given, only the events of the listed kinds are passed to the sink.
+/// Webhooks can be `http://` URLs or, if the server is built with `tls-connections`,
+/// `https://` ones, otherwise HTTPS-only endpoints like Discord need a local relay.
+#[derive(Deserialize, Debug)]
+pub struct SinkConfig {
+    pub file: Option<PathBuf>,
+    pub socket: Option<PathBuf>,
+    pub webhook: Option<String>,
+    pub events: Option<Vec<String>>,
+}
+
+pub fn load_config(path: &Path) -> io::Result<Vec<SinkConfig>> {
+    let contents = fs::read_to_string(path)?;
+    serde_yaml::from_str(&contents).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))
+}
+
+/// Events waiting for the sinks, newer events are dropped when the sinks can't keep up
+const EVENT_QUEUE_SIZE: usize = 1024;
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Serialize)]
+struct EventRecord<'a> {
+    time: u64,
+    #[serde(flatten)]
+    event: &'a ServerEvent,
+}
+
+pub trait EventSink: Send {
+    /// Receives an event serialized as a single line of JSON without the line break
+    fn publish(&mut self, line: &str);
+}
+
+/// Appends the events to a file, which can also be a named pipe with a reader on the other end
+pub struct JsonLinesSink {
+    path: PathBuf,
+    file: File,
+}
+
+impl JsonLinesSink {
+    pub fn open(path: PathBuf) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        Ok(Self { path, file })
+    }
+}
+
+impl EventSink for JsonLinesSink {
+    fn publish(&mut self, line: &str) {
+        if let Err(e) = writeln!(self.file, "{}", line) {
+            warn!("Unable to write the event to {:?}: {}", self.path, e);
+        }
+    }
+}
+
+/// Streams the events to every process connected to a local socket.
+/// Subscribers that can't keep up with the events are disconnected.
+pub struct UnixSocketSink {
+    path: PathBuf,
+    listener: UnixListener,
+    subscribers: Vec<UnixStream>,
+}
+
+impl UnixSocketSink {
+    pub fn bind(path: PathBuf) -> io::Result<Self> {
+        if path.exists() {
+            fs::remove_file(&path)?;
+        }
+        let listener = UnixListener::bind(&path)?;
+        listener.set_nonblocking(true)?;
+        Ok(Self {
+            path,
+            listener,
+            subscribers: vec![],
+        })
+    }
+
+    fn accept_subscribers(&mut self) {
+        loop {
+            match self.listener.accept() {
+                Ok((stream, _)) => match stream.set_nonblocking(true) {
+                    Ok(()) => self.subscribers.push(stream),
+                    Err(e) => warn!("Unable to set up the event subscriber: {}", e),
+                },
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    warn!("Unable to accept the event subscriber: {}", e);
+                    break;
+                }
+            }
+        }
+    }
+}
+
+impl EventSink for UnixSocketSink {
+    fn publish(&mut self, line: &str) {
+        self.accept_subscribers();
+        let data = format!("{}\n", line);
+        self.subscribers.retain(|stream| {
+            let mut stream = stream;
+            stream.write_all(data.as_bytes()).is_ok()
+        });
+    }
+}
+
+impl Drop for UnixSocketSink {
+    fn drop(&mut self) {
+        let _res = fs::remove_file(&self.path);
+    }
+}
+
+/// Posts the events to an HTTP or HTTPS endpoint
+pub struct WebhookSink {
+    url: String,
+}
+
+impl WebhookSink {
+    pub fn new(url: String) -> Self {
+        Self { url }
+    }
+}
+
+impl EventSink for WebhookSink {
+    fn publish(&mut self, line: &str) {
+        if let Err(e) = post_json(&self.url, line) {
+            warn!("Unable to post the event to \"{}\": {}", self.url, e);
+        }
+    }
+}
+
+/// The sinks along with the kinds of events each of them receives
+pub struct EventSinks {
+    sinks: Vec<(Box<dyn EventSink>, Option<HashSet<String>>)>,
+}
+
+impl EventSinks {
+    pub fn new() -> Self {
+        Self { sinks: vec![] }
+    }
+
+    pub fn add_sink(&mut self, sink: Box<dyn EventSink>, events: Option<Vec<String>>) {
+        self.sinks
+            .push((sink, events.map(|e| e.into_iter().collect())));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sinks.is_empty()
+    }
+
+    fn dispatch(&mut self, time: u64, event: &ServerEvent) {
+        let kind = event.kind();
+        let mut sinks = self
+            .sinks
+            .iter_mut()
+            .filter(|(_, events)| events.as_ref().map_or(true, |e| e.contains(kind)))
+            .map(|(sink, _)| sink)
+            .peekable();
+
+        if sinks.peek().is_none() {
+            return;
+        }
+
+        match serde_json::to_string(&EventRecord { time, event }) {
+            Ok(line) => {
+                for sink in sinks {
+                    sink.publish(&line)
+                }
+            }
+            Err(e) => warn!("Unable to serialize the {} event: {}", kind, e),
+        }
+    }
+}
+
+/// Passes the events to the sinks running on their own thread,
+/// so that a slow sink doesn't hold up the server
+pub struct EventBus {
+    sender: Option<mpsc::SyncSender<(u64, ServerEvent)>>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl EventBus {
+    pub fn new(sinks: EventSinks) -> Self {
+        if sinks.is_empty() {
+            return Self {
+                sender: None,
+                thread: None,
+            };
+        }
+
+        let (sender, receiver) = mpsc::sync_channel(EVENT_QUEUE_SIZE);
+        let mut sinks = sinks;
+        let thread = thread::Builder::new()
+            .name("event sinks".to_string())
+            .spawn(move || {
+                while let Ok((time, event)) = receiver.recv() {
+                    sinks.dispatch(time, &event);
+                }
+            })
+            .expect("Unable to spawn the event sinks thread");
+
+        Self {
+            sender: Some(sender),
+            thread: Some(thread),
+        }
+    }
+
+    pub fn publish(&mut self, event: ServerEvent) {
+        if let Some(sender) = &self.sender {
+            let time = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs());
+            match sender.try_send((time, event)) {
+                Ok(()) => (),
+                Err(mpsc::TrySendError::Full((_, event))) => {
+                    warn!(
+                        "The event queue is full, dropping the {} event",
+                        event.kind()
+                    )
+                }
+                Err(mpsc::TrySendError::Disconnected(_)) => {
+                    error!("The event sinks thread stopped, events are no longer published");
+                    self.sender = None;
+                }
+            }
+        }
+    }
+
+    /// Waits for the queued events to reach the sinks
+    pub fn shut_down(self) {
+        drop(self.sender);
+        if let Some(thread) = self.thread {
+            if thread.join().is_err() {
+                error!("The event sinks thread panicked");
+            }
+        }
+    }
+}
+
+struct WebhookUrl<'a> {
+    /// The host with the port as given in the URL
+    authority: &'a str,
+    host: &'a str,
+    port: u16,
+    path: &'a str,
+    is_secure: bool,
+}
+
+fn parse_url(url: &str) -> io::Result<WebhookUrl<'_>> {
+    let (address, port, is_secure) = if url.starts_with("http://") {
+        (&url[7..], 80, false)
+    } else if url.starts_with("https://") {
+        (&url[8..], 443, true)
+    } else {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "only http:// and https:// webhooks are supported",
+        ));
+    };
+    let (authority, path) = match address.find('/') {
+        Some(index) => address.split_at(index),
+        None => (address, "/"),
+    };
+    let (host, port) = match authority.rfind(':') {
+        Some(index) => match authority[index + 1..].parse() {
+            Ok(port) => (&authority[..index], port),
+            Err(_) => return Err(Error::new(ErrorKind::InvalidInput, "invalid port")),
+        },
+        None => (authority, port),
+    };
+    Ok(WebhookUrl {
+        authority,
+        host,
+        port,
+        path,
+        is_secure,
+    })
+}
+
+fn post_json(url: &str, body: &str) -> io::Result<()> {
+    let url = parse_url(url)?;
+    let socket_address = (url.host, url.port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| Error::new(ErrorKind::NotFound, "unable to resolve the host"))?;
+
+    let stream = TcpStream::connect_timeout(&socket_address, WEBHOOK_TIMEOUT)?;
+    stream.set_read_timeout(Some(WEBHOOK_TIMEOUT))?;
+    stream.set_write_timeout(Some(WEBHOOK_TIMEOUT))?;
+
+    if url.is_secure {
+        #[cfg(feature = "tls-connections")]
+        {
+            let connector = SslConnector::builder(SslMethod::tls())?.build();
+            let stream = connector
+                .connect(url.host, stream)
+                .map_err(|e| Error::new(ErrorKind::Other, e.to_string()))?;
+            send_request(stream, &url, body)
+        }
+
+        #[cfg(not(feature = "tls-connections"))]
+        Err(Error::new(
+            ErrorKind::InvalidInput,
+            "https:// webhooks require the tls-connections feature",
+        ))
+    } else {
+        send_request(stream, &url, body)
+    }
+}
+
+fn send_request<S: Read + Write>(mut stream: S, url: &WebhookUrl, body: &str) -> io::Result<()> {
+    write!(
+        stream,
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        url.path,
+        url.authority,
+        body.len(),
+        body
+    )?;
+
+    let mut status_line = [0u8; 12];
+    stream.read_exact(&mut status_line)?;
+    if status_line.starts_with(b"HTTP/") && status_line[9] == b'2' {
+        Ok(())
+    } else {
+        Err(Error::new(
+            ErrorKind::Other,
+            format!(
+                "unexpected response \"{}\"",
+                String::from_utf8_lossy(&status_line)
+            ),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::sync::{Arc, Mutex};
+
+    struct TestSink(Arc<Mutex<Vec<String>>>);
+
+    impl EventSink for TestSink {
+        fn publish(&mut self, line: &str) {
+            self.0.lock().unwrap().push(line.to_string())
+        }
+    }
+
+    fn test_sink(sinks: &mut EventSinks, events: Option<&[&str]>) -> Arc<Mutex<Vec<String>>> {
+        let lines = Arc::new(Mutex::new(vec![]));
+        let events = events.map(|e| e.iter().map(|kind| kind.to_string()).collect());
+        sinks.add_sink(Box::new(TestSink(lines.clone())), events);
+        lines
+    }
+
+    fn room_closed(room: &str) -> ServerEvent {
+        ServerEvent::RoomClosed {
+            room: room.to_string(),
+        }
+    }
+
+    #[test]
+    fn sink_filtering() {
+        let mut sinks = EventSinks::new();
+        let all = test_sink(&mut sinks, None);
+        let rooms = test_sink(&mut sinks, Some(&["room_created", "room_closed"]));
+        let messages = test_sink(&mut sinks, Some(&["global_message"]));
+
+        sinks.dispatch(1, &room_closed("room"));
+        sinks.dispatch(
+            2,
+            &ServerEvent::GlobalMessage {
+                nick: "admin".to_string(),
+                message: "hi".to_string(),
+            },
+        );
+
+        let room_line = r#"{"time":1,"event":"room_closed","room":"room"}"#;
+        let message_line = r#"{"time":2,"event":"global_message","nick":"admin","message":"hi"}"#;
+        assert_eq!(*all.lock().unwrap(), vec![room_line, message_line]);
+        assert_eq!(*rooms.lock().unwrap(), vec![room_line]);
+        assert_eq!(*messages.lock().unwrap(), vec![message_line]);
+    }
+
+    #[test]
+    fn bus_dispatch() {
+        let mut sinks = EventSinks::new();
+        let lines = test_sink(&mut sinks, None);
+        let mut bus = EventBus::new(sinks);
+        for index in 0..10 {
+            bus.publish(room_closed(&index.to_string()));
+        }
+        drop(bus);
+
+        for _ in 0..100 {
+            if lines.lock().unwrap().len() == 10 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        let rooms: Vec<_> = lines
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["room"].clone())
+            .collect();
+        let expected: Vec<_> = (0..10)
+            .map(|i| serde_json::Value::from(i.to_string()))
+            .collect();
+        assert_eq!(rooms, expected);
+    }
+
+    #[test]
+    fn webhook_url() {
+        let url = parse_url("https://discord.com/api/webhooks/1/token").unwrap();
+        assert_eq!(
+            (url.authority, url.host, url.port, url.path, url.is_secure),
+            (
+                "discord.com",
+                "discord.com",
+                443,
+                "/api/webhooks/1/token",
+                true
+            )
+        );
+        let url = parse_url("http://localhost:8080").unwrap();
+        assert_eq!(
+            (url.authority, url.host, url.port, url.path, url.is_secure),
+            ("localhost:8080", "localhost", 8080, "/", false)
+        );
+        assert!(parse_url("ftp://localhost/").is_err());
+        assert!(parse_url("http://localhost:port/").is_err());
+    }
+
+    #[test]
+    fn event_record() {
+        let event = ServerEvent::RoomClosed {
+            room: "room".to_string(),
+        };
+        let line = serde_json::to_string(&EventRecord {
+            time: 1,
+            event: &event,
+        })
+        .unwrap();
+        assert_eq!(line, r#"{"time":1,"event":"room_closed","room":"room"}"#);
+    }
+}
@@ -0,0 +1,350 @@
+use std::{
+    fs,
+    io::{self, ErrorKind, Read, Write},
+    net::SocketAddr,
+    os::unix::{
+        io::{AsRawFd, FromRawFd, RawFd},
+        net::{UnixListener, UnixStream},
+    },
+    path::Path,
+    time::Duration,
+};
+
+use crate::{
+    core::{anteroom::HwAnteroom, server::HwServer, types::ClientId},
+    protocol::ProtocolDecoder,
+    utils,
+};
+use log::*;
+use mio::net::{TcpListener, TcpStream};
+use nix::sys::{
+    socket::{recvmsg, sendmsg, ControlMessage, ControlMessageOwned, MsgFlags},
+    uio::IoVec,
+};
+use serde_derive::{Deserialize, Serialize};
+
+/// Linux refuses to pass more than 253 descriptors in a single message
+const FDS_PER_MESSAGE: usize = 128;
+
+/// How long the old process waits for the tasks in flight before giving up on the handoff
+pub const DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long either side waits for the other one while passing the state
+const TRANSFER_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Sent by the new process once it owns the state, the old one keeps serving the clients until then
+const ACKNOWLEDGEMENT: u8 = 1;
+
+#[derive(Serialize)]
+pub struct ClientSnapshotRef<'a> {
+    pub id: ClientId,
+    pub peer_addr: SocketAddr,
+    pub decoder: &'a ProtocolDecoder,
+    #[serde(with = "utils::serde_buf")]
+    pub buf_out: &'a [u8],
+    pub idle_time: Duration,
+    pub pending_close: bool,
+}
+
+#[derive(Deserialize)]
+pub struct ClientSnapshot {
+    pub id: ClientId,
+    pub peer_addr: SocketAddr,
+    pub decoder: ProtocolDecoder,
+    #[serde(with = "utils::serde_buf")]
+    pub buf_out: Vec<u8>,
+    pub idle_time: Duration,
+    pub pending_close: bool,
+}
+
+/// State passed to the new server process, the descriptors of the listeners and the clients
+/// follow in the same order
+#[derive(Serialize)]
+pub struct SnapshotRef<'a> {
+    pub server: &'a HwServer,
+    pub anteroom: &'a HwAnteroom,
+    pub languages: Vec<(ClientId, &'static str)>,
+    pub has_secure_listener: bool,
+    pub clients: Vec<ClientSnapshotRef<'a>>,
+}
+
+#[derive(Deserialize)]
+pub struct Snapshot {
+    pub server: HwServer,
+    pub anteroom: HwAnteroom,
+    pub languages: Vec<(ClientId, String)>,
+    pub has_secure_listener: bool,
+    pub clients: Vec<ClientSnapshot>,
+}
+
+pub struct RestoredState {
+    pub server: HwServer,
+    pub anteroom: HwAnteroom,
+    pub languages: Vec<(ClientId, String)>,
+    pub clients: Vec<(ClientSnapshot, TcpStream)>,
+}
+
+pub struct Handoff {
+    pub listener: TcpListener,
+    pub secure_listener: Option<TcpListener>,
+    pub state: RestoredState,
+}
+
+/// Binds the socket the next server process connects to, replacing the one left by the
+/// previous process
+pub fn listen(path: &Path) -> io::Result<UnixListener> {
+    if path.exists() {
+        fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    listener.set_nonblocking(true)?;
+    Ok(listener)
+}
+
+/// Passes the state to the new process and waits for it to take over
+pub fn send(stream: &UnixStream, snapshot: &SnapshotRef, fds: &[RawFd]) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_write_timeout(Some(TRANSFER_TIMEOUT))?;
+    stream.set_read_timeout(Some(TRANSFER_TIMEOUT))?;
+
+    let data = serde_json::to_vec(snapshot)?;
+    let mut header = [0u8; 8];
+    header[..4].copy_from_slice(&(data.len() as u32).to_le_bytes());
+    header[4..].copy_from_slice(&(fds.len() as u32).to_le_bytes());
+
+    let mut writer = stream;
+    writer.write_all(&header)?;
+    writer.write_all(&data)?;
+
+    for chunk in fds.chunks(FDS_PER_MESSAGE) {
+        sendmsg(
+            stream.as_raw_fd(),
+            &[IoVec::from_slice(&[0])],
+            &[ControlMessage::ScmRights(chunk)],
+            MsgFlags::empty(),
+            None,
+        )
+        .map_err(|e| io::Error::new(ErrorKind::Other, e))?;
+    }
+
+    let mut acknowledgement = [0u8; 1];
+    writer.read_exact(&mut acknowledgement)?;
+    if acknowledgement[0] == ACKNOWLEDGEMENT {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            ErrorKind::InvalidData,
+            "unexpected handoff acknowledgement",
+        ))
+    }
+}
+
+fn receive(stream: &UnixStream) -> io::Result<(Snapshot, Vec<RawFd>)> {
+    let mut reader = stream;
+    let mut header = [0u8; 8];
+    reader.read_exact(&mut header)?;
+    let mut length = [0u8; 4];
+    length.copy_from_slice(&header[..4]);
+    let mut count = [0u8; 4];
+    count.copy_from_slice(&header[4..]);
+
+    let mut data = vec![0u8; u32::from_le_bytes(length) as usize];
+    reader.read_exact(&mut data)?;
+    let snapshot: Snapshot = serde_json::from_slice(&data)?;
+
+    let fds_count = u32::from_le_bytes(count) as usize;
+    let mut fds = Vec::with_capacity(fds_count);
+    let mut cmsg_buffer = nix::cmsg_space!([RawFd; FDS_PER_MESSAGE]);
+    while fds.len() < fds_count {
+        let mut byte = [0u8; 1];
+        let message = recvmsg(
+            stream.as_raw_fd(),
+            &[IoVec::from_mut_slice(&mut byte)],
+            Some(&mut cmsg_buffer),
+            MsgFlags::empty(),
+        )
+        .map_err(|e| io::Error::new(ErrorKind::Other, e))?;
+        if message.bytes == 0 {
+            return Err(ErrorKind::UnexpectedEof.into());
+        }
+        for cmsg in message.cmsgs() {
+            if let ControlMessageOwned::ScmRights(received) = cmsg {
+                fds.extend(received);
+            }
+        }
+    }
+
+    Ok((snapshot, fds))
+}
+
+/// Takes over the listeners and the clients of the server process listening on `path`.
+/// Returns `None` if there is no such process.
+pub fn take_over(path: &Path) -> io::Result<Option<Handoff>> {
+    let stream = match UnixStream::connect(path) {
+        Ok(stream) => stream,
+        Err(ref e)
+            if e.kind() == ErrorKind::NotFound || e.kind() == ErrorKind::ConnectionRefused =>
+        {
+            return Ok(None)
+        }
+        Err(e) => return Err(e),
+    };
+    stream.set_read_timeout(Some(DRAIN_TIMEOUT + TRANSFER_TIMEOUT))?;
+    stream.set_write_timeout(Some(TRANSFER_TIMEOUT))?;
+
+    let (snapshot, fds) = receive(&stream)?;
+    let listeners_count = if snapshot.has_secure_listener { 2 } else { 1 };
+    if fds.len() != listeners_count + snapshot.clients.len() {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            "descriptor count doesn't match the snapshot",
+        ));
+    }
+
+    let mut fds = fds.into_iter();
+    let mut next_listener = || unsafe {
+        TcpListener::from_std(std::net::TcpListener::from_raw_fd(fds.next().unwrap()))
+    };
+    let listener = next_listener()?;
+    let secure_listener = if snapshot.has_secure_listener {
+        Some(next_listener()?)
+    } else {
+        None
+    };
+
+    let mut clients = Vec::with_capacity(snapshot.clients.len());
+    for (client, fd) in snapshot.clients.into_iter().zip(fds) {
+        let stream = unsafe { std::net::TcpStream::from_raw_fd(fd) };
+        clients.push((client, TcpStream::from_stream(stream)?));
+    }
+
+    let mut writer = &stream;
+    writer.write_all(&[ACKNOWLEDGEMENT])?;
+    info!("Took over {} clients", clients.len());
+
+    Ok(Some(Handoff {
+        listener,
+        secure_listener,
+        state: RestoredState {
+            server: snapshot.server,
+            anteroom: snapshot.anteroom,
+            languages: snapshot.languages,
+            clients,
+        },
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{core::anteroom::HwAnteroomClient, protocol::messages::HwProtocolMessage};
+    use std::{net, num::NonZeroU16, thread};
+
+    fn create_server() -> HwServer {
+        let mut server = HwServer::new(4, 4, None);
+        server.add_client(
+            1,
+            HwAnteroomClient {
+                nick: Some("player".to_string()),
+                protocol_number: NonZeroU16::new(60),
+                server_salt: String::new(),
+                is_checker: false,
+                is_local_admin: false,
+                is_registered: false,
+                is_admin: false,
+                is_contributor: false,
+                is_secure: false,
+            },
+        );
+        server
+            .create_room(1, "room".to_string(), Some("secret".to_string()))
+            .unwrap();
+        server
+    }
+
+    #[test]
+    fn state_transfer() {
+        let path = std::env::temp_dir().join(format!("hw-handoff-{}", std::process::id()));
+        let handoff_listener = listen(&path).unwrap();
+        handoff_listener.set_nonblocking(false).unwrap();
+
+        let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let mut remote = net::TcpStream::connect(address).unwrap();
+        let (local, peer_addr) = listener.accept().unwrap();
+
+        let sender = thread::spawn(move || {
+            let server = create_server();
+            let mut anteroom = HwAnteroom::new(4);
+            anteroom.add_client(2, "salt".to_string(), false, false);
+            let mut decoder = ProtocolDecoder::new();
+            decoder.read_from(&mut &b"NICK\nnew"[..]).unwrap();
+
+            let snapshot = SnapshotRef {
+                server: &server,
+                anteroom: &anteroom,
+                languages: vec![(1, "ru")],
+                has_secure_listener: false,
+                clients: vec![ClientSnapshotRef {
+                    id: 1,
+                    peer_addr,
+                    decoder: &decoder,
+                    buf_out: b"PING\n\n",
+                    idle_time: Duration::from_secs(5),
+                    pending_close: false,
+                }],
+            };
+            let (stream, _) = handoff_listener.accept().unwrap();
+            send(
+                &stream,
+                &snapshot,
+                &[listener.as_raw_fd(), local.as_raw_fd()],
+            )
+        });
+
+        let handoff = take_over(&path).unwrap().unwrap();
+        sender.join().unwrap().unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(handoff.listener.local_addr().unwrap(), address);
+        assert!(handoff.secure_listener.is_none());
+
+        let state = handoff.state;
+        assert_eq!(state.server.find_client("player").unwrap().id, 1);
+        let room = state.server.find_room("room").unwrap();
+        assert!(room.password.as_ref().unwrap().matches("secret"));
+        assert_eq!(state.server.client(1).room_id, Some(room.id));
+        assert!(state.anteroom.clients.contains(2));
+        assert_eq!(state.languages, vec![(1, "ru".to_string())]);
+
+        let (mut client, mut stream) = state.clients.into_iter().next().unwrap();
+        assert_eq!(client.id, 1);
+        assert_eq!(client.peer_addr, peer_addr);
+        assert_eq!(client.buf_out, b"PING\n\n");
+        assert_eq!(client.idle_time, Duration::from_secs(5));
+        client.decoder.read_from(&mut &b"\n\n"[..]).unwrap();
+        match &client.decoder.extract_messages()[..] {
+            [HwProtocolMessage::Nick(nick)] => assert_eq!(nick, "new"),
+            _ => panic!("the partial message wasn't restored"),
+        }
+
+        stream.write_all(b"PONG\n\n").unwrap();
+        let mut reply = [0u8; 6];
+        remote.read_exact(&mut reply).unwrap();
+        assert_eq!(&reply, b"PONG\n\n");
+    }
+
+    #[test]
+    fn buffers_as_base64() {
+        let decoder = ProtocolDecoder::new();
+        let client = ClientSnapshotRef {
+            id: 0,
+            peer_addr: "127.0.0.1:46631".parse().unwrap(),
+            decoder: &decoder,
+            buf_out: b"BYE\n\n",
+            idle_time: Duration::from_secs(0),
+            pending_close: true,
+        };
+        let data = serde_json::to_value(&client).unwrap();
+        assert_eq!(data["buf_out"], "QllFCgo=");
+    }
+}
@@ -1,14 +1,16 @@
 use std::{
+    fs,
     fs::{File, OpenOptions},
     io::{Error, ErrorKind, Read, Result, Write},
+    mem::replace,
+    path::Path,
     sync::mpsc,
     thread,
 };
 
-use crate::{
-    handlers::{IoResult, IoTask},
-    server::database::Database,
-};
+use crate::handlers::{exchange_registry, IoResult, IoTask};
+#[cfg(feature = "official-server")]
+use crate::server::database::Database;
 use log::*;
 use mio::{Evented, Poll, PollOpt};
 use mio_extras::channel;
@@ -18,6 +20,9 @@ pub type RequestId = u32;
 pub struct IoThread {
     core_tx: mpsc::Sender<(RequestId, IoTask)>,
     core_rx: channel::Receiver<(RequestId, IoResult)>,
+    thread: thread::JoinHandle<()>,
+    #[cfg(feature = "official-server")]
+    team_reservations: Vec<(String, String)>,
 }
 
 impl IoThread {
@@ -25,12 +30,22 @@ impl IoThread {
         let (core_tx, io_rx) = mpsc::channel();
         let (io_tx, core_rx) = channel::channel();
 
-        let mut db = Database::new();
-        db.connect("localhost");
+        #[cfg(feature = "official-server")]
+        let (mut db, team_reservations) = {
+            let mut db = Database::new();
+            db.connect("localhost");
+
+            let team_reservations = db.get_team_reservations().unwrap_or_else(|e| {
+                warn!("Unable to load team reservations: {}", e);
+                vec![]
+            });
+            (db, team_reservations)
+        };
 
-        thread::spawn(move || {
+        let thread = thread::spawn(move || {
             while let Ok((request_id, task)) = io_rx.recv() {
                 let response = match task {
+                    #[cfg(feature = "official-server")]
                     IoTask::CheckRegistered { nick } => match db.is_registered(&nick) {
                         Ok(is_registered) => IoResult::AccountRegistered(is_registered),
                         Err(e) => {
@@ -39,6 +54,7 @@ impl IoThread {
                         }
                     },
 
+                    #[cfg(feature = "official-server")]
                     IoTask::GetAccount {
                         nick,
                         protocol,
@@ -61,6 +77,7 @@ impl IoThread {
                         }
                     }
 
+                    #[cfg(feature = "official-server")]
                     IoTask::GetCheckerAccount { nick, password } => {
                         match db.get_checker_account(&nick, &password) {
                             Ok(is_registered) => IoResult::CheckerAccount { is_registered },
@@ -73,6 +90,7 @@ impl IoThread {
                         }
                     }
 
+                    #[cfg(feature = "official-server")]
                     IoTask::GetReplay { id } => {
                         let result = match db.get_replay_name(id) {
                             Ok(Some(filename)) => {
@@ -135,18 +153,109 @@ impl IoThread {
                         };
                         IoResult::LoadRoom(room_id, result)
                     }
+
+                    #[cfg(feature = "official-server")]
+                    IoTask::StoreTeam { owner, team } => {
+                        let result = serde_yaml::to_string(&team)
+                            .map_err(|e| e.to_string())
+                            .and_then(|data| {
+                                db.store_team(&owner, &team.name, &data)
+                                    .map_err(|e| e.to_string())
+                            });
+                        let is_stored = match result {
+                            Ok(is_stored) => is_stored,
+                            Err(e) => {
+                                warn!("Unable to store team \"{}\": {}", team.name, e);
+                                false
+                            }
+                        };
+                        IoResult::StoreTeam(team.name, is_stored)
+                    }
+
+                    #[cfg(feature = "official-server")]
+                    IoTask::GetStoredTeam {
+                        room_id,
+                        owner,
+                        name,
+                    } => {
+                        let team = match db.get_stored_team(&owner, &name) {
+                            Ok(Some(data)) => match serde_yaml::from_str(&data) {
+                                Ok(team) => Some(Box::new(team)),
+                                Err(e) => {
+                                    warn!("Unable to deserialize stored team \"{}\": {}", name, e);
+                                    None
+                                }
+                            },
+                            Ok(None) => None,
+                            Err(e) => {
+                                warn!("Unable to get stored team \"{}\": {}", name, e);
+                                None
+                            }
+                        };
+                        IoResult::StoredTeam(room_id, team)
+                    }
+
+                    IoTask::SaveFixedRooms { path, contents } => {
+                        if let Err(e) = save_file_atomically(&path, &contents) {
+                            warn!("Unable to save fixed rooms to {:?}: {}", path, e);
+                        }
+                        continue;
+                    }
+
+                    IoTask::SyncFederation {
+                        registry,
+                        port,
+                        contents,
+                        now,
+                    } => IoResult::FederationInstances(exchange_registry(
+                        &registry, port, &contents, now,
+                    )),
+
+                    #[cfg(feature = "official-server")]
+                    IoTask::DeleteStoredTeam { owner, name } => {
+                        let is_deleted = match db.delete_stored_team(&owner, &name) {
+                            Ok(is_deleted) => is_deleted,
+                            Err(e) => {
+                                warn!("Unable to delete stored team \"{}\": {}", name, e);
+                                false
+                            }
+                        };
+                        IoResult::DeleteStoredTeam(name, is_deleted)
+                    }
+
+                    #[cfg(not(feature = "official-server"))]
+                    _ => {
+                        warn!("The IO task is only supported by the official server");
+                        continue;
+                    }
                 };
                 io_tx.send((request_id, response));
             }
         });
 
-        Self { core_rx, core_tx }
+        Self {
+            core_rx,
+            core_tx,
+            thread,
+            #[cfg(feature = "official-server")]
+            team_reservations,
+        }
+    }
+
+    #[cfg(feature = "official-server")]
+    pub fn take_team_reservations(&mut self) -> Vec<(String, String)> {
+        replace(&mut self.team_reservations, vec![])
     }
 
     pub fn send(&self, request_id: RequestId, task: IoTask) {
         self.core_tx.send((request_id, task)).unwrap();
     }
 
+    /// Sends a task which has no result to report back
+    pub fn post(&self, task: IoTask) {
+        self.send(0, task)
+    }
+
     pub fn try_recv(&self) -> Option<(RequestId, IoResult)> {
         match self.core_rx.try_recv() {
             Ok(result) => Some(result),
@@ -159,6 +268,17 @@ impl IoThread {
         self.core_rx
             .register(poll, token, mio::Ready::readable(), PollOpt::edge())
     }
+
+    /// Waits for the queued tasks to complete, their results are discarded
+    pub fn shut_down(self) {
+        let IoThread {
+            core_tx, thread, ..
+        } = self;
+        drop(core_tx);
+        if thread.join().is_err() {
+            error!("The IO thread panicked");
+        }
+    }
 }
 
 fn save_file(filename: &str, contents: &str) -> Result<()> {
@@ -166,6 +286,16 @@ fn save_file(filename: &str, contents: &str) -> Result<()> {
     writer.write_all(contents.as_bytes())
 }
 
+/// Writes the file next to its final location first so that a crash in the middle
+/// of the write can't leave a truncated file behind
+fn save_file_atomically(path: &Path, contents: &str) -> Result<()> {
+    let temp_path = path.with_extension("tmp");
+    let mut writer = File::create(&temp_path)?;
+    writer.write_all(contents.as_bytes())?;
+    writer.sync_all()?;
+    fs::rename(temp_path, path)
+}
+
 fn load_file(filename: &str) -> Result<String> {
     let mut reader = File::open(filename)?;
     let mut result = String::new();
@@ -1,11 +1,15 @@
 extern crate slab;
 
 use std::{
-    collections::HashSet,
+    collections::{HashMap, HashSet, VecDeque},
     io,
     io::{Error, ErrorKind, Read, Write},
     mem::{replace, swap},
     net::{IpAddr, Ipv4Addr, SocketAddr},
+    ops::RangeInclusive,
+    os::unix::{io::AsRawFd, net::UnixStream},
+    path::PathBuf,
+    thread,
 };
 
 use log::*;
@@ -20,13 +24,19 @@ use slab::Slab;
 use crate::{
     core::types::ClientId,
     handlers,
-    handlers::{IoResult, IoTask, ServerState},
+    handlers::{Federation, IdlePolicy, IoResult, IoTask, ServerState},
     protocol::{messages::HwServerMessage::Redirect, messages::*, ProtocolDecoder},
     utils,
 };
 
-#[cfg(feature = "official-server")]
-use super::io::{IoThread, RequestId};
+use super::{
+    events::{
+        EventBus, EventSink, EventSinks, JsonLinesSink, SinkConfig, UnixSocketSink, WebhookSink,
+    },
+    handoff,
+    handoff::{ClientSnapshot, ClientSnapshotRef, Handoff, RestoredState, SnapshotRef},
+    io::{IoThread, RequestId},
+};
 
 #[cfg(feature = "tls-connections")]
 use openssl::{
@@ -36,11 +46,14 @@ use openssl::{
         SslMethod, SslOptions, SslStream, SslStreamBuilder, SslVerifyMode,
     },
 };
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 const MAX_BYTES_PER_READ: usize = 2048;
 const SEND_PING_TIMEOUT: Duration = Duration::from_secs(30);
 const DROP_CLIENT_TIMEOUT: Duration = Duration::from_secs(30);
+const FEDERATION_SYNC_TIMEOUT: Duration = Duration::from_secs(5);
+const IDLE_CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+const GAME_CHECK_TIMEOUT: Duration = Duration::from_millis(250);
 const PING_PROBES_COUNT: u8 = 2;
 
 #[derive(Hash, Eq, PartialEq, Copy, Clone)]
@@ -84,7 +97,12 @@ pub struct NetworkClient {
     decoder: ProtocolDecoder,
     buf_out: netbuf::Buf,
     timeout: timer::Timeout,
+    last_activity: Instant,
     pending_close: bool,
+    #[cfg(feature = "tls-connections")]
+    is_initialized: bool,
+    #[cfg(feature = "tls-connections")]
+    pending_tls_upgrade: bool,
 }
 
 impl NetworkClient {
@@ -101,7 +119,80 @@ impl NetworkClient {
             decoder: ProtocolDecoder::new(),
             buf_out: netbuf::Buf::new(),
             timeout,
+            last_activity: Instant::now(),
             pending_close: false,
+            #[cfg(feature = "tls-connections")]
+            is_initialized: false,
+            #[cfg(feature = "tls-connections")]
+            pending_tls_upgrade: false,
+        }
+    }
+
+    fn restore(snapshot: ClientSnapshot, stream: TcpStream, timeout: timer::Timeout) -> Self {
+        let mut client = NetworkClient::new(
+            snapshot.id,
+            ClientSocket::Plain(stream),
+            snapshot.peer_addr,
+            timeout,
+        );
+        client.decoder = snapshot.decoder;
+        client.send_raw_msg(&snapshot.buf_out);
+        client.last_activity = Instant::now()
+            .checked_sub(snapshot.idle_time)
+            .unwrap_or_else(Instant::now);
+        client.pending_close = snapshot.pending_close;
+        #[cfg(feature = "tls-connections")]
+        {
+            client.is_initialized = true;
+        }
+        client
+    }
+
+    /// TLS sessions can't be passed to another process, neither can the connections
+    /// that are about to start one
+    fn can_hand_off(&self) -> bool {
+        match self.socket {
+            #[cfg(feature = "tls-connections")]
+            ClientSocket::Plain(_) => !self.pending_tls_upgrade,
+            #[cfg(not(feature = "tls-connections"))]
+            ClientSocket::Plain(_) => true,
+            #[cfg(feature = "tls-connections")]
+            _ => false,
+        }
+    }
+
+    #[cfg(feature = "tls-connections")]
+    fn start_tls(&mut self, context: &SslContext) -> io::Result<()> {
+        let stream = match replace(&mut self.socket, ClientSocket::SslHandshake(None)) {
+            ClientSocket::Plain(stream) => stream,
+            socket => {
+                self.socket = socket;
+                return Ok(());
+            }
+        };
+
+        debug!(
+            "Upgrading the connection of {} ({}) to TLS",
+            self.id, self.peer_addr
+        );
+        let ssl = Ssl::new(context).map_err(|e| Error::new(ErrorKind::Other, e))?;
+        let mut builder = SslStreamBuilder::new(ssl, stream);
+        builder.set_accept_state();
+        match builder.handshake() {
+            Ok(stream) => {
+                self.socket = ClientSocket::SslStream(stream);
+                Ok(())
+            }
+            Err(HandshakeError::WouldBlock(stream)) => {
+                self.socket = ClientSocket::SslHandshake(Some(stream));
+                Ok(())
+            }
+            Err(HandshakeError::Failure(stream)) => {
+                self.socket = ClientSocket::SslHandshake(Some(stream));
+                debug!("TLS handshake with {} ({}) failed", self.id, self.peer_addr);
+                Err(Error::new(ErrorKind::Other, "Connection failure"))
+            }
+            Err(HandshakeError::SetupFailure(e)) => Err(Error::new(ErrorKind::Other, e)),
         }
     }
 
@@ -171,7 +262,15 @@ impl NetworkClient {
     }
 
     pub fn read(&mut self) -> NetworkResult<Vec<HwProtocolMessage>> {
-        match self.socket {
+        #[cfg(feature = "tls-connections")]
+        {
+            // anything sent before the handshake must not be trusted
+            if self.pending_tls_upgrade {
+                return Ok((Vec::new(), NetworkClientState::Idle));
+            }
+        }
+
+        let result = match self.socket {
             ClientSocket::Plain(ref mut stream) => {
                 NetworkClient::read_impl(&mut self.decoder, stream, self.id, &self.peer_addr)
             }
@@ -184,7 +283,18 @@ impl NetworkClient {
             ClientSocket::SslStream(ref mut stream) => {
                 NetworkClient::read_impl(&mut self.decoder, stream, self.id, &self.peer_addr)
             }
+        };
+
+        if let Ok((messages, _)) = &result {
+            let is_active = messages.iter().any(|m| match m {
+                HwProtocolMessage::Ping | HwProtocolMessage::Pong => false,
+                _ => true,
+            });
+            if is_active {
+                self.last_activity = Instant::now();
+            }
         }
+        result
     }
 
     fn write_impl<W: Write>(
@@ -258,14 +368,12 @@ struct ServerSsl {
     context: SslContext,
 }
 
-#[cfg(feature = "official-server")]
 pub struct IoLayer {
     next_request_id: RequestId,
-    request_queue: Vec<(RequestId, ClientId)>,
+    request_queue: Vec<(RequestId, Option<ClientId>)>,
     io_thread: IoThread,
 }
 
-#[cfg(feature = "official-server")]
 impl IoLayer {
     fn new() -> Self {
         Self {
@@ -275,14 +383,14 @@ impl IoLayer {
         }
     }
 
-    fn send(&mut self, client_id: ClientId, task: IoTask) {
+    fn send(&mut self, client_id: Option<ClientId>, task: IoTask) {
         let request_id = self.next_request_id;
         self.next_request_id += 1;
         self.request_queue.push((request_id, client_id));
         self.io_thread.send(request_id, task);
     }
 
-    fn try_recv(&mut self) -> Option<(ClientId, IoResult)> {
+    fn try_recv(&mut self) -> Option<(Option<ClientId>, IoResult)> {
         let (request_id, result) = self.io_thread.try_recv()?;
         if let Some(index) = self
             .request_queue
@@ -296,10 +404,14 @@ impl IoLayer {
         }
     }
 
+    fn has_pending_requests(&self) -> bool {
+        !self.request_queue.is_empty()
+    }
+
     fn cancel(&mut self, client_id: ClientId) {
         let mut index = 0;
         while index < self.request_queue.len() {
-            if self.request_queue[index].1 == client_id {
+            if self.request_queue[index].1 == Some(client_id) {
                 self.request_queue.swap_remove(index);
             } else {
                 index += 1;
@@ -323,9 +435,13 @@ pub struct NetworkLayer {
     pending_cache: Vec<(ClientId, NetworkClientState)>,
     #[cfg(feature = "tls-connections")]
     ssl: ServerSsl,
-    #[cfg(feature = "official-server")]
     io: IoLayer,
     timer: timer::Timer<TimerData>,
+    federation_timer: timer::Timer<()>,
+    idle_timer: timer::Timer<()>,
+    game_timer: timer::Timer<()>,
+    events: EventBus,
+    lost_clients: Vec<ClientId>,
 }
 
 fn register_read<E: Evented>(poll: &Poll, evented: &E, token: mio::Token) -> io::Result<()> {
@@ -351,18 +467,139 @@ fn create_drop_timeout(timer: &mut timer::Timer<TimerData>, client_id: ClientId)
 }
 
 impl NetworkLayer {
-    pub fn register(&self, poll: &Poll) -> io::Result<()> {
+    /// Registers the server sockets, as well as the clients taken over from the previous
+    /// server process
+    pub fn register(&mut self, poll: &Poll) -> io::Result<()> {
         register_read(poll, &self.listener, utils::SERVER_TOKEN)?;
         #[cfg(feature = "tls-connections")]
         register_read(poll, &self.ssl.listener, utils::SECURE_SERVER_TOKEN)?;
         register_read(poll, &self.timer, utils::TIMER_TOKEN)?;
+        register_read(poll, &self.federation_timer, utils::FEDERATION_TOKEN)?;
+        register_read(poll, &self.idle_timer, utils::IDLE_TOKEN)?;
+        register_read(poll, &self.game_timer, utils::GAME_TOKEN)?;
 
-        #[cfg(feature = "official-server")]
         self.io.io_thread.register_rx(poll, utils::IO_TOKEN)?;
 
+        for (client_id, client) in self.clients.iter() {
+            let interest = if client.pending_close {
+                Ready::writable()
+            } else {
+                Ready::readable() | Ready::writable()
+            };
+            poll.register(
+                client.socket.inner(),
+                Token(client_id),
+                interest,
+                PollOpt::edge(),
+            )?;
+        }
+
+        for client_id in replace(&mut self.lost_clients, vec![]) {
+            let mut response = handlers::Response::new(client_id);
+            handlers::handle_client_loss(&mut self.server_state, client_id, &mut response);
+            self.handle_response(response, poll);
+        }
+
         Ok(())
     }
 
+    /// Passes the listeners, the clients and the server state to the new server process.
+    /// The tasks in flight are completed first, the clients that can't be passed along
+    /// are disconnected.
+    pub fn hand_off(&mut self, poll: &Poll, stream: &UnixStream) -> io::Result<()> {
+        self.drain_tasks(poll)?;
+        self.drop_unmovable_clients(poll);
+        self.drain_tasks(poll)?;
+
+        let now = Instant::now();
+        let mut fds = vec![self.listener.as_raw_fd()];
+        #[cfg(feature = "tls-connections")]
+        fds.push(self.ssl.listener.as_raw_fd());
+
+        let mut clients = vec![];
+        for (client_id, client) in self.clients.iter() {
+            if client.can_hand_off() {
+                fds.push(client.socket.inner().as_raw_fd());
+                clients.push(ClientSnapshotRef {
+                    id: client_id,
+                    peer_addr: client.peer_addr,
+                    decoder: &client.decoder,
+                    buf_out: &client.buf_out[..],
+                    idle_time: now.duration_since(client.last_activity),
+                    pending_close: client.pending_close,
+                });
+            }
+        }
+
+        let languages = self
+            .server_state
+            .server
+            .iter_clients()
+            .filter_map(|c| c.language.map(|l| (c.id, l)))
+            .collect();
+
+        let snapshot = SnapshotRef {
+            server: &self.server_state.server,
+            anteroom: &self.server_state.anteroom,
+            languages,
+            has_secure_listener: cfg!(feature = "tls-connections"),
+            clients,
+        };
+        handoff::send(stream, &snapshot, &fds)?;
+        info!("Handed off {} clients", snapshot.clients.len());
+        Ok(())
+    }
+
+    /// Waits for the results of the IO tasks, they would be lost with this process otherwise
+    fn drain_tasks(&mut self, poll: &Poll) -> io::Result<()> {
+        let deadline = Instant::now() + handoff::DRAIN_TIMEOUT;
+        loop {
+            self.handle_io_result(poll)?;
+
+            if !self.io.has_pending_requests() {
+                return Ok(());
+            } else if Instant::now() >= deadline {
+                return Err(Error::new(
+                    ErrorKind::TimedOut,
+                    "the tasks in flight didn't complete",
+                ));
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    fn drop_unmovable_clients(&mut self, poll: &Poll) {
+        let client_ids: Vec<_> = self
+            .clients
+            .iter()
+            .filter(|(_, client)| !client.can_hand_off() && !client.pending_close)
+            .map(|(client_id, _)| client_id)
+            .collect();
+        if !client_ids.is_empty() {
+            warn!(
+                "Disconnecting {} clients that can't be handed off",
+                client_ids.len()
+            );
+        }
+
+        for client_id in client_ids {
+            let mut response = handlers::Response::new(client_id);
+            handlers::handle_client_drop(&mut self.server_state, client_id, &mut response);
+            self.handle_response(response, poll);
+            if let Some(client) = self.clients.get_mut(client_id) {
+                if let Err(e) = client.write() {
+                    debug!("Unable to send the goodbye to client {}: {}", client_id, e);
+                }
+            }
+        }
+    }
+
+    /// Lets the IO and the event sinks threads finish their queues
+    pub fn shut_down(self) {
+        self.io.io_thread.shut_down();
+        self.events.shut_down();
+    }
+
     fn deregister_client(&mut self, poll: &Poll, id: ClientId, is_error: bool) {
         if let Some(ref mut client) = self.clients.get_mut(id) {
             poll.deregister(client.socket.inner())
@@ -386,7 +623,6 @@ impl NetworkLayer {
                 info!("client {} ({}) removed", client.id, client.peer_addr);
                 self.clients.remove(id);
             }
-            #[cfg(feature = "official-server")]
             self.io.cancel(id);
         }
     }
@@ -420,12 +656,18 @@ impl NetworkLayer {
     }
 
     fn handle_response(&mut self, mut response: handlers::Response, poll: &Poll) {
+        if let Some((path, contents)) = self.server_state.server.take_fixed_rooms_update() {
+            self.io
+                .io_thread
+                .post(IoTask::SaveFixedRooms { path, contents });
+        }
+
         if response.is_empty() {
             return;
         }
 
         debug!("{} pending server messages", response.len());
-        let output = response.extract_messages(&mut self.server_state.server);
+        let output = response.extract_messages(&self.server_state);
         for (clients, message) in output {
             debug!("Message {:?} to {:?}", message, clients);
             let msg_string = message.to_raw_protocol();
@@ -437,18 +679,31 @@ impl NetworkLayer {
                 }
             }
         }
+        self.server_state
+            .server
+            .update_room_listings(response.extract_room_listings());
+
+        for event in response.extract_events() {
+            self.events.publish(event);
+        }
 
         for client_id in response.extract_removed_clients() {
             self.deregister_client(poll, client_id, false);
         }
 
-        #[cfg(feature = "official-server")]
-        {
-            let client_id = response.client_id();
-            for task in response.extract_io_tasks() {
-                self.io.send(client_id, task);
+        #[cfg(feature = "tls-connections")]
+        for client_id in response.extract_tls_upgrades() {
+            if let Some(client) = self.clients.get_mut(client_id) {
+                client.pending_tls_upgrade = true;
+                self.pending
+                    .insert((client_id, NetworkClientState::NeedsWrite));
             }
         }
+
+        let client_id = response.sender_id();
+        for task in response.extract_io_tasks() {
+            self.io.send(client_id, task);
+        }
     }
 
     pub fn handle_timeout(&mut self, poll: &Poll) -> io::Result<()> {
@@ -485,12 +740,61 @@ impl NetworkLayer {
         Ok(())
     }
 
-    #[cfg(feature = "official-server")]
+    pub fn handle_federation_sync(&mut self, poll: &Poll) -> io::Result<()> {
+        while let Some(()) = self.federation_timer.poll() {
+            let mut response = handlers::Response::for_server();
+            handlers::handle_federation_sync(&self.server_state, &mut response);
+            self.handle_response(response, poll);
+            self.federation_timer
+                .set_timeout(FEDERATION_SYNC_TIMEOUT, ());
+        }
+        Ok(())
+    }
+
+    pub fn handle_idle_check(&mut self, poll: &Poll) -> io::Result<()> {
+        while let Some(()) = self.idle_timer.poll() {
+            let now = Instant::now();
+            let idle_times: HashMap<_, _> = self
+                .clients
+                .iter()
+                .map(|(id, client)| (id, now.duration_since(client.last_activity)))
+                .collect();
+            for response in handlers::handle_idle_clients(&mut self.server_state, &idle_times) {
+                self.handle_response(response, poll);
+            }
+            self.idle_timer.set_timeout(IDLE_CHECK_TIMEOUT, ());
+        }
+        Ok(())
+    }
+
+    pub fn handle_game_check(&mut self, poll: &Poll) -> io::Result<()> {
+        while let Some(()) = self.game_timer.poll() {
+            for response in handlers::handle_running_games(&mut self.server_state, Instant::now()) {
+                self.handle_response(response, poll);
+            }
+            self.game_timer.set_timeout(GAME_CHECK_TIMEOUT, ());
+        }
+        Ok(())
+    }
+
     pub fn handle_io_result(&mut self, poll: &Poll) -> io::Result<()> {
         while let Some((client_id, result)) = self.io.try_recv() {
-            debug!("Handling io result {:?} for client {}", result, client_id);
-            let mut response = handlers::Response::new(client_id);
-            handlers::handle_io_result(&mut self.server_state, client_id, &mut response, result);
+            let response = if let Some(client_id) = client_id {
+                debug!("Handling io result {:?} for client {}", result, client_id);
+                let mut response = handlers::Response::new(client_id);
+                handlers::handle_io_result(
+                    &mut self.server_state,
+                    client_id,
+                    &mut response,
+                    result,
+                );
+                response
+            } else {
+                debug!("Handling io result {:?} for the server", result);
+                let mut response = handlers::Response::for_server();
+                handlers::handle_server_io_result(&mut self.server_state, &mut response, result);
+                response
+            };
             self.handle_response(response, poll);
         }
         Ok(())
@@ -515,8 +819,38 @@ impl NetworkLayer {
         }
     }
 
+    /// Switches the client to TLS after the reply to its STARTTLS is sent in plaintext
+    #[cfg(feature = "tls-connections")]
+    fn upgrade_client(&mut self, poll: &Poll, client_id: ClientId) -> io::Result<()> {
+        if let Some(client) = self.clients.get_mut(client_id) {
+            if client.pending_tls_upgrade && !client.has_pending_sends() {
+                client.pending_tls_upgrade = false;
+                if let Err(e) = client.start_tls(&self.ssl.context) {
+                    return self.operation_failed(poll, client_id, &e, "Unable to start TLS");
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "tls-connections")]
+    fn handle_tls_connected(&mut self, poll: &Poll, client_id: ClientId) {
+        if !self.clients[client_id].is_initialized {
+            self.init_client(poll, client_id)
+        }
+    }
+
     fn init_client(&mut self, poll: &Poll, client_id: ClientId) {
         let mut response = handlers::Response::new(client_id);
+        #[cfg(feature = "tls-connections")]
+        {
+            self.clients[client_id].is_initialized = true;
+        }
+        let is_secure = match self.clients[client_id].socket {
+            ClientSocket::Plain(_) => false,
+            #[cfg(feature = "tls-connections")]
+            _ => true,
+        };
 
         if let ClientSocket::Plain(_) = self.clients[client_id].socket {
             #[cfg(feature = "tls-connections")]
@@ -530,6 +864,7 @@ impl NetworkLayer {
                 &mut response,
                 addr.octets(),
                 addr.is_loopback(),
+                is_secure,
             );
             self.handle_response(response, poll);
         } else {
@@ -595,6 +930,13 @@ impl NetworkLayer {
                 for message in messages {
                     debug!("Handling message {:?} for client {}", message, client_id);
                     handlers::handle(&mut self.server_state, client_id, &mut response, message);
+                    if response.has_tls_upgrades() {
+                        // the client must wait for the reply to STARTTLS before sending more
+                        if let Some(client) = self.clients.get_mut(client_id) {
+                            client.decoder.discard();
+                        }
+                        break;
+                    }
                 }
                 match state {
                     NetworkClientState::NeedsRead => {
@@ -602,7 +944,7 @@ impl NetworkLayer {
                     }
                     NetworkClientState::Closed => self.client_error(&poll, client_id)?,
                     #[cfg(feature = "tls-connections")]
-                    NetworkClientState::Connected => self.init_client(poll, client_id),
+                    NetworkClientState::Connected => self.handle_tls_connected(poll, client_id),
                     _ => {}
                 };
             }
@@ -634,6 +976,11 @@ impl NetworkLayer {
             Ok(((), state)) if state == NetworkClientState::Closed => {
                 self.deregister_client(poll, client_id, false);
             }
+            #[cfg(feature = "tls-connections")]
+            Ok(((), NetworkClientState::Connected)) => self.handle_tls_connected(poll, client_id),
+            #[cfg(feature = "tls-connections")]
+            Ok(_) => self.upgrade_client(poll, client_id)?,
+            #[cfg(not(feature = "tls-connections"))]
             Ok(_) => (),
             Err(e) => {
                 self.operation_failed(poll, client_id, &e, "Error while writing to client socket")?
@@ -682,6 +1029,13 @@ pub struct NetworkLayerBuilder {
     secure_listener: Option<TcpListener>,
     clients_capacity: usize,
     rooms_capacity: usize,
+    fixed_rooms_file: Option<PathBuf>,
+    federation: Option<Federation>,
+    event_sinks: Vec<SinkConfig>,
+    idle_policy: IdlePolicy,
+    #[cfg(feature = "tls-connections")]
+    requires_secure_login: bool,
+    restored_state: Option<RestoredState>,
 }
 
 impl Default for NetworkLayerBuilder {
@@ -691,6 +1045,13 @@ impl Default for NetworkLayerBuilder {
             rooms_capacity: 512,
             listener: None,
             secure_listener: None,
+            fixed_rooms_file: None,
+            federation: None,
+            event_sinks: vec![],
+            idle_policy: IdlePolicy::default(),
+            #[cfg(feature = "tls-connections")]
+            requires_secure_login: false,
+            restored_state: None,
         }
     }
 }
@@ -710,6 +1071,61 @@ impl NetworkLayerBuilder {
         }
     }
 
+    pub fn with_fixed_rooms_file(self, path: PathBuf) -> Self {
+        Self {
+            fixed_rooms_file: Some(path),
+            ..self
+        }
+    }
+
+    pub fn with_federation(
+        self,
+        registry: PathBuf,
+        port: u16,
+        protocols: Option<RangeInclusive<u16>>,
+    ) -> Self {
+        Self {
+            federation: Some(Federation::new(registry, port, protocols)),
+            ..self
+        }
+    }
+
+    pub fn with_event_sinks(self, event_sinks: Vec<SinkConfig>) -> Self {
+        Self {
+            event_sinks,
+            ..self
+        }
+    }
+
+    pub fn with_idle_policy(self, idle_policy: IdlePolicy) -> Self {
+        Self {
+            idle_policy,
+            ..self
+        }
+    }
+
+    #[cfg(feature = "tls-connections")]
+    pub fn with_secure_login_required(self) -> Self {
+        Self {
+            requires_secure_login: true,
+            ..self
+        }
+    }
+
+    pub fn with_handoff(self, handoff: Handoff) -> Self {
+        Self {
+            listener: Some(handoff.listener),
+            secure_listener: handoff.secure_listener.or(self.secure_listener),
+            restored_state: Some(handoff.state),
+            ..self
+        }
+    }
+
+    #[cfg(feature = "tls-connections")]
+    pub fn has_secure_listener(&self) -> bool {
+        self.secure_listener.is_some()
+    }
+
     #[cfg(feature = "tls-connections")]
     fn create_ssl_context(listener: TcpListener) -> ServerSsl {
         let mut builder = SslContextBuilder::new(SslMethod::tls()).unwrap();
@@ -732,12 +1148,93 @@ impl NetworkLayerBuilder {
     }
 
     pub fn build(self) -> NetworkLayer {
-        let server_state = ServerState::new(self.clients_capacity, self.rooms_capacity);
+        let mut server_state = ServerState::new(
+            self.clients_capacity,
+            self.rooms_capacity,
+            self.fixed_rooms_file,
+        );
+        server_state.federation = self.federation;
+        server_state.idle_policy = self.idle_policy;
+        #[cfg(feature = "tls-connections")]
+        {
+            server_state.requires_secure_login = self.requires_secure_login;
+        }
+
+        let io = IoLayer::new();
+        #[cfg(feature = "official-server")]
+        let io = {
+            let mut io = io;
+            for (name, owner) in io.io_thread.take_team_reservations() {
+                server_state.server.reserve_team_name(&name, &owner);
+            }
+            io
+        };
 
-        let clients = Slab::with_capacity(self.clients_capacity);
+        let mut sinks = EventSinks::new();
+        for config in self.event_sinks {
+            let sink: io::Result<Box<dyn EventSink>> =
+                match (config.file, config.socket, config.webhook) {
+                    (Some(path), None, None) => {
+                        JsonLinesSink::open(path).map(|s| Box::new(s) as Box<dyn EventSink>)
+                    }
+                    (None, Some(path), None) => {
+                        UnixSocketSink::bind(path).map(|s| Box::new(s) as Box<dyn EventSink>)
+                    }
+                    (None, None, Some(url)) => Ok(Box::new(WebhookSink::new(url))),
+                    _ => Err(Error::new(
+                        ErrorKind::InvalidInput,
+                        "exactly one of file, socket or webhook should be set",
+                    )),
+                };
+            match sink {
+                Ok(sink) => sinks.add_sink(sink, config.events),
+                Err(e) => warn!("Unable to create the event sink: {}", e),
+            }
+        }
+        let events = EventBus::new(sinks);
+
+        let mut clients = Slab::with_capacity(self.clients_capacity);
         let pending = HashSet::with_capacity(2 * self.clients_capacity);
         let pending_cache = Vec::with_capacity(2 * self.clients_capacity);
-        let timer = timer::Builder::default().build();
+        let mut timer = timer::Builder::default().build();
+
+        let mut lost_clients = vec![];
+        if let Some(state) = self.restored_state {
+            server_state.server = state.server;
+            server_state.anteroom = state.anteroom;
+            for (client_id, language) in state.languages {
+                let language = server_state.localization.find_language(&language);
+                server_state.server.set_client_language(client_id, language);
+            }
+
+            clients = state
+                .clients
+                .into_iter()
+                .map(|(snapshot, stream)| {
+                    let client_id = snapshot.id;
+                    let timeout = create_ping_timeout(&mut timer, PING_PROBES_COUNT - 1, client_id);
+                    (client_id, NetworkClient::restore(snapshot, stream, timeout))
+                })
+                .collect();
+
+            lost_clients = server_state
+                .server
+                .iter_clients()
+                .map(|c| c.id)
+                .chain(server_state.anteroom.clients.iter().map(|(id, _)| id))
+                .filter(|id| !clients.contains(*id))
+                .collect();
+        }
+        let mut federation_timer = timer::Builder::default().build();
+        if server_state.federation.is_some() {
+            federation_timer.set_timeout(FEDERATION_SYNC_TIMEOUT, ());
+        }
+        let mut idle_timer = timer::Builder::default().build();
+        if server_state.idle_policy.is_enabled() {
+            idle_timer.set_timeout(IDLE_CHECK_TIMEOUT, ());
+        }
+        let mut game_timer = timer::Builder::default().build();
+        game_timer.set_timeout(GAME_CHECK_TIMEOUT, ());
 
         NetworkLayer {
             listener: self.listener.expect("No listener provided"),
@@ -749,9 +1246,13 @@ impl NetworkLayerBuilder {
             ssl: Self::create_ssl_context(
                 self.secure_listener.expect("No secure listener provided"),
             ),
-            #[cfg(feature = "official-server")]
-            io: IoLayer::new(),
+            io,
             timer,
+            federation_timer,
+            idle_timer,
+            game_timer,
+            events,
+            lost_clients,
         }
     }
 }
@@ -7,6 +7,33 @@ pub const SERVER_TOKEN: mio::Token = mio::Token(1_000_000_000);
 pub const SECURE_SERVER_TOKEN: mio::Token = mio::Token(1_000_000_001);
 pub const TIMER_TOKEN: mio::Token = mio::Token(1_000_000_002);
 pub const IO_TOKEN: mio::Token = mio::Token(1_000_000_003);
+pub const FEDERATION_TOKEN: mio::Token = mio::Token(1_000_000_004);
+pub const IDLE_TOKEN: mio::Token = mio::Token(1_000_000_005);
+pub const HANDOFF_TOKEN: mio::Token = mio::Token(1_000_000_006);
+pub const GAME_TOKEN: mio::Token = mio::Token(1_000_000_007);
+
+/// Serializes byte buffers as base64 strings, JSON would otherwise spell out every byte as a number
+pub mod serde_buf {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+    use std::io::Write;
+
+    pub fn serialize<B: AsRef<[u8]>, S: Serializer>(
+        buf: &B,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&base64::encode(buf.as_ref()))
+    }
+
+    pub fn deserialize<'de, B: Default + Write, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<B, D::Error> {
+        let data = String::deserialize(deserializer)?;
+        let data = base64::decode(&data).map_err(D::Error::custom)?;
+        let mut buf = B::default();
+        buf.write_all(&data).map_err(D::Error::custom)?;
+        Ok(buf)
+    }
+}
 
 pub fn is_name_illegal(name: &str) -> bool {
     name.len() > 40
@@ -26,51 +53,3 @@ where
     tmp.extend(msg);
     encode(&tmp)
 }
-
-pub fn protocol_version_string(protocol_number: u16) -> &'static str {
-    match protocol_number {
-        17 => "0.9.7-dev",
-        19 => "0.9.7",
-        20 => "0.9.8-dev",
-        21 => "0.9.8",
-        22 => "0.9.9-dev",
-        23 => "0.9.9",
-        24 => "0.9.10-dev",
-        25 => "0.9.10",
-        26 => "0.9.11-dev",
-        27 => "0.9.11",
-        28 => "0.9.12-dev",
-        29 => "0.9.12",
-        30 => "0.9.13-dev",
-        31 => "0.9.13",
-        32 => "0.9.14-dev",
-        33 => "0.9.14",
-        34 => "0.9.15-dev",
-        35 => "0.9.14.1",
-        37 => "0.9.15",
-        38 => "0.9.16-dev",
-        39 => "0.9.16",
-        40 => "0.9.17-dev",
-        41 => "0.9.17",
-        42 => "0.9.18-dev",
-        43 => "0.9.18",
-        44 => "0.9.19-dev",
-        45 => "0.9.19",
-        46 => "0.9.20-dev",
-        47 => "0.9.20",
-        48 => "0.9.21-dev",
-        49 => "0.9.21",
-        50 => "0.9.22-dev",
-        51 => "0.9.22",
-        52 => "0.9.23-dev",
-        53 => "0.9.23",
-        54 => "0.9.24-dev",
-        55 => "0.9.24",
-        56 => "0.9.25-dev",
-        57 => "0.9.25",
-        58 => "1.0.0-dev",
-        59 => "1.0.0",
-        60 => "1.0.1-dev",
-        _ => "Unknown",
-    }
-}
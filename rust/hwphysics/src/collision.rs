@@ -4,7 +4,7 @@ use crate::{common::GearId, data::GearDataManager, grid::Grid};
 
 use fpnum::*;
 use integral_geometry::{Point, Size};
-use land2d::Land2D;
+use land2d::{Land2D, LandFlags};
 
 pub fn fppoint_round(point: &FPPoint) -> Point {
     Point::new(point.x().round(), point.y().round())
@@ -127,7 +127,7 @@ impl CollisionProcessor {
 
     pub fn process(
         &mut self,
-        land: &Land2D<u32>,
+        land: &Land2D<LandFlags>,
         updates: &crate::physics::PositionUpdates,
     ) -> &DetectedCollisions {
         self.detected_collisions.clear();
@@ -141,7 +141,7 @@ impl CollisionProcessor {
             if collision
                 .bounds
                 .rows()
-                .any(|(y, r)| (&land[y][r]).iter().any(|v| *v != 0))
+                .any(|(y, r)| (&land[y][r]).iter().any(|v| v.is_solid()))
             {
                 self.detected_collisions
                     .push(gear_id, None, &collision.bounds.center)
@@ -6,7 +6,7 @@ pub mod physics;
 pub mod time;
 
 use integral_geometry::Size;
-use land2d::Land2D;
+use land2d::{Land2D, LandFlags};
 
 use crate::{
     collision::CollisionProcessor,
@@ -52,7 +52,7 @@ impl World {
         self.allocator.free(gear_id)
     }
 
-    pub fn step(&mut self, time_step: Millis, land: &Land2D<u32>) {
+    pub fn step(&mut self, time_step: Millis, land: &Land2D<LandFlags>) {
         let updates = if time_step == Millis::new(1) {
             self.physics.process_single_tick(&mut self.data)
         } else {
@@ -79,7 +79,7 @@ mod tests {
     };
     use fpnum::{fp, FPNum, FPPoint};
     use integral_geometry::Size;
-    use land2d::Land2D;
+    use land2d::{Land2D, LandFlags};
 
     #[test]
     fn data_flow() {
@@ -101,7 +101,10 @@ mod tests {
             },
         );
 
-        let land = Land2D::new(Size::new(world_size.width - 2, world_size.height - 2), 0);
+        let land = Land2D::new(
+            Size::new(world_size.width - 2, world_size.height - 2),
+            LandFlags::empty(),
+        );
 
         world.step(Millis::new(1), &land);
     }
@@ -6,7 +6,9 @@ use glutin::{
 use hedgewars_engine::instance::EngineInstance;
 
 use integral_geometry::Point;
-use std::time::Duration;
+use std::{env, path::Path, time::Duration};
+
+const DEFAULT_DATA_PATH: &str = "../../share/hedgewars/Data";
 
 fn init(event_loop: &EventsLoop, size: dpi::LogicalSize) -> WindowedContext {
     use glutin::{ContextBuilder, WindowBuilder};
@@ -41,7 +43,16 @@ fn main() {
     let (w, h) = (1024.0, 768.0);
     let window = init(&event_loop, dpi::LogicalSize::new(w, h));
 
-    let mut engine = EngineInstance::new();
+    let data_path = env::args()
+        .nth(1)
+        .unwrap_or_else(|| DEFAULT_DATA_PATH.to_string());
+    let mut engine = match EngineInstance::new(Path::new(&data_path)) {
+        Ok(engine) => engine,
+        Err(e) => {
+            eprintln!("Unable to load the game data from {}: {}", data_path, e);
+            return;
+        }
+    };
     engine.world.create_renderer(w as u16, h as u16);
 
     let mut dragging = false;
@@ -0,0 +1,90 @@
+use bitflags::*;
+
+use crate::Land2D;
+use integral_geometry::{Point, Rect};
+
+bitflags! {
+    /// Terrain types of a land pixel, the bits are the same as in the engine's land array
+    /// except for `BACKGROUND`
+    #[derive(Default)]
+    pub struct LandFlags: u16 {
+        /// Normal destructible terrain, black in map masks
+        const BASIC = 0x8000;
+        /// Terrain that can't be destroyed, red in map masks
+        const INDESTRUCTIBLE = 0x4000;
+        /// Destructible terrain belonging to a land object, white in map masks
+        const OBJECT = 0x2000;
+        const DAMAGED = 0x1000;
+        /// Slippery terrain, blue in map masks
+        const ICE = 0x0800;
+        /// Terrain gears bounce off, green in map masks
+        const BOUNCY = 0x0400;
+        /// Passable pixel showing the background texture, e.g. where land has been destroyed.
+        /// Only used on the Rust side, the engine has no such bit and keeps the background
+        /// in its pixel data instead, so clear it before handing land over: the bit lies
+        /// in the engine's terrain mask and would be taken for land there
+        const BACKGROUND = 0x0200;
+
+        const SOLID = Self::BASIC.bits | Self::INDESTRUCTIBLE.bits | Self::OBJECT.bits;
+    }
+}
+
+impl LandFlags {
+    /// Whether gears collide with the pixel
+    #[inline]
+    pub fn is_solid(self) -> bool {
+        self.intersects(Self::SOLID)
+    }
+}
+
+/// Pixel type of the land, plain numbers are never indestructible
+pub trait LandPixel: Copy + PartialEq {
+    fn is_indestructible(self) -> bool;
+}
+
+impl LandPixel for LandFlags {
+    #[inline]
+    fn is_indestructible(self) -> bool {
+        self.contains(Self::INDESTRUCTIBLE)
+    }
+}
+
+macro_rules! plain_land_pixel {
+    ($($t: ty),*) => {
+        $(impl LandPixel for $t {
+            #[inline]
+            fn is_indestructible(self) -> bool {
+                false
+            }
+        })*
+    };
+}
+
+plain_land_pixel!(u8, u16, u32);
+
+impl Land2D<LandFlags> {
+    /// Flags of the pixel, pixels outside of the land are empty
+    #[inline]
+    pub fn flags_at(&self, point: Point) -> LandFlags {
+        if self.is_valid_coordinate(point.x, point.y) {
+            self[point.y as usize][point.x as usize]
+        } else {
+            LandFlags::empty()
+        }
+    }
+
+    /// Union of the flags of all pixels in the rectangle
+    pub fn flags_in(&self, rect: &Rect) -> LandFlags {
+        let left = rect.left().max(0);
+        let right = rect.right().min(self.width() as i32 - 1);
+        let top = rect.top().max(0);
+        let bottom = rect.bottom().min(self.height() as i32 - 1);
+        if left > right || top > bottom {
+            return LandFlags::empty();
+        }
+
+        (top as usize..=bottom as usize)
+            .flat_map(|y| self[y][left as usize..=right as usize].iter())
+            .fold(LandFlags::empty(), |flags, pixel| flags | *pixel)
+    }
+}
@@ -1,8 +1,11 @@
+mod flags;
+
 use std::{
     cmp,
     ops::Index
 };
 
+pub use self::flags::{LandFlags, LandPixel};
 use integral_geometry::{ArcPoints, EquidistantPoints, Line, Point, Rect, Size, SizeMask};
 
 pub struct Land2D<T> {
@@ -27,7 +30,7 @@ impl<T: Copy + PartialEq> Land2D<T> {
     }
 
     pub fn raw_pixels(&self) -> &[T] {
-        &self.pixels.as_slice()
+        self.pixels.as_slice()
     }
 
     pub fn raw_pixel_bytes(&self) -> &[u8] {
@@ -125,7 +128,6 @@ impl<T: Copy + PartialEq> Land2D<T> {
     }
 
     pub fn fill(&mut self, start_point: Point, border_value: T, fill_value: T) {
-        assert!(self.is_valid_coordinate(start_point.x - 1, start_point.y));
         assert!(self.is_valid_coordinate(start_point.x, start_point.y));
 
         let mask = self.mask;
@@ -144,9 +146,9 @@ impl<T: Copy + PartialEq> Land2D<T> {
             if mask.contains_y(yd as usize) {
                 stack.push((xl, xr, yd as usize, dir));
             }
-        };
+        }
 
-        let start_x_l = (start_point.x - 1) as usize;
+        let start_x_l = cmp::max(start_point.x - 1, 0) as usize;
         let start_x_r = start_point.x as usize;
         for dir in [-1, 1].iter().cloned() {
             push(mask, &mut stack, start_x_l, start_x_r, start_point.y as usize, dir);
@@ -193,14 +195,22 @@ impl<T: Copy + PartialEq> Land2D<T> {
         x_from: i32,
         x_to: i32,
         f: &F,
-    ) -> usize {
+    ) -> usize
+    where
+        T: LandPixel,
+    {
         let mut result = 0;
+        let x_from = cmp::max(x_from, 0);
+        let x_to = cmp::min(x_to, self.width() as i32 - 1);
 
-        if self.is_valid_y(y) {
-            for i in cmp::min(x_from, 0) as usize..cmp::max(x_to as usize, self.width() - 1) {
+        if self.is_valid_y(y) && x_from <= x_to {
+            for i in x_from as usize..=x_to as usize {
                 unsafe {
                     // coordinates are valid at this point
-                    result += f(self.pixels.get_unchecked_mut(y as usize, i));
+                    let pixel = self.pixels.get_unchecked_mut(y as usize, i);
+                    if !pixel.is_indestructible() {
+                        result += f(pixel);
+                    }
                 }
             }
         }
@@ -216,40 +226,56 @@ impl<T: Copy + PartialEq> Land2D<T> {
         dx: i32,
         dy: i32,
         f: &F,
-    ) -> usize {
+    ) -> usize
+    where
+        T: LandPixel,
+    {
         self.fill_circle_line(y + dy, x - dx, x + dx, f)
             + self.fill_circle_line(y - dy, x - dx, x + dx, f)
             + self.fill_circle_line(y + dx, x - dy, x + dy, f)
             + self.fill_circle_line(y - dx, x - dy, x + dy, f)
     }
 
+    /// Applies `f` to every pixel of the circle except the indestructible ones
     pub fn change_round<F: Fn(&mut T) -> usize>(
         &mut self,
         x: i32,
         y: i32,
         radius: i32,
         f: F,
-    ) -> usize {
+    ) -> usize
+    where
+        T: LandPixel,
+    {
         ArcPoints::new(radius)
             .map(&mut |p: Point| self.fill_circle_lines(x, y, p.x, p.y, &f))
             .sum()
     }
 
-    fn fill_row(&mut self, center: Point, offset: Point, value: T) -> usize {
+    fn fill_row(&mut self, center: Point, offset: Point, value: T) -> usize
+    where
+        T: LandPixel,
+    {
         let row_index = center.y + offset.y;
-        if self.is_valid_y(row_index) {
-            let from_x = cmp::max(0, center.x - offset.x) as usize;
-            let to_x = cmp::min(self.width() - 1, (center.x + offset.x) as usize);
-            self.pixels[row_index as usize][from_x..=to_x]
+        let from_x = cmp::max(0, center.x - offset.x);
+        let to_x = cmp::min(self.width() as i32 - 1, center.x + offset.x);
+        if self.is_valid_y(row_index) && from_x <= to_x {
+            self.pixels[row_index as usize][from_x as usize..=to_x as usize]
                 .iter_mut()
-                .for_each(|v| *v = value);
-            to_x - from_x + 1
+                .filter(|v| value.is_indestructible() || !v.is_indestructible())
+                .map(|v| *v = value)
+                .count()
         } else {
             0
         }
     }
 
-    pub fn fill_circle(&mut self, center: Point, radius: i32, value: T) -> usize {
+    /// Fills the circle with the value, indestructible pixels are only overwritten
+    /// by indestructible values
+    pub fn fill_circle(&mut self, center: Point, radius: i32, value: T) -> usize
+    where
+        T: LandPixel,
+    {
         let transforms = [[0, 1, 1, 0], [0, 1, -1, 0], [1, 0, 0, 1], [1, 0, 0, -1]];
         ArcPoints::new(radius)
             .map(|vector| {
@@ -260,14 +286,18 @@ impl<T: Copy + PartialEq> Land2D<T> {
             }).sum()
     }
 
-    pub fn draw_thick_line(&mut self, line: Line, radius: i32, value: T) -> usize {
+    /// Draws the line like `fill_circle` moved along it
+    pub fn draw_thick_line(&mut self, line: Line, radius: i32, value: T) -> usize
+    where
+        T: LandPixel,
+    {
         let mut result = 0;
 
         for vector in ArcPoints::new(radius) {
             for delta in EquidistantPoints::new(vector) {
                 for point in line.into_iter() {
                     self.map_point(point + delta, |p| {
-                        if *p != value {
+                        if *p != value && (value.is_indestructible() || !p.is_indestructible()) {
                             *p = value;
                             result += 1;
                         }
@@ -339,4 +369,48 @@ mod tests {
         assert_eq!(l.pixels[120][100], 4);
         assert_eq!(l.pixels[100][64], 4);
     }
+
+    #[test]
+    fn fill_from_left_border() {
+        let mut l: Land2D<u8> = Land2D::new(Size::square(64), 1);
+        l.draw_line(Line::new(Point::new(0, 32), Point::new(63, 32)), 0);
+
+        l.fill(Point::new(0, 0), 0, 0);
+
+        assert_eq!(l.pixels[0][0], 0);
+        assert_eq!(l.pixels[31][63], 0);
+        assert_eq!(l.pixels[33][0], 1);
+    }
+
+    #[test]
+    fn indestructible() {
+        let mut l = Land2D::new(Size::square(64), LandFlags::BASIC);
+        l.fill_circle(Point::new(32, 32), 4, LandFlags::INDESTRUCTIBLE);
+
+        assert_eq!(l.fill_circle(Point::new(32, 32), 4, LandFlags::empty()), 0);
+        assert!(l.fill_circle(Point::new(40, 32), 8, LandFlags::empty()) > 0);
+        assert_eq!(l.flags_at(Point::new(32, 32)), LandFlags::INDESTRUCTIBLE);
+        assert_eq!(l.flags_at(Point::new(44, 32)), LandFlags::empty());
+        assert_eq!(l.flags_at(Point::new(-1, 32)), LandFlags::empty());
+
+        let damaged = l.change_round(32, 32, 12, |p| {
+            if p.contains(LandFlags::BASIC) {
+                p.insert(LandFlags::DAMAGED);
+                1
+            } else {
+                0
+            }
+        });
+        assert!(damaged > 0);
+        assert_eq!(l.flags_at(Point::new(32, 20)), LandFlags::BASIC | LandFlags::DAMAGED);
+        assert_eq!(l.flags_at(Point::new(32, 32)), LandFlags::INDESTRUCTIBLE);
+        assert_eq!(l.flags_at(Point::new(32, 50)), LandFlags::BASIC);
+
+        assert_eq!(
+            l.flags_in(&Rect::from_size_coords(30, 30, 4, 4)),
+            LandFlags::INDESTRUCTIBLE
+        );
+        assert!(l.flags_in(&Rect::from_size_coords(-8, 0, 16, 16)).is_solid());
+        assert!(!l.flags_in(&Rect::from_size_coords(64, 0, 16, 16)).is_solid());
+    }
 }
@@ -8,13 +8,17 @@ use structopt::StructOpt;
 
 use integral_geometry::{Point, Rect, Size};
 use landgen::{
-    outline_template::OutlineTemplate,
+    drawn::{self, DrawnLandGenerator, DrawnMap},
+    maze::{MazeLandGenerator, MazeTemplate},
+    outline_template::{OutlineTemplate, DEFAULT_MAX_HEDGEHOGS},
+    perlin::{PerlinLandGenerator, PerlinTemplate},
     template_based::TemplatedLandGenerator,
     LandGenerationParameters,
     LandGenerator
 };
 use mapgen::{
     MapGenerator,
+    objects::ObjectParameters,
     theme::{Theme, slice_u32_to_u8}
 };
 use lfprng::LaggedFibonacciPRNG;
@@ -36,7 +40,15 @@ struct Opt {
     #[structopt(short = "t", long = "template-type")]
     template_type: Option<String>,
     #[structopt(short = "z", long = "theme-dir")]
-    theme_dir: Option<String>
+    theme_dir: Option<String>,
+    #[structopt(short = "g", long = "map-generator", default_value = "template")]
+    map_generator: String,
+    #[structopt(short = "m", long = "maze-size", default_value = "0")]
+    maze_size: u32,
+    #[structopt(short = "e", long = "feature-size", default_value = "12")]
+    feature_size: u32,
+    #[structopt(short = "w", long = "drawn-map-file")]
+    drawn_map_file: Option<String>
 }
 
 fn template() -> OutlineTemplate {
@@ -52,8 +64,8 @@ fn template() -> OutlineTemplate {
     template
 }
 
-fn dump(
-    template: &OutlineTemplate,
+fn dump<G: LandGenerator>(
+    landgen: &G,
     seed: &[u8],
     distance_divisor: u32,
     skip_distort: bool,
@@ -61,7 +73,6 @@ fn dump(
     file_name: &Path,
 ) -> std::io::Result<Land2D<u8>> {
     let params = LandGenerationParameters::new(0 as u8, 255, distance_divisor, skip_distort, skip_bezier);
-    let landgen = TemplatedLandGenerator::new(template.clone());
     let mut prng = LaggedFibonacciPRNG::new(seed);
     let land = landgen.generate_land(&params, &mut prng);
 
@@ -79,9 +90,14 @@ fn dump(
     Ok(land)
 }
 
-fn texturize(theme_dir: &Path, land: &Land2D<u8>, output_filename: &Path) {
+fn texturize(theme_dir: &Path, mut land: Land2D<u8>, seed: &[u8], output_filename: &Path) {
     let theme = Theme::load(theme_dir).unwrap();
-    let texture = MapGenerator::new().make_texture(land, &theme);
+    let generator = MapGenerator::new();
+    let mut texture = generator.make_texture(&land, &theme);
+
+    let params = ObjectParameters::new(255, 128, DEFAULT_MAX_HEDGEHOGS);
+    let mut prng = LaggedFibonacciPRNG::new(seed);
+    generator.place_objects(&mut land, &mut texture, &[], &theme, &params, &mut prng);
 
     let file = File::create(output_filename).unwrap();
     let ref mut w = BufWriter::new(file);
@@ -96,35 +112,10 @@ fn texturize(theme_dir: &Path, land: &Land2D<u8>, output_filename: &Path) {
     writer.write_image_data(slice_u32_to_u8(texture.as_slice())).unwrap();
 }
 
-fn main() {
-    let opt = Opt::from_args();
-    println!("{:?}", opt);
-
-    let template =
-        if let Some(path) = opt.templates_file {
-            let mut result = String::new();
-            File::open(path)
-                .expect("Unable to read templates file")
-                .read_to_string(&mut result);
-
-            let mut generator = MapGenerator::new();
-
-            let source =  &result[..];
-
-            generator.import_yaml_templates(source);
-
-            let template_type = &opt.template_type
-                .expect("No template type specified");
-            generator.get_template(template_type)
-                .expect(&format!("Template type {} not found", template_type))
-                .clone()
-        } else {
-            template()
-        };
-
+fn dump_all<G: LandGenerator>(landgen: &G, opt: &Opt) -> Land2D<u8> {
     if opt.dump_before_distort {
         dump(
-            &template,
+            landgen,
             opt.seed.as_str().as_bytes(),
             opt.distance_divisor,
             true,
@@ -135,7 +126,7 @@ fn main() {
     }
     if opt.dump_before_bezierize {
         dump(
-            &template,
+            landgen,
             opt.seed.as_str().as_bytes(),
             opt.distance_divisor,
             false,
@@ -144,20 +135,80 @@ fn main() {
         )
         .unwrap();
     }
-    let land = dump(
-        &template,
+    dump(
+        landgen,
         opt.seed.as_str().as_bytes(),
         opt.distance_divisor,
         false,
         false,
         Path::new("out.full.png"),
     )
-    .unwrap();
+    .unwrap()
+}
+
+fn main() {
+    let opt = Opt::from_args();
+    println!("{:?}", opt);
+
+    let land = match opt.map_generator.as_str() {
+        "maze" => {
+            let template = MazeTemplate::from_maze_size(opt.maze_size, opt.feature_size)
+                .unwrap_or_else(|| panic!("Invalid maze size {}", opt.maze_size));
+            dump_all(&MazeLandGenerator::new(template), &opt)
+        }
+        "perlin" => {
+            let template = PerlinTemplate::from_maze_size(opt.maze_size, opt.feature_size)
+                .unwrap_or_else(|| panic!("Invalid maze size {}", opt.maze_size));
+            dump_all(&PerlinLandGenerator::new(template), &opt)
+        }
+        "drawn" => {
+            let path = opt.drawn_map_file.as_ref()
+                .expect("No drawn map file specified");
+            let mut result = String::new();
+            File::open(path)
+                .expect("Unable to read drawn map file")
+                .read_to_string(&mut result)
+                .expect("Unable to read drawn map file");
+
+            let drawn_map = DrawnMap::decode(result.trim())
+                .expect("Invalid drawn map");
+            let play_size = drawn::play_size(opt.feature_size);
+            dump_all(&DrawnLandGenerator::new(drawn_map, play_size), &opt)
+        }
+        "template" => {
+            let template =
+                if let Some(ref path) = opt.templates_file {
+                    let mut result = String::new();
+                    File::open(path)
+                        .expect("Unable to read templates file")
+                        .read_to_string(&mut result);
+
+                    let mut generator = MapGenerator::new();
+
+                    let source =  &result[..];
+
+                    generator.import_yaml_templates(source);
+
+                    let template_type = opt.template_type.as_ref()
+                        .expect("No template type specified");
+                    let mut prng = LaggedFibonacciPRNG::new(opt.seed.as_bytes());
+                    generator.get_template(template_type, &mut prng)
+                        .expect(&format!("Template type {} not found", template_type))
+                        .clone()
+                } else {
+                    template()
+                };
+
+            dump_all(&TemplatedLandGenerator::new(template), &opt)
+        }
+        map_generator => panic!("Unknown map generator {}", map_generator)
+    };
 
     if let Some(dir) = opt.theme_dir {
         texturize(
             &Path::new(&dir),
-            &land,
+            land,
+            opt.seed.as_str().as_bytes(),
             &Path::new("out.texture.png")
         );
     }
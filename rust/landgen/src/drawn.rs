@@ -0,0 +1,332 @@
+use std::io::{Read, Write};
+
+use crate::{LandGenerationParameters, LandGenerator};
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+use integral_geometry::{Line, Point, Size};
+use land2d::{Land2D, LandPixel};
+
+const POINT_SIZE: usize = 5;
+const LINE_START_FLAG: u8 = 0x80;
+const ERASING_FLAG: u8 = 0x40;
+const WIDTH_MASK: u8 = 0x3F;
+
+// points are drawn for a 4096x2048 map and scaled to the actual size
+const REFERENCE_SIZE: Size = Size::new(4096, 2048);
+const COORDINATE_MARGIN: i32 = 318;
+const MAX_DATA_LENGTH: usize = 1 << 24;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum DrawnMapError {
+    InvalidBase64,
+    InvalidCompression,
+    TooLarge(usize),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SpecialPoint {
+    pub position: Point,
+    pub flags: u8,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DrawnLine {
+    pub points: Vec<Point>,
+    pub width: u8,
+    pub erasing: bool,
+}
+
+impl DrawnLine {
+    pub fn radius(&self) -> i32 {
+        (self.width & WIDTH_MASK) as i32 * 5 + 3
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DrawnMap {
+    pub special_points: Vec<SpecialPoint>,
+    pub lines: Vec<DrawnLine>,
+}
+
+impl DrawnMap {
+    /// Parses the list of points as sent to the engine, trailing bytes are ignored
+    pub fn from_bytes(data: &[u8]) -> Self {
+        let mut map = Self::default();
+
+        for chunk in data.chunks_exact(POINT_SIZE) {
+            let position = Point::new(
+                i16::from_be_bytes([chunk[0], chunk[1]]) as i32,
+                i16::from_be_bytes([chunk[2], chunk[3]]) as i32,
+            );
+            let flags = chunk[4];
+
+            if flags & LINE_START_FLAG != 0 {
+                map.lines.push(DrawnLine {
+                    points: vec![position],
+                    width: flags & WIDTH_MASK,
+                    erasing: flags & ERASING_FLAG != 0,
+                });
+            } else if let Some(line) = map.lines.last_mut() {
+                line.points.push(position);
+            } else {
+                map.special_points.push(SpecialPoint { position, flags });
+            }
+        }
+
+        map
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        fn push_point(data: &mut Vec<u8>, position: Point, flags: u8) {
+            data.extend_from_slice(&(position.x as i16).to_be_bytes());
+            data.extend_from_slice(&(position.y as i16).to_be_bytes());
+            data.push(flags);
+        }
+
+        let mut data = Vec::new();
+
+        for point in &self.special_points {
+            push_point(&mut data, point.position, point.flags & !LINE_START_FLAG);
+        }
+
+        for line in &self.lines {
+            let mut flags = LINE_START_FLAG | (line.width & WIDTH_MASK);
+            if line.erasing {
+                flags |= ERASING_FLAG;
+            }
+
+            for point in &line.points {
+                push_point(&mut data, *point, flags);
+                flags = 0;
+            }
+        }
+
+        data
+    }
+
+    /// Decodes the map from the room config, which is compressed the same way as `qCompress`
+    /// does it and encoded in base64
+    pub fn decode(value: &str) -> Result<Self, DrawnMapError> {
+        let compressed = base64::decode(value).map_err(|_| DrawnMapError::InvalidBase64)?;
+        if compressed.len() < 4 {
+            return Err(DrawnMapError::InvalidCompression);
+        }
+
+        let (header, stream) = compressed.split_at(4);
+        let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        if length > MAX_DATA_LENGTH {
+            return Err(DrawnMapError::TooLarge(length));
+        }
+
+        let mut data = Vec::with_capacity(length);
+        if length > 0 {
+            ZlibDecoder::new(stream)
+                .take(length as u64 + 1)
+                .read_to_end(&mut data)
+                .map_err(|_| DrawnMapError::InvalidCompression)?;
+        }
+
+        if data.len() == length {
+            Ok(Self::from_bytes(&data))
+        } else {
+            Err(DrawnMapError::InvalidCompression)
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        let data = self.to_bytes();
+        let mut compressed = (data.len() as u32).to_be_bytes().to_vec();
+
+        if !data.is_empty() {
+            let mut encoder = ZlibEncoder::new(compressed, Compression::best());
+            encoder.write_all(&data).unwrap();
+            compressed = encoder.finish().unwrap();
+        }
+
+        base64::encode(&compressed)
+    }
+}
+
+/// Size of the drawn map as the engine picks it for the feature size
+pub fn play_size(feature_size: u32) -> Size {
+    let feature_size = feature_size as usize;
+    if feature_size >= 25 {
+        return Size::new(8192, 4096);
+    }
+
+    let (lower_feature_size, lower_size, upper_feature_size) = match feature_size {
+        0..=5 => (1, Size::new(1024, 512), 6),
+        6..=11 => (6, Size::new(2048, 1024), 12),
+        _ => (12, Size::new(4096, 2048), 25),
+    };
+
+    let step = |lower: usize| lower / (upper_feature_size - lower_feature_size);
+    let distance = feature_size.saturating_sub(lower_feature_size);
+
+    Size::new(
+        lower_size.width + step(lower_size.width) * distance,
+        lower_size.height + step(lower_size.height) * distance,
+    )
+}
+
+pub struct DrawnLandGenerator {
+    drawn_map: DrawnMap,
+    play_size: Size,
+}
+
+impl DrawnLandGenerator {
+    pub fn new(drawn_map: DrawnMap, play_size: Size) -> Self {
+        Self {
+            drawn_map,
+            play_size,
+        }
+    }
+}
+
+impl LandGenerator for DrawnLandGenerator {
+    fn generate_land<T: LandPixel, I: Iterator<Item = u32>>(
+        &self,
+        parameters: &LandGenerationParameters<T>,
+        _random_numbers: &mut I,
+    ) -> Land2D<T> {
+        let mut land = Land2D::new(self.play_size, parameters.zero);
+        let play_box = land.play_box();
+        let (play_width, play_height) = (self.play_size.width as i32, self.play_size.height as i32);
+
+        let scale = |point: Point| {
+            let x = point.x.clamp(
+                -COORDINATE_MARGIN,
+                REFERENCE_SIZE.width as i32 + COORDINATE_MARGIN,
+            );
+            let y = point.y.clamp(
+                -COORDINATE_MARGIN,
+                REFERENCE_SIZE.height as i32 + COORDINATE_MARGIN,
+            );
+            Point::new(
+                x * play_width / REFERENCE_SIZE.width as i32,
+                y * play_height / REFERENCE_SIZE.height as i32,
+            ) + play_box.top_left()
+        };
+
+        for line in &self.drawn_map.lines {
+            let value = if line.erasing {
+                parameters.zero
+            } else {
+                parameters.basic
+            };
+            let radius = line.radius() * play_width / REFERENCE_SIZE.width as i32;
+
+            let mut points = line.points.iter().map(|p| scale(*p));
+            if let Some(mut previous) = points.next() {
+                land.fill_circle(previous, radius, value);
+
+                for point in points {
+                    land.draw_thick_line(Line::new(previous, point), radius, value);
+                    previous = point;
+                }
+            }
+        }
+
+        // erase everything drawn outside of the play area
+        for y in 0..land.height() as i32 {
+            for x in 0..land.width() as i32 {
+                if !play_box.contains(Point::new(x, y)) {
+                    land.map(y, x, |p| *p = parameters.zero);
+                }
+            }
+        }
+
+        land
+    }
+}
+
+#[test]
+fn drawn_map_encoding_test() {
+    let map = DrawnMap {
+        special_points: vec![SpecialPoint {
+            position: Point::new(100, 200),
+            flags: 1,
+        }],
+        lines: vec![
+            DrawnLine {
+                points: vec![Point::new(-300, 1000), Point::new(4200, 1100)],
+                width: 12,
+                erasing: false,
+            },
+            DrawnLine {
+                points: vec![Point::new(2048, 1024)],
+                width: 63,
+                erasing: true,
+            },
+        ],
+    };
+
+    let bytes = map.to_bytes();
+    assert_eq!(bytes.len(), 4 * POINT_SIZE);
+    assert_eq!(&bytes[5..10], &[0xfe, 0xd4, 0x03, 0xe8, 0x80 | 12]);
+    assert_eq!(DrawnMap::from_bytes(&bytes), map);
+
+    assert_eq!(DrawnMap::decode(&map.encode()), Ok(map));
+    assert_eq!(
+        DrawnMap::decode(&DrawnMap::default().encode()),
+        Ok(DrawnMap::default())
+    );
+    assert_eq!(DrawnMap::decode("!"), Err(DrawnMapError::InvalidBase64));
+    assert_eq!(
+        DrawnMap::decode("AAAACng="),
+        Err(DrawnMapError::InvalidCompression)
+    );
+
+    assert_eq!(play_size(1), Size::new(1024, 512));
+    assert_eq!(play_size(12), Size::new(4096, 2048));
+    assert_eq!(play_size(25), Size::new(8192, 4096));
+}
+
+#[test]
+fn drawn_land_rasterization_test() {
+    let line = |points: &[(i32, i32)], width, erasing| DrawnLine {
+        points: points.iter().map(|&(x, y)| Point::new(x, y)).collect(),
+        width,
+        erasing,
+    };
+    let map = DrawnMap {
+        special_points: vec![],
+        lines: vec![
+            line(&[(1000, 1000), (3000, 1000)], 2, false),
+            line(&[(-5000, 0), (100, 100)], 2, false),
+            line(&[(2048, 1000)], 0, true),
+        ],
+    };
+
+    // feature size 8 plays on 2730x1364 at the bottom center of a 4096x2048 land
+    let generator = DrawnLandGenerator::new(map, play_size(8));
+    let parameters = LandGenerationParameters::new(0u8, 1, 1, false, false);
+    let land = generator.generate_land(&parameters, &mut std::iter::empty());
+    assert_eq!(land.size(), Size::new(4096, 2048));
+    assert_eq!(land.play_box().top_left(), Point::new(683, 684));
+    let pixel = |x: i32, y: i32| land.raw_pixels()[y as usize * land.width() + x as usize];
+
+    // the engine scales the radius 2 * 5 + 3 = 13 to 13 * 2730 div 4096 = 8
+    // and the first point to (1000 * 2730 div 4096 + 683, 1000 * 1364 div 2048 + 684)
+    let (x, y) = (1349, 1350);
+    assert_eq!(pixel(x - 8, y), 1);
+    assert_eq!(pixel(x - 9, y), 0);
+    assert_eq!(pixel(2000, y - 8), 1);
+    assert_eq!(pixel(2000, y - 9), 0);
+    assert_eq!(pixel(2682, y + 8), 1);
+    assert_eq!(pixel(2682 + 9, y), 0);
+
+    // the eraser of radius 3 * 2730 div 4096 = 1 cuts through the line
+    assert_eq!(pixel(2048, y), 0);
+    assert_eq!(pixel(2047, y), 0);
+    assert_eq!(pixel(2049, y), 0);
+    assert_eq!(pixel(2046, y), 1);
+    assert_eq!(pixel(2048, y + 2), 1);
+
+    // the line from the point clamped to (-318, 0) reaches the play area at y = 734
+    // instead of y = 748 for the unclamped one, nothing is left outside of the play area
+    assert_eq!(pixel(690, 737), 1);
+    assert_eq!(pixel(690, 752), 0);
+    assert_eq!(pixel(683, 734), 1);
+    assert_eq!(pixel(682, 734), 0);
+    assert_eq!(pixel(600, 715), 0);
+}
@@ -0,0 +1,162 @@
+use crate::LandGenerationParameters;
+use integral_geometry::{Point, Rect, Size};
+use land2d::Land2D;
+
+/// Height of the default girder sprite, themes may ship their own
+pub const DEFAULT_GIRDER_HEIGHT: usize = 16;
+
+const MIN_GIRDER_SPACING: usize = 256;
+const MIN_GIRDER_LENGTH: i32 = 200;
+const MAX_GIRDER_LENGTH: i32 = 900;
+
+fn count_non_zero<T: Copy + PartialEq>(
+    land: &Land2D<T>,
+    x: i32,
+    y: i32,
+    height: usize,
+    zero: T,
+) -> usize {
+    (y as usize..y as usize + height)
+        .filter(|&y| land[y][x as usize] != zero)
+        .count()
+}
+
+/// Looks for a gap between two walls around the `x` column and returns the rectangle
+/// of the girder bridging it, the search is the same as in the engine's `AddGirder`
+fn find_girder<T: Copy + PartialEq>(
+    land: &Land2D<T>,
+    x: i32,
+    height: usize,
+    zero: T,
+    reserved: &[Rect],
+) -> Option<Rect> {
+    let play_box = land.play_box();
+    let (left, right) = (play_box.left(), play_box.right());
+    let count = |x, y| count_non_zero(land, x, y, height, zero);
+
+    let mut y = play_box.top() + 150;
+    loop {
+        y += 24;
+        let mut x1 = x;
+        let mut x2 = x;
+
+        while x1 > left + 150 && count(x1, y) == 0 {
+            x1 -= 2;
+        }
+
+        let limit = x1 - 12;
+        let mut k;
+        loop {
+            k = count(x1, y);
+            x1 -= 2;
+            if x1 < left + 100 || k == 0 || k == height || x1 < limit {
+                break;
+            }
+        }
+        x1 += 2;
+
+        if k == height {
+            while x2 < right - 100 && count(x2, y) == 0 {
+                x2 += 2;
+            }
+
+            let limit = x2 + 12;
+            loop {
+                x2 += 2;
+                k = count(x2, y);
+                if x2 >= right - 150
+                    || k == 0
+                    || k == height
+                    || x2 > limit
+                    || x2 - x1 >= MAX_GIRDER_LENGTH
+                {
+                    break;
+                }
+            }
+
+            let clearance = Rect::from_size_coords(x1 - 32, y - 64, (x2 - x1 + 64) as usize, 144);
+            if x2 < right - 100
+                && k == height
+                && x2 - x1 > MIN_GIRDER_LENGTH
+                && x2 - x1 < MAX_GIRDER_LENGTH
+                && !reserved.iter().any(|r| r.intersects(&clearance))
+            {
+                return Some(Rect::from_size_coords(x1, y, (x2 - x1) as usize, height));
+            }
+        }
+
+        if y > land.height() as i32 - 125 {
+            return None;
+        }
+    }
+}
+
+/// Area around the girder kept free of other girders and theme objects
+pub fn reserved_area(girder: &Rect) -> Rect {
+    Rect::from_size(
+        girder.top_left() - Point::new(8, 32),
+        Size::new(girder.width() + 16, 80),
+    )
+}
+
+/// Bridges gaps in the land with girders of the given height spread along the play area
+/// and returns their rectangles
+pub fn place_girders<T: Copy + PartialEq>(
+    land: &mut Land2D<T>,
+    girder_height: usize,
+    parameters: &LandGenerationParameters<T>,
+) -> Vec<Rect> {
+    let play_box = land.play_box();
+    let step = (land.play_width() / 8).max(MIN_GIRDER_SPACING) as i32;
+
+    let mut reserved = Vec::new();
+    let mut girders = Vec::new();
+    let mut x = play_box.left() + step;
+
+    loop {
+        if let Some(girder) = find_girder(land, x, girder_height, parameters.zero, &reserved) {
+            for y in girder.y_range() {
+                for x in girder.x_range() {
+                    land.map(y, x, |p| *p = parameters.basic);
+                }
+            }
+
+            reserved.push(reserved_area(&girder));
+            girders.push(girder);
+        }
+
+        x += step;
+        if x > play_box.right() - step {
+            break;
+        }
+    }
+
+    girders
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bridges_known_gap() {
+        // two walls from the row 400 down with a 300 pixels gap between them
+        let mut land = Land2D::new(Size::new(2048, 1024), 0u8);
+        for y in 400..1024 {
+            for x in (0..700).chain(1000..2048) {
+                land.map(y, x, |p| *p = 1);
+            }
+        }
+
+        let parameters = LandGenerationParameters::new(0u8, 1, 1, true, true);
+        let girders = place_girders(&mut land, DEFAULT_GIRDER_HEIGHT, &parameters);
+
+        let girder = Rect::from_size_coords(698, 414, 304, DEFAULT_GIRDER_HEIGHT);
+        assert_eq!(girders, vec![girder]);
+        for y in girder.y_range() {
+            assert!(girder.x_range().all(|x| land[y as usize][x as usize] == 1));
+        }
+        assert_eq!(land[413][850], 0);
+        assert_eq!(land[430][850], 0);
+    }
+}
@@ -1,7 +1,13 @@
+pub mod drawn;
+pub mod girders;
+pub mod maze;
 mod outline;
 pub mod outline_template;
+pub mod perlin;
 pub mod template_based;
 
+use land2d::LandPixel;
+
 pub struct LandGenerationParameters<T> {
     zero: T,
     basic: T,
@@ -23,7 +29,7 @@ impl<T: Copy + PartialEq> LandGenerationParameters<T> {
 }
 
 pub trait LandGenerator {
-    fn generate_land<T: Copy + PartialEq, I: Iterator<Item = u32>>(
+    fn generate_land<T: LandPixel, I: Iterator<Item = u32>>(
         &self,
         parameters: &LandGenerationParameters<T>,
         random_numbers: &mut I,
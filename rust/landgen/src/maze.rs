@@ -0,0 +1,623 @@
+use std::cmp::max;
+
+use crate::{outline::OutlinePoints, LandGenerationParameters, LandGenerator};
+use integral_geometry::{Point, Polygon, Rect, Size};
+use land2d::{Land2D, LandPixel};
+
+const LAND_SIZE: Size = Size::new(4096, 2048);
+const SMALL_CELL_SIZE: usize = 128;
+const MEDIUM_CELL_SIZE: usize = 192;
+const LARGE_CELL_SIZE: usize = 256;
+const BRAIDNESS: u32 = 10;
+
+// separates the islands in the list of vertices, same as in the engine
+const NTPX: i32 = i16::MIN as i32;
+
+const DIR_N: Point = Point::new(0, -1);
+const DIR_E: Point = Point::new(1, 0);
+const DIR_S: Point = Point::new(0, 1);
+const DIR_W: Point = Point::new(-1, 0);
+
+#[derive(Clone, Debug)]
+pub struct MazeTemplate {
+    pub cell_size: usize,
+    pub inverted: bool,
+    pub distance_divisor: u32,
+    pub min_distance: i32,
+    pub braidness: u32,
+}
+
+impl MazeTemplate {
+    /// Creates the template for the maze size as sent in the game config: small, medium and
+    /// large tunnels followed by small, medium and large islands
+    pub fn from_maze_size(maze_size: u32, feature_size: u32) -> Option<Self> {
+        let feature_size = feature_size as i32;
+        let (cell_size, inverted, min_distance, distance_divisor) = match maze_size {
+            0 => (SMALL_CELL_SIZE, false, max(feature_size * 8, 32), 150),
+            1 => (MEDIUM_CELL_SIZE, false, max(feature_size * 6, 20), 100),
+            2 => (LARGE_CELL_SIZE, false, max(feature_size * 5, 12), 90),
+            3 => (SMALL_CELL_SIZE, true, max(feature_size * 8, 32), 130),
+            4 => (MEDIUM_CELL_SIZE, true, max(feature_size * 6, 20), 100),
+            5 => (LARGE_CELL_SIZE, true, max(feature_size * 5, 12), 85),
+            _ => return None,
+        };
+
+        Some(Self {
+            cell_size,
+            inverted,
+            distance_divisor,
+            min_distance,
+            braidness: BRAIDNESS,
+        })
+    }
+}
+
+fn get_random<I: Iterator<Item = u32>>(random_numbers: &mut I, modulo: u32) -> u32 {
+    random_numbers.next().unwrap() % modulo
+}
+
+fn grid<T: Clone>(width: i32, height: i32, value: T) -> Vec<Vec<T>> {
+    vec![vec![value; height as usize]; width as usize]
+}
+
+#[inline]
+fn turn_clockwise(dir: Point) -> Point {
+    Point::new(-dir.y, dir.x)
+}
+
+#[inline]
+fn turn_counterclockwise(dir: Point) -> Point {
+    dir.rotate90()
+}
+
+struct Maze {
+    inverted: bool,
+    braidness: u32,
+    cell_size: i32,
+    off_y: i32,
+    num_cells: Point,
+    num_edges: Point,
+    seen_cells: Point,
+    seen_list: Vec<Vec<i32>>,
+    x_walls: Vec<Vec<bool>>,
+    y_walls: Vec<Vec<bool>>,
+    x_edge_list: Vec<Vec<bool>>,
+    y_edge_list: Vec<Vec<bool>>,
+    last_cell: Vec<Point>,
+    came_from: Vec<Vec<Point>>,
+    came_from_pos: Vec<i32>,
+    step_done: Vec<bool>,
+    vertices: Vec<Point>,
+}
+
+impl Maze {
+    fn new(template: &MazeTemplate) -> Self {
+        let cell_size = template.cell_size as i32;
+        let num_steps = if template.inverted { 3 } else { 1 };
+
+        let mut num_cells = Point::new(
+            LAND_SIZE.width as i32 / cell_size,
+            LAND_SIZE.height as i32 / cell_size,
+        );
+        // needs to be odd
+        if num_cells.x % 2 == 0 {
+            num_cells.x -= 1;
+        }
+        if num_cells.y % 2 == 0 {
+            num_cells.y -= 1;
+        }
+
+        let num_edges = Point::new(num_cells.x - 1, num_cells.y - 1);
+        let seen_cells = Point::new(num_cells.x / 2, num_cells.y / 2);
+
+        Self {
+            inverted: template.inverted,
+            braidness: template.braidness,
+            cell_size,
+            off_y: LAND_SIZE.height as i32 - num_cells.y * cell_size,
+            num_cells,
+            num_edges,
+            seen_cells,
+            seen_list: grid(seen_cells.x, seen_cells.y, -1),
+            x_walls: grid(seen_cells.x, seen_cells.y - 1, true),
+            y_walls: grid(seen_cells.x - 1, seen_cells.y, true),
+            x_edge_list: grid(num_edges.x, num_cells.y, false),
+            y_edge_list: grid(num_cells.x, num_edges.y, false),
+            last_cell: vec![Point::new(0, 0); num_steps],
+            came_from: vec![
+                vec![Point::new(0, 0); (num_cells.x * num_cells.y) as usize];
+                num_steps
+            ],
+            came_from_pos: vec![0; num_steps],
+            step_done: vec![false; num_steps],
+            vertices: Vec::new(),
+        }
+    }
+
+    fn play_size(&self) -> Size {
+        Size::new(
+            (self.num_cells.x * self.cell_size) as usize,
+            (self.num_cells.y * self.cell_size) as usize,
+        )
+    }
+
+    fn when_seen(&self, cell: Point, current_step: usize) -> i32 {
+        if cell.x < 0 || cell.x >= self.seen_cells.x || cell.y < 0 || cell.y >= self.seen_cells.y {
+            current_step as i32
+        } else {
+            self.seen_list[cell.x as usize][cell.y as usize]
+        }
+    }
+
+    fn is_x_edge(&self, x: i32, y: i32) -> bool {
+        x >= 0
+            && y >= 0
+            && self
+                .x_edge_list
+                .get(x as usize)
+                .and_then(|column| column.get(y as usize))
+                .cloned()
+                .unwrap_or(false)
+    }
+
+    fn is_y_edge(&self, x: i32, y: i32) -> bool {
+        x >= 0
+            && y >= 0
+            && self
+                .y_edge_list
+                .get(x as usize)
+                .and_then(|column| column.get(y as usize))
+                .cloned()
+                .unwrap_or(false)
+    }
+
+    fn see_cell<I: Iterator<Item = u32>>(&mut self, current_step: usize, random_numbers: &mut I) {
+        loop {
+            let Point { x, y } = self.last_cell[current_step];
+            let (ux, uy) = (x as usize, y as usize);
+            self.seen_list[ux][uy] = current_step as i32;
+
+            let mut dir = [DIR_N, DIR_E, DIR_S, DIR_W][get_random(random_numbers, 4) as usize];
+            let next_dir_clockwise = get_random(random_numbers, 2) == 1;
+            let mut found_cell = false;
+            let mut tries = 0;
+
+            while tries < 5 && !found_cell {
+                let next_cell = Point::new(x + dir.x, y + dir.y);
+                let seen = self.when_seen(next_cell, current_step);
+                if seen == current_step as i32 {
+                    // we have already seen the target cell, decide if we should remove the wall anyway
+                    if !self.inverted && get_random(random_numbers, self.braidness) == 0 {
+                        if dir.x == -1 && x > 0 {
+                            self.y_walls[ux - 1][uy] = false;
+                        } else if dir.x == 1 && x < self.seen_cells.x - 1 {
+                            self.y_walls[ux][uy] = false;
+                        }
+                        if dir.y == -1 && y > 0 {
+                            self.x_walls[ux][uy - 1] = false;
+                        } else if dir.y == 1 && y < self.seen_cells.y - 1 {
+                            self.x_walls[ux][uy] = false;
+                        }
+                    }
+
+                    dir = if next_dir_clockwise {
+                        turn_clockwise(dir)
+                    } else {
+                        turn_counterclockwise(dir)
+                    };
+                } else if seen == -1 {
+                    // cell was not seen yet, go there
+                    match dir.y {
+                        -1 => self.x_walls[ux][uy - 1] = false,
+                        1 => self.x_walls[ux][uy] = false,
+                        _ => {}
+                    }
+                    match dir.x {
+                        -1 => self.y_walls[ux - 1][uy] = false,
+                        1 => self.y_walls[ux][uy] = false,
+                        _ => {}
+                    }
+
+                    self.last_cell[current_step] = next_cell;
+                    self.came_from_pos[current_step] += 1;
+                    let pos = self.came_from_pos[current_step] as usize;
+                    self.came_from[current_step][pos] = Point::new(x, y);
+                    found_cell = true;
+                } else {
+                    // we are seeing someone else, quit
+                    self.step_done[current_step] = true;
+                    found_cell = true;
+                }
+
+                tries += 1;
+            }
+
+            if found_cell {
+                break;
+            }
+
+            let pos = self.came_from_pos[current_step] as usize;
+            self.last_cell[current_step] = self.came_from[current_step][pos];
+            self.came_from_pos[current_step] -= 1;
+
+            if self.came_from_pos[current_step] < 0 {
+                self.step_done[current_step] = true;
+                break;
+            }
+        }
+    }
+
+    fn add_vertex(&mut self, x: i32, y: i32) {
+        let num_vertices = self.vertices.len();
+
+        if x == NTPX {
+            if num_vertices >= 6 && self.vertices[num_vertices - 6].x == NTPX {
+                // drop the loop around a single cell
+                self.vertices.truncate(num_vertices - 5);
+            } else {
+                self.vertices.push(Point::new(NTPX, 0));
+            }
+        } else {
+            let tmp_x = if self.inverted || x % 2 == 0 {
+                self.cell_size
+            } else {
+                self.cell_size * 2 / 3
+            };
+
+            let tmp_y = if self.inverted || y % 2 == 0 {
+                self.cell_size
+            } else {
+                self.cell_size * 2 / 3
+            };
+
+            let new_point = Point::new(
+                (x - 1) * self.cell_size + tmp_x,
+                (y - 1) * self.cell_size + tmp_y + self.off_y,
+            );
+
+            // merge collinear edges
+            if num_vertices > 2 {
+                let p1 = self.vertices[num_vertices - 2];
+                let p2 = self.vertices[num_vertices - 1];
+                if (p1.x == p2.x && p2.x == new_point.x) || (p1.y == p2.y && p2.y == new_point.y) {
+                    self.vertices.pop();
+                }
+            }
+
+            self.vertices.push(new_point);
+        }
+    }
+
+    fn add_edge(&mut self, mut x: i32, mut y: i32, mut dir: Point) {
+        'edges: loop {
+            dir = turn_counterclockwise(dir);
+
+            for _ in 0..4 {
+                dir = turn_clockwise(dir);
+
+                if dir == DIR_N && self.is_x_edge(x, y) {
+                    self.x_edge_list[x as usize][y as usize] = false;
+                    self.add_vertex(x + 1, y);
+                    y -= 1;
+                    continue 'edges;
+                }
+
+                if dir == DIR_E && self.is_y_edge(x + 1, y) {
+                    self.y_edge_list[x as usize + 1][y as usize] = false;
+                    self.add_vertex(x + 2, y + 1);
+                    x += 1;
+                    continue 'edges;
+                }
+
+                if dir == DIR_S && self.is_x_edge(x, y + 1) {
+                    self.x_edge_list[x as usize][y as usize + 1] = false;
+                    self.add_vertex(x + 1, y + 2);
+                    y += 1;
+                    continue 'edges;
+                }
+
+                if dir == DIR_W && self.is_y_edge(x, y) {
+                    self.y_edge_list[x as usize][y as usize] = false;
+                    self.add_vertex(x, y + 1);
+                    x -= 1;
+                    continue 'edges;
+                }
+            }
+
+            break;
+        }
+    }
+
+    fn generate<I: Iterator<Item = u32>>(&mut self, random_numbers: &mut I) {
+        let num_steps = self.step_done.len();
+
+        for current_step in 0..num_steps {
+            let x = get_random(random_numbers, self.seen_cells.x as u32 - 1) / num_steps as u32;
+            self.last_cell[current_step] = Point::new(
+                x as i32 + current_step as i32 * self.seen_cells.x / num_steps as i32,
+                get_random(random_numbers, self.seen_cells.y as u32) as i32,
+            );
+        }
+
+        while self.step_done.iter().any(|done| !done) {
+            for current_step in 0..num_steps {
+                if !self.step_done[current_step] {
+                    self.see_cell(current_step, random_numbers);
+                }
+            }
+        }
+
+        let mut maze = grid(self.num_cells.x, self.num_cells.y, false);
+
+        for x in 0..self.seen_cells.x as usize {
+            for y in 0..self.seen_cells.y as usize {
+                if self.seen_list[x][y] > -1 {
+                    maze[(x + 1) * 2 - 1][(y + 1) * 2 - 1] = true;
+                }
+            }
+        }
+
+        for (x, column) in self.x_walls.iter().enumerate() {
+            for (y, &wall) in column.iter().enumerate() {
+                if !wall {
+                    maze[x * 2 + 1][y * 2 + 2] = true;
+                }
+            }
+        }
+
+        for (x, column) in self.y_walls.iter().enumerate() {
+            for (y, &wall) in column.iter().enumerate() {
+                if !wall {
+                    maze[x * 2 + 2][y * 2 + 1] = true;
+                }
+            }
+        }
+
+        for (x, column) in self.x_edge_list.iter_mut().enumerate() {
+            for (y, edge) in column.iter_mut().enumerate() {
+                *edge = maze[x][y] != maze[x + 1][y];
+            }
+        }
+
+        for (x, column) in self.y_edge_list.iter_mut().enumerate() {
+            for (y, edge) in column.iter_mut().enumerate() {
+                *edge = maze[x][y] != maze[x][y + 1];
+            }
+        }
+
+        for x in 0..self.num_edges.x {
+            for y in 0..self.num_cells.y {
+                if self.x_edge_list[x as usize][y as usize] {
+                    self.x_edge_list[x as usize][y as usize] = false;
+                    self.add_vertex(x + 1, y + 1);
+                    self.add_vertex(x + 1, y);
+                    self.add_edge(x, y - 1, DIR_N);
+                    self.add_vertex(NTPX, 0);
+                }
+            }
+        }
+    }
+
+    fn islands(&self) -> Vec<Polygon> {
+        self.vertices
+            .split(|p| p.x == NTPX)
+            .filter(|island| !island.is_empty())
+            .map(Polygon::new)
+            .collect()
+    }
+}
+
+/// Generates the maze the same way the engine does, the distance divisor of the template
+/// overrides the one from the generation parameters
+pub struct MazeLandGenerator {
+    maze_template: MazeTemplate,
+}
+
+impl MazeLandGenerator {
+    pub fn new(maze_template: MazeTemplate) -> Self {
+        Self { maze_template }
+    }
+
+    /// The engine allows the same number of hedgehogs on every maze
+    pub fn max_hedgehogs(&self) -> u8 {
+        32
+    }
+}
+
+impl LandGenerator for MazeLandGenerator {
+    fn generate_land<T: LandPixel, I: Iterator<Item = u32>>(
+        &self,
+        parameters: &LandGenerationParameters<T>,
+        random_numbers: &mut I,
+    ) -> Land2D<T> {
+        let mut maze = Maze::new(&self.maze_template);
+        maze.generate(random_numbers);
+
+        let play_size = maze.play_size();
+        let mut land = Land2D::new(LAND_SIZE, parameters.zero);
+        for y in maze.off_y..LAND_SIZE.height as i32 {
+            for x in 0..=play_size.width as i32 {
+                land.map(y, x, |p| *p = parameters.basic);
+            }
+        }
+
+        let mut points = OutlinePoints::from_islands(
+            maze.islands(),
+            vec![Point::new(1, 1 + maze.off_y)],
+            Rect::from_box(
+                0,
+                play_size.width as i32,
+                maze.off_y,
+                LAND_SIZE.height as i32,
+            ),
+            LAND_SIZE,
+            self.maze_template.min_distance,
+        );
+
+        if !parameters.skip_distort {
+            points.distort(self.maze_template.distance_divisor, random_numbers);
+        }
+
+        if !parameters.skip_bezier {
+            points.bezierize(5);
+        }
+
+        points.draw(&mut land, parameters.zero);
+
+        if self.maze_template.inverted {
+            land.fill(
+                Point::new(1, 1 + maze.off_y),
+                parameters.zero,
+                parameters.zero,
+            );
+        } else {
+            // fill the solid land outside of the outer tunnel walls
+            let y = maze.cell_size / 2 + maze.cell_size + maze.off_y;
+            let row = &land[y as usize];
+            let mut x = 0;
+            while x < row.len() && row[x] == parameters.basic {
+                x += 1;
+            }
+            while x < row.len() && row[x] == parameters.zero {
+                x += 1;
+            }
+
+            land.fill(
+                Point::new(x as i32 + 1, y),
+                parameters.zero,
+                parameters.zero,
+            );
+        }
+
+        land
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lfprng::LaggedFibonacciPRNG;
+
+    const SEED: &[u8] = b"{3c4fbf5c-35d4-4e5e-a3e0-a2c9bd0b0b56}";
+
+    fn generate_maze(maze_size: u32) -> Maze {
+        let template = MazeTemplate::from_maze_size(maze_size, 12).unwrap();
+        let mut maze = Maze::new(&template);
+        maze.generate(&mut LaggedFibonacciPRNG::new(SEED));
+        maze
+    }
+
+    fn checksum(vertices: &[Point]) -> i64 {
+        vertices
+            .iter()
+            .enumerate()
+            .map(|(i, p)| (i as i64 + 1) * (p.x as i64 * 3 + p.y as i64))
+            .sum()
+    }
+
+    /// Samples the undistorted land in the middle of every maze cell, `#` marks the solid ones.
+    /// The engine drops the outlines of the islands one cell high, so those get flooded.
+    fn cell_map(maze_size: u32) -> Vec<String> {
+        let template = MazeTemplate::from_maze_size(maze_size, 12).unwrap();
+        let parameters = LandGenerationParameters::new(0u32, 1, 1, true, true);
+        let land = MazeLandGenerator::new(template.clone())
+            .generate_land(&parameters, &mut LaggedFibonacciPRNG::new(SEED));
+
+        let maze = Maze::new(&template);
+        let boundary = |k: i32| {
+            let offset = if maze.inverted || k % 2 == 0 {
+                maze.cell_size
+            } else {
+                maze.cell_size * 2 / 3
+            };
+            (k - 1) * maze.cell_size + offset
+        };
+        let center = |k: i32| (boundary(k) + boundary(k + 1)) / 2;
+
+        (0..maze.num_cells.y)
+            .map(|y| {
+                (0..maze.num_cells.x)
+                    .map(|x| {
+                        if land[(center(y) + maze.off_y) as usize][center(x) as usize] == 0 {
+                            '.'
+                        } else {
+                            '#'
+                        }
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn small_tunnels() {
+        let maze = generate_maze(0);
+        assert_eq!(maze.vertices.len(), 161);
+        assert_eq!(maze.islands().len(), 10);
+        assert_eq!(checksum(&maze.vertices), -4515883);
+        assert_eq!(
+            maze.vertices[..6],
+            [
+                Point::new(85, 384),
+                Point::new(85, 213),
+                Point::new(256, 213),
+                Point::new(256, 725),
+                Point::new(512, 725),
+                Point::new(512, 981)
+            ]
+        );
+
+        assert_eq!(
+            cell_map(0),
+            [
+                "###############################",
+                "#.#.....#...........#.......#.#",
+                "#.#.###.#...#######.#.#.###.#.#",
+                "#.#...#.....#.....#...#...#...#",
+                "#.###.###.###.#########.#.###.#",
+                "#...#.#.....#.#.........#.....#",
+                "#.#.#.#.###.#.#.#.#####.#.....#",
+                "#.#...#.#.......#.....#.......#",
+                "#.#.#.#.#######.#.....###...#.#",
+                "#.#.#...#.......#.....#.....#.#",
+                "#.#.###.#.#########.#.#######.#",
+                "#.#...#...#.......#.#.....#...#",
+                "#.###.#.#######...#.###...#.#.#",
+                "#...................#.......#.#",
+                "###############################",
+            ]
+        );
+    }
+
+    #[test]
+    fn large_islands() {
+        let maze = generate_maze(5);
+        assert_eq!(maze.vertices.len(), 17);
+        assert_eq!(maze.islands().len(), 2);
+        assert_eq!(checksum(&maze.vertices), -1488896);
+        assert_eq!(
+            maze.vertices[..6],
+            [
+                Point::new(256, 768),
+                Point::new(256, 512),
+                Point::new(2048, 512),
+                Point::new(2048, 768),
+                Point::new(512, 768),
+                Point::new(512, 1792)
+            ]
+        );
+
+        assert_eq!(
+            cell_map(5),
+            [
+                "...............",
+                ".#######.......",
+                ".#.............",
+                ".#...........#.",
+                ".#...........#.",
+                ".#.......#####.",
+                "...............",
+            ]
+        );
+    }
+}
@@ -12,8 +12,11 @@ pub struct OutlinePoints {
     pub size: Size,
     pub play_box: Rect,
     intersections_box: Rect,
+    min_distance: i32,
 }
 
+const MAP_BORDER_MARGIN: i32 = 40;
+
 impl OutlinePoints {
     pub fn from_outline_template<I: Iterator<Item = u32>>(
         outline_template: &OutlineTemplate,
@@ -28,18 +31,43 @@ impl OutlinePoints {
                 .islands
                 .iter()
                 .map(|i| {
-                    i.iter()
+                    let island = i
+                        .iter()
                         .zip(random_numbers.tuples())
                         .map(|(rect, (rnd_a, rnd_b))| {
                             play_box.top_left() + rect.quotient(rnd_a as usize, rnd_b as usize)
                         })
-                        .collect::<Vec<_>>()
-                        .into()
+                        .collect::<Vec<_>>();
+
+                    // the engine templates end every island with a separator point,
+                    // its coordinates are drawn too
+                    random_numbers.nth(1);
+
+                    island.into()
                 })
                 .collect(),
             fill_points: outline_template.fill_points.clone(),
             intersections_box: Rect::at_origin(size)
                 .with_margin(size.to_square().width as i32 * -2),
+            min_distance: 40,
+        }
+    }
+
+    pub fn from_islands(
+        islands: Vec<Polygon>,
+        fill_points: Vec<Point>,
+        play_box: Rect,
+        size: Size,
+        min_distance: i32,
+    ) -> Self {
+        Self {
+            islands,
+            fill_points,
+            play_box,
+            size,
+            intersections_box: Rect::at_origin(size)
+                .with_margin(size.to_square().width as i32 * -2),
+            min_distance,
         }
     }
 
@@ -119,9 +147,9 @@ impl OutlinePoints {
             }
         }
 
-        let min_distance = 40;
+        let min_distance = self.min_distance;
         // new point should fall inside this box
-        let map_box = self.play_box.with_margin(min_distance);
+        let map_box = self.play_box.with_margin(MAP_BORDER_MARGIN);
 
         let normal = segment.scaled_normal();
         let normal_len = normal.integral_norm();
@@ -308,7 +336,7 @@ impl OutlinePoints {
     }
 }
 
-#[test()]
+#[test]
 fn points_test() {
     let size = Size::square(100);
     let mut points = OutlinePoints {
@@ -320,6 +348,7 @@ fn points_test() {
         play_box: Rect::at_origin(size).with_margin(10),
         size: Size::square(100),
         intersections_box: Rect::at_origin(size),
+        min_distance: 40,
     };
 
     let segments: Vec<Line> = points.segments_iter().collect();
@@ -1,5 +1,8 @@
 use integral_geometry::{Point, Rect, Size};
 
+// the engine falls back to this when a map doesn't specify the limit
+pub const DEFAULT_MAX_HEDGEHOGS: u8 = 18;
+
 #[derive(Clone, Debug)]
 pub struct OutlineTemplate {
     pub islands: Vec<Vec<Rect>>,
@@ -9,6 +12,8 @@ pub struct OutlineTemplate {
     pub can_invert: bool,
     pub can_mirror: bool,
     pub is_negative: bool,
+    pub put_girders: bool,
+    pub max_hedgehogs: u8,
 }
 
 impl OutlineTemplate {
@@ -21,6 +26,8 @@ impl OutlineTemplate {
             can_invert: false,
             can_mirror: false,
             is_negative: false,
+            put_girders: false,
+            max_hedgehogs: DEFAULT_MAX_HEDGEHOGS,
         }
     }
 
@@ -52,6 +59,20 @@ impl OutlineTemplate {
         }
     }
 
+    pub fn with_girders(self) -> Self {
+        Self {
+            put_girders: true,
+            ..self
+        }
+    }
+
+    pub fn with_max_hedgehogs(self, max_hedgehogs: u8) -> Self {
+        Self {
+            max_hedgehogs,
+            ..self
+        }
+    }
+
     pub fn with_fill_points(self, fill_points: Vec<Point>) -> Self {
         Self {
             fill_points,
@@ -0,0 +1,348 @@
+use std::cmp::{max, min};
+
+use crate::{LandGenerationParameters, LandGenerator};
+use integral_geometry::{Point, Size};
+use land2d::{Land2D, LandPixel};
+
+const LAND_SIZE: Size = Size::new(4096, 2048);
+const MIN_Y: usize = 500;
+const MARGIN: i32 = 200;
+
+// pixels of tunnel maps stay OBJECT until they are found to be connected to the bottom
+const EMPTY: u8 = 0;
+const OBJECT: u8 = 1;
+const BASIC: u8 = 2;
+
+const FADE: [i32; 256] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 2, 3, 3, 4, 6, 7, 9, 10, 12, 14, 17, 19, 22, 25, 29, 32, 36, 40,
+    45, 49, 54, 60, 65, 71, 77, 84, 91, 98, 105, 113, 121, 130, 139, 148, 158, 167, 178, 188, 199,
+    211, 222, 234, 247, 259, 273, 286, 300, 314, 329, 344, 359, 374, 390, 407, 424, 441, 458, 476,
+    494, 512, 531, 550, 570, 589, 609, 630, 651, 672, 693, 715, 737, 759, 782, 805, 828, 851, 875,
+    899, 923, 948, 973, 998, 1023, 1049, 1074, 1100, 1127, 1153, 1180, 1207, 1234, 1261, 1289,
+    1316, 1344, 1372, 1400, 1429, 1457, 1486, 1515, 1543, 1572, 1602, 1631, 1660, 1690, 1719, 1749,
+    1778, 1808, 1838, 1868, 1898, 1928, 1958, 1988, 2018, 2048, 2077, 2107, 2137, 2167, 2197, 2227,
+    2257, 2287, 2317, 2346, 2376, 2405, 2435, 2464, 2493, 2523, 2552, 2580, 2609, 2638, 2666, 2695,
+    2723, 2751, 2779, 2806, 2834, 2861, 2888, 2915, 2942, 2968, 2995, 3021, 3046, 3072, 3097, 3122,
+    3147, 3172, 3196, 3220, 3244, 3267, 3290, 3313, 3336, 3358, 3380, 3402, 3423, 3444, 3465, 3486,
+    3506, 3525, 3545, 3564, 3583, 3601, 3619, 3637, 3654, 3672, 3688, 3705, 3721, 3736, 3751, 3766,
+    3781, 3795, 3809, 3822, 3836, 3848, 3861, 3873, 3884, 3896, 3907, 3917, 3928, 3937, 3947, 3956,
+    3965, 3974, 3982, 3990, 3997, 4004, 4011, 4018, 4024, 4030, 4035, 4041, 4046, 4050, 4055, 4059,
+    4063, 4066, 4070, 4073, 4076, 4078, 4081, 4083, 4085, 4086, 4088, 4089, 4091, 4092, 4092, 4093,
+    4094, 4094, 4095, 4095, 4095, 4095, 4095, 4095, 4095,
+];
+
+#[derive(Clone, Debug)]
+pub struct PerlinTemplate {
+    pub islands: bool,
+    pub frequency: i64,
+    pub cutoff: i32,
+    pub max_hedgehogs: u8,
+}
+
+impl PerlinTemplate {
+    /// The perlin generator reuses the maze size setting of the game config: its quotient by 3
+    /// chooses between tunnels and islands, the remainder picks the size of the features
+    pub fn from_maze_size(maze_size: u32, feature_size: u32) -> Option<Self> {
+        if maze_size > 5 {
+            return None;
+        }
+
+        let islands = maze_size / 3 == 1;
+        let features = (maze_size % 3) as i32;
+        // feature size is a slider from 1 to 25, flip it for perlin
+        let feature_size = feature_size as i32;
+        let detail = (26 - feature_size) as i64 * 16000 + 50000;
+
+        Some(Self {
+            islands,
+            frequency: detail * (6 - features as i64 * 2),
+            cutoff: ((26 - feature_size) * 4).clamp(15, 85),
+            max_hedgehogs: max_hedgehogs(islands, features, feature_size),
+        })
+    }
+}
+
+/// Estimates the number of hedgehogs fitting on the map the same way the engine does
+fn max_hedgehogs(islands: bool, features: i32, feature_size: i32) -> u8 {
+    let count = if !islands {
+        // 12..24 for the small tunnels, 14..24 for the medium ones and 16..24 for the large ones
+        let count = match features {
+            0 => 12 + (feature_size - 1),
+            1 => 14 + max(0, feature_size - 3),
+            _ => 16 + max(0, feature_size - 5),
+        };
+        min(count, 24)
+    } else if feature_size <= 25 {
+        // 64..32 for the small islands, 56..28 for the medium ones and 48..24 for the large ones
+        let base = 32 - features * 4;
+        base + (((25 - (feature_size - 1)) * 1_000_000 / 24) * base) / 1_000_000
+    } else {
+        0
+    };
+
+    // only even numbers
+    max(count - count % 2, 0) as u8
+}
+
+struct Noise {
+    permutations: [usize; 512],
+}
+
+impl Noise {
+    fn new<I: Iterator<Item = u32>>(random_numbers: &mut I) -> Self {
+        let mut permutations = [0; 512];
+        for (i, p) in permutations.iter_mut().take(255).enumerate() {
+            *p = i + 1;
+        }
+
+        for i in 0..255 {
+            let j = (random_numbers.next().unwrap() % (256 - i as u32)) as usize + i;
+            permutations.swap(i, j);
+        }
+
+        let (head, tail) = permutations.split_at_mut(256);
+        tail.copy_from_slice(head);
+
+        Self { permutations }
+    }
+
+    #[inline]
+    fn fade(t: i32) -> i32 {
+        let t0 = FADE[(t >> 8) as usize];
+        let t1 = if t0 == FADE[255] {
+            t0
+        } else {
+            FADE[(t >> 8) as usize + 1]
+        };
+
+        t0 + (((t & 255) * (t1 - t0)) >> 8)
+    }
+
+    #[inline]
+    fn lerp(t: i32, a: i32, b: i32) -> i32 {
+        (a as i64 + (((b as i64 - a as i64) * t as i64) >> 12)) as i32
+    }
+
+    #[inline]
+    fn grad(hash: usize, x: i32, y: i32) -> i32 {
+        let h = hash & 15;
+        let mut u = if h < 8 { x } else { y };
+        let mut v = if h < 4 {
+            y
+        } else if h == 12 || h == 14 {
+            x
+        } else {
+            0
+        };
+
+        if h & 1 != 0 {
+            u = -u;
+        }
+        if h & 2 != 0 {
+            v = -v;
+        }
+
+        u + v
+    }
+
+    fn noise(&self, x: i32, y: i32) -> i32 {
+        const N: i32 = 0x10000;
+        let p = &self.permutations;
+
+        let xx = ((x >> 16) & 255) as usize;
+        let yy = ((y >> 16) & 255) as usize;
+
+        let x = x & 0xFFFF;
+        let y = y & 0xFFFF;
+
+        let u = Self::fade(x);
+        let v = Self::fade(y);
+
+        let a = p[xx] + yy;
+        let aa = p[a];
+        let ab = p[a + 1];
+        let b = p[xx + 1] + yy;
+        let ba = p[b];
+        let bb = p[b + 1];
+
+        Self::lerp(
+            v,
+            Self::lerp(u, Self::grad(p[aa], x, y), Self::grad(p[ba], x - N, y)),
+            Self::lerp(
+                u,
+                Self::grad(p[ab], x, y - N),
+                Self::grad(p[bb], x - N, y - N),
+            ),
+        )
+    }
+}
+
+pub struct PerlinLandGenerator {
+    perlin_template: PerlinTemplate,
+}
+
+impl PerlinLandGenerator {
+    pub fn new(perlin_template: PerlinTemplate) -> Self {
+        Self { perlin_template }
+    }
+
+    pub fn max_hedgehogs(&self) -> u8 {
+        self.perlin_template.max_hedgehogs
+    }
+}
+
+impl LandGenerator for PerlinLandGenerator {
+    fn generate_land<T: LandPixel, I: Iterator<Item = u32>>(
+        &self,
+        parameters: &LandGenerationParameters<T>,
+        random_numbers: &mut I,
+    ) -> Land2D<T> {
+        let noise = Noise::new(random_numbers);
+        let frequency = self.perlin_template.frequency;
+        let (width, height) = (LAND_SIZE.width as i32, LAND_SIZE.height as i32);
+        let solid = if self.perlin_template.islands {
+            BASIC
+        } else {
+            OBJECT
+        };
+
+        let mut pixels = Land2D::new(LAND_SIZE, EMPTY);
+        for y in MIN_Y as i32..height {
+            let di = (frequency * y as i64 / height as i64) as i32;
+            for x in 0..width {
+                let dj = (frequency * x as i64 / width as i64) as i32;
+
+                let mut r = ((noise.noise(di, dj).abs() + y * 4) % 65536 - (height - y) * 8) / 256;
+
+                // fade on edges
+                if x < MARGIN || x > width - MARGIN {
+                    r = r - (x - width / 2).abs() + width / 2 - MARGIN;
+                }
+
+                if r >= self.perlin_template.cutoff {
+                    pixels.map(y, x, |p| *p = solid);
+                }
+            }
+        }
+
+        // only keep the land connected to the bottom of the map
+        if !self.perlin_template.islands {
+            let bottom = height - 1;
+            for x in 0..width {
+                if pixels[bottom as usize][x as usize] == OBJECT {
+                    pixels.fill(Point::new(x, bottom), EMPTY, BASIC);
+                }
+            }
+        }
+
+        let mut land = Land2D::new(LAND_SIZE, parameters.zero);
+        for (y, row) in pixels.rows().enumerate() {
+            for (x, &pixel) in row.iter().enumerate() {
+                if pixel == BASIC {
+                    land.map(y as i32, x as i32, |p| *p = parameters.basic);
+                }
+            }
+        }
+
+        land
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lfprng::LaggedFibonacciPRNG;
+
+    const SEED: &[u8] = b"{3c4fbf5c-35d4-4e5e-a3e0-a2c9bd0b0b56}";
+
+    /// Counts the solid pixels and samples the land below `MIN_Y` every 128 pixels
+    fn generate(maze_size: u32) -> (usize, Vec<String>) {
+        let template = PerlinTemplate::from_maze_size(maze_size, 12).unwrap();
+        let parameters = LandGenerationParameters::new(0u8, 1, 1, false, false);
+        let land = PerlinLandGenerator::new(template)
+            .generate_land(&parameters, &mut LaggedFibonacciPRNG::new(SEED));
+
+        let count = land
+            .rows()
+            .map(|row| row.iter().filter(|&&p| p != 0).count())
+            .sum();
+        let map = (512..LAND_SIZE.height)
+            .step_by(128)
+            .map(|y| {
+                (0..LAND_SIZE.width)
+                    .step_by(128)
+                    .map(|x| if land[y + 64][x + 64] == 0 { '.' } else { '#' })
+                    .collect()
+            })
+            .collect();
+        (count, map)
+    }
+
+    #[test]
+    fn tunnels() {
+        let (count, map) = generate(0);
+        assert_eq!(count, 248905);
+        assert_eq!(
+            map,
+            [
+                "................................",
+                "................................",
+                "................................",
+                "................................",
+                "................................",
+                "................................",
+                "................................",
+                "................................",
+                "................................",
+                "................................",
+                "...##.#.........................",
+                "..###..##..#.....#.......###....",
+            ]
+        );
+    }
+
+    #[test]
+    fn islands() {
+        let (count, map) = generate(4);
+        assert_eq!(count, 2221236);
+        assert_eq!(
+            map,
+            [
+                ".#....#.....#..#...##.#.........",
+                "..#.......#.#...#.#.#.#.........",
+                "..#.#...#...#...##....#...#.....",
+                ".#..............#.....#......#..",
+                "............#...#......#...#.#..",
+                "........#.....#.#....#####.#....",
+                "..#...#.....#.#.#.#........#.#..",
+                "....#.#...#.........#..#........",
+                "..#.....#.#...#.#.#.##..##......",
+                ".##.#.#...#.#.####....####......",
+                ".#......#.###.#...#.########.#..",
+                ".#..####...######.############..",
+            ]
+        );
+    }
+
+    #[test]
+    fn max_hedgehogs() {
+        let max_hedgehogs = |maze_size| {
+            [1, 2, 4, 12, 25]
+                .iter()
+                .map(|&feature_size| {
+                    PerlinTemplate::from_maze_size(maze_size, feature_size)
+                        .unwrap()
+                        .max_hedgehogs
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(max_hedgehogs(0), [12, 12, 14, 22, 24]);
+        assert_eq!(max_hedgehogs(1), [14, 14, 14, 22, 24]);
+        assert_eq!(max_hedgehogs(2), [16, 16, 16, 22, 24]);
+        assert_eq!(max_hedgehogs(3), [64, 64, 60, 50, 32]);
+        assert_eq!(max_hedgehogs(4), [56, 56, 52, 44, 28]);
+        assert_eq!(max_hedgehogs(5), [48, 48, 44, 36, 24]);
+        assert_eq!(PerlinTemplate::from_maze_size(3, 26).unwrap().max_hedgehogs, 0);
+    }
+}
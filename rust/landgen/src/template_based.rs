@@ -1,6 +1,8 @@
-use integral_geometry::{Point, Size};
-use land2d::Land2D;
+use integral_geometry::{Point, Rect};
+use land2d::{Land2D, LandPixel};
+use std::cmp::max;
 use crate::{
+    girders::{place_girders, DEFAULT_GIRDER_HEIGHT},
     LandGenerationParameters,
     LandGenerator,
     outline::OutlinePoints,
@@ -9,20 +11,47 @@ use crate::{
 
 pub struct TemplatedLandGenerator {
     outline_template: OutlineTemplate,
+    girder_height: usize,
+    cavern_filter: bool,
 }
 
 impl TemplatedLandGenerator {
     pub fn new(outline_template: OutlineTemplate) -> Self {
-        Self { outline_template }
+        Self {
+            outline_template,
+            girder_height: DEFAULT_GIRDER_HEIGHT,
+            cavern_filter: false,
+        }
     }
-}
 
-impl LandGenerator for TemplatedLandGenerator {
-    fn generate_land<T: Copy + PartialEq, I: Iterator<Item = u32>>(
+    pub fn with_girder_height(self, girder_height: usize) -> Self {
+        Self {
+            girder_height,
+            ..self
+        }
+    }
+
+    /// The engine inverts every template picked by the cavern filter without drawing
+    /// a random number for it, whatever the template says, and pulls the outlines of
+    /// the positive ones off the floor first
+    pub fn for_cavern_filter(self) -> Self {
+        Self {
+            cavern_filter: true,
+            ..self
+        }
+    }
+
+    pub fn max_hedgehogs(&self) -> u8 {
+        self.outline_template.max_hedgehogs
+    }
+
+    /// Generates the land and returns it along with the rectangles of the placed girders,
+    /// theme objects must not be put over them
+    pub fn generate_land_with_girders<T: LandPixel, I: Iterator<Item = u32>>(
         &self,
         parameters: &LandGenerationParameters<T>,
         random_numbers: &mut I,
-    ) -> Land2D<T> {
+    ) -> (Land2D<T>, Vec<Rect>) {
         let mut land = Land2D::new(self.outline_template.size, parameters.basic);
 
         let mut points = OutlinePoints::from_outline_template(
@@ -32,19 +61,24 @@ impl LandGenerator for TemplatedLandGenerator {
             random_numbers,
         );
 
-        // mirror
+        // mirror, the engine does it on even random numbers
         if self.outline_template.can_mirror {
             if let Some(b) = random_numbers.next() {
-                if b & 1 != 0 {
+                if b & 1 == 0 {
                     points.mirror();
                 }
             }
         }
 
-        // flip
+        // positive templates get recycled as caverns, pull them off the floor a bit
+        if self.cavern_filter && !self.outline_template.is_negative {
+            points.iter_mut().for_each(|p| p.y = max(p.y - 100, 0));
+        }
+
+        // flip, on even random numbers too
         if self.outline_template.can_flip {
             if let Some(b) = random_numbers.next() {
-                if b & 1 != 0 {
+                if b & 1 == 0 {
                     points.flip();
                 }
             }
@@ -66,6 +100,131 @@ impl LandGenerator for TemplatedLandGenerator {
 
         points.draw(&mut land, parameters.basic);
 
-        land
+        let invert = if self.cavern_filter {
+            true
+        } else if self.outline_template.can_invert {
+            random_numbers.next().is_some_and(|b| b & 1 == 0)
+        } else {
+            self.outline_template.is_negative
+        };
+
+        if invert {
+            let play_box = land.play_box();
+            for y in 0..land.height() as i32 {
+                for x in 0..land.width() as i32 {
+                    let inside = play_box.contains(Point::new(x, y));
+                    land.map(y, x, |p| {
+                        *p = if inside && *p == parameters.zero {
+                            parameters.basic
+                        } else {
+                            parameters.zero
+                        }
+                    });
+                }
+            }
+        }
+
+        let girders = if self.outline_template.put_girders {
+            place_girders(&mut land, self.girder_height, parameters)
+        } else {
+            Vec::new()
+        };
+
+        (land, girders)
+    }
+}
+
+impl LandGenerator for TemplatedLandGenerator {
+    fn generate_land<T: LandPixel, I: Iterator<Item = u32>>(
+        &self,
+        parameters: &LandGenerationParameters<T>,
+        random_numbers: &mut I,
+    ) -> Land2D<T> {
+        self.generate_land_with_girders(parameters, random_numbers).0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use integral_geometry::Size;
+
+    const INSIDE: Point = Point::new(250, 150);
+    const OUTSIDE: Point = Point::new(20, 20);
+
+    fn template() -> OutlineTemplate {
+        let corner = |x, y| Rect::from_size_coords(x, y, 1, 1);
+        OutlineTemplate::new(Size::new(500, 250))
+            .add_island(&[
+                corner(100, 50),
+                corner(400, 50),
+                corner(400, 200),
+                corner(100, 200),
+            ])
+            .add_fill_points(&[Point::new(10, 10)])
+    }
+
+    /// Generates the land out of a sequence of the same random number and returns it along
+    /// with the number of random numbers left
+    fn generate(generator: &TemplatedLandGenerator, random_number: u32) -> (Land2D<u8>, usize) {
+        let parameters = LandGenerationParameters::new(0u8, 1, 1, true, true);
+        let mut random_numbers = std::iter::repeat_n(random_number, 100);
+        let land = generator.generate_land(&parameters, &mut random_numbers);
+        (land, random_numbers.count())
+    }
+
+    fn pixel(land: &Land2D<u8>, point: Point) -> u8 {
+        land[point.y as usize][point.x as usize]
+    }
+
+    #[test]
+    fn positive_template() {
+        let (land, left) = generate(&TemplatedLandGenerator::new(template()), 0);
+
+        assert_eq!(left, 90);
+        assert_eq!(pixel(&land, INSIDE), 1);
+        assert_eq!(pixel(&land, OUTSIDE), 0);
+    }
+
+    #[test]
+    fn negative_template() {
+        let (land, left) = generate(&TemplatedLandGenerator::new(template().negative()), 1);
+
+        assert_eq!(left, 90);
+        assert_eq!(pixel(&land, INSIDE), 0);
+        assert_eq!(pixel(&land, OUTSIDE), 1);
+        assert!(!land.play_box().contains(Point::new(2, 2)));
+        assert_eq!(pixel(&land, Point::new(2, 2)), 0);
+    }
+
+    #[test]
+    fn random_inversion() {
+        let generator = TemplatedLandGenerator::new(template().invertable());
+
+        let (land, left) = generate(&generator, 1);
+        assert_eq!(left, 89);
+        assert_eq!(pixel(&land, INSIDE), 1);
+
+        let (land, left) = generate(&generator, 0);
+        assert_eq!(left, 89);
+        assert_eq!(pixel(&land, INSIDE), 0);
+    }
+
+    #[test]
+    fn cavern_filter() {
+        let generator = TemplatedLandGenerator::new(template().negative()).for_cavern_filter();
+        let (land, left) = generate(&generator, 1);
+
+        assert_eq!(left, 90);
+        assert_eq!(pixel(&land, INSIDE), 0);
+        assert_eq!(pixel(&land, OUTSIDE), 1);
+
+        // the island of a positive template is pulled up before getting inverted
+        let generator = TemplatedLandGenerator::new(template().invertable()).for_cavern_filter();
+        let (land, left) = generate(&generator, 1);
+
+        assert_eq!(left, 90);
+        assert_eq!(pixel(&land, Point::new(250, 60)), 0);
+        assert_eq!(pixel(&land, INSIDE), 1);
     }
 }
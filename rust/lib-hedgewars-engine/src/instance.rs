@@ -6,8 +6,12 @@ use hedgewars_engine_messages::queue::*;
 
 use integral_geometry::{Point, Rect, Size};
 use landgen::outline_template::OutlineTemplate;
+use std::{io, path::Path};
 
-use super::{ipc::*, world::World};
+use super::{
+    ipc::*,
+    world::{MapGeneratorType, World},
+};
 
 pub struct EngineInstance {
     pub world: World,
@@ -16,8 +20,8 @@ pub struct EngineInstance {
 }
 
 impl EngineInstance {
-    pub fn new() -> Self {
-        let mut world = World::new();
+    pub fn new(data_path: &Path) -> io::Result<Self> {
+        let mut world = World::new(data_path)?;
 
         fn template() -> OutlineTemplate {
             let mut template = OutlineTemplate::new(Size::new(4096 * 1, 2048 * 1));
@@ -34,11 +38,11 @@ impl EngineInstance {
 
         world.init(template());
 
-        Self {
+        Ok(Self {
             world,
             ipc_channel: Channel::new(),
             ipc_queue: MessagesQueue::new(QueueChatStrategy::LocalGame),
-        }
+        })
     }
 
     pub fn render(&mut self) {
@@ -55,6 +59,17 @@ impl EngineInstance {
     fn process_config_message(&mut self, message: &ConfigEngineMessage) {
         match message {
             SetSeed(seed) => self.world.set_seed(seed.as_bytes()),
+            SetMapGenerator(value) => {
+                if let Some(map_generator) = MapGeneratorType::from_config(value) {
+                    self.world.set_map_generator(map_generator)
+                }
+            }
+            SetTemplateFilter(value) => {
+                if let Ok(template_filter) = value.parse() {
+                    self.world.set_template_filter(template_filter)
+                }
+            }
+            SetFeatureSize(size) => self.world.set_feature_size(*size as u32),
             _ => unimplemented!(),
         }
     }
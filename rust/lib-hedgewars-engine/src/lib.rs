@@ -4,10 +4,12 @@ mod render;
 mod world;
 
 use std::{
-    ffi::CString,
+    ffi::{CStr, CString},
     io::{Read, Write},
     mem::replace,
     os::raw::{c_char, c_void},
+    path::Path,
+    ptr,
 };
 
 use integral_geometry::Point;
@@ -86,11 +88,27 @@ pub extern "C" fn hedgewars_engine_protocol_version() -> u32 {
     58
 }
 
+/// Starts the engine with the game data found at `data_path`, returns null when there is
+/// no path or the data can't be loaded
 #[no_mangle]
-pub extern "C" fn start_engine() -> *mut EngineInstance {
-    let engine_state = Box::new(EngineInstance::new());
+pub extern "C" fn start_engine(data_path: *const c_char) -> *mut EngineInstance {
+    if data_path.is_null() {
+        return ptr::null_mut();
+    }
 
-    Box::leak(engine_state)
+    let data_path = unsafe { CStr::from_ptr(data_path) };
+    let data_path = match data_path.to_str() {
+        Ok(data_path) => Path::new(data_path),
+        Err(_) => return ptr::null_mut(),
+    };
+
+    match EngineInstance::new(data_path) {
+        Ok(engine_state) => Box::leak(Box::new(engine_state)),
+        Err(e) => {
+            println!("Unable to start the engine: {}", e);
+            ptr::null_mut()
+        }
+    }
 }
 
 #[no_mangle]
@@ -103,7 +121,7 @@ pub extern "C" fn generate_preview(engine_state: &mut EngineInstance, preview: &
         *preview = PreviewInfo {
             width: land_preview.width() as u32,
             height: land_preview.height() as u32,
-            hedgehogs_number: 0,
+            hedgehogs_number: (*engine_state).world.preview_max_hedgehogs(),
             land: land_preview.raw_pixels().as_ptr(),
         };
     }
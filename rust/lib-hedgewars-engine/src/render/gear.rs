@@ -27,11 +27,11 @@ struct SpriteData {
 const ATLAS_SIZE: Size = Size::square(2048);
 
 impl GearRenderer {
-    pub fn new() -> Self {
+    pub fn new(graphics_path: &Path) -> Self {
         let mut lookup = Vec::with_capacity(2048);
 
         let mut atlas = AtlasCollection::new(ATLAS_SIZE);
-        let mut sprites = load_sprites(Path::new("../../share/hedgewars/Data/Graphics/"))
+        let mut sprites = load_sprites(graphics_path)
             .expect("Unable to load Graphics");
         let max_size = sprites
             .iter()
@@ -1,5 +1,5 @@
 use integral_geometry::{GridIndex, Point, Rect, Size};
-use land2d::Land2D;
+use land2d::{Land2D, LandFlags};
 use vec2d::Vec2D;
 
 use super::{
@@ -207,7 +207,7 @@ impl MapRenderer {
         }
     }
 
-    pub fn update(&mut self, land: &Land2D<u32>, region: Rect) {}
+    pub fn update(&mut self, land: &Land2D<LandFlags>, region: Rect) {}
 
     pub fn render(&mut self, camera: &Camera) {
         let viewport = camera.viewport();
@@ -4,29 +4,76 @@ use hwphysics::{
     common::{GearId, Millis},
 };
 use integral_geometry::{Point, Rect, Size};
-use land2d::Land2D;
+use land2d::{Land2D, LandFlags};
 use landgen::{
-    outline_template::OutlineTemplate, template_based::TemplatedLandGenerator,
+    maze::{MazeLandGenerator, MazeTemplate},
+    outline_template::OutlineTemplate,
+    perlin::{PerlinLandGenerator, PerlinTemplate},
+    template_based::TemplatedLandGenerator,
     LandGenerationParameters, LandGenerator,
 };
 use lfprng::LaggedFibonacciPRNG;
+use mapgen::MapGenerator;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
 
 use crate::render::{camera::Camera, GearRenderer, MapRenderer};
 
 struct GameState {
-    land: Land2D<u32>,
+    land: Land2D<LandFlags>,
     physics: hwp::World,
 }
 
 impl GameState {
-    fn new(land: Land2D<u32>, physics: hwp::World) -> Self {
+    fn new(land: Land2D<LandFlags>, physics: hwp::World) -> Self {
         Self { land, physics }
     }
 }
 
+const TEMPLATES_FILE: &str = "map_templates.yaml";
+const CAVERN_TEMPLATE_FILTER: u32 = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MapGeneratorType {
+    Template,
+    Maze,
+    Perlin,
+    Drawn,
+    Forts,
+}
+
+impl MapGeneratorType {
+    pub fn from_config(value: &str) -> Option<Self> {
+        use MapGeneratorType::*;
+        match value {
+            "0" => Some(Template),
+            "1" => Some(Maze),
+            "2" => Some(Perlin),
+            "3" => Some(Drawn),
+            "4" => Some(Forts),
+            _ => None,
+        }
+    }
+}
+
+fn load_templates(data_path: &Path) -> io::Result<MapGenerator> {
+    let text = fs::read_to_string(data_path.join(TEMPLATES_FILE))?;
+    let mut generator = MapGenerator::new();
+    generator.import_yaml_templates(&text);
+    Ok(generator)
+}
+
 pub struct World {
+    data_path: PathBuf,
     random_numbers_gen: LaggedFibonacciPRNG,
+    map_generator: MapGeneratorType,
+    templates: MapGenerator,
+    template_filter: u32,
+    feature_size: u32,
     preview: Option<Land2D<u8>>,
+    preview_max_hedgehogs: u8,
     game_state: Option<GameState>,
     map_renderer: Option<MapRenderer>,
     gear_renderer: Option<GearRenderer>,
@@ -34,31 +81,37 @@ pub struct World {
 }
 
 impl World {
-    pub fn new() -> Self {
-        Self {
+    /// Creates the world loading the map templates from the data directory the host
+    /// passes, fails when they can't be read
+    pub fn new(data_path: &Path) -> io::Result<Self> {
+        Ok(Self {
+            data_path: data_path.to_owned(),
             random_numbers_gen: LaggedFibonacciPRNG::new(&[]),
+            map_generator: MapGeneratorType::Template,
+            templates: load_templates(data_path)?,
+            template_filter: 0,
+            feature_size: 12,
             preview: None,
+            preview_max_hedgehogs: 0,
             game_state: None,
             map_renderer: None,
             gear_renderer: None,
             camera: Camera::new(),
-        }
+        })
     }
 
     pub fn create_renderer(&mut self, width: u16, height: u16) {
         let land_tile_size = Size::square(512);
         self.map_renderer = Some(MapRenderer::new(land_tile_size));
-        self.gear_renderer = Some(GearRenderer::new());
+        self.gear_renderer = Some(GearRenderer::new(&self.data_path.join("Graphics")));
         self.camera = Camera::with_size(Size::new(width as usize, height as usize));
 
-        use mapgen::{theme::Theme, MapGenerator};
-        use std::path::Path;
+        use mapgen::theme::Theme;
 
         if let Some(ref state) = self.game_state {
             self.camera.position = state.land.play_box().center();
 
-            let theme =
-                Theme::load(Path::new("../../share/hedgewars/Data/Themes/Cheese/")).unwrap();
+            let theme = Theme::load(&self.data_path.join("Themes/Cheese")).unwrap();
             let texture = MapGenerator::new().make_texture(&state.land, &theme);
             if let Some(ref mut renderer) = self.map_renderer {
                 renderer.init(&texture);
@@ -70,10 +123,27 @@ impl World {
         self.random_numbers_gen = LaggedFibonacciPRNG::new(seed);
     }
 
+    pub fn set_map_generator(&mut self, map_generator: MapGeneratorType) {
+        self.map_generator = map_generator;
+    }
+
+    pub fn set_template_filter(&mut self, template_filter: u32) {
+        self.template_filter = template_filter;
+    }
+
+    pub fn set_feature_size(&mut self, feature_size: u32) {
+        self.feature_size = feature_size;
+    }
+
     pub fn preview(&self) -> &Option<Land2D<u8>> {
         &self.preview
     }
 
+    /// Hedgehog limit of the previewed map
+    pub fn preview_max_hedgehogs(&self) -> u8 {
+        self.preview_max_hedgehogs
+    }
+
     pub fn generate_preview(&mut self) {
         fn template() -> OutlineTemplate {
             let mut template = OutlineTemplate::new(Size::new(4096, 2048));
@@ -89,8 +159,48 @@ impl World {
         }
 
         let params = LandGenerationParameters::new(0u8, u8::max_value(), 5, false, false);
-        let landgen = TemplatedLandGenerator::new(template());
-        self.preview = Some(landgen.generate_land(&params, &mut self.random_numbers_gen));
+        let rng = &mut self.random_numbers_gen;
+        let land = match self.map_generator {
+            MapGeneratorType::Maze => {
+                MazeTemplate::from_maze_size(self.template_filter, self.feature_size).map(|t| {
+                    let landgen = MazeLandGenerator::new(t);
+                    (landgen.generate_land(&params, rng), landgen.max_hedgehogs())
+                })
+            }
+            MapGeneratorType::Perlin => {
+                PerlinTemplate::from_maze_size(self.template_filter, self.feature_size).map(|t| {
+                    let landgen = PerlinLandGenerator::new(t);
+                    (landgen.generate_land(&params, rng), landgen.max_hedgehogs())
+                })
+            }
+            _ => None,
+        };
+
+        let (land, max_hedgehogs) = match land {
+            Some(generated) => generated,
+            None => {
+                let (template_filter, template) =
+                    match self.templates.select_template(self.template_filter, rng) {
+                        Some((filter, template)) => (filter, template.clone()),
+                        None => (self.template_filter, template()),
+                    };
+                let mut landgen = TemplatedLandGenerator::new(template);
+                if template_filter == CAVERN_TEMPLATE_FILTER {
+                    landgen = landgen.for_cavern_filter();
+                }
+                let params = LandGenerationParameters::new(
+                    0u8,
+                    u8::max_value(),
+                    MapGenerator::distance_divisor(rng),
+                    false,
+                    false,
+                );
+                (landgen.generate_land(&params, rng), landgen.max_hedgehogs())
+            }
+        };
+
+        self.preview = Some(land);
+        self.preview_max_hedgehogs = max_hedgehogs;
     }
 
     pub fn dispose_preview(&mut self) {
@@ -100,7 +210,13 @@ impl World {
     pub fn init(&mut self, template: OutlineTemplate) {
         let physics = hwp::World::new(template.size);
 
-        let params = LandGenerationParameters::new(0u32, u32::max_value(), 5, false, false);
+        let params = LandGenerationParameters::new(
+            LandFlags::empty(),
+            LandFlags::BASIC,
+            5,
+            false,
+            false,
+        );
         let landgen = TemplatedLandGenerator::new(template);
         let land = landgen.generate_land(&params, &mut self.random_numbers_gen);
 
@@ -1,10 +1,11 @@
+pub mod objects;
+pub mod static_map;
 pub mod theme;
 
-use self::theme::Theme;
+use self::{objects::ObjectParameters, theme::Theme};
 use integral_geometry::{Point, Rect, Size};
 use land2d::Land2D;
 use landgen::outline_template::OutlineTemplate;
-use rand::{thread_rng, Rng};
 use serde_derive::Deserialize;
 use serde_yaml;
 use std::{borrow::Borrow, collections::hash_map::HashMap, mem::replace};
@@ -71,10 +72,15 @@ impl From<&TemplateDesc> for OutlineTemplate {
             can_invert: desc.can_invert,
             can_mirror: desc.can_mirror,
             is_negative: desc.is_negative,
+            put_girders: desc.put_girders,
+            max_hedgehogs: desc.max_hedgehogs,
         }
     }
 }
 
+/// Template types in the order of the template filter values of the room config
+const TEMPLATE_FILTER_TYPES: [&str; 5] = ["small", "medium", "large", "cavern", "wacky"];
+
 #[derive(PartialEq, Eq, Hash, Clone, Debug)]
 struct TemplateType(String);
 
@@ -111,10 +117,73 @@ impl MapGenerator {
             .collect();
     }
 
-    pub fn get_template(&self, template_type: &str) -> Option<&OutlineTemplate> {
-        self.templates
-            .get(template_type)
-            .and_then(|t| thread_rng().choose(t))
+    /// Picks a template of the given type, the engine draws a number for the type even
+    /// when it's fixed so the same is done here to keep the sequences in sync
+    pub fn get_template<I: Iterator<Item = u32>>(
+        &self,
+        template_type: &str,
+        random_numbers: &mut I,
+    ) -> Option<&OutlineTemplate> {
+        random_numbers.next();
+        self.choose_template(template_type, random_numbers)
+    }
+
+    /// Picks a template for the template filter of the room config, zero picks the type
+    /// first with every grouped template being equally likely. Returns the template along
+    /// with the filter it was picked by, the engine treats the zero filter as the picked type
+    /// for the rest of the generation
+    pub fn select_template<I: Iterator<Item = u32>>(
+        &self,
+        template_filter: u32,
+        random_numbers: &mut I,
+    ) -> Option<(u32, &OutlineTemplate)> {
+        if template_filter != 0 {
+            let template_type = TEMPLATE_FILTER_TYPES.get(template_filter as usize - 1)?;
+            return self
+                .get_template(template_type, random_numbers)
+                .map(|template| (template_filter, template));
+        }
+
+        let counts: Vec<_> = TEMPLATE_FILTER_TYPES
+            .iter()
+            .map(|t| self.templates.get(*t).map_or(0, Vec::len))
+            .collect();
+        let total = counts.iter().sum::<usize>() as u32;
+        if total == 0 {
+            return None;
+        }
+
+        let mut index = get_random(random_numbers, total) as usize;
+        let (filter_index, _) = counts.into_iter().enumerate().find(|&(_, count)| {
+            if index < count {
+                true
+            } else {
+                index -= count;
+                false
+            }
+        })?;
+
+        self.choose_template(TEMPLATE_FILTER_TYPES[filter_index], random_numbers)
+            .map(|template| (filter_index as u32 + 1, template))
+    }
+
+    /// Draws the divisor of the distortion distances, the engine does it right after
+    /// selecting the template
+    pub fn distance_divisor<I: Iterator<Item = u32>>(random_numbers: &mut I) -> u32 {
+        get_random(random_numbers, 21) + 100
+    }
+
+    fn choose_template<I: Iterator<Item = u32>>(
+        &self,
+        template_type: &str,
+        random_numbers: &mut I,
+    ) -> Option<&OutlineTemplate> {
+        let templates = self.templates.get(template_type)?;
+        if templates.is_empty() {
+            None
+        } else {
+            templates.get(get_random(random_numbers, templates.len() as u32) as usize)
+        }
     }
 
     pub fn make_texture<LandT>(&self, land: &Land2D<LandT>, theme: &Theme) -> Vec2D<u32>
@@ -179,6 +248,81 @@ impl MapGenerator {
 
         texture
     }
+
+    /// Tiles the land back texture of the theme over the land, it should be made before the
+    /// objects are placed so that destroyed objects leave holes instead of the background
+    pub fn make_back_texture<LandT>(&self, land: &Land2D<LandT>, theme: &Theme) -> Option<Vec2D<u32>>
+    where
+        LandT: Copy + Default + PartialEq,
+    {
+        let back_sprite = theme.land_back_texture()?;
+        let mut texture = Vec2D::new(land.size(), 0);
+
+        for (row_index, (land_row, tex_row)) in land.rows().zip(texture.rows_mut()).enumerate() {
+            let sprite_row = back_sprite.get_row(row_index % back_sprite.height());
+            for (x, (land_v, tex_v)) in land_row.iter().zip(tex_row.iter_mut()).enumerate() {
+                if *land_v != LandT::default() {
+                    *tex_v = sprite_row[x % back_sprite.width()];
+                }
+            }
+        }
+
+        Some(texture)
+    }
+
+    /// Updates the texture of the region after the land in it has been destroyed with
+    /// `Land2D::fill_circle` or `Land2D::change_round`: opaque land is replaced by the back
+    /// texture where there is one and everything else that was destroyed becomes transparent
+    pub fn update_texture<LandT>(
+        &self,
+        land: &Land2D<LandT>,
+        texture: &mut Vec2D<u32>,
+        back_texture: Option<&Vec2D<u32>>,
+        region: Rect,
+    ) where
+        LandT: Copy + Default + PartialEq,
+    {
+        let left = region.left().max(0) as usize;
+        let right = region.right().min(land.width() as i32 - 1);
+        let top = region.top().max(0) as usize;
+        let bottom = region.bottom().min(land.height() as i32 - 1);
+        if right < left as i32 || bottom < top as i32 {
+            return;
+        }
+
+        for y in top..=bottom as usize {
+            for x in left..=right as usize {
+                let tex_v = &mut texture[y][x];
+                let alpha = Color(*tex_v).alpha();
+
+                if land[y][x] == LandT::default() && alpha != 0 {
+                    *tex_v = match back_texture.map(|t| t[y][x]) {
+                        Some(back_v) if alpha == 255 && back_v != 0 => back_v,
+                        _ => 0,
+                    };
+                }
+            }
+        }
+    }
+
+    /// Scatters the sprays of the theme over the land texture and places its objects away
+    /// from the `girders` of the generator, all random choices are taken from `random_numbers`
+    /// so the result is reproducible. Returns the rectangles of the placed objects
+    pub fn place_objects<LandT, I>(
+        &self,
+        land: &mut Land2D<LandT>,
+        texture: &mut Vec2D<u32>,
+        girders: &[Rect],
+        theme: &Theme,
+        parameters: &ObjectParameters<LandT>,
+        random_numbers: &mut I,
+    ) -> Vec<Rect>
+    where
+        LandT: Copy + Default + PartialEq,
+        I: Iterator<Item = u32>,
+    {
+        objects::place_objects(land, texture, girders, theme, parameters, random_numbers)
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -206,6 +350,11 @@ impl Color {
     }
 }
 
+#[inline]
+fn get_random<I: Iterator<Item = u32>>(random_numbers: &mut I, modulo: u32) -> u32 {
+    random_numbers.next().unwrap() % modulo
+}
+
 #[inline]
 fn lerp(from: u8, to: u8, coef: u8) -> u8 {
     ((from as u16 * (256 - coef as u16) + to as u16 * coef as u16) / 256) as u8
@@ -235,7 +384,7 @@ where
             .zip(offsets.iter_mut())
             .enumerate()
         {
-            *offset_v = if *land_v == LandT::default() {
+            *offset_v = if *land_v != LandT::default() {
                 if *offset_v < border_width {
                     *tex_v = blend(pixel_getter(x, *offset_v as usize), *tex_v)
                 }
@@ -253,16 +402,24 @@ where
 {
     for ((land_v, tex_v), sprite_v) in land_row.iter().zip(tex_row.iter_mut()).zip(sprite_row) {
         *tex_v = if *land_v == LandT::default() {
-            *sprite_v
-        } else {
             0
+        } else {
+            *sprite_v
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{MapGenerator, TemplateType};
+    use crate::{MapGenerator, TemplateType, TEMPLATE_FILTER_TYPES};
+    use integral_geometry::{Point, Rect, Size};
+    use land2d::Land2D;
+    use landgen::{
+        outline_template::OutlineTemplate, template_based::TemplatedLandGenerator,
+        LandGenerationParameters, LandGenerator,
+    };
+    use lfprng::LaggedFibonacciPRNG;
+    use vec2d::Vec2D;
 
     #[test]
     fn simple_load() {
@@ -303,8 +460,123 @@ template_types:
             .templates
             .contains_key(&TemplateType("test".to_string())));
 
-        let template = generator.get_template("test").unwrap();
+        let template = generator
+            .get_template("test", &mut LaggedFibonacciPRNG::new(&[]))
+            .unwrap();
 
         assert_eq!(template.islands[0].len(), 7);
+        assert!(template.put_girders);
+        assert_eq!(template.max_hedgehogs, 18);
+    }
+
+    fn shipped_templates() -> MapGenerator {
+        let mut generator = MapGenerator::new();
+        generator.import_yaml_templates(include_str!(
+            "../../../share/hedgewars/Data/map_templates.yaml"
+        ));
+        generator
+    }
+
+    #[test]
+    fn engine_template_groups() {
+        let generator = shipped_templates();
+        let counts: Vec<_> = TEMPLATE_FILTER_TYPES
+            .iter()
+            .map(|t| generator.templates[*t].len())
+            .collect();
+
+        assert_eq!(counts, [3, 19, 18, 6, 5]);
+        assert!(generator
+            .select_template(6, &mut LaggedFibonacciPRNG::new(&[]))
+            .is_none());
+    }
+
+    /// The expectations come from the engine's `SelectTemplate` and `GenTemplated` run on
+    /// the same seeds. The distortion isn't ported draw for draw, so like the maze tests
+    /// this compares the land before it, the draws up to there match the engine's
+    #[test]
+    fn known_seed_templates() {
+        let generator = shipped_templates();
+
+        // seed suffix, picked filter, template position in its type, template size,
+        // land pixels, sum of their offsets, next random number
+        let cases = [
+            ("56", 2, 10, Size::new(4096, 2048), 606425, 4355140700373, 1208522135),
+            // mirrored
+            ("53", 3, 12, Size::new(4096, 2048), 1249237, 8739145370767, 1639019002),
+            // cavern, pulled off the floor and inverted
+            ("55", 4, 2, Size::new(4096, 2048), 3799787, 20258052690753, 1489282781),
+            // wacky #43 mirrored, the fill point ends up on the left border
+            ("57", 5, 3, Size::new(4096, 4096), 1760788, 14297741117100, 1337347379),
+        ];
+
+        for (suffix, filter, position, size, count, offsets, next) in cases {
+            let seed = format!("{{3c4fbf5c-35d4-4e5e-a3e0-a2c9bd0b0b{}}}", suffix);
+            let mut random_numbers = LaggedFibonacciPRNG::new(seed.as_bytes());
+
+            let (template_filter, template) = generator
+                .select_template(0, &mut random_numbers)
+                .unwrap();
+            let group = &generator.templates[TEMPLATE_FILTER_TYPES[filter - 1]];
+            assert_eq!(template_filter, filter as u32);
+            assert!(std::ptr::eq(template, &group[position]));
+
+            let distance_divisor = MapGenerator::distance_divisor(&mut random_numbers);
+            let params = LandGenerationParameters::new(0u8, 1, distance_divisor, true, true);
+
+            // the engine adds the girders along with the theme objects
+            let template = OutlineTemplate {
+                put_girders: false,
+                ..template.clone()
+            };
+            let mut landgen = TemplatedLandGenerator::new(template);
+            if template_filter == 4 {
+                landgen = landgen.for_cavern_filter();
+            }
+            let land = landgen.generate_land(&params, &mut random_numbers);
+
+            let pixels = land.raw_pixels().iter().enumerate().filter(|(_, p)| **p != 0);
+            assert_eq!(land.size(), size);
+            assert_eq!(pixels.clone().count(), count);
+            assert_eq!(pixels.map(|(i, _)| i as u64).sum::<u64>(), offsets);
+            assert_eq!(random_numbers.next(), Some(next));
+        }
+    }
+
+    #[test]
+    fn destroyed_land_texture() {
+        let mut land = Land2D::new(Size::new(64, 32), 0u8);
+        land.fill_circle(Point::new(32, 16), 12, 255);
+
+        let mut texture = Vec2D::new(land.size(), 0);
+        for (land_row, tex_row) in land.rows().zip(texture.rows_mut()) {
+            for (land_v, tex_v) in land_row.iter().zip(tex_row.iter_mut()) {
+                if *land_v != 0 {
+                    *tex_v = 0xFF00_00FF;
+                }
+            }
+        }
+        texture[16][26] = 0x8000_00FF;
+
+        let mut back_texture = Vec2D::new(land.size(), 0xFF00_FF00);
+        back_texture[16][38] = 0;
+
+        land.fill_circle(Point::new(32, 16), 8, 0);
+        let generator = MapGenerator::new();
+        generator.update_texture(
+            &land,
+            &mut texture,
+            Some(&back_texture),
+            Rect::from_box(20, 44, 4, 28),
+        );
+
+        assert_eq!(texture[16][32], 0xFF00_FF00);
+        assert_eq!(texture[16][26], 0);
+        assert_eq!(texture[16][38], 0);
+        assert_eq!(texture[16][44], 0xFF00_00FF);
+        assert_eq!(texture[0][0], 0);
+
+        generator.update_texture(&land, &mut texture, None, Rect::from_box(0, 63, 0, 31));
+        assert_eq!(texture[16][32], 0);
     }
 }
@@ -0,0 +1,613 @@
+use crate::{
+    static_map::mask_land_value,
+    theme::{Theme, ThemeObject, ThemeSpray, ThemeSprite},
+    get_random, Color,
+};
+use integral_geometry::Rect;
+use land2d::{Land2D, LandFlags};
+use landgen::{girders::reserved_area, outline_template::DEFAULT_MAX_HEDGEHOGS};
+use vec2d::Vec2D;
+
+const MAX_OBJECT_POINTS: usize = 2047;
+const MAX_SPRAY_POINTS: usize = 8095;
+
+// leave room for a hedgehog to teleport in
+const OBJECT_TOP_MARGIN: i32 = 32;
+
+pub struct ObjectParameters<T> {
+    basic: T,
+    object: T,
+    max_hedgehogs: u8,
+}
+
+impl<T> ObjectParameters<T> {
+    /// `basic` is the value of the generated land and `object` is stamped under the placed
+    /// objects, object counts are scaled by the hedgehog limit of the map
+    pub fn new(basic: T, object: T, max_hedgehogs: u8) -> Self {
+        Self {
+            basic,
+            object,
+            max_hedgehogs,
+        }
+    }
+}
+
+#[inline]
+fn rgba(red: u32, green: u32, blue: u32, alpha: u32) -> u32 {
+    red | green << 8 | blue << 16 | alpha << 24
+}
+
+#[inline]
+fn lerp_byte(source: u8, target: u8, coef: u8) -> u32 {
+    ((255 - coef as u32) * source as u32 + coef as u32 * target as u32) / 255
+}
+
+/// Blends the sprite under the land texture, visible land keeps covering the object
+fn blend_under(color: u32, land_color: u32) -> u32 {
+    let (color, land_color) = (Color(color), Color(land_color));
+    let land_alpha = land_color.alpha();
+    rgba(
+        lerp_byte(color.red(), land_color.red(), land_alpha),
+        lerp_byte(color.green(), land_color.green(), land_alpha),
+        lerp_byte(color.blue(), land_color.blue(), land_alpha),
+        lerp_byte(land_alpha, 255, color.alpha()),
+    )
+}
+
+/// Blends the overlay over the land texture
+fn blend_over(color: u32, land_color: u32) -> u32 {
+    let (color, land_color) = (Color(color), Color(land_color));
+    let alpha = color.alpha();
+    rgba(
+        lerp_byte(land_color.red(), color.red(), alpha),
+        lerp_byte(land_color.green(), color.green(), alpha),
+        lerp_byte(land_color.blue(), color.blue(), alpha),
+        lerp_byte(alpha, 255, land_color.alpha()),
+    )
+}
+
+/// Blends the spray into the land texture the same way the engine's `copyToXY` does
+fn blend_spray(color: u32, land_color: u32) -> u32 {
+    let (color, land_color) = (Color(color), Color(land_color));
+    let (alpha, land_alpha) = (color.alpha() as u32, land_color.alpha() as u32);
+    if alpha == 255 || land_alpha == 0 {
+        return color.0;
+    }
+
+    let mix = |land: u8, color: u8| (land as u32 * (255 - alpha) + color as u32 * alpha) / 255;
+    rgba(
+        mix(land_color.red(), color.red()),
+        mix(land_color.green(), color.green()),
+        mix(land_color.blue(), color.blue()),
+        land_alpha + (255 - land_alpha) * alpha / 255,
+    )
+}
+
+fn scaled_count(max_count: u32, max_hedgehogs: u8) -> u32 {
+    // counts are proportional to the map size but every object gets a chance on tiny maps
+    (max_count * max_hedgehogs as u32 / DEFAULT_MAX_HEDGEHOGS as u32).max(1)
+}
+
+struct ObjectPlacer<'a, T> {
+    land: &'a mut Land2D<T>,
+    texture: &'a mut Vec2D<u32>,
+    parameters: &'a ObjectParameters<T>,
+    zero: T,
+    reserved: Vec<Rect>,
+    placed: Vec<Rect>,
+}
+
+impl<'a, T: Copy + PartialEq> ObjectPlacer<'a, T> {
+    #[inline]
+    fn pixel(&self, x: i32, y: i32) -> Option<T> {
+        if self.land.is_valid_coordinate(x, y) {
+            Some(self.land[y as usize][x as usize])
+        } else {
+            None
+        }
+    }
+
+    fn is_reserved(&self, x: i32, y: i32, width: usize, height: usize) -> bool {
+        let rect = Rect::from_size_coords(x, y, width, height);
+        self.reserved.iter().any(|r| r.intersects(&rect))
+    }
+
+    /// Checks that the border of the rectangle moved by `(dx, dy)` only consists of `value`,
+    /// the right and bottom edges are one pixel outside of the rectangle like in the engine
+    fn check_land(&self, rect: &Rect, dx: i32, dy: i32, value: T) -> bool {
+        let (x, y) = (rect.left() + dx, rect.top() + dy);
+        let (width, height) = (rect.width() as i32, rect.height() as i32);
+        let (bx, by) = (x + width, y + height);
+        let is_value = |x, y| self.pixel(x, y) == Some(value);
+
+        let (mut x1, mut x2) = (x, bx);
+        while x1 < bx - width / 2 {
+            if !(is_value(x1, y) && is_value(x1, by) && is_value(x2, y) && is_value(x2, by)) {
+                return false;
+            }
+            x1 += 1;
+            x2 -= 1;
+        }
+
+        let (mut y1, mut y2) = (y + 1, by - 1);
+        while y1 < by - height / 2 {
+            if !(is_value(x, y1) && is_value(bx, y1) && is_value(x, y2) && is_value(bx, y2)) {
+                return false;
+            }
+            y1 += 1;
+            y2 -= 1;
+        }
+
+        true
+    }
+
+    /// Checks whether the border of the rectangle moved by `(dx, dy)` touches any land
+    fn check_land_any(&self, rect: &Rect, dx: i32, dy: i32) -> bool {
+        let (x, y) = (rect.left() + dx, rect.top() + dy);
+        let (bx, by) = (x + rect.width() as i32 - 1, y + rect.height() as i32 - 1);
+
+        if !self.land.is_valid_coordinate(x, y) || !self.land.is_valid_coordinate(bx, by) {
+            return false;
+        }
+
+        let is_land = |x, y| self.pixel(x, y) != Some(self.zero);
+        (x..=bx).any(|x| is_land(x, y) || is_land(x, by))
+            || (y..=by).any(|y| is_land(x, y) || is_land(bx, y))
+    }
+
+    fn can_place(&self, object: &ThemeObject, x: i32, y: i32) -> bool {
+        let config = object.config();
+        let sprite = object.sprite();
+
+        let overlays_fit = config.overlays.iter().zip(object.overlays()).all(|(o, s)| {
+            let (x1, y1) = (x + o.position.x, y + o.position.y);
+            let (x2, y2) = (x1 + s.width() as i32 - 1, y1 + s.height() as i32 - 1);
+            self.land.is_valid_coordinate(x1, y1)
+                && self.land.is_valid_coordinate(x2, y2)
+                && !self.is_reserved(x1, y1, s.width(), s.height())
+        });
+
+        overlays_fit
+            && config
+                .inland_rects
+                .iter()
+                .all(|r| self.check_land(r, x, y, self.parameters.basic))
+            && config
+                .outland_rects
+                .iter()
+                .all(|r| self.check_land(r, x, y, self.zero))
+            && (config.anchors.is_empty()
+                || config.anchors.iter().any(|r| self.check_land_any(r, x, y)))
+            && !self.is_reserved(x, y, sprite.width(), sprite.height())
+    }
+
+    fn blit<F>(&mut self, sprite: &ThemeSprite, x: i32, y: i32, mut f: F)
+    where
+        F: FnMut(&mut T, &mut u32, u32, usize, usize),
+    {
+        for (sprite_y, row) in sprite.rows().enumerate() {
+            for (sprite_x, &color) in row.iter().enumerate() {
+                let (land_x, land_y) = (x + sprite_x as i32, y + sprite_y as i32);
+                if self.land.is_valid_coordinate(land_x, land_y) {
+                    let texture_pixel = &mut self.texture[land_y as usize][land_x as usize];
+                    self.land.map(land_y, land_x, |p| {
+                        f(p, texture_pixel, color, sprite_x, sprite_y)
+                    });
+                }
+            }
+        }
+    }
+
+    fn blit_object(&mut self, object: &ThemeObject, x: i32, y: i32) {
+        let (zero, object_value) = (self.zero, self.parameters.object);
+        let basic = self.parameters.basic;
+
+        let blend = |texture_pixel: &mut u32, color: u32| {
+            let land_alpha = Color(*texture_pixel).alpha();
+            if Color(color).alpha() != 0 && land_alpha != 255 {
+                *texture_pixel = if land_alpha == 0 {
+                    color
+                } else {
+                    blend_under(color, *texture_pixel)
+                };
+            }
+        };
+
+        if let Some(mask) = object.mask() {
+            self.blit(object.sprite(), x, y, |land_pixel, texture_pixel, color, sx, sy| {
+                blend(texture_pixel, color);
+
+                if *land_pixel == zero || *land_pixel == object_value {
+                    match mask_land_value(mask.get_pixel(sx, sy)) {
+                        Some(value) if value.is_empty() => *land_pixel = zero,
+                        Some(value) if value.contains(LandFlags::OBJECT) => {
+                            *land_pixel = object_value
+                        }
+                        Some(_) => *land_pixel = basic,
+                        None => (),
+                    }
+                }
+            });
+        } else {
+            self.blit(object.sprite(), x, y, |land_pixel, texture_pixel, color, _, _| {
+                blend(texture_pixel, color);
+
+                if Color(color).alpha() != 0 && *land_pixel == zero {
+                    *land_pixel = object_value;
+                }
+            });
+        }
+    }
+
+    fn blit_overlay(&mut self, sprite: &ThemeSprite, x: i32, y: i32) {
+        let (zero, object_value) = (self.zero, self.parameters.object);
+
+        self.blit(sprite, x, y, |land_pixel, texture_pixel, color, _, _| {
+            let alpha = Color(color).alpha();
+            if alpha != 0 {
+                *texture_pixel = if alpha != 255 && *texture_pixel != 0 {
+                    blend_over(color, *texture_pixel)
+                } else {
+                    color
+                };
+
+                if *land_pixel == zero {
+                    *land_pixel = object_value;
+                }
+            }
+        });
+    }
+
+    /// Places the object at a random spot among all the spots it fits in,
+    /// a literal port of the engine's `TryPut`
+    fn try_put_object<I>(
+        &mut self,
+        object: &ThemeObject,
+        max_count: &mut u32,
+        random_numbers: &mut I,
+    ) -> bool
+    where
+        I: Iterator<Item = u32>,
+    {
+        if *max_count == 0 {
+            return false;
+        }
+
+        let sprite = object.sprite();
+        let (width, height) = (sprite.width() as i32, sprite.height() as i32);
+        let play_box = self.land.play_box();
+        let land_height = self.land.height() as i32;
+
+        // objects with an empty first inland rectangle sit at the bottom of the map
+        let on_bottom = object
+            .config()
+            .inland_rects
+            .first()
+            .is_some_and(|r| r.left() == 0 && r.top() == 0 && r.area() == 0);
+
+        let mut points = Vec::new();
+        let mut is_full = false;
+        let mut x = play_box.left();
+
+        loop {
+            let mut y = play_box.top() + OBJECT_TOP_MARGIN;
+            loop {
+                if on_bottom {
+                    y = land_height - height;
+                }
+
+                if self.can_place(object, x, y) {
+                    if points.len() < MAX_OBJECT_POINTS {
+                        points.push((x, y));
+                    } else {
+                        is_full = true;
+                        break;
+                    }
+                }
+
+                y += 3;
+                if y >= land_height - height {
+                    break;
+                }
+            }
+
+            x += get_random(random_numbers, 6) as i32 + 3;
+            if is_full || x >= play_box.right() - width {
+                break;
+            }
+        }
+
+        if points.is_empty() {
+            *max_count = 0;
+            return false;
+        }
+
+        let (x, y) = points[get_random(random_numbers, points.len() as u32) as usize];
+        self.blit_object(object, x, y);
+        let rect = Rect::from_size_coords(x, y, sprite.width(), sprite.height());
+        self.reserved.push(rect);
+        self.placed.push(rect);
+
+        for (overlay, overlay_sprite) in object.config().overlays.iter().zip(object.overlays()) {
+            let (overlay_x, overlay_y) = (x + overlay.position.x, y + overlay.position.y);
+            self.blit_overlay(overlay_sprite, overlay_x, overlay_y);
+            // the engine reserves the size of the object and not of the overlay
+            self.reserved.push(Rect::from_size_coords(
+                overlay_x,
+                overlay_y,
+                sprite.width(),
+                sprite.height(),
+            ));
+        }
+
+        *max_count -= 1;
+        true
+    }
+
+    /// Sprays the texture at a random spot on solid land, the scan starts at a random
+    /// position and wraps around once on each axis like the engine's `TryPut2`
+    fn try_put_spray<I>(
+        &mut self,
+        spray: &ThemeSpray,
+        max_count: &mut u32,
+        random_numbers: &mut I,
+    ) -> bool
+    where
+        I: Iterator<Item = u32>,
+    {
+        if *max_count == 0 {
+            return false;
+        }
+
+        let sprite = spray.sprite();
+        let (width, height) = (sprite.width() as u32, sprite.height() as u32);
+        let (land_width, land_height) = (self.land.width() as u32, self.land.height() as u32);
+
+        if width >= land_width || height + 16 >= land_height {
+            *max_count = 0;
+            return false;
+        }
+
+        let x_start = get_random(random_numbers, land_width - width);
+        let y_start = 8 + get_random(random_numbers, land_height - height - 16);
+        let check_rect = Rect::from_size_coords(0, 0, width as usize, height as usize + 16);
+
+        let mut points = Vec::new();
+        let (mut x, mut y) = (x_start, y_start);
+        let mut x_wraps = 0;
+
+        loop {
+            let mut y_wraps = 0;
+            loop {
+                if self.check_land(&check_rect, x as i32, y as i32 - 8, self.parameters.basic)
+                    && !self.is_reserved(x as i32, y as i32, width as usize, height as usize)
+                {
+                    if points.len() < MAX_SPRAY_POINTS {
+                        points.push((x as i32, y as i32));
+                    } else {
+                        // move out of the land so that nothing else fits
+                        x = 0xFF00_0000;
+                        y = 0xFF00_0000;
+                    }
+                }
+
+                y = y.wrapping_add(12);
+                if y >= land_height - height - 8 || (y_wraps > 0 && y >= y_start) {
+                    y_wraps += 1;
+                    y = 8;
+                }
+                if y_wraps > 1 {
+                    break;
+                }
+            }
+
+            x = x.wrapping_add(get_random(random_numbers, 12) + 12);
+            if x >= land_width - width || (x_wraps > 0 && x >= x_start) {
+                x_wraps += 1;
+                x = 0;
+            }
+            if x_wraps > 1 {
+                break;
+            }
+        }
+
+        if points.is_empty() {
+            *max_count = 0;
+            return false;
+        }
+
+        let (x, y) = points[get_random(random_numbers, points.len() as u32) as usize];
+        for (sprite_y, row) in sprite.rows().enumerate() {
+            let texture_y = y as usize + sprite_y;
+            if texture_y >= self.texture.height() {
+                break;
+            }
+
+            for (sprite_x, &color) in row.iter().enumerate() {
+                let texture_x = x as usize + sprite_x;
+                if texture_x < self.texture.width() {
+                    let texture_pixel = &mut self.texture[texture_y][texture_x];
+                    *texture_pixel = blend_spray(color, *texture_pixel);
+                }
+            }
+        }
+
+        self.reserved.push(Rect::from_size_coords(
+            x - 32,
+            y - 32,
+            sprite.width() + 64,
+            sprite.height() + 64,
+        ));
+
+        *max_count -= 1;
+        true
+    }
+}
+
+/// Cycles through the items starting from a random one until none of them fits anymore
+fn place_all<I, F>(max_counts: &mut [u32], random_numbers: &mut I, mut try_put: F)
+where
+    I: Iterator<Item = u32>,
+    F: FnMut(usize, &mut u32, &mut I) -> bool,
+{
+    let count = max_counts.len();
+    if count == 0 {
+        return;
+    }
+
+    loop {
+        let start = get_random(random_numbers, count as u32) as usize;
+        let mut is_placed = false;
+
+        for i in 0..count {
+            let index = (i + start) % count;
+            // like in the engine, once something is placed the rest of the round is skipped
+            if max_counts[index] != 0 && !is_placed {
+                is_placed = try_put(index, &mut max_counts[index], random_numbers);
+            }
+        }
+
+        if !is_placed {
+            break;
+        }
+    }
+}
+
+pub(crate) fn place_objects<T, I>(
+    land: &mut Land2D<T>,
+    texture: &mut Vec2D<u32>,
+    girders: &[Rect],
+    theme: &Theme,
+    parameters: &ObjectParameters<T>,
+    random_numbers: &mut I,
+) -> Vec<Rect>
+where
+    T: Copy + Default + PartialEq,
+    I: Iterator<Item = u32>,
+{
+    let mut placer = ObjectPlacer {
+        land,
+        texture,
+        parameters,
+        zero: T::default(),
+        reserved: Vec::new(),
+        placed: Vec::new(),
+    };
+
+    let sprays = theme.sprays();
+    let mut max_counts: Vec<_> = sprays
+        .iter()
+        .map(|s| scaled_count(s.config().max_count, parameters.max_hedgehogs))
+        .collect();
+    place_all(&mut max_counts, random_numbers, |i, max_count, random_numbers| {
+        placer.try_put_spray(&sprays[i], max_count, random_numbers)
+    });
+
+    // sprays don't collide with the objects but the girders are placed before the objects
+    placer.reserved = girders.iter().map(reserved_area).collect();
+
+    let objects = theme.objects();
+    let mut max_counts: Vec<_> = objects
+        .iter()
+        .map(|o| scaled_count(o.config().max_count, parameters.max_hedgehogs))
+        .collect();
+    place_all(&mut max_counts, random_numbers, |i, max_count, random_numbers| {
+        placer.try_put_object(&objects[i], max_count, random_numbers)
+    });
+
+    placer.placed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use integral_geometry::Size;
+    use lfprng::LaggedFibonacciPRNG;
+    use std::path::Path;
+
+    const GROUND_COLOR: u32 = 0xFF20_4060;
+
+    #[test]
+    fn blending() {
+        assert_eq!(scaled_count(4, 18), 4);
+        assert_eq!(scaled_count(4, 2), 1);
+        assert_eq!(scaled_count(4, 48), 10);
+
+        assert_eq!(blend_spray(0xFF11_2233, 0x8044_5566), 0xFF11_2233);
+        assert_eq!(blend_spray(0x8011_2233, 0x0044_5566), 0x8011_2233);
+        assert_eq!(blend_spray(0x8000_0000, 0xFFFF_FFFF), 0xFF7F_7F7F);
+
+        assert_eq!(blend_over(0xFF00_0000, 0xFFFF_FFFF), 0xFF00_0000);
+        assert_eq!(blend_under(0xFF00_0000, 0x80FF_FFFF), 0xFF80_8080);
+    }
+
+    #[test]
+    fn fixed_seed_placement() {
+        let theme_path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../../share/hedgewars/Data/Themes/EarthRise");
+        let theme = Theme::load(&theme_path).unwrap();
+
+        // two platforms and a girder above the right one
+        let mut land = Land2D::new(Size::new(2048, 1024), LandFlags::empty());
+        let mut texture = Vec2D::new(land.size(), 0u32);
+        let girder = Rect::from_size_coords(1300, 500, 300, 16);
+        let solid_rects = [
+            Rect::from_size_coords(200, 600, 400, 100),
+            Rect::from_size_coords(1100, 750, 600, 100),
+            girder,
+        ];
+        for rect in &solid_rects {
+            for y in rect.y_range() {
+                for x in rect.x_range() {
+                    land.map(y, x, |p| *p = LandFlags::BASIC);
+                    texture[y as usize][x as usize] = GROUND_COLOR;
+                }
+            }
+        }
+
+        let parameters =
+            ObjectParameters::new(LandFlags::BASIC, LandFlags::OBJECT, DEFAULT_MAX_HEDGEHOGS);
+        let mut random_numbers =
+            LaggedFibonacciPRNG::new(b"{3c4fbf5c-35d4-4e5e-a3e0-a2c9bd0b0b56}");
+        let placed = place_objects(
+            &mut land,
+            &mut texture,
+            &[girder],
+            &theme,
+            &parameters,
+            &mut random_numbers,
+        );
+
+        // the engine puts a rock on top of the girder if it isn't reserved
+        assert_eq!(
+            placed,
+            vec![
+                Rect::from_size_coords(265, 371, 240, 260),
+                Rect::from_size_coords(1151, 593, 163, 167),
+                Rect::from_size_coords(1530, 587, 163, 167),
+                Rect::from_size_coords(1345, 596, 163, 167),
+            ]
+        );
+
+        let basic_count = land
+            .raw_pixels()
+            .iter()
+            .filter(|&&p| p == LandFlags::BASIC)
+            .count();
+        assert_eq!(basic_count, 161002);
+        assert!(land
+            .raw_pixels()
+            .iter()
+            .all(|&p| p == LandFlags::BASIC || p.is_empty()));
+        assert_eq!(
+            texture.as_slice().iter().map(|&c| c as u64).sum::<u64>(),
+            690127209460025
+        );
+
+        assert_eq!(land[501][385], LandFlags::BASIC);
+        assert_eq!(texture[501][385], 0xFF4F_4F4F);
+        assert_eq!(land[381][275], LandFlags::empty());
+        assert_eq!(texture[381][275], 0);
+        assert_eq!(texture[611][345], GROUND_COLOR);
+    }
+}
@@ -0,0 +1,309 @@
+use std::{fs, io, path::Path};
+
+use crate::theme::{load_sprite, ThemeLoadError, ThemeSprite};
+use integral_geometry::Size;
+use land2d::{Land2D, LandFlags};
+use landgen::outline_template::DEFAULT_MAX_HEDGEHOGS;
+use png::DecodingError;
+use vec2d::Vec2D;
+
+const MAP_FILE_NAME: &str = "map.png";
+const MASK_FILE_NAME: &str = "mask.png";
+const CONFIG_FILE_NAME: &str = "map.cfg";
+
+#[derive(Debug)]
+pub enum StaticMapLoadError {
+    File(io::Error),
+    Decoding(DecodingError),
+    Format(String),
+}
+
+impl From<io::Error> for StaticMapLoadError {
+    fn from(e: io::Error) -> Self {
+        StaticMapLoadError::File(e)
+    }
+}
+
+impl From<ThemeLoadError> for StaticMapLoadError {
+    fn from(e: ThemeLoadError) -> Self {
+        match e {
+            ThemeLoadError::File(e) => StaticMapLoadError::File(e),
+            ThemeLoadError::Decoding(e) => StaticMapLoadError::Decoding(e),
+            ThemeLoadError::Format(e) => StaticMapLoadError::Format(e),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MapConfig {
+    pub theme: String,
+    pub max_hedgehogs: u8,
+}
+
+impl Default for MapConfig {
+    fn default() -> Self {
+        Self {
+            theme: String::new(),
+            max_hedgehogs: DEFAULT_MAX_HEDGEHOGS,
+        }
+    }
+}
+
+impl MapConfig {
+    /// The first line is the theme name and the second one is the hedgehog limit,
+    /// the rest is only used by the frontend
+    pub fn parse(text: &str) -> Self {
+        let mut lines = text.lines().map(str::trim);
+        let theme = lines.next().unwrap_or_default().to_string();
+        let max_hedgehogs = lines
+            .next()
+            .and_then(|s| s.parse().ok())
+            .filter(|&n| n != 0)
+            .unwrap_or(DEFAULT_MAX_HEDGEHOGS);
+
+        Self {
+            theme,
+            max_hedgehogs,
+        }
+    }
+}
+
+pub struct StaticMap {
+    pub land: Land2D<LandFlags>,
+    pub texture: Option<Vec2D<u32>>,
+    pub config: MapConfig,
+}
+
+impl StaticMap {
+    /// Loads the map from its directory, mask-only maps have no texture and have to be
+    /// textured with the theme. Maps without a config get the default one like in the engine
+    pub fn load(path: &Path) -> Result<StaticMap, StaticMapLoadError> {
+        let config = match fs::read_to_string(path.join(CONFIG_FILE_NAME)) {
+            Ok(text) => MapConfig::parse(&text),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => MapConfig::default(),
+            Err(e) => return Err(e.into()),
+        };
+
+        let map_path = path.join(MAP_FILE_NAME);
+        let image = if map_path.exists() {
+            Some(load_sprite(&map_path)?)
+        } else {
+            None
+        };
+
+        let mask_path = path.join(MASK_FILE_NAME);
+        let mask = if mask_path.exists() {
+            Some(load_sprite(&mask_path)?)
+        } else {
+            None
+        };
+
+        let play_size = match (&image, &mask) {
+            (Some(image), _) => image.size(),
+            (None, Some(mask)) => mask.size(),
+            (None, None) => {
+                return Err(StaticMapLoadError::Format(format!(
+                    "Neither {} nor {} found",
+                    MAP_FILE_NAME, MASK_FILE_NAME
+                )))
+            }
+        };
+
+        let mut land = Land2D::new(play_size, LandFlags::empty());
+        let mut texture = None;
+
+        if let Some(image) = image {
+            let mut pixels = Vec2D::new(land.size(), 0);
+            blit(&mut land, &image, |land_pixel, color, x, y| {
+                if alpha(color) != 0 {
+                    *land_pixel = LandFlags::OBJECT;
+                    pixels[y][x] = color;
+                }
+            });
+            texture = Some(pixels);
+        }
+
+        if let Some(mask) = mask {
+            if mask.size() != play_size {
+                return Err(StaticMapLoadError::Format(format!(
+                    "Mask size {:?} doesn't match the map size {:?}",
+                    mask.size(),
+                    play_size
+                )));
+            }
+
+            blit(&mut land, &mask, |land_pixel, color, _, _| {
+                if let Some(value) = mask_land_value(color) {
+                    *land_pixel = value;
+                }
+            });
+        }
+
+        Ok(StaticMap {
+            land,
+            texture,
+            config,
+        })
+    }
+
+    pub fn size(&self) -> Size {
+        self.land.size()
+    }
+}
+
+fn blit<F>(land: &mut Land2D<LandFlags>, sprite: &ThemeSprite, mut f: F)
+where
+    F: FnMut(&mut LandFlags, u32, usize, usize),
+{
+    let top_left = land.play_box().top_left();
+    for (y, row) in sprite.rows().enumerate() {
+        let land_y = top_left.y + y as i32;
+        for (x, &color) in row.iter().enumerate() {
+            let land_x = top_left.x + x as i32;
+            land.map(land_y, land_x, |p| {
+                f(p, color, land_x as usize, land_y as usize)
+            });
+        }
+    }
+}
+
+#[inline]
+fn alpha(color: u32) -> u32 {
+    color >> 24
+}
+
+/// Classifies mask pixels the same way the engine does, colours only matter by which
+/// channels are set so that map authors don't have to be precise
+pub(crate) fn mask_land_value(color: u32) -> Option<LandFlags> {
+    if alpha(color) == 0 {
+        return Some(LandFlags::empty());
+    }
+
+    let red = color & 0xFF != 0;
+    let green = (color >> 8) & 0xFF != 0;
+    let blue = (color >> 16) & 0xFF != 0;
+
+    match (red, green, blue) {
+        (true, true, true) => Some(LandFlags::OBJECT),
+        (false, false, false) => Some(LandFlags::BASIC),
+        (true, false, false) => Some(LandFlags::INDESTRUCTIBLE),
+        (false, false, true) => Some(LandFlags::OBJECT | LandFlags::ICE),
+        (false, true, false) => Some(LandFlags::OBJECT | LandFlags::BOUNCY),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{collections::BTreeMap, path::PathBuf};
+
+    fn map_path(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../../share/hedgewars/Data/Maps")
+            .join(name)
+    }
+
+    fn land_counts(land: &Land2D<LandFlags>) -> Vec<(u16, usize)> {
+        let mut counts = BTreeMap::new();
+        for pixel in land.raw_pixels() {
+            *counts.entry(pixel.bits()).or_insert(0) += 1;
+        }
+        counts.into_iter().collect()
+    }
+
+    #[test]
+    fn map_config() {
+        assert_eq!(
+            MapConfig::parse("Deepspace\n64\nDefault\nCrazy\n"),
+            MapConfig {
+                theme: "Deepspace".to_string(),
+                max_hedgehogs: 64
+            }
+        );
+        assert_eq!(
+            MapConfig::parse("Snow\r\n\r\n"),
+            MapConfig {
+                theme: "Snow".to_string(),
+                max_hedgehogs: DEFAULT_MAX_HEDGEHOGS
+            }
+        );
+    }
+
+    #[test]
+    fn map_with_mask() {
+        let map = StaticMap::load(&map_path("HedgeFortress")).unwrap();
+
+        assert_eq!(
+            map.config,
+            MapConfig {
+                theme: "Nature".to_string(),
+                max_hedgehogs: DEFAULT_MAX_HEDGEHOGS
+            }
+        );
+        assert_eq!(map.size(), Size::new(2048, 1024));
+        assert_eq!(
+            land_counts(&map.land),
+            vec![(0, 1469043), (0x2000, 289507), (0x8000, 338602)]
+        );
+
+        let texture = map.texture.unwrap();
+        let textured = texture.as_slice().iter().filter(|&&c| c != 0);
+        assert_eq!(textured.clone().count(), 631266);
+        assert_eq!(
+            textured.map(|&c| c as u64).sum::<u64>(),
+            2690589354884183
+        );
+    }
+
+    #[test]
+    fn mask_only_map() {
+        // ClimbHome is the only shipped mask-only map but it's too large to load in a test,
+        // so another map is stripped of its image instead
+        let path = std::env::temp_dir().join(format!("mask-only-map-{}", std::process::id()));
+        fs::create_dir_all(&path).unwrap();
+        for name in &[MASK_FILE_NAME, CONFIG_FILE_NAME] {
+            fs::copy(map_path("BasketballField").join(name), path.join(name)).unwrap();
+        }
+        let map = StaticMap::load(&path);
+        fs::remove_dir_all(&path).unwrap();
+        let map = map.unwrap();
+
+        assert_eq!(map.config.max_hedgehogs, 12);
+        assert_eq!(map.size(), Size::new(2048, 1024));
+        assert!(map.texture.is_none());
+        assert_eq!(
+            land_counts(&map.land),
+            vec![(0, 1381682), (0x4000, 715470)]
+        );
+    }
+
+    #[test]
+    fn map_without_config() {
+        let map = StaticMap::load(&map_path("test")).unwrap();
+
+        assert_eq!(map.config, MapConfig::default());
+        assert_eq!(map.config.max_hedgehogs, 18);
+        assert!(map.texture.is_some());
+    }
+
+    #[test]
+    fn mask_colors() {
+        assert_eq!(mask_land_value(0x00FF_FFFF), Some(LandFlags::empty()));
+        assert_eq!(mask_land_value(0xFFFF_FFFF), Some(LandFlags::OBJECT));
+        assert_eq!(mask_land_value(0xFF00_0000), Some(LandFlags::BASIC));
+        assert_eq!(
+            mask_land_value(0xFF00_00FF),
+            Some(LandFlags::INDESTRUCTIBLE)
+        );
+        assert_eq!(
+            mask_land_value(0xFFFF_0000),
+            Some(LandFlags::OBJECT | LandFlags::ICE)
+        );
+        assert_eq!(
+            mask_land_value(0xFF00_FF00),
+            Some(LandFlags::OBJECT | LandFlags::BOUNCY)
+        );
+        assert_eq!(mask_land_value(0xFF00_FFFF), None);
+    }
+}
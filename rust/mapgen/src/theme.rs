@@ -1,6 +1,6 @@
 use png::{ColorType, Decoder, DecodingError};
 use std::{
-    fs::{read_dir, File},
+    fs::{read_dir, read_to_string, File},
     io,
     io::BufReader,
     path::Path,
@@ -10,6 +10,19 @@ use std::{
 use integral_geometry::Size;
 use vec2d::Vec2D;
 
+mod config;
+
+pub use self::config::{
+    FlakesConfig, ObjectOverlayConfig, SprayConfig, SuddenDeathConfig, ThemeColor, ThemeConfig,
+    ThemeObjectConfig, WaterAnimation, WaterConfig,
+};
+
+const CONFIG_FILE_NAME: &str = "theme.cfg";
+
+// horizontal parallax of the background layers relative to the camera
+const SKY_PARALLAX: (i32, i32) = (3, 8);
+const HORIZON_PARALLAX: (i32, i32) = (3, 5);
+
 pub struct ThemeSprite {
     pixels: Vec2D<u32>,
 }
@@ -109,19 +122,181 @@ impl TiledSprite {
     }
 }
 
+pub struct ThemeObject {
+    config: ThemeObjectConfig,
+    sprite: ThemeSprite,
+    mask: Option<ThemeSprite>,
+    overlays: Vec<ThemeSprite>,
+}
+
+impl ThemeObject {
+    pub fn config(&self) -> &ThemeObjectConfig {
+        &self.config
+    }
+
+    pub fn sprite(&self) -> &ThemeSprite {
+        &self.sprite
+    }
+
+    pub fn mask(&self) -> Option<&ThemeSprite> {
+        self.mask.as_ref()
+    }
+
+    pub fn overlays(&self) -> &[ThemeSprite] {
+        &self.overlays
+    }
+}
+
+pub struct ThemeSpray {
+    config: SprayConfig,
+    sprite: ThemeSprite,
+}
+
+impl ThemeSpray {
+    pub fn config(&self) -> &SprayConfig {
+        &self.config
+    }
+
+    pub fn sprite(&self) -> &ThemeSprite {
+        &self.sprite
+    }
+}
+
+/// A background layer made of a center sprite and optional sprites repeated to its left and
+/// right, without the side sprites the center one is tiled over the whole screen
+pub struct BackgroundLayer {
+    sprite: ThemeSprite,
+    left: Option<ThemeSprite>,
+    right: Option<ThemeSprite>,
+    parallax: (i32, i32),
+}
+
+impl BackgroundLayer {
+    fn load(path: &Path, name: &str, parallax: (i32, i32)) -> Result<Option<Self>, ThemeLoadError> {
+        let load_optional = |suffix| {
+            let sprite_path = path.join(format!("{}{}.png", name, suffix));
+            if sprite_path.exists() {
+                load_sprite(&sprite_path).map(Some)
+            } else {
+                Ok(None)
+            }
+        };
+
+        Ok(match load_optional("")? {
+            Some(sprite) => Some(Self {
+                sprite,
+                left: load_optional("L")?,
+                right: load_optional("R")?,
+                parallax,
+            }),
+            None => None,
+        })
+    }
+
+    pub fn sprite(&self) -> &ThemeSprite {
+        &self.sprite
+    }
+
+    pub fn left(&self) -> Option<&ThemeSprite> {
+        self.left.as_ref()
+    }
+
+    pub fn right(&self) -> Option<&ThemeSprite> {
+        self.right.as_ref()
+    }
+
+    /// Horizontal shift of the layer for the offset of the world on the screen
+    pub fn shift(&self, world_dx: i32, land_width: usize) -> i32 {
+        let (numerator, denominator) = self.parallax;
+        (world_dx + land_width as i32 / 2) * numerator / denominator
+    }
+
+    /// Horizontal positions of the sprites covering the screen, which spans from
+    /// `-screen_width` to `screen_width` around its center like in the engine
+    pub fn placements(&self, shift: i32, screen_width: i32) -> Vec<(i32, &ThemeSprite)> {
+        let width = self.sprite.width() as i32;
+        let mut placements = Vec::new();
+
+        if let Some(left) = &self.left {
+            let shift = shift - width / 2;
+            placements.push((shift, &self.sprite));
+
+            let left_width = left.width() as i32;
+            let mut x = shift - left_width;
+            while x >= -screen_width - left_width {
+                placements.push((x, left));
+                x -= left_width;
+            }
+
+            // the left sprite is repeated on the right too when there is no right one
+            let right = self.right.as_ref().unwrap_or(left);
+            let mut x = shift + width;
+            while x <= screen_width {
+                placements.push((x, right));
+                x += right.width() as i32;
+            }
+        } else {
+            let mut x = shift % width;
+            if x > 0 {
+                x -= width;
+            }
+            x -= width * (screen_width / width + 1);
+
+            while x <= screen_width {
+                placements.push((x, &self.sprite));
+                x += width;
+            }
+        }
+
+        placements
+    }
+}
+
+#[derive(Default)]
 pub struct Theme {
+    config: ThemeConfig,
     land_texture: Option<ThemeSprite>,
+    land_back_texture: Option<ThemeSprite>,
     border_texture: Option<ThemeSprite>,
+    sky: Option<BackgroundLayer>,
+    horizon: Option<BackgroundLayer>,
+    objects: Vec<ThemeObject>,
+    sprays: Vec<ThemeSpray>,
 }
 
 impl Theme {
+    pub fn config(&self) -> &ThemeConfig {
+        &self.config
+    }
+
     pub fn land_texture(&self) -> Option<&ThemeSprite> {
         self.land_texture.as_ref()
     }
 
+    /// Texture showing through where the land has been destroyed
+    pub fn land_back_texture(&self) -> Option<&ThemeSprite> {
+        self.land_back_texture.as_ref()
+    }
+
     pub fn border_texture(&self) -> Option<&ThemeSprite> {
         self.border_texture.as_ref()
     }
+
+    pub fn sky(&self) -> Option<&BackgroundLayer> {
+        self.sky.as_ref()
+    }
+
+    pub fn horizon(&self) -> Option<&BackgroundLayer> {
+        self.horizon.as_ref()
+    }
+
+    pub fn objects(&self) -> &[ThemeObject] {
+        &self.objects
+    }
+
+    pub fn sprays(&self) -> &[ThemeSpray] {
+        &self.sprays
+    }
 }
 
 #[derive(Debug)]
@@ -145,42 +320,97 @@ impl From<DecodingError> for ThemeLoadError {
 
 impl Theme {
     pub fn new() -> Self {
-        Theme {
-            land_texture: None,
-            border_texture: None,
-        }
+        Self::default()
     }
 
     pub fn load(path: &Path) -> Result<Theme, ThemeLoadError> {
         let mut theme = Self::new();
+        theme.config = ThemeConfig::parse(&read_to_string(path.join(CONFIG_FILE_NAME))?)?;
 
         for entry in read_dir(path)? {
             let file = entry?;
             if file.file_name() == "LandTex.png" {
                 theme.land_texture = Some(load_sprite(&file.path())?)
+            } else if file.file_name() == "LandBackTex.png" {
+                theme.land_back_texture = Some(load_sprite(&file.path())?)
             } else if file.file_name() == "Border.png" {
                 theme.border_texture = Some(load_sprite(&file.path())?)
             }
         }
 
+        theme.sky = BackgroundLayer::load(path, "Sky", SKY_PARALLAX)?;
+        theme.horizon = BackgroundLayer::load(path, "horizont", HORIZON_PARALLAX)?;
+
+        let sprite_path = |name: &str| path.join(format!("{}.png", name));
+
+        for object_config in &theme.config.objects {
+            let mask_path = sprite_path(&format!("{}_mask", object_config.name));
+            let mask = if mask_path.exists() {
+                Some(load_sprite(&mask_path)?)
+            } else {
+                None
+            };
+
+            let overlays = object_config
+                .overlays
+                .iter()
+                .map(|overlay| load_sprite(&sprite_path(&overlay.name)))
+                .collect::<Result<_, _>>()?;
+
+            theme.objects.push(ThemeObject {
+                config: object_config.clone(),
+                sprite: load_sprite(&sprite_path(&object_config.name))?,
+                mask,
+                overlays,
+            });
+        }
+
+        for spray_config in &theme.config.sprays {
+            theme.sprays.push(ThemeSpray {
+                config: spray_config.clone(),
+                sprite: load_sprite(&sprite_path(&spray_config.name))?,
+            });
+        }
+
         Ok(theme)
     }
 }
 
-fn load_sprite(path: &Path) -> Result<ThemeSprite, ThemeLoadError> {
+pub(crate) fn load_sprite(path: &Path) -> Result<ThemeSprite, ThemeLoadError> {
     let decoder = Decoder::new(BufReader::new(File::open(path)?));
     let (info, mut reader) = decoder.read_info()?;
 
-    if info.color_type != ColorType::RGBA {
-        return Err(ThemeLoadError::Format(format!(
-            "Unexpected format: {:?}",
-            info.color_type
-        )));
-    }
     let size = Size::new(info.width as usize, info.height as usize);
-
     let mut pixels: Vec2D<u32> = Vec2D::new(size, 0);
-    reader.next_frame(slice_u32_to_u8_mut(pixels.as_mut_slice()))?;
+
+    if info.color_type == ColorType::RGBA {
+        reader.next_frame(slice_u32_to_u8_mut(pixels.as_mut_slice()))?;
+    } else {
+        let channels = match info.color_type {
+            ColorType::RGB => 3,
+            ColorType::Grayscale => 1,
+            ColorType::GrayscaleAlpha => 2,
+            _ => {
+                return Err(ThemeLoadError::Format(format!(
+                    "Unexpected format: {:?}",
+                    info.color_type
+                )))
+            }
+        };
+
+        let mut data = vec![0; info.buffer_size()];
+        reader.next_frame(&mut data)?;
+
+        for (pixel, source) in pixels.as_mut_slice().iter_mut().zip(data.chunks(channels)) {
+            let [red, green, blue, alpha] = match *source {
+                [red, green, blue] => [red, green, blue, 255],
+                [gray] => [gray, gray, gray, 255],
+                [gray, alpha] => [gray, gray, gray, alpha],
+                _ => unreachable!(),
+            };
+            *pixel = u32::from_le_bytes([red, green, blue, alpha]);
+        }
+    }
 
     Ok(ThemeSprite { pixels })
 }
@@ -192,3 +422,189 @@ pub fn slice_u32_to_u8(slice_u32: &[u32]) -> &[u8] {
 pub fn slice_u32_to_u8_mut(slice_u32: &mut [u32]) -> &mut [u8] {
     unsafe { from_raw_parts_mut::<u8>(slice_u32.as_mut_ptr() as *mut u8, slice_u32.len() * 4) }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{blend, MapGenerator};
+    use land2d::Land2D;
+
+    fn sprite(width: usize) -> ThemeSprite {
+        ThemeSprite {
+            pixels: Vec2D::new(Size::new(width, 1), 0),
+        }
+    }
+
+    fn layer(width: usize, left: Option<usize>, right: Option<usize>) -> BackgroundLayer {
+        BackgroundLayer {
+            sprite: sprite(width),
+            left: left.map(sprite),
+            right: right.map(sprite),
+            parallax: HORIZON_PARALLAX,
+        }
+    }
+
+    /// Names the sprites of the placements as the center, left or right one
+    fn placements(layer: &BackgroundLayer, shift: i32) -> Vec<(i32, char)> {
+        layer
+            .placements(shift, 640)
+            .into_iter()
+            .map(|(x, sprite)| {
+                let name = if std::ptr::eq(sprite, &layer.sprite) {
+                    'c'
+                } else if layer.left().is_some_and(|s| std::ptr::eq(sprite, s)) {
+                    'l'
+                } else {
+                    'r'
+                };
+                (x, name)
+            })
+            .collect()
+    }
+
+    // the expectations below come from the engine's DrawRepeated and the shifts
+    // the sky and the horizon are drawn with
+    #[test]
+    fn background_shift() {
+        let sky = BackgroundLayer {
+            parallax: SKY_PARALLAX,
+            ..layer(256, None, None)
+        };
+        assert_eq!(sky.shift(-300, 4096), 655);
+        assert_eq!(sky.shift(-2999, 4096), -356);
+
+        let horizon = layer(512, None, None);
+        assert_eq!(horizon.shift(-1800, 4096), 148);
+        assert_eq!(horizon.shift(-2400, 4096), -211);
+    }
+
+    #[test]
+    fn tiled_background() {
+        let layer = layer(256, None, None);
+
+        assert_eq!(
+            placements(&layer, -356),
+            [
+                (-868, 'c'),
+                (-612, 'c'),
+                (-356, 'c'),
+                (-100, 'c'),
+                (156, 'c'),
+                (412, 'c')
+            ]
+        );
+        assert_eq!(
+            placements(&layer, 655),
+            [
+                (-881, 'c'),
+                (-625, 'c'),
+                (-369, 'c'),
+                (-113, 'c'),
+                (143, 'c'),
+                (399, 'c')
+            ]
+        );
+    }
+
+    #[test]
+    fn background_with_sides() {
+        assert_eq!(
+            placements(&layer(512, Some(128), Some(192)), 148),
+            [
+                (-108, 'c'),
+                (-236, 'l'),
+                (-364, 'l'),
+                (-492, 'l'),
+                (-620, 'l'),
+                (-748, 'l'),
+                (404, 'r'),
+                (596, 'r')
+            ]
+        );
+        assert_eq!(
+            placements(&layer(512, Some(128), None), -211),
+            [
+                (-467, 'c'),
+                (-595, 'l'),
+                (-723, 'l'),
+                (45, 'l'),
+                (173, 'l'),
+                (301, 'l'),
+                (429, 'l'),
+                (557, 'l')
+            ]
+        );
+    }
+
+    fn land_pixel(x: usize, y: usize) -> u32 {
+        u32::from_le_bytes([x as u8 * 10, y as u8 * 10, 100, 255])
+    }
+
+    fn border_pixel(x: usize, y: usize) -> u32 {
+        u32::from_le_bytes([200, x as u8 * 10, y as u8 * 5, 255])
+    }
+
+    fn painted_sprite(size: Size, paint: fn(usize, usize) -> u32) -> ThemeSprite {
+        let mut pixels = Vec2D::new(size, 0);
+        for y in 0..size.height {
+            for x in 0..size.width {
+                pixels[y][x] = paint(x, y);
+            }
+        }
+        ThemeSprite { pixels }
+    }
+
+    // the engine's DrawBorderFromImage draws the lower half of the border sprite over the
+    // bottom of every land run that doesn't reach the bottom of the map, then the upper
+    // half over the top of every run that doesn't start at the top
+    #[test]
+    fn land_texture_and_border() {
+        let theme = Theme {
+            land_texture: Some(painted_sprite(Size::square(4), land_pixel)),
+            border_texture: Some(painted_sprite(Size::new(4, 32), border_pixel)),
+            ..Theme::default()
+        };
+
+        let mut land = Land2D::new(Size::new(8, 64), 0u8);
+        for y in (4..28).chain(30..64) {
+            (0..4).for_each(|x| land.map(y, x, |v| *v = 1));
+        }
+        for y in 0..20 {
+            (4..8).for_each(|x| land.map(y, x, |v| *v = 1));
+        }
+
+        let texture = MapGenerator::new().make_texture(&land, &theme);
+        // the land pixel at (x, y) with the given border sprite row blended in
+        let bordered =
+            |x: usize, y: usize, row| blend(border_pixel(x % 4, row), land_pixel(x % 4, y % 4));
+
+        // the first run gets both borders, the top one drawn over the bottom one
+        assert_eq!(texture[3][1], 0);
+        assert_eq!(texture[4][1], bordered(1, 4, 0));
+        assert_eq!(texture[11][1], bordered(1, 11, 7));
+        assert_eq!(
+            texture[12][1],
+            blend(border_pixel(1, 8), bordered(1, 12, 16))
+        );
+        assert_eq!(
+            texture[19][1],
+            blend(border_pixel(1, 15), bordered(1, 19, 23))
+        );
+        assert_eq!(texture[20][1], bordered(1, 20, 24));
+        assert_eq!(texture[27][1], bordered(1, 27, 31));
+        assert_eq!(texture[28][1], 0);
+
+        // the second run reaches the bottom of the map
+        assert_eq!(texture[30][2], bordered(2, 30, 0));
+        assert_eq!(texture[45][2], bordered(2, 45, 15));
+        assert_eq!(texture[46][2], land_pixel(2, 2));
+        assert_eq!(texture[63][2], land_pixel(2, 3));
+
+        // this one starts at the top of the map
+        assert_eq!(texture[0][5], land_pixel(1, 0));
+        assert_eq!(texture[3][5], land_pixel(1, 3));
+        assert_eq!(texture[4][5], bordered(5, 4, 16));
+        assert_eq!(texture[19][5], bordered(5, 19, 31));
+        assert_eq!(texture[20][5], 0);
+    }
+}
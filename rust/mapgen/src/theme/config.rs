@@ -0,0 +1,524 @@
+use std::{convert::TryFrom, str::Split};
+
+use super::ThemeLoadError;
+use integral_geometry::{Point, Rect};
+
+const MAX_THEME_OBJECTS: usize = 32;
+const MAX_OBJECT_RECTS: usize = 16;
+const MAX_ROPE_LAYERS: u32 = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ThemeColor {
+    pub red: u8,
+    pub green: u8,
+    pub blue: u8,
+    pub alpha: u8,
+}
+
+impl ThemeColor {
+    pub const fn rgb(red: u8, green: u8, blue: u8) -> Self {
+        Self::rgba(red, green, blue, 255)
+    }
+
+    pub const fn rgba(red: u8, green: u8, blue: u8, alpha: u8) -> Self {
+        Self {
+            red,
+            green,
+            blue,
+            alpha,
+        }
+    }
+
+    /// Packs the colour the same way the sprite pixels are stored
+    pub fn to_pixel(self) -> u32 {
+        self.red as u32
+            | (self.green as u32) << 8
+            | (self.blue as u32) << 16
+            | (self.alpha as u32) << 24
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WaterAnimation {
+    pub frames: u32,
+    pub frame_ticks: u32,
+    pub movement: i32,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct WaterConfig {
+    pub top: Option<ThemeColor>,
+    pub bottom: Option<ThemeColor>,
+    pub opacity: Option<u8>,
+    pub animation: Option<WaterAnimation>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FlakesConfig {
+    pub count: u32,
+    pub frames: u32,
+    pub frame_ticks: u32,
+    pub velocity: i32,
+    pub fall_speed: i32,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ObjectOverlayConfig {
+    pub position: Point,
+    pub name: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ThemeObjectConfig {
+    pub name: String,
+    pub max_count: u32,
+    pub inland_rects: Vec<Rect>,
+    pub outland_rects: Vec<Rect>,
+    pub anchors: Vec<Rect>,
+    pub overlays: Vec<ObjectOverlayConfig>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SprayConfig {
+    pub name: String,
+    pub max_count: u32,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SuddenDeathConfig {
+    pub tint: Option<ThemeColor>,
+    pub music: Option<String>,
+    pub fallback_music: Option<String>,
+    pub clouds: Option<u32>,
+    pub flakes: Option<FlakesConfig>,
+    pub water: WaterConfig,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ThemeConfig {
+    pub sky: ThemeColor,
+    pub reduced_quality_sky: Option<ThemeColor>,
+    pub border: ThemeColor,
+    pub music: Option<String>,
+    pub fallback_music: Option<String>,
+    pub clouds: u32,
+    pub flakes: Option<FlakesConfig>,
+    pub water: WaterConfig,
+    pub objects: Vec<ThemeObjectConfig>,
+    pub sprays: Vec<SprayConfig>,
+    pub flatten_flakes: bool,
+    pub flatten_clouds: bool,
+    pub ice: bool,
+    pub snow: bool,
+    pub hidden: bool,
+    pub rope_step: Option<u32>,
+    pub rope_layers: Option<u32>,
+    pub sudden_death: SuddenDeathConfig,
+}
+
+impl Default for ThemeConfig {
+    fn default() -> Self {
+        Self {
+            sky: ThemeColor::rgb(0, 0, 0),
+            reduced_quality_sky: None,
+            border: ThemeColor::rgb(0, 0, 0),
+            music: None,
+            fallback_music: None,
+            clouds: 0,
+            flakes: None,
+            water: WaterConfig::default(),
+            objects: vec![],
+            sprays: vec![],
+            flatten_flakes: false,
+            flatten_clouds: false,
+            ice: false,
+            snow: false,
+            hidden: false,
+            rope_step: None,
+            rope_layers: None,
+            sudden_death: SuddenDeathConfig::default(),
+        }
+    }
+}
+
+fn format_error(line: usize, message: String) -> ThemeLoadError {
+    ThemeLoadError::Format(format!("theme.cfg, line {}: {}", line, message))
+}
+
+/// Reads the comma separated values of a single line
+struct Values<'a> {
+    line: usize,
+    values: Split<'a, char>,
+}
+
+impl<'a> Values<'a> {
+    fn new(line: usize, text: &'a str) -> Self {
+        Self {
+            line,
+            values: text.split(','),
+        }
+    }
+
+    fn error(&self, message: String) -> ThemeLoadError {
+        format_error(self.line, message)
+    }
+
+    fn remaining(&self) -> usize {
+        self.values.clone().count()
+    }
+
+    fn string(&mut self) -> Result<&'a str, ThemeLoadError> {
+        match self.values.next().map(str::trim) {
+            Some(value) if !value.is_empty() => Ok(value),
+            _ => Err(self.error("value expected".to_string())),
+        }
+    }
+
+    fn number<T: TryFrom<i64>>(&mut self) -> Result<T, ThemeLoadError> {
+        let value = self.string()?;
+        let number = if let Some(hex) = value.strip_prefix('$') {
+            i64::from_str_radix(hex, 16)
+        } else if let Some(hex) = value.strip_prefix("0x") {
+            i64::from_str_radix(hex, 16)
+        } else {
+            value.parse()
+        };
+
+        number
+            .ok()
+            .and_then(|n| T::try_from(n).ok())
+            .ok_or_else(|| self.error(format!("invalid number: {}", value)))
+    }
+
+    fn color(&mut self) -> Result<ThemeColor, ThemeLoadError> {
+        Ok(ThemeColor::rgb(
+            self.number()?,
+            self.number()?,
+            self.number()?,
+        ))
+    }
+
+    fn color_with_alpha(&mut self) -> Result<ThemeColor, ThemeLoadError> {
+        Ok(ThemeColor::rgba(
+            self.number()?,
+            self.number()?,
+            self.number()?,
+            self.number()?,
+        ))
+    }
+
+    fn rect(&mut self) -> Result<Rect, ThemeLoadError> {
+        let x = self.number()?;
+        let y = self.number()?;
+        let width = self.number()?;
+        let height = self.number()?;
+        Ok(Rect::from_size_coords(x, y, width, height))
+    }
+
+    fn rect_count(&mut self, kind: &str) -> Result<usize, ThemeLoadError> {
+        let count = self.number()?;
+        if count > MAX_OBJECT_RECTS {
+            Err(self.error(format!(
+                "{} count should be no more than {} (it was {})",
+                kind, MAX_OBJECT_RECTS, count
+            )))
+        } else {
+            Ok(count)
+        }
+    }
+
+    fn rects(&mut self, count: usize) -> Result<Vec<Rect>, ThemeLoadError> {
+        (0..count).map(|_| self.rect()).collect()
+    }
+
+    fn water_animation(&mut self) -> Result<WaterAnimation, ThemeLoadError> {
+        Ok(WaterAnimation {
+            frames: self.number()?,
+            frame_ticks: self.number()?,
+            movement: self.number()?,
+        })
+    }
+
+    fn flakes(&mut self) -> Result<Option<FlakesConfig>, ThemeLoadError> {
+        let count = self.number()?;
+        if count == 0 {
+            // the rest of the values don't matter without flakes
+            self.values.by_ref().for_each(drop);
+            Ok(None)
+        } else {
+            Ok(Some(FlakesConfig {
+                count,
+                frames: self.number()?,
+                frame_ticks: self.number()?,
+                velocity: self.number()?,
+                fall_speed: self.number()?,
+            }))
+        }
+    }
+
+    fn object(&mut self) -> Result<ThemeObjectConfig, ThemeLoadError> {
+        let name = self.string()?.to_string();
+        let max_count = self.number()?;
+        if max_count < 1 || max_count > MAX_THEME_OBJECTS as u32 {
+            return Err(self.error(format!(
+                "object's max count should be between 1 and {} (it was {})",
+                MAX_THEME_OBJECTS, max_count
+            )));
+        }
+
+        // the old format has a single inland rectangle without a count
+        let inland_count = if self.remaining() % 2 == 1 {
+            1
+        } else {
+            self.rect_count("inland rectangle")?
+        };
+        let inland_rects = self.rects(inland_count)?;
+
+        let outland_count = self.rect_count("outland rectangle")?;
+        let outland_rects = self.rects(outland_count)?;
+
+        Ok(ThemeObjectConfig {
+            name,
+            max_count,
+            inland_rects,
+            outland_rects,
+            anchors: vec![],
+            overlays: vec![],
+        })
+    }
+
+    fn anchors(&mut self) -> Result<(&'a str, Vec<Rect>), ThemeLoadError> {
+        let name = self.string()?;
+        let count = self.rect_count("anchor rectangle")?;
+        Ok((name, self.rects(count)?))
+    }
+
+    fn overlays(&mut self) -> Result<(&'a str, Vec<ObjectOverlayConfig>), ThemeLoadError> {
+        let name = self.string()?;
+        let count = self.rect_count("overlay")?;
+        let overlays = (0..count)
+            .map(|_| self.overlay())
+            .collect::<Result<_, _>>()?;
+        Ok((name, overlays))
+    }
+
+    fn overlay(&mut self) -> Result<ObjectOverlayConfig, ThemeLoadError> {
+        Ok(ObjectOverlayConfig {
+            position: Point::new(self.number()?, self.number()?),
+            name: self.string()?.to_string(),
+        })
+    }
+
+    fn spray(&mut self) -> Result<SprayConfig, ThemeLoadError> {
+        Ok(SprayConfig {
+            name: self.string()?.to_string(),
+            max_count: self.number()?,
+        })
+    }
+
+    /// Reads the whole line with the given function, leftover values are an error
+    fn parse<T, F>(mut self, f: F) -> Result<T, ThemeLoadError>
+    where
+        F: FnOnce(&mut Self) -> Result<T, ThemeLoadError>,
+    {
+        let value = f(&mut self)?;
+        match self.values.next() {
+            Some(extra) => Err(self.error(format!("unexpected value: {}", extra.trim()))),
+            None => Ok(value),
+        }
+    }
+}
+
+impl ThemeConfig {
+    fn object_mut(
+        &mut self,
+        line: usize,
+        name: &str,
+    ) -> Result<&mut ThemeObjectConfig, ThemeLoadError> {
+        self.objects
+            .iter_mut()
+            .find(|o| o.name == name)
+            .ok_or_else(|| format_error(line, format!("unknown object: {}", name)))
+    }
+
+    pub fn parse(text: &str) -> Result<Self, ThemeLoadError> {
+        let mut config = Self::default();
+
+        for (index, line) in text.lines().enumerate() {
+            let line_number = index + 1;
+            if line.is_empty() || line.starts_with(';') {
+                continue;
+            }
+
+            // lines without a key are ignored the same way the engine does it
+            let (key, value) = match line.find('=') {
+                Some(i) => (line[..i].trim(), &line[i + 1..]),
+                None => continue,
+            };
+
+            let values = Values::new(line_number, value);
+            let sudden_death = &mut config.sudden_death;
+
+            match key {
+                "sky" => config.sky = values.parse(Values::color)?,
+                "rq-sky" => config.reduced_quality_sky = Some(values.parse(Values::color)?),
+                "border" => config.border = values.parse(Values::color)?,
+                "water-top" => config.water.top = Some(values.parse(Values::color)?),
+                "water-bottom" => config.water.bottom = Some(values.parse(Values::color)?),
+                "water-opacity" => config.water.opacity = Some(values.parse(Values::number)?),
+                "water-animation" => {
+                    config.water.animation = Some(values.parse(Values::water_animation)?)
+                }
+                "music" => config.music = Some(values.parse(Values::string)?.to_string()),
+                "fallback-music" => {
+                    config.fallback_music = Some(values.parse(Values::string)?.to_string())
+                }
+                "clouds" => config.clouds = values.parse(Values::number)?,
+                "flakes" => config.flakes = values.parse(Values::flakes)?,
+                "flatten-flakes" => config.flatten_flakes = true,
+                "flatten-clouds" => config.flatten_clouds = true,
+                "ice" => config.ice = true,
+                "snow" => config.snow = true,
+                "hidden" => config.hidden = value.trim() == "true",
+                "rope-step" => {
+                    let step: u32 = values.parse(Values::number)?;
+                    config.rope_step = Some(step.max(1))
+                }
+                "rope-layers" => {
+                    let layers: u32 = values.parse(Values::number)?;
+                    config.rope_layers = Some(layers.clamp(1, MAX_ROPE_LAYERS))
+                }
+                "object" => {
+                    if config.objects.len() >= MAX_THEME_OBJECTS {
+                        return Err(format_error(
+                            line_number,
+                            format!("no more than {} objects are allowed", MAX_THEME_OBJECTS),
+                        ));
+                    }
+                    config.objects.push(values.parse(Values::object)?)
+                }
+                "anchors" => {
+                    let (name, anchors) = values.parse(Values::anchors)?;
+                    let object = config.object_mut(line_number, name)?;
+                    if !object.anchors.is_empty() {
+                        return Err(format_error(
+                            line_number,
+                            format!("duplicate anchors declaration for object {}", name),
+                        ));
+                    }
+                    object.anchors = anchors
+                }
+                "overlays" => {
+                    let (name, overlays) = values.parse(Values::overlays)?;
+                    let object = config.object_mut(line_number, name)?;
+                    if !object.overlays.is_empty() {
+                        return Err(format_error(
+                            line_number,
+                            format!("duplicate overlays declaration for object {}", name),
+                        ));
+                    }
+                    object.overlays = overlays
+                }
+                "spray" => config.sprays.push(values.parse(Values::spray)?),
+                "sd-tint" => sudden_death.tint = Some(values.parse(Values::color_with_alpha)?),
+                "sd-music" => sudden_death.music = Some(values.parse(Values::string)?.to_string()),
+                "fallback-sd-music" => {
+                    sudden_death.fallback_music = Some(values.parse(Values::string)?.to_string())
+                }
+                "sd-clouds" => sudden_death.clouds = Some(values.parse(Values::number)?),
+                "sd-flakes" => sudden_death.flakes = values.parse(Values::flakes)?,
+                "sd-water-top" => sudden_death.water.top = Some(values.parse(Values::color)?),
+                "sd-water-bottom" => sudden_death.water.bottom = Some(values.parse(Values::color)?),
+                "sd-water-opacity" => {
+                    sudden_death.water.opacity = Some(values.parse(Values::number)?)
+                }
+                "sd-water-animation" => {
+                    sudden_death.water.animation = Some(values.parse(Values::water_animation)?)
+                }
+                _ => {}
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_theme_config() {
+        let text = "\
+sky = 88, 143, 221
+border = 238, 204, 160
+water-opacity = 198
+music = Beach.ogg
+flakes = 10, 40, 95, 0, -10
+; comment
+flatten-flakes = true
+spray = Shell, 1
+object = Pier, 1, 2, 0, 0, 0, 0, 320, 30, 1, 40, 1, 0, 0, 200, 140
+object = plant1, 3, 65, 258, 80, 2, 1, 0, 0, 240, 215
+anchors = Pier, 1, 280, 50, 4, 4
+overlays = plant1, 1, 0, 0, plant1_overlay
+sd-tint = $93, $6f, $54, $ff
+sd-water-bottom  = $15, $17, $18
+";
+        let config = ThemeConfig::parse(text).unwrap();
+
+        assert_eq!(config.sky, ThemeColor::rgb(88, 143, 221));
+        assert_eq!(config.water.opacity, Some(198));
+        assert_eq!(config.music.as_deref(), Some("Beach.ogg"));
+        assert_eq!(config.flakes.unwrap().fall_speed, -10);
+        assert!(config.flatten_flakes);
+        assert_eq!(config.sprays[0].name, "Shell");
+
+        let pier = &config.objects[0];
+        assert_eq!(pier.inland_rects.len(), 2);
+        assert_eq!(pier.inland_rects[1], Rect::from_size_coords(320, 30, 1, 40));
+        assert_eq!(
+            pier.outland_rects,
+            vec![Rect::from_size_coords(0, 0, 200, 140)]
+        );
+        assert_eq!(pier.anchors, vec![Rect::from_size_coords(280, 50, 4, 4)]);
+
+        let plant = &config.objects[1];
+        assert_eq!(
+            plant.inland_rects,
+            vec![Rect::from_size_coords(65, 258, 80, 2)]
+        );
+        assert_eq!(plant.overlays[0].name, "plant1_overlay");
+
+        assert_eq!(
+            config.sudden_death.tint,
+            Some(ThemeColor::rgba(0x93, 0x6f, 0x54, 0xff))
+        );
+        assert_eq!(
+            config.sudden_death.water.bottom,
+            Some(ThemeColor::rgb(0x15, 0x17, 0x18))
+        );
+    }
+
+    #[test]
+    fn theme_config_errors() {
+        let error = |text| match ThemeConfig::parse(text) {
+            Err(ThemeLoadError::Format(message)) => message,
+            _ => panic!("format error expected"),
+        };
+
+        assert_eq!(
+            error("sky = 1, 2, 3\nborder = 1, 2, 300"),
+            "theme.cfg, line 2: invalid number: 300"
+        );
+        assert_eq!(error("sky = 1, 2"), "theme.cfg, line 1: value expected");
+        assert_eq!(
+            error("\nobject = tree, 0, 1, 1, 1, 1, 0"),
+            "theme.cfg, line 2: object's max count should be between 1 and 32 (it was 0)"
+        );
+        assert_eq!(
+            error("anchors = tree, 1, 0, 0, 1, 1"),
+            "theme.cfg, line 1: unknown object: tree"
+        );
+    }
+}